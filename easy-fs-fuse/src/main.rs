@@ -103,6 +103,8 @@ fn efs_test() -> std::io::Result<()> {
         f
     })));
     EasyFileSystem::create(block_file.clone(), 4096, 1);
+    let block_device: Arc<dyn BlockDevice> = block_file.clone();
+    assert_eq!(EasyFileSystem::check(&block_device), Ok(()));
     let efs = EasyFileSystem::open(block_file.clone());
     let root_inode = EasyFileSystem::root_inode(&efs);
     root_inode.create("filea");
@@ -5,4 +5,9 @@ use core::any::Any;
 pub trait BlockDevice : Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// Whether a request has failed, after which what the device reads
+    /// cannot be trusted
+    fn failed(&self) -> bool {
+        false
+    }
 }
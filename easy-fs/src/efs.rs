@@ -4,6 +4,7 @@ use super::{
     BlockDevice,
     Bitmap,
     SuperBlock,
+    SuperBlockError,
     DiskInode,
     DiskInodeType,
     Inode,
@@ -89,6 +90,12 @@ impl EasyFileSystem {
         block_cache_sync_all();
         Arc::new(Mutex::new(efs))
     }
+    /// Check the super block of a block device before opening it
+    pub fn check(block_device: &Arc<dyn BlockDevice>) -> Result<(), SuperBlockError> {
+        get_block_cache(0, Arc::clone(block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.check())
+    }
     /// Open a block device as a filesystem
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
         // read SuperBlock
@@ -134,6 +141,13 @@ impl EasyFileSystem {
         let block_id = self.inode_area_start_block + inode_id / inodes_per_block;
         (block_id, (inode_id % inodes_per_block) as usize * inode_size)
     }
+    /// Get inode id by position, the inverse of `get_disk_inode_pos`
+    pub fn get_inode_id(&self, block_id: u32, block_offset: usize) -> u32 {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        (block_id - self.inode_area_start_block) * inodes_per_block
+            + (block_offset / inode_size) as u32
+    }
     /// Get data block by id
     pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
         self.data_area_start_block + data_block_id
@@ -1,4 +1,4 @@
-use core::fmt::{Debug, Formatter, Result};
+use core::fmt::{Debug, Display, Formatter, Result};
 use super::{
     BLOCK_SZ,
     BlockDevice,
@@ -7,8 +7,10 @@ use super::{
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
-/// Magic number for sanity check
-const EFS_MAGIC: u32 = 0x3b800001;
+/// Magic number for sanity check, bumped with every change of the format
+const EFS_MAGIC: u32 = 0x3b800002;
+/// Magic number of images from before the super block checksum
+const EFS_MAGIC_V1: u32 = 0x3b800001;
 /// The max number of direct inodes
 const INODE_DIRECT_COUNT: usize = 28;
 /// The max length of inode name
@@ -34,6 +36,52 @@ pub struct SuperBlock {
     pub inode_area_blocks: u32,
     pub data_bitmap_blocks: u32,
     pub data_area_blocks: u32,
+    /// FNV-1a of the fields above
+    checksum: u32,
+}
+
+/// Why a super block cannot be trusted
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SuperBlockError {
+    /// not an easy-fs image at all
+    Magic(u32),
+    /// an easy-fs image of an older format, to be built again
+    OldFormat(u32),
+    /// the areas do not add up to the total
+    Layout { total_blocks: u32, area_blocks: u64 },
+    /// the fields were changed behind the back of the filesystem
+    Checksum { stored: u32, computed: u32 },
+}
+
+impl SuperBlockError {
+    /// Whether the filesystem can still be read: only the checksum is
+    /// wrong, so the areas are where the super block says
+    pub fn readable(&self) -> bool {
+        matches!(self, Self::Checksum { .. })
+    }
+}
+
+impl Display for SuperBlockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Magic(magic) => write!(f, "bad magic {:#x}, not an easy-fs image", magic),
+            Self::OldFormat(magic) => write!(
+                f,
+                "magic {:#x} of an older easy-fs format, the image must be built again",
+                magic
+            ),
+            Self::Layout { total_blocks, area_blocks } => write!(
+                f,
+                "areas of {} blocks in a filesystem of {} blocks",
+                area_blocks, total_blocks
+            ),
+            Self::Checksum { stored, computed } => write!(
+                f,
+                "super block checksum {:#x}, expected {:#x}",
+                stored, computed
+            ),
+        }
+    }
 }
 
 impl Debug for SuperBlock {
@@ -65,12 +113,56 @@ impl SuperBlock {
             inode_area_blocks,
             data_bitmap_blocks,
             data_area_blocks,
-        }
+            checksum: 0,
+        };
+        self.checksum = self.compute_checksum();
     }
     /// Check if a super block is valid using efs magic
     pub fn is_valid(&self) -> bool {
         self.magic == EFS_MAGIC
     }
+    fn compute_checksum(&self) -> u32 {
+        [
+            self.magic,
+            self.total_blocks,
+            self.inode_bitmap_blocks,
+            self.inode_area_blocks,
+            self.data_bitmap_blocks,
+            self.data_area_blocks,
+        ]
+        .iter()
+        .flat_map(|field| field.to_le_bytes())
+        .fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+    }
+    /// Check the magic, that the areas after the super block fill the
+    /// filesystem, and the checksum, in that order
+    pub fn check(&self) -> core::result::Result<(), SuperBlockError> {
+        if self.magic == EFS_MAGIC_V1 {
+            return Err(SuperBlockError::OldFormat(self.magic));
+        }
+        if !self.is_valid() {
+            return Err(SuperBlockError::Magic(self.magic));
+        }
+        let area_blocks = 1
+            + self.inode_bitmap_blocks as u64
+            + self.inode_area_blocks as u64
+            + self.data_bitmap_blocks as u64
+            + self.data_area_blocks as u64;
+        if area_blocks != self.total_blocks as u64 {
+            return Err(SuperBlockError::Layout {
+                total_blocks: self.total_blocks,
+                area_blocks,
+            });
+        }
+        let computed = self.compute_checksum();
+        if self.checksum != computed {
+            return Err(SuperBlockError::Checksum {
+                stored: self.checksum,
+                computed,
+            });
+        }
+        Ok(())
+    }
 }
 
 /// Type of a disk inode
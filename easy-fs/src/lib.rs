@@ -13,6 +13,7 @@ mod block_cache;
 pub const BLOCK_SZ: usize = 512;
 pub use block_dev::BlockDevice;
 pub use efs::EasyFileSystem;
+pub use layout::SuperBlockError;
 pub use vfs::Inode;
 use layout::*;
 use bitmap::Bitmap;
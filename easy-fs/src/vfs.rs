@@ -5,6 +5,7 @@ use super::{
     DirEntry,
     EasyFileSystem,
     DIRENT_SZ,
+    BLOCK_SZ,
     get_block_cache,
     block_cache_sync_all,
 };
@@ -50,6 +51,20 @@ impl Inode {
             Arc::clone(&self.block_device)
         ).lock().modify(self.block_offset, f)
     }
+    /// Get the id of current inode
+    pub fn inode_id(&self) -> u32 {
+        self.fs.lock().get_inode_id(self.block_id as u32, self.block_offset)
+    }
+    /// Size of current inode in bytes
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+    /// Whether current inode is a directory
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
     /// Find inode under a disk inode by name
     fn find_inode_id(
         &self,
@@ -185,6 +200,23 @@ impl Inode {
             disk_inode.read_at(offset, buf, &self.block_device)
         })
     }
+    /// Load the blocks holding `[offset, offset + len)` of current inode
+    /// into the block cache, return how many there are
+    pub fn prefetch(&self, offset: usize, len: usize) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            let end = (offset + len).min(disk_inode.size as usize);
+            if offset >= end {
+                return 0;
+            }
+            let (first, last) = (offset / BLOCK_SZ, (end - 1) / BLOCK_SZ);
+            for inner_id in first..=last {
+                let block_id = disk_inode.get_block_id(inner_id as u32, &self.block_device);
+                get_block_cache(block_id as usize, Arc::clone(&self.block_device));
+            }
+            last - first + 1
+        })
+    }
     /// Write data to current inode
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
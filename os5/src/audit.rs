@@ -0,0 +1,112 @@
+//! Audit trail of security-relevant events
+//!
+//! The kernel appends an [`AuditRecord`] to a ring holding the last
+//! [`AUDIT_RECORDS`] whenever a program is executed or spawned, a task
+//! changes its capabilities, a signal is sent, a priority is raised, or a
+//! privilege check fails. Unlike the trace ring it is always on, and only a
+//! process holding [`Capabilities::AUDIT`](crate::task::Capabilities::AUDIT)
+//! can read it, with `sys_audit_read`, which takes the records it returns
+//! out of the ring. Records are numbered, so a reader can tell how many
+//! were overwritten before it got to them.
+
+use crate::sync::UPSafeCell;
+use crate::task::current_task;
+use crate::timer::get_time_us;
+use alloc::collections::VecDeque;
+use lazy_static::*;
+
+/// Number of records kept, older ones are overwritten
+pub const AUDIT_RECORDS: usize = 128;
+
+/// Bytes of the program name kept in a record
+pub const AUDIT_NAME_LEN: usize = 16;
+
+/// What an [`AuditRecord`] is about
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuditKind {
+    /// `exec` of the program in `name`; `object` is the subject
+    Exec = 0,
+    /// `spawn` of the program in `name` as the child `object`
+    Spawn = 1,
+    /// `sys_capset`, `arg` is the effective set in the high 32 bits and the
+    /// inheritable set in the low ones
+    CapSet = 2,
+    /// signal `arg` sent to the pid (or group) `object` as `sys_kill` takes it
+    Kill = 3,
+    /// the priority of `object` raised to `arg`
+    PriorityRaise = 4,
+    /// a privileged operation refused for lack of the capabilities in `arg`
+    Denied = 5,
+}
+
+/// One audited event, as `sys_audit_read` copies it out
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct AuditRecord {
+    /// number of the record, counting from 0 at boot
+    pub seq: u64,
+    pub time_us: u64,
+    pub kind: AuditKind,
+    /// whether the operation was carried out
+    pub allowed: bool,
+    /// pid of the task that did it
+    pub subject: u64,
+    /// pid it was done to
+    pub object: u64,
+    pub arg: u64,
+    /// NUL-padded program name, for [`AuditKind::Exec`] and [`AuditKind::Spawn`]
+    pub name: [u8; AUDIT_NAME_LEN],
+}
+
+struct AuditRing {
+    records: VecDeque<AuditRecord>,
+    next_seq: u64,
+}
+
+lazy_static! {
+    static ref AUDIT_RING: UPSafeCell<AuditRing> = unsafe {
+        UPSafeCell::new(AuditRing {
+            records: VecDeque::with_capacity(AUDIT_RECORDS),
+            next_seq: 0,
+        })
+    };
+}
+
+/// Record an event done by the current task to `object`
+pub fn audit(kind: AuditKind, allowed: bool, object: usize, arg: u64) {
+    audit_named(kind, allowed, object, arg, "");
+}
+
+/// Record an event about the program `name`, see [`audit`]
+pub fn audit_named(kind: AuditKind, allowed: bool, object: usize, arg: u64, name: &str) {
+    // the pid is outside the TCB inner, which callers may hold
+    let subject = current_task().map_or(0, |task| task.getpid());
+    let mut record = AuditRecord {
+        seq: 0,
+        time_us: get_time_us() as u64,
+        kind,
+        allowed,
+        subject: subject as u64,
+        object: object as u64,
+        arg,
+        name: [0; AUDIT_NAME_LEN],
+    };
+    let len = name.len().min(AUDIT_NAME_LEN);
+    record.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    let mut ring = AUDIT_RING.exclusive_access();
+    record.seq = ring.next_seq;
+    ring.next_seq += 1;
+    if ring.records.len() == AUDIT_RECORDS {
+        ring.records.pop_front();
+    }
+    ring.records.push_back(record);
+}
+
+/// Take out up to `count` of the oldest records
+pub fn take_records(count: usize) -> VecDeque<AuditRecord> {
+    let mut ring = AUDIT_RING.exclusive_access();
+    let count = count.min(ring.records.len());
+    let rest = ring.records.split_off(count);
+    core::mem::replace(&mut ring.records, rest)
+}
@@ -0,0 +1,123 @@
+//! The kernel command line
+//!
+//! The SBI hands the boot hart the physical address of the flattened device
+//! tree in `a1`; QEMU puts its `-append` string there as the `bootargs`
+//! property of `/chosen`. [`init`] copies it out before the frame allocator
+//! may hand the pages of the tree out, arguments are then read with
+//! [`bootarg`]. They are whitespace separated `key=value` pairs, or a bare
+//! `key`. The `rng-seed` property next to it, if any, seeds
+//! [`random`](crate::random).
+
+use crate::random::add_entropy;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Longest command line kept, the rest is dropped
+const MAX_BOOTARGS_LEN: usize = 256;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_HEADER_LEN: usize = 40;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+
+/// Written once by [`init`] on the boot hart, before any other hart is up
+static mut BOOTARGS: [u8; MAX_BOOTARGS_LEN] = [0; MAX_BOOTARGS_LEN];
+static BOOTARGS_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Copy the command line out of the device tree at `dtb` and take in its
+/// random seed, a missing or malformed tree leaving the command line empty
+pub fn init(dtb: usize) {
+    if dtb == 0 {
+        return;
+    }
+    if let Some(seed) = unsafe { chosen_property(dtb, b"rng-seed") } {
+        add_entropy(seed);
+    }
+    let args = match unsafe { chosen_property(dtb, b"bootargs") } {
+        Some(args) => cstr(args, 0).unwrap_or(args),
+        None => return,
+    };
+    let len = args.len().min(MAX_BOOTARGS_LEN);
+    unsafe {
+        BOOTARGS[..len].copy_from_slice(&args[..len]);
+    }
+    BOOTARGS_LEN.store(len, Ordering::Release);
+}
+
+/// The whole command line, empty if there was none
+pub fn bootargs() -> &'static str {
+    let len = BOOTARGS_LEN.load(Ordering::Acquire);
+    core::str::from_utf8(unsafe { &BOOTARGS[..len] }).unwrap_or("")
+}
+
+/// Value of the `key=value` argument, or `""` for a bare `key`
+pub fn bootarg(key: &str) -> Option<&'static str> {
+    bootargs().split_whitespace().find_map(|arg| match arg.split_once('=') {
+        Some((k, value)) if k == key => Some(value),
+        None if arg == key => Some(""),
+        _ => None,
+    })
+}
+
+fn be32(fdt: &[u8], at: usize) -> Option<u32> {
+    let bytes = fdt.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The NUL-terminated string at `at`, without the NUL
+fn cstr(fdt: &[u8], at: usize) -> Option<&[u8]> {
+    let rest = fdt.get(at..)?;
+    Some(&rest[..rest.iter().position(|&b| b == 0)?])
+}
+
+fn align4(at: usize) -> usize {
+    (at + 3) & !3
+}
+
+/// The value of the property `wanted` of `/chosen`
+///
+/// # Safety
+///
+/// `dtb` has to be readable for the length its header claims
+unsafe fn chosen_property(dtb: usize, wanted: &[u8]) -> Option<&'static [u8]> {
+    let header = core::slice::from_raw_parts(dtb as *const u8, FDT_HEADER_LEN);
+    if be32(header, 0)? != FDT_MAGIC {
+        return None;
+    }
+    let fdt = core::slice::from_raw_parts(dtb as *const u8, be32(header, 4)? as usize);
+    let strings = be32(fdt, 12)? as usize;
+    let mut at = be32(fdt, 8)? as usize;
+    // the root node is at depth 1, its children at 2
+    let mut depth = 0usize;
+    let mut in_chosen = false;
+    loop {
+        let token = be32(fdt, at)?;
+        at += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = cstr(fdt, at)?;
+                at = align4(at + name.len() + 1);
+                depth += 1;
+                in_chosen = depth == 2 && (name == b"chosen" || name.starts_with(b"chosen@"));
+            }
+            FDT_END_NODE => {
+                depth = depth.checked_sub(1)?;
+                // properties come before subnodes, so this leaves /chosen
+                in_chosen = false;
+            }
+            FDT_PROP => {
+                let len = be32(fdt, at)? as usize;
+                let name = cstr(fdt, strings + be32(fdt, at + 4)? as usize)?;
+                let value = fdt.get(at + 8..at + 8 + len)?;
+                at = align4(at + 8 + len);
+                if in_chosen && name == wanted {
+                    return Some(value);
+                }
+            }
+            FDT_NOP => {}
+            // FDT_END, or not a tree after all
+            _ => return None,
+        }
+    }
+}
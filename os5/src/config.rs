@@ -1,13 +1,52 @@
 //! Constants used in rCore
 
 pub const USER_STACK_SIZE: usize = 4096 * 2;
+/// Most the user stack of a process grows to on faults below it, the
+/// `USER_STACK_SIZE` mapped from the start included
+pub const USER_STACK_LIMIT: usize = 4096 * 64;
+/// Kernel stack of a task that runs user code, for its traps and syscalls
 pub const KERNEL_STACK_SIZE: usize = 4096 * 20;
+/// Kernel stack of a kernel thread, and of the scrubber, which only run
+/// their own function
+pub const KTHREAD_STACK_SIZE: usize = 4096 * 4;
+/// Boot stack of each hart, on which its idle loop runs; `entry.asm` sets
+/// them aside, its shift and size are to be kept in step
+pub const IDLE_STACK_SIZE: usize = 4096 * 16;
 pub const KERNEL_HEAP_SIZE: usize = 0x20_0000;
 pub const MEMORY_END: usize = 0x88000000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// longest path accepted from user space, excluding the NUL terminator
+pub const MAX_PATH_LEN: usize = 256;
+/// most arguments accepted by `sys_exec` and `sys_spawn`
+pub const MAX_ARGS: usize = 16;
+/// longest argument accepted, excluding the NUL terminator
+pub const MAX_ARG_LEN: usize = 128;
+/// most bytes the arguments take on the new user stack, their NUL
+/// terminators and the `argv` array included
+pub const ARG_MAX: usize = 2048;
+/// most files a process may have open
+pub const MAX_FDS: usize = 64;
+/// most mutexes a process may create
+pub const MAX_MUTEXES: usize = 64;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
+/// Threads of a process, the main one included
+pub const MAX_THREADS: usize = 16;
 pub const CLOCK_FREQ: usize = 12500000;
+/// Most harts the kernel brings up, `entry.asm` sets aside a boot stack for
+/// each
+pub const MAX_HARTS: usize = 4;
+
+pub const PLIC_BASE: usize = 0x0c00_0000;
+pub const UART_BASE: usize = 0x1000_0000;
+/// The first virtio-mmio slot, where the disk is attached
+pub const VIRTIO0: usize = 0x1000_1000;
+/// Device memory mapped into kernel space, as (start, length)
+pub const MMIO: &[(usize, usize)] = &[
+    (PLIC_BASE, 0x21_0000),
+    (UART_BASE, 0x1000),
+    (VIRTIO0, 0x1000),
+];
@@ -1,7 +1,12 @@
 //! SBI console driver, for text output
 
 use crate::sbi::console_putchar;
+use alloc::vec::Vec;
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the last byte sent to the console was a newline
+static AT_LINE_START: AtomicBool = AtomicBool::new(true);
 
 struct Stdout;
 
@@ -10,6 +15,9 @@ impl Write for Stdout {
         for c in s.chars() {
             console_putchar(c as usize);
         }
+        if let Some(c) = s.chars().last() {
+            AT_LINE_START.store(c == '\n', Ordering::Relaxed);
+        }
         Ok(())
     }
 }
@@ -18,6 +26,89 @@ pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
 
+/// Write raw bytes to the console
+pub fn write_bytes(bytes: &[u8]) {
+    for &b in bytes {
+        console_putchar(b as usize);
+    }
+    if let Some(&b) = bytes.last() {
+        AT_LINE_START.store(b == b'\n', Ordering::Relaxed);
+    }
+}
+
+/// Break the current console line if something was left unterminated on it,
+/// so that what follows starts in column 0
+pub fn ensure_line_start() {
+    if !AT_LINE_START.load(Ordering::Relaxed) {
+        write_bytes(b"\n");
+    }
+}
+
+/// Marker opening every line of the result channel
+pub const RESULT_PREFIX: &[u8] = b"@@result ";
+
+/// Emit `text` on the result channel
+///
+/// There is only the one SBI console, so results share it with everything
+/// else. Each line is written whole, starts in column 0 and carries
+/// [`RESULT_PREFIX`], so graders can pick them out with a fixed pattern no
+/// matter what kernel logs or user output surround them.
+pub fn report(text: &[u8]) {
+    ensure_line_start();
+    for line in text.split(|&b| b == b'\n').filter(|line| !line.is_empty()) {
+        write_bytes(RESULT_PREFIX);
+        write_bytes(line);
+        write_bytes(b"\n");
+    }
+}
+
+/// Bytes a [`LineBuffer`] holds before it flushes without a newline
+const LINE_BUFFER_SIZE: usize = 1024;
+
+/// Per-process stdout buffer
+///
+/// Output is held until a newline, a full buffer or an explicit
+/// [`flush`](LineBuffer::flush), so lines of processes that get switched
+/// mid-line do not interleave. With buffering disabled, writes go straight
+/// to the console.
+pub struct LineBuffer {
+    buf: Vec<u8>,
+    buffered: bool,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            buffered: true,
+        }
+    }
+    pub fn write(&mut self, bytes: &[u8]) {
+        if !self.buffered {
+            write_bytes(bytes);
+            return;
+        }
+        for &b in bytes {
+            self.buf.push(b);
+            if b == b'\n' || self.buf.len() >= LINE_BUFFER_SIZE {
+                self.flush();
+            }
+        }
+    }
+    pub fn flush(&mut self) {
+        write_bytes(&self.buf);
+        self.buf.clear();
+    }
+    /// Turn buffering on or off, flushing whatever is pending
+    pub fn set_buffered(&mut self, buffered: bool) {
+        self.flush();
+        self.buffered = buffered;
+    }
+    pub fn is_buffered(&self) -> bool {
+        self.buffered
+    }
+}
+
 #[macro_export]
 /// print string macro
 macro_rules! print {
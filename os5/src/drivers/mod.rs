@@ -0,0 +1,214 @@
+//! Device drivers of the QEMU virt machine
+//!
+//! Console input is driven by the kernel itself: the UART raises an
+//! interrupt through the PLIC when characters arrive, see [`uart`]. Output
+//! still goes through the SBI. Each interrupt source goes to one hart, the
+//! boot hart unless [`route_irq`] or the `irqaffinity` boot argument say
+//! otherwise. The disk, if QEMU has one attached and the
+//! `fs` feature is on, is the virtio block device of [`virtio_blk`] behind
+//! a [`request_queue`], and may be split up into [`partition`]s.
+
+#[cfg(feature = "fs")]
+mod partition;
+mod plic;
+#[cfg(feature = "fs")]
+mod request_queue;
+pub mod uart;
+#[cfg(feature = "fs")]
+mod virtio_blk;
+
+use crate::bootargs::bootarg;
+use crate::config::MAX_HARTS;
+use crate::hart::{self, hart_id};
+use crate::sync::UPSafeCell;
+#[cfg(feature = "fs")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "fs")]
+use easy_fs::BlockDevice;
+use lazy_static::*;
+#[cfg(feature = "fs")]
+pub use partition::{Partition, PartitionKind};
+#[cfg(feature = "fs")]
+pub use request_queue::QueueStats;
+#[cfg(feature = "fs")]
+use request_queue::RequestQueue;
+use riscv::register::{sie, sip};
+#[cfg(feature = "fs")]
+use virtio_blk::VirtIOBlock;
+
+#[cfg(feature = "fs")]
+lazy_static! {
+    /// The request queue of the disk, `None` if none is attached
+    static ref DISK_QUEUE: Option<Arc<RequestQueue>> =
+        VirtIOBlock::probe().map(|disk| Arc::new(RequestQueue::new(Arc::new(disk))));
+    /// The disk, through its request queue
+    pub static ref BLOCK_DEVICE: Option<Arc<dyn BlockDevice>> =
+        DISK_QUEUE.clone().map(|queue| queue as Arc<dyn BlockDevice>);
+    /// The partitions of the disk, `None` if it has no partition table
+    pub static ref PARTITIONS: Option<Vec<Arc<Partition>>> =
+        BLOCK_DEVICE.as_ref().and_then(partition::read_mbr);
+}
+
+/// Write out the writes queued for the disk
+#[cfg(feature = "fs")]
+pub fn flush_disk() {
+    if let Some(queue) = DISK_QUEUE.as_ref() {
+        queue.flush();
+    }
+}
+
+/// What the request queue of the disk did, `None` without a disk
+#[cfg(feature = "fs")]
+pub fn disk_queue_stats() -> Option<QueueStats> {
+    DISK_QUEUE.as_ref().map(|queue| queue.stats())
+}
+
+/// What easy-fs is on: the first [`PartitionKind::Fs`] partition of a
+/// partitioned disk, else the whole disk
+#[cfg(feature = "fs")]
+pub fn fs_device() -> Option<Arc<dyn BlockDevice>> {
+    match PARTITIONS.as_ref() {
+        Some(partitions) => partitions
+            .iter()
+            .find(|partition| partition.kind == PartitionKind::Fs)
+            .map(|partition| partition.clone() as Arc<dyn BlockDevice>),
+        None => BLOCK_DEVICE.clone(),
+    }
+}
+
+/// Where an interrupt source goes, see [`route_irq`]
+struct Route {
+    irq: u32,
+    name: &'static str,
+    /// hart it is routed to, which takes it while online
+    hart: usize,
+    /// hart it is enabled for now, the boot hart while `hart` is offline
+    target: usize,
+    /// interrupts handled on each hart
+    counts: [u64; MAX_HARTS],
+}
+
+lazy_static! {
+    static ref ROUTES: UPSafeCell<Vec<Route>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+/// The hart the `irqaffinity` boot argument, `<irq>:<hart>` pairs split by
+/// commas, routes `irq` to
+fn boot_route(irq: u32) -> Option<usize> {
+    bootarg("irqaffinity")?.split(',').find_map(|pair| {
+        let (source, hart) = pair.split_once(':')?;
+        (source.parse() == Ok(irq))
+            .then(|| hart.parse().ok())
+            .flatten()
+    })
+}
+
+/// Enable `irq` for the hart it is routed to by boot argument, if that
+/// is a hart there can be, else for the calling one
+fn add_source(irq: u32, name: &'static str) {
+    let hart = boot_route(irq)
+        .filter(|&hart| hart < MAX_HARTS)
+        .unwrap_or_else(hart_id);
+    plic::enable(irq, hart_id());
+    ROUTES.exclusive_access().push(Route {
+        irq,
+        name,
+        hart,
+        target: hart_id(),
+        counts: [0; MAX_HARTS],
+    });
+    reroute_irqs();
+}
+
+/// Enable every source for the hart it is routed to if that is online,
+/// for the boot hart if not
+pub fn reroute_irqs() {
+    for route in ROUTES.exclusive_access().iter_mut() {
+        let target = if hart::is_online(route.hart) {
+            route.hart
+        } else {
+            hart::boot_hart()
+        };
+        if target != route.target {
+            plic::disable(route.irq, route.target);
+            plic::enable(route.irq, target);
+            route.target = target;
+        }
+    }
+}
+
+/// Route `irq` to `hart` from now on; `false` if `irq` is not a source
+/// the kernel handles or `hart` is not online
+pub fn route_irq(irq: u32, hart: usize) -> bool {
+    if !hart::is_online(hart) {
+        return false;
+    }
+    match ROUTES
+        .exclusive_access()
+        .iter_mut()
+        .find(|route| route.irq == irq)
+    {
+        Some(route) => route.hart = hart,
+        None => return false,
+    }
+    reroute_irqs();
+    true
+}
+
+/// Each source: its number, name, the hart it is enabled for and the
+/// interrupts handled on each hart, for `/proc/interrupts`
+#[cfg_attr(not(feature = "fs"), allow(dead_code))]
+pub fn irq_routes() -> Vec<(u32, &'static str, usize, [u64; MAX_HARTS])> {
+    ROUTES
+        .exclusive_access()
+        .iter()
+        .map(|route| (route.irq, route.name, route.target, route.counts))
+        .collect()
+}
+
+/// Take over console input and enable external interrupts on the boot
+/// hart
+pub fn init() {
+    uart::init();
+    add_source(uart::UART_IRQ, "uart");
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// Enable external interrupts on a secondary hart coming online, and take
+/// back the sources routed to it
+pub fn init_hart() {
+    unsafe {
+        sie::set_sext();
+    }
+    reroute_irqs();
+}
+
+/// Handle every interrupt the PLIC has pending for the calling hart
+pub fn irq_handler() {
+    shadow_guard!();
+    while let Some(irq) = plic::claim() {
+        if let Some(route) = ROUTES
+            .exclusive_access()
+            .iter_mut()
+            .find(|route| route.irq == irq)
+        {
+            route.counts[hart_id()] += 1;
+        }
+        match irq {
+            uart::UART_IRQ => uart::handle_irq(),
+            _ => warn!("[kernel] unexpected external interrupt {}", irq),
+        }
+        plic::complete(irq);
+    }
+}
+
+/// Handle pending external interrupts while the processor idles with
+/// interrupts off
+pub fn poll_irqs() {
+    if sip::read().sext() {
+        irq_handler();
+    }
+}
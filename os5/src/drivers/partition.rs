@@ -0,0 +1,116 @@
+//! MBR partitions of the disk
+//!
+//! `make PARTITIONED=1` puts the easy-fs image, a swap area and a crash
+//! dump area on one disk behind an MBR. A disk without the MBR signature
+//! is taken to be a bare easy-fs image, the way `make` builds it by
+//! default, and has no partitions. Extended partitions and GPT are not
+//! read.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use easy_fs::{BlockDevice, BLOCK_SZ};
+
+/// Offset of the four primary entries in the first block
+const MBR_ENTRIES: usize = 446;
+const MBR_ENTRY_LEN: usize = 16;
+/// Last two bytes of the first block of a partitioned disk
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// What a partition holds, by its MBR type
+pub enum PartitionKind {
+    /// 0x83, "Linux", an easy-fs image
+    Fs,
+    /// 0x82, "Linux swap"
+    Swap,
+    /// 0xda, "Non-FS data", space for crash dumps
+    Dump,
+    Other(u8),
+}
+
+impl PartitionKind {
+    fn from_type(part_type: u8) -> Self {
+        match part_type {
+            0x83 => Self::Fs,
+            0x82 => Self::Swap,
+            0xda => Self::Dump,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// A range of blocks of the disk, addressed from 0
+pub struct Partition {
+    pub kind: PartitionKind,
+    /// first block on the disk
+    pub start: usize,
+    pub blocks: usize,
+    disk: Arc<dyn BlockDevice>,
+    /// Set by the first request beyond the partition
+    failed: AtomicBool,
+}
+
+impl Partition {
+    /// Whether `block_id` lies in the partition; if not, the partition is
+    /// marked failed, as the filesystem on it cannot be trusted
+    fn check(&self, block_id: usize) -> bool {
+        if block_id < self.blocks {
+            return true;
+        }
+        error!(
+            "[kernel] block {} beyond the {} blocks of a {:?} partition",
+            block_id, self.blocks, self.kind
+        );
+        self.failed.store(true, Ordering::Relaxed);
+        false
+    }
+}
+
+/// A block beyond the partition reads as zeros and is not written, so
+/// nothing outside it is touched
+impl BlockDevice for Partition {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        if self.check(block_id) {
+            self.disk.read_block(self.start + block_id, buf);
+        } else {
+            buf.fill(0);
+        }
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        if self.check(block_id) {
+            self.disk.write_block(self.start + block_id, buf);
+        }
+    }
+    fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed) || self.disk.failed()
+    }
+}
+
+fn le32(bytes: &[u8]) -> usize {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+}
+
+/// The used primary partitions of `disk`, `None` if it has no MBR
+pub fn read_mbr(disk: &Arc<dyn BlockDevice>) -> Option<Vec<Arc<Partition>>> {
+    let mut mbr = [0u8; BLOCK_SZ];
+    disk.read_block(0, &mut mbr);
+    if mbr[BLOCK_SZ - 2..] != MBR_SIGNATURE {
+        return None;
+    }
+    let partitions = mbr[MBR_ENTRIES..MBR_ENTRIES + 4 * MBR_ENTRY_LEN]
+        .chunks(MBR_ENTRY_LEN)
+        // type 0 is an unused entry
+        .filter(|entry| entry[4] != 0 && le32(&entry[12..]) != 0)
+        .map(|entry| {
+            Arc::new(Partition {
+                kind: PartitionKind::from_type(entry[4]),
+                start: le32(&entry[8..]),
+                blocks: le32(&entry[12..]),
+                disk: disk.clone(),
+                failed: AtomicBool::new(false),
+            })
+        })
+        .collect();
+    Some(partitions)
+}
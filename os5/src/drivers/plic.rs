@@ -0,0 +1,61 @@
+//! The platform-level interrupt controller, as seen from supervisor mode
+//!
+//! A source is enabled for one hart at a time, the one it is routed to by
+//! [`route_irq`](super::route_irq); only that hart ever has it pending and
+//! claims it.
+
+use crate::config::PLIC_BASE;
+use crate::hart::hart_id;
+
+/// Context of supervisor mode on `hart`, QEMU virt numbering machine mode
+/// `2 * hart` and supervisor mode the one after
+fn context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+fn priority(irq: u32) -> *mut u32 {
+    (PLIC_BASE + irq as usize * 4) as *mut u32
+}
+
+fn enable_word(irq: u32, hart: usize) -> *mut u32 {
+    (PLIC_BASE + 0x2000 + context(hart) * 0x80 + irq as usize / 32 * 4) as *mut u32
+}
+
+fn threshold(hart: usize) -> *mut u32 {
+    (PLIC_BASE + 0x20_0000 + context(hart) * 0x1000) as *mut u32
+}
+
+fn claim_complete() -> *mut u32 {
+    (PLIC_BASE + 0x20_0004 + context(hart_id()) * 0x1000) as *mut u32
+}
+
+/// Let `irq` through to supervisor mode on `hart`
+pub fn enable(irq: u32, hart: usize) {
+    unsafe {
+        priority(irq).write_volatile(1);
+        let word = enable_word(irq, hart);
+        word.write_volatile(word.read_volatile() | 1 << (irq % 32));
+        threshold(hart).write_volatile(0);
+    }
+}
+
+/// Keep `irq` from supervisor mode on `hart`
+pub fn disable(irq: u32, hart: usize) {
+    unsafe {
+        let word = enable_word(irq, hart);
+        word.write_volatile(word.read_volatile() & !(1 << (irq % 32)));
+    }
+}
+
+/// The highest priority interrupt pending on the calling hart, which is
+/// then being handled until [`complete`]
+pub fn claim() -> Option<u32> {
+    let irq = unsafe { claim_complete().read_volatile() };
+    (irq != 0).then_some(irq)
+}
+
+pub fn complete(irq: u32) {
+    unsafe {
+        claim_complete().write_volatile(irq);
+    }
+}
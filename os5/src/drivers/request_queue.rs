@@ -0,0 +1,117 @@
+//! Request queue in front of the disk
+//!
+//! Writes are queued by block instead of going to the device at once, so
+//! writing a block again before it went out replaces the queued copy, the
+//! two requests are merged. The queue is dispatched in one elevator sweep:
+//! the blocks at or above the last one dispatched in ascending order, then
+//! the rest from the lowest, so runs of adjacent blocks reach the device
+//! back to back. The driver takes one block per request, a run is not
+//! turned into a single larger one.
+//!
+//! The queue is dispatched when it holds [`QUEUE_DEPTH`] blocks, when a
+//! hart runs out of tasks, and before the machine is shut down. Reads are
+//! synchronous: a block in the queue is read from there, any other goes to
+//! the device right away.
+
+use crate::sync::UPSafeCell;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use easy_fs::{BlockDevice, BLOCK_SZ};
+
+/// Most blocks waiting to be written
+const QUEUE_DEPTH: usize = 32;
+
+#[derive(Copy, Clone, Default, Debug)]
+/// What the queue did, as `/proc/stat` reports it
+pub struct QueueStats {
+    /// writes submitted
+    pub queued: u64,
+    /// writes that replaced a queued copy of their block
+    pub merged: u64,
+    /// writes that went to the device
+    pub dispatched: u64,
+    /// dispatched writes not to the block after the previous one
+    pub seeks: u64,
+    /// reads served from the queue
+    pub read_hits: u64,
+}
+
+struct QueueInner {
+    pending: BTreeMap<usize, Box<[u8; BLOCK_SZ]>>,
+    /// block of the last dispatched write, where the next sweep starts
+    head: usize,
+    stats: QueueStats,
+}
+
+pub struct RequestQueue {
+    disk: Arc<dyn BlockDevice>,
+    inner: UPSafeCell<QueueInner>,
+}
+
+impl RequestQueue {
+    pub fn new(disk: Arc<dyn BlockDevice>) -> Self {
+        Self {
+            disk,
+            inner: unsafe {
+                UPSafeCell::new(QueueInner {
+                    pending: BTreeMap::new(),
+                    head: 0,
+                    stats: QueueStats::default(),
+                })
+            },
+        }
+    }
+    /// Write out every queued block in one sweep
+    pub fn flush(&self) {
+        let mut inner = self.inner.exclusive_access();
+        let pending = core::mem::take(&mut inner.pending);
+        let head = inner.head;
+        let (below, above): (BTreeMap<_, _>, BTreeMap<_, _>) =
+            pending.into_iter().partition(|(block_id, _)| *block_id < head);
+        for (block_id, data) in above.into_iter().chain(below) {
+            if block_id != inner.head + 1 {
+                inner.stats.seeks += 1;
+            }
+            self.disk.write_block(block_id, &data[..]);
+            inner.head = block_id;
+            inner.stats.dispatched += 1;
+        }
+    }
+    pub fn stats(&self) -> QueueStats {
+        self.inner.exclusive_access().stats
+    }
+}
+
+impl BlockDevice for RequestQueue {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let mut inner = self.inner.exclusive_access();
+        match inner.pending.get(&block_id) {
+            Some(data) => {
+                buf.copy_from_slice(&data[..]);
+                inner.stats.read_hits += 1;
+            }
+            None => {
+                drop(inner);
+                self.disk.read_block(block_id, buf);
+            }
+        }
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut inner = self.inner.exclusive_access();
+        let mut data = Box::new([0u8; BLOCK_SZ]);
+        data.copy_from_slice(buf);
+        inner.stats.queued += 1;
+        if inner.pending.insert(block_id, data).is_some() {
+            inner.stats.merged += 1;
+        }
+        let full = inner.pending.len() >= QUEUE_DEPTH;
+        drop(inner);
+        if full {
+            self.flush();
+        }
+    }
+    fn failed(&self) -> bool {
+        self.disk.failed()
+    }
+}
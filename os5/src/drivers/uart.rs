@@ -0,0 +1,124 @@
+//! Console input from the NS16550a UART
+//!
+//! The SBI has set the UART up already; [`init`] only turns on its receive
+//! interrupt. [`handle_irq`] moves what arrived into a buffer, from which
+//! [`read`] takes, blocking the reading task while the buffer is empty.
+
+use crate::config::UART_BASE;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task_interrupted, wake_up, TaskControlBlock, WaitQueue, WakeOrder};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Formatter};
+use lazy_static::*;
+
+pub const UART_IRQ: u32 = 10;
+
+/// receive buffer, read only
+const RBR: usize = 0;
+/// interrupt enable register
+const IER: usize = 1;
+/// modem control register
+const MCR: usize = 4;
+/// line status register, read only
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1;
+/// needed for the interrupt to leave the chip
+const MCR_OUT2: u8 = 1 << 3;
+const LSR_DATA_READY: u8 = 1;
+
+/// Most characters buffered; more are dropped until some are read
+const INPUT_BUFFER_SIZE: usize = 256;
+
+struct Input {
+    buffer: VecDeque<u8>,
+    /// tasks blocked in [`read`]
+    readers: WaitQueue,
+}
+
+impl Debug for Input {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Input({} buffered)", self.buffer.len())
+    }
+}
+
+lazy_static! {
+    static ref INPUT: UPSafeCell<Input> = unsafe {
+        UPSafeCell::new(Input {
+            buffer: VecDeque::new(),
+            readers: WaitQueue::new(WakeOrder::Urgency),
+        })
+    };
+}
+
+fn reg(offset: usize) -> *mut u8 {
+    (UART_BASE + offset) as *mut u8
+}
+
+pub fn init() {
+    unsafe {
+        reg(MCR).write_volatile(reg(MCR).read_volatile() | MCR_OUT2);
+        reg(IER).write_volatile(IER_RX_AVAILABLE);
+    }
+}
+
+/// Buffer every character received and wake the readers
+pub fn handle_irq() {
+    let mut input = INPUT.exclusive_access();
+    while unsafe { reg(LSR).read_volatile() } & LSR_DATA_READY != 0 {
+        let c = unsafe { reg(RBR).read_volatile() };
+        if input.buffer.len() < INPUT_BUFFER_SIZE {
+            input.buffer.push_back(c);
+        }
+    }
+    let readers = input.readers.take();
+    drop(input);
+    wake_up(readers);
+}
+
+/// Take up to `len` buffered characters, blocking until there is at least
+/// one. `None` if a signal interrupts the wait.
+pub fn read(len: usize) -> Option<Vec<u8>> {
+    loop {
+        let mut input = INPUT.exclusive_access();
+        if !input.buffer.is_empty() {
+            let n = len.min(input.buffer.len());
+            return Some(input.buffer.drain(..n).collect());
+        }
+        drop(input);
+        // the signal is dealt with on the way back to user mode
+        if current_task_interrupted() {
+            return None;
+        }
+        block_current_and_run_next(|task| INPUT.exclusive_access().readers.push(task));
+    }
+}
+
+/// Whether [`read`] would return without blocking
+pub fn input_ready() -> bool {
+    input_len() > 0
+}
+
+/// Characters buffered for [`read`]
+pub fn input_len() -> usize {
+    INPUT.exclusive_access().buffer.len()
+}
+
+/// Wake the blocking `task` with the readers once input arrives
+pub fn poll_wait(task: &Arc<TaskControlBlock>) {
+    INPUT.exclusive_access().readers.push(task.clone());
+}
+
+/// Take `task` off the readers [`poll_wait`] added it to
+pub fn poll_cancel(task: &Arc<TaskControlBlock>) {
+    INPUT.exclusive_access().readers.remove(task);
+}
+
+/// Wake every task blocked in [`read`], for one of them to notice it is
+/// interrupted; the others go back to waiting
+pub fn interrupt_readers() {
+    let readers = INPUT.exclusive_access().readers.take();
+    wake_up(readers);
+}
@@ -0,0 +1,113 @@
+//! The virtio block device, the disk holding the filesystem
+//!
+//! QEMU attaches it, when asked for one, at the first virtio-mmio slot.
+//! Requests are polled for completion, the device interrupt stays off. The
+//! `virtio_*` functions at the bottom are what the driver calls back into
+//! for memory it shares with the device.
+
+use crate::config::VIRTIO0;
+use crate::mm::{
+    frame_alloc_contiguous, FrameTracker, PhysAddr, PhysPageNum, VirtAddr, KERNEL_SPACE,
+};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use easy_fs::BlockDevice;
+use lazy_static::*;
+use virtio_drivers::{VirtIOBlk, VirtIOHeader};
+
+/// "virt", the first register of every virtio-mmio slot
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+/// Offset of the device id register, in 32-bit registers
+const DEVICE_ID: usize = 2;
+/// Device id of a block device, 0 means the slot is empty
+const DEVICE_ID_BLOCK: u32 = 2;
+
+pub struct VirtIOBlock {
+    blk: UPSafeCell<VirtIOBlk<'static>>,
+    /// Set by the first request the device fails
+    failed: AtomicBool,
+}
+
+impl VirtIOBlock {
+    /// The disk at the first virtio-mmio slot, if one is attached
+    pub fn probe() -> Option<Self> {
+        let regs = VIRTIO0 as *const u32;
+        let (magic, device_id) =
+            unsafe { (regs.read_volatile(), regs.add(DEVICE_ID).read_volatile()) };
+        if magic != VIRTIO_MAGIC || device_id != DEVICE_ID_BLOCK {
+            return None;
+        }
+        let blk = VirtIOBlk::new(unsafe { &mut *(VIRTIO0 as *mut VirtIOHeader) })
+            .expect("cannot set up the virtio block device");
+        Some(Self {
+            blk: unsafe { UPSafeCell::new(blk) },
+            failed: AtomicBool::new(false),
+        })
+    }
+}
+
+/// A failed request marks the device failed rather than panicking, a read
+/// giving zeros; see [`BlockDevice::failed`]
+impl BlockDevice for VirtIOBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        if let Err(err) = self.blk.exclusive_access().read_block(block_id, buf) {
+            error!("[kernel] virtio-blk: cannot read block {}: {:?}", block_id, err);
+            buf.fill(0);
+            self.failed.store(true, Ordering::Relaxed);
+        }
+    }
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        if let Err(err) = self.blk.exclusive_access().write_block(block_id, buf) {
+            error!("[kernel] virtio-blk: cannot write block {}: {:?}", block_id, err);
+            self.failed.store(true, Ordering::Relaxed);
+        }
+    }
+    fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+lazy_static! {
+    /// Frames shared with the device, by their first page
+    static ref DMA_FRAMES: UPSafeCell<BTreeMap<usize, Vec<FrameTracker>>> =
+        unsafe { UPSafeCell::new_labeled("DMA_FRAMES", BTreeMap::new()) };
+}
+
+#[no_mangle]
+pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
+    let frames = frame_alloc_contiguous(pages).expect("out of frames for virtio");
+    let first = frames[0].ppn;
+    DMA_FRAMES.exclusive_access().insert(first.0, frames);
+    first.into()
+}
+
+#[no_mangle]
+pub extern "C" fn virtio_dma_dealloc(pa: PhysAddr, pages: usize) -> i32 {
+    let first: PhysPageNum = pa.into();
+    match DMA_FRAMES.exclusive_access().remove(&first.0) {
+        Some(frames) if frames.len() == pages => 0,
+        _ => panic!(
+            "virtio freed {} pages at {:#x} it was not given",
+            pages, first.0
+        ),
+    }
+}
+
+/// Physical memory is mapped identically into kernel space
+#[no_mangle]
+pub extern "C" fn virtio_phys_to_virt(paddr: PhysAddr) -> VirtAddr {
+    VirtAddr(paddr.0)
+}
+
+/// The driver also hands over buffers on the kernel stack, which is not
+/// mapped identically
+#[no_mangle]
+pub extern "C" fn virtio_virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
+    let pte = KERNEL_SPACE
+        .exclusive_access()
+        .translate(vaddr.floor())
+        .expect("virtio buffer is not mapped");
+    PhysAddr(PhysAddr::from(pte.ppn()).0 + vaddr.page_offset())
+}
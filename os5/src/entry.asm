@@ -1,12 +1,36 @@
     .section .text.entry
     .globl _start
 _start:
-    la sp, boot_stack_top
+    # a0 is the id of this hart, which the kernel keeps in tp
+    mv tp, a0
+    call set_boot_stack
     call rust_main
 
+    .globl _start_secondary
+_start_secondary:
+    mv tp, a0
+    call set_boot_stack
+    call rust_main_secondary
+
+# the boot stack of hart tp, hart 0 having the topmost one; a hart with
+# an id past the MAX_HARTS stacks has none and is parked for good, before
+# it writes a byte
+set_boot_stack:
+    li t0, 4            # MAX_HARTS
+    bgeu tp, t0, park
+    slli t0, tp, 16     # log2(IDLE_STACK_SIZE)
+    la sp, boot_stack_top
+    sub sp, sp, t0
+    ret
+
+park:
+    wfi
+    j park
+
     .section .bss.stack
     .globl boot_stack
 boot_stack:
-    .space 4096 * 16
+    # IDLE_STACK_SIZE for each of the MAX_HARTS harts
+    .space 4096 * 16 * 4
     .globl boot_stack_top
-boot_stack_top:
\ No newline at end of file
+boot_stack_top:
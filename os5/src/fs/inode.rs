@@ -0,0 +1,325 @@
+//! Files of the easy-fs filesystem on the disk
+//!
+//! easy-fs has a single directory, its root, which is mounted as `/`.
+//!
+//! The super block is checked before the disk is mounted. A disk that is
+//! not easy-fs, or whose areas do not add up, is not mounted at all, the
+//! kernel then runs the apps linked into it. One with only a bad checksum
+//! is mounted read-only: files can be read and run, not created, emptied
+//! or written.
+//!
+//! A file read sequentially, each read starting where the last one ended,
+//! has the next [`READ_AHEAD_LEN`] bytes after the offset loaded into the
+//! block cache of easy-fs ahead of time. The reader only queues that, a
+//! hart that runs out of tasks does it, see [`read_ahead`].
+
+use super::mount::FileSystem;
+use super::{File, IoClass, Stat, StatMode};
+use crate::drivers::fs_device;
+use crate::mm::{evict_program, FileBacking, UserBuffer};
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use easy_fs::{BlockDevice, EasyFileSystem, Inode, BLOCK_SZ};
+use lazy_static::*;
+
+lazy_static! {
+    /// The root directory of the disk, `None` without one or with one that
+    /// cannot be mounted
+    pub static ref ROOT_INODE: Option<Arc<Inode>> = fs_device().and_then(mount_disk);
+    /// Ranges of files to load into the block cache, oldest first
+    static ref READ_AHEAD: UPSafeCell<VecDeque<(Arc<Inode>, usize, usize)>> =
+        unsafe { UPSafeCell::new(VecDeque::new()) };
+}
+
+/// Bytes loaded ahead of a sequential reader, a few blocks of the 16 the
+/// block cache of easy-fs has
+pub const READ_AHEAD_LEN: usize = 4 * BLOCK_SZ;
+/// Most ranges waiting in [`READ_AHEAD`], later ones are dropped
+const READ_AHEAD_QUEUE: usize = 8;
+/// Blocks [`read_ahead`] loaded, for `/proc/stat`
+static READ_AHEAD_BLOCKS: AtomicU64 = AtomicU64::new(0);
+
+/// Set when the disk is mounted read-only
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+fn mount_disk(disk: Arc<dyn BlockDevice>) -> Option<Arc<Inode>> {
+    match EasyFileSystem::check(&disk) {
+        Ok(()) => {}
+        Err(err) if err.readable() => {
+            warn!("[kernel] easy-fs: {}, mounting the disk read-only", err);
+            READ_ONLY.store(true, Ordering::Relaxed);
+        }
+        Err(err) => {
+            error!("[kernel] easy-fs: {}, not mounting the disk", err);
+            return None;
+        }
+    }
+    if disk.failed() {
+        error!("[kernel] easy-fs: the disk failed, not mounting it");
+        return None;
+    }
+    let efs = EasyFileSystem::open(disk);
+    Some(Arc::new(EasyFileSystem::root_inode(&efs)))
+}
+
+/// Whether the disk is mounted read-only, see [`ROOT_INODE`]
+pub fn disk_read_only() -> bool {
+    READ_ONLY.load(Ordering::Relaxed)
+}
+
+bitflags! {
+    /// Flags of `sys_openat`
+    pub struct OpenFlags: u32 {
+        const RDONLY = 0;
+        const WRONLY = 1 << 0;
+        const RDWR = 1 << 1;
+        /// create the file if it does not exist
+        const CREATE = 1 << 9;
+        /// empty the file
+        const TRUNC = 1 << 10;
+        /// every write goes to the end of the file, wherever the offset is
+        const APPEND = 1 << 11;
+    }
+}
+
+impl OpenFlags {
+    /// Whether the flags ask for at most one access mode
+    pub fn is_valid(&self) -> bool {
+        !self.contains(Self::WRONLY | Self::RDWR)
+    }
+    /// Whether the file is opened for reading and for writing
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.contains(Self::RDWR) {
+            (true, true)
+        } else if self.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, false)
+        }
+    }
+}
+
+/// An open file of the disk
+pub struct OSInode {
+    /// the name in the root directory, which programs are cached by
+    name: String,
+    readable: bool,
+    writable: bool,
+    /// opened with [`OpenFlags::APPEND`]
+    append: bool,
+    inode: Arc<Inode>,
+    /// where the next read or write starts, shared by the descriptors
+    /// `dup` and `fork` make of the file
+    offset: UPSafeCell<usize>,
+    read_ahead: UPSafeCell<ReadAhead>,
+}
+
+/// Where the reads of a file went so far
+#[derive(Default)]
+struct ReadAhead {
+    /// offset the last read ended at
+    prev_end: usize,
+    /// end of the last range queued for [`read_ahead`]
+    until: usize,
+}
+
+impl OSInode {
+    pub fn new(name: &str, flags: OpenFlags, inode: Arc<Inode>) -> Self {
+        let (readable, writable) = flags.read_write();
+        Self {
+            name: String::from(name),
+            readable,
+            writable,
+            append: flags.contains(OpenFlags::APPEND),
+            inode,
+            offset: unsafe { UPSafeCell::new(0) },
+            read_ahead: unsafe { UPSafeCell::new(ReadAhead::default()) },
+        }
+    }
+    /// Queue the next [`READ_AHEAD_LEN`] bytes after `end` once a read from
+    /// `start` to `end` continues the previous one and is halfway through
+    /// what was queued before
+    fn note_read(&self, start: usize, end: usize) {
+        let mut ahead = self.read_ahead.exclusive_access();
+        let sequential = start == ahead.prev_end;
+        ahead.prev_end = end;
+        if !sequential || end + READ_AHEAD_LEN / 2 < ahead.until {
+            return;
+        }
+        let from = ahead.until.max(end);
+        ahead.until = end + READ_AHEAD_LEN;
+        let mut queue = READ_AHEAD.exclusive_access();
+        if queue.len() < READ_AHEAD_QUEUE {
+            queue.push_back((self.inode.clone(), from, ahead.until - from));
+        }
+    }
+    /// Read the file from the current offset to its end
+    pub fn read_all(&self) -> Vec<u8> {
+        let mut offset = self.offset.exclusive_access();
+        let mut buffer = [0u8; 512];
+        let mut data = Vec::new();
+        loop {
+            let len = self.inode.read_at(*offset, &mut buffer);
+            if len == 0 {
+                break;
+            }
+            *offset += len;
+            data.extend_from_slice(&buffer[..len]);
+        }
+        data
+    }
+}
+
+/// Load the ranges sequential readers are about to reach into the block
+/// cache, while the hart has nothing else to do
+pub fn read_ahead() {
+    loop {
+        let next = READ_AHEAD.exclusive_access().pop_front();
+        let (inode, offset, len) = match next {
+            Some(range) => range,
+            None => return,
+        };
+        let blocks = inode.prefetch(offset, len);
+        READ_AHEAD_BLOCKS.fetch_add(blocks as u64, Ordering::Relaxed);
+    }
+}
+
+/// Blocks [`read_ahead`] loaded since boot
+pub fn read_ahead_blocks() -> u64 {
+    READ_AHEAD_BLOCKS.load(Ordering::Relaxed)
+}
+
+/// Open the file at the absolute, normalized `path` on the disk, creating
+/// it if `flags` say so
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+    open_in_root(path.strip_prefix('/')?, flags)
+}
+
+fn open_in_root(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+    let root = ROOT_INODE.as_ref()?;
+    if name.is_empty() || name.contains('/') {
+        return None;
+    }
+    let changes = flags.read_write().1 || flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC);
+    if changes && disk_read_only() {
+        return None;
+    }
+    let inode = match root.find(name) {
+        Some(inode) => {
+            if flags.contains(OpenFlags::TRUNC) {
+                inode.clear();
+                evict_program(name);
+            }
+            inode
+        }
+        None if flags.contains(OpenFlags::CREATE) => root.create(name)?,
+        None => return None,
+    };
+    Some(Arc::new(OSInode::new(name, flags, inode)))
+}
+
+/// A file of the disk as mapped into an address space
+struct MappedFile {
+    name: String,
+    inode: Arc<Inode>,
+}
+
+impl FileBacking for MappedFile {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.inode.read_at(offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        evict_program(&self.name);
+        self.inode.write_at(offset, buf)
+    }
+    fn size(&self) -> usize {
+        self.inode.size()
+    }
+}
+
+/// The disk, as the mount table has it
+pub struct DiskFs;
+
+impl FileSystem for DiskFs {
+    fn name(&self) -> &'static str {
+        "easy-fs"
+    }
+    fn read_only(&self) -> bool {
+        disk_read_only()
+    }
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File>> {
+        Some(open_in_root(path, flags)?)
+    }
+}
+
+impl File for OSInode {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> Option<usize> {
+        if !self.readable {
+            return None;
+        }
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        let mut total = 0;
+        for slice in buf.buffers.iter_mut() {
+            let len = self.inode.read_at(*offset, slice);
+            *offset += len;
+            total += len;
+            if len < slice.len() {
+                break;
+            }
+        }
+        if total > 0 {
+            self.note_read(start, *offset);
+        }
+        Some(total)
+    }
+    fn write(&self, buf: UserBuffer) -> Option<usize> {
+        if !self.writable {
+            return None;
+        }
+        // a later exec must load what is written, not the cached pages
+        evict_program(&self.name);
+        let mut offset = self.offset.exclusive_access();
+        if self.append {
+            *offset = self.inode.size();
+        }
+        let mut total = 0;
+        for slice in buf.buffers.iter() {
+            let len = self.inode.write_at(*offset, slice);
+            *offset += len;
+            total += len;
+            if len < slice.len() {
+                break;
+            }
+        }
+        Some(total)
+    }
+    fn stat(&self) -> Option<Stat> {
+        let mode = if self.inode.is_dir() {
+            StatMode::DIR
+        } else {
+            StatMode::FILE
+        };
+        // easy-fs has no hard links yet
+        Some(Stat::new(self.inode.inode_id() as u64, mode, 1))
+    }
+    fn io_class(&self) -> IoClass {
+        IoClass::File
+    }
+    fn mmap_backing(&self) -> Option<Arc<dyn FileBacking>> {
+        Some(Arc::new(MappedFile {
+            name: self.name.clone(),
+            inode: self.inode.clone(),
+        }))
+    }
+}
@@ -0,0 +1,194 @@
+//! Filesystem namespace
+//!
+//! The root directory is the one on the disk, see [`inode`], so
+//! `/ch5b_user_shell` and `ch5b_user_shell` (from `/`) name the same file.
+//! `exec` and `spawn` load programs from there. Without a disk, the
+//! applications linked into the kernel are its entries as far as they go,
+//! and `open` finds nothing there. Built without the `fs` feature, the
+//! kernel has no disk and no `open` at all.
+//!
+//! `open` goes through the [`mount`] table, which has [`procfs`] at `/proc`
+//! and [`devfs`] at `/dev` next to the disk. Their mount points are
+//! directories, though not ones anyone can list.
+//!
+//! What a process reads and writes through file descriptors is a [`File`]:
+//! the console, see [`stdio`], one end of a [`pipe`], a file on the disk,
+//! or a process to wait for, see [`pidfd`].
+
+#[cfg(feature = "fs")]
+mod devfs;
+#[cfg(feature = "fs")]
+mod inode;
+#[cfg(feature = "fs")]
+mod mount;
+mod path;
+mod pidfd;
+mod pipe;
+#[cfg(feature = "fs")]
+mod procfs;
+mod stdio;
+
+#[cfg(feature = "fs")]
+pub use inode::{open_file, read_ahead, OpenFlags, ROOT_INODE};
+pub use path::resolve_path;
+pub use pidfd::PidFd;
+pub use pipe::{make_pipe, Pipe, PIPE_MAX_SIZE, PIPE_MIN_SIZE};
+#[cfg(feature = "fs")]
+pub use mount::open;
+pub use stdio::{Stdin, Stdout};
+
+use crate::loader::{self, get_app_data_by_name};
+use crate::mm::{FileBacking, UserBuffer};
+use crate::task::TaskControlBlock;
+use alloc::borrow::Cow;
+use alloc::sync::Arc;
+
+/// Number of [`IoClass`]es
+pub const IO_CLASSES: usize = 4;
+
+#[repr(usize)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// What kind of file the bytes of a read or write went through, which the
+/// I/O accounting of tasks counts apart
+pub enum IoClass {
+    Console = 0,
+    Pipe = 1,
+    /// a file on the disk
+    #[cfg(feature = "fs")]
+    File = 2,
+    /// any other file
+    Device = 3,
+}
+
+/// An open file, shared by the descriptors that refer to it
+pub trait File: Send + Sync {
+    fn readable(&self) -> bool;
+    fn writable(&self) -> bool;
+    /// Read into `buf`, returning the bytes read, 0 at the end. `None` if
+    /// the file is not readable or a signal interrupts the task while
+    /// blocked.
+    fn read(&self, buf: UserBuffer) -> Option<usize>;
+    /// Write `buf`, returning the bytes written. `None` if the file is not
+    /// writable, nobody is left to read it, or a signal interrupts the task
+    /// while blocked before writing anything.
+    fn write(&self, buf: UserBuffer) -> Option<usize>;
+    /// Wake the tasks blocked on the file, for an interrupted one to notice
+    fn interrupt(&self) {}
+    /// Whether a read would return without blocking, for `sys_ppoll`
+    fn read_ready(&self) -> bool {
+        true
+    }
+    /// Whether a write would return without blocking, for `sys_ppoll`
+    fn write_ready(&self) -> bool {
+        true
+    }
+    /// Put the blocking `task` on the wait queues woken when the file may
+    /// have become ready, for `sys_ppoll`; nothing for files always ready
+    fn poll_wait(&self, _task: &Arc<TaskControlBlock>) {}
+    /// Take `task` off the queues [`File::poll_wait`] put it on
+    fn poll_cancel(&self, _task: &Arc<TaskControlBlock>) {}
+    /// What `sys_fstat` reports, `None` for files that are not on the disk
+    fn stat(&self) -> Option<Stat> {
+        None
+    }
+    /// What the I/O through the file is counted as
+    fn io_class(&self) -> IoClass {
+        IoClass::Device
+    }
+    /// Bytes a read could take right away, as `FIONREAD` reports them;
+    /// `None` for files that cannot tell
+    fn buffered(&self) -> Option<usize> {
+        None
+    }
+    /// The file as a pipe end, for the pipe controls of `sys_fcntl`
+    fn as_pipe(&self) -> Option<&Pipe> {
+        None
+    }
+    /// What mapping the file reads and writes, `None` for files that
+    /// cannot be mapped
+    fn mmap_backing(&self) -> Option<Arc<dyn FileBacking>> {
+        None
+    }
+}
+
+/// Status of a file, as `sys_fstat` reports it
+#[repr(C)]
+#[derive(Debug)]
+pub struct Stat {
+    /// device the file is on
+    pub dev: u64,
+    /// inode number
+    pub ino: u64,
+    pub mode: StatMode,
+    /// number of hard links
+    pub nlink: u32,
+    pad: [u64; 7],
+}
+
+impl Stat {
+    #[cfg(feature = "fs")]
+    pub fn new(ino: u64, mode: StatMode, nlink: u32) -> Self {
+        Self {
+            dev: 0,
+            ino,
+            mode,
+            nlink,
+            pad: [0; 7],
+        }
+    }
+}
+
+bitflags! {
+    /// Type of a file
+    pub struct StatMode: u32 {
+        const NULL = 0;
+        const DIR = 0o040000;
+        const FILE = 0o100000;
+    }
+}
+
+/// Whether the absolute, normalized `path` names a directory
+pub fn is_dir(path: &str) -> bool {
+    #[cfg(feature = "fs")]
+    if mount::is_mount_point(path) {
+        return true;
+    }
+    path == "/"
+}
+
+/// Look up the ELF image of the application at the absolute, normalized
+/// `path`: the file on the disk, or the app linked into the kernel when
+/// there is no disk
+pub fn lookup_app(path: &str) -> Option<Cow<'static, [u8]>> {
+    #[cfg(feature = "fs")]
+    if ROOT_INODE.is_some() {
+        return open_file(path, OpenFlags::RDONLY).map(|file| Cow::Owned(file.read_all()));
+    }
+    path.strip_prefix('/')
+        .and_then(get_app_data_by_name)
+        .map(Cow::Borrowed)
+}
+
+/// Print the applications during kernel initialization
+#[cfg(not(feature = "fs"))]
+pub fn list_apps() {
+    loader::list_apps();
+}
+
+/// Print the applications during kernel initialization
+#[cfg(feature = "fs")]
+pub fn list_apps() {
+    match ROOT_INODE.as_ref() {
+        Some(root) => {
+            println!("/**** APPS ****");
+            for app in root.ls() {
+                println!("{}", app);
+            }
+            println!("**************/");
+        }
+        None => {
+            println!("[kernel] no disk, running the apps linked into the kernel");
+            loader::list_apps();
+        }
+    }
+}
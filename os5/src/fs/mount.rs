@@ -0,0 +1,99 @@
+//! The mount table
+//!
+//! Each filesystem of the namespace is mounted at a directory: the disk at
+//! `/` when there is one, [`procfs`](super::procfs) at `/proc` and
+//! [`devfs`](super::devfs) at `/dev`. [`open`] hands a path to the
+//! filesystem with the longest mount point above it, with the mount point
+//! cut off.
+
+use super::devfs::DevFs;
+use super::inode::{DiskFs, ROOT_INODE};
+use super::procfs::ProcFs;
+use super::{File, OpenFlags};
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// A filesystem that can be mounted
+pub trait FileSystem: Send + Sync {
+    /// What `/proc/mounts` calls it
+    fn name(&self) -> &'static str;
+    /// Whether no file can be opened for writing
+    fn read_only(&self) -> bool {
+        false
+    }
+    /// Open the file at `path`, relative to the root of the filesystem and
+    /// without a leading `/`
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File>>;
+}
+
+struct Mount {
+    /// absolute and normalized
+    point: &'static str,
+    fs: Arc<dyn FileSystem>,
+}
+
+lazy_static! {
+    static ref MOUNTS: UPSafeCell<Vec<Mount>> = unsafe {
+        let mut mounts = Vec::new();
+        if ROOT_INODE.is_some() {
+            mounts.push(Mount {
+                point: "/",
+                fs: Arc::new(DiskFs),
+            });
+        }
+        mounts.push(Mount {
+            point: "/proc",
+            fs: Arc::new(ProcFs),
+        });
+        mounts.push(Mount {
+            point: "/dev",
+            fs: Arc::new(DevFs),
+        });
+        UPSafeCell::new(mounts)
+    };
+}
+
+/// `path` relative to `point` if it is at or below it
+fn below<'a>(point: &str, path: &'a str) -> Option<&'a str> {
+    if point == "/" {
+        return path.strip_prefix('/');
+    }
+    match path.strip_prefix(point)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('/'),
+    }
+}
+
+/// Open the file at the absolute, normalized `path` in the filesystem it
+/// is on, creating it if `flags` say so and the filesystem can
+pub fn open(path: &str, flags: OpenFlags) -> Option<Arc<dyn File>> {
+    let (fs, rest) = {
+        let mounts = MOUNTS.exclusive_access();
+        let (mount, rest) = mounts
+            .iter()
+            .filter_map(|mount| Some((mount, below(mount.point, path)?)))
+            .max_by_key(|(mount, _)| mount.point.len())?;
+        (mount.fs.clone(), rest)
+    };
+    fs.open(rest, flags)
+}
+
+/// Whether a filesystem is mounted at the absolute, normalized `path`
+pub fn is_mount_point(path: &str) -> bool {
+    MOUNTS
+        .exclusive_access()
+        .iter()
+        .any(|mount| mount.point == path)
+}
+
+/// The mount points, the names of their filesystems and whether they are
+/// read-only, in mount order
+pub fn mounts() -> Vec<(&'static str, &'static str, bool)> {
+    MOUNTS
+        .exclusive_access()
+        .iter()
+        .map(|mount| (mount.point, mount.fs.name(), mount.fs.read_only()))
+        .collect()
+}
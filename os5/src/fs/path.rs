@@ -0,0 +1,26 @@
+//! Path resolution against the current working directory
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Turn `path` into a normalized absolute path, resolving it against `cwd`
+/// when it is relative.
+///
+/// Empty and `.` components are dropped and `..` removes the previous
+/// component (`..` of `/` is `/`), so the result never contains them.
+pub fn resolve_path(cwd: &str, path: &str) -> String {
+    let base = if path.starts_with('/') { "" } else { cwd };
+    let mut components: Vec<&str> = Vec::new();
+    for component in base.split('/').chain(path.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name),
+        }
+    }
+    let mut resolved = String::from("/");
+    resolved.push_str(&components.join("/"));
+    resolved
+}
@@ -0,0 +1,65 @@
+//! Process descriptors, see `sys_pidfd_open`
+//!
+//! A [`PidFd`] is read-ready once its process has exited, so `sys_ppoll`
+//! can wait for children together with pipes and the console, on the
+//! `child_exit` queue of the parent that the exit wakes. It holds the
+//! process weakly and never keeps a reaped one around.
+
+use super::File;
+use crate::mm::UserBuffer;
+use crate::task::{TaskControlBlock, TaskStatus};
+use alloc::sync::{Arc, Weak};
+
+pub struct PidFd {
+    task: Weak<TaskControlBlock>,
+}
+
+impl PidFd {
+    /// A descriptor of the process whose main thread is `task`
+    pub fn new(task: &Arc<TaskControlBlock>) -> Self {
+        Self {
+            task: Arc::downgrade(task),
+        }
+    }
+    /// The parent of the process, whose `child_exit` queue its exit wakes
+    fn parent(&self) -> Option<Arc<TaskControlBlock>> {
+        let task = self.task.upgrade()?;
+        let inner = task.inner_exclusive_access();
+        inner.parent.as_ref()?.upgrade()
+    }
+    /// Whether the process is a zombie or already reaped
+    fn exited(&self) -> bool {
+        self.task.upgrade().map_or(true, |task| {
+            task.inner_exclusive_access().task_status == TaskStatus::Zombie
+        })
+    }
+}
+
+impl File for PidFd {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// There is nothing to read, only to poll for
+    fn read(&self, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    fn write(&self, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    fn read_ready(&self) -> bool {
+        self.exited()
+    }
+    fn poll_wait(&self, task: &Arc<TaskControlBlock>) {
+        if let Some(parent) = self.parent() {
+            parent.inner_exclusive_access().child_exit.push(task.clone());
+        }
+    }
+    fn poll_cancel(&self, task: &Arc<TaskControlBlock>) {
+        if let Some(parent) = self.parent() {
+            parent.inner_exclusive_access().child_exit.remove(task);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Anonymous pipes
+//!
+//! The two ends share a ring buffer that counts the ends still open. A
+//! read blocks while the buffer is empty and some write end is open, and
+//! returns what is there, 0 once every write end is closed. A write blocks
+//! while the buffer is full and fails once every read end is closed.
+//! Blocked readers and writers are woken the most urgent first, see
+//! [`WakeOrder::Urgency`].
+//!
+//! The buffer holds [`PIPE_DEFAULT_SIZE`] bytes to begin with, which
+//! `F_SETPIPE_SZ` changes within [`PIPE_MIN_SIZE`] and [`PIPE_MAX_SIZE`].
+//!
+//! [`Pipe::splice_out`] and [`Pipe::splice_in`] move bytes between the
+//! buffer and another file with no copy in between, for `sys_splice`.
+
+use super::{File, IoClass};
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::{block_current_and_run_next, current_task, current_task_interrupted, wake_up, TaskControlBlock, WaitQueue, WakeOrder};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Formatter};
+
+pub const PIPE_DEFAULT_SIZE: usize = 512;
+/// Smallest buffer, smaller sizes asked for are rounded up to it
+pub const PIPE_MIN_SIZE: usize = 512;
+/// Largest buffer, out of the kernel heap
+pub const PIPE_MAX_SIZE: usize = 64 * 1024;
+
+struct PipeRingBuffer {
+    arr: Vec<u8>,
+    head: usize,
+    len: usize,
+    /// read ends open
+    readers: usize,
+    /// write ends open
+    writers: usize,
+    /// tasks waiting for data
+    read_wait: WaitQueue,
+    /// tasks waiting for room
+    write_wait: WaitQueue,
+}
+
+impl PipeRingBuffer {
+    fn capacity(&self) -> usize {
+        self.arr.len()
+    }
+    fn push(&mut self, byte: u8) {
+        let at = (self.head + self.len) % self.capacity();
+        self.arr[at] = byte;
+        self.len += 1;
+    }
+    fn pop(&mut self) -> u8 {
+        let byte = self.arr[self.head];
+        self.head = (self.head + 1) % self.capacity();
+        self.len -= 1;
+        byte
+    }
+    /// The `n` bytes of the ring from `start` on as a buffer of one or two
+    /// slices, which another file reads into or writes from
+    ///
+    /// The lifetime is a lie: the result must be dropped before `self` is
+    /// borrowed again.
+    fn span(&mut self, start: usize, n: usize) -> UserBuffer {
+        let first = n.min(self.capacity() - start);
+        let (low, high) = self.arr.split_at_mut(start);
+        let mut slices = vec![&mut high[..first]];
+        if n > first {
+            slices.push(&mut low[..n - first]);
+        }
+        UserBuffer::new(
+            slices
+                .into_iter()
+                .map(|slice| unsafe { core::slice::from_raw_parts_mut(slice.as_mut_ptr(), slice.len()) })
+                .collect(),
+        )
+    }
+    /// Move the buffered bytes into a buffer of `capacity`, which holds them
+    fn resize(&mut self, capacity: usize) {
+        let mut arr = vec![0; capacity];
+        for (i, byte) in arr.iter_mut().take(self.len).enumerate() {
+            *byte = self.arr[(self.head + i) % self.capacity()];
+        }
+        self.arr = arr;
+        self.head = 0;
+    }
+}
+
+impl Debug for PipeRingBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Pipe({} buffered)", self.len)
+    }
+}
+
+/// One end of a pipe
+pub struct Pipe {
+    writable: bool,
+    buffer: Arc<UPSafeCell<PipeRingBuffer>>,
+}
+
+/// Create a pipe, returning its read end and its write end
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let buffer = Arc::new(unsafe {
+        UPSafeCell::new(PipeRingBuffer {
+            arr: vec![0; PIPE_DEFAULT_SIZE],
+            head: 0,
+            len: 0,
+            readers: 1,
+            writers: 1,
+            read_wait: WaitQueue::new(WakeOrder::Urgency),
+            write_wait: WaitQueue::new(WakeOrder::Urgency),
+        })
+    });
+    let read_end = Arc::new(Pipe {
+        writable: false,
+        buffer: buffer.clone(),
+    });
+    let write_end = Arc::new(Pipe {
+        writable: true,
+        buffer,
+    });
+    (read_end, write_end)
+}
+
+impl Pipe {
+    /// Block the current task on `queue` of the buffer
+    fn wait(&self, queue: fn(&mut PipeRingBuffer) -> &mut WaitQueue) {
+        block_current_and_run_next(|task| queue(&mut self.buffer.exclusive_access()).push(task));
+    }
+    /// Write up to `len` buffered bytes straight to `file`, blocking while
+    /// the buffer is empty as a read does; the bytes moved, 0 once every
+    /// write end is closed. `None` if a signal interrupts the wait or
+    /// `file` fails.
+    ///
+    /// `file` must not be a pipe, and its write must not block.
+    pub fn splice_out(&self, file: &dyn File, len: usize) -> Option<usize> {
+        loop {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.len == 0 {
+                if ring.writers == 0 || len == 0 {
+                    return Some(0);
+                }
+                if current_task_interrupted() {
+                    return None;
+                }
+                drop(ring);
+                self.wait(|ring| &mut ring.read_wait);
+                continue;
+            }
+            let n = len.min(ring.len);
+            let head = ring.head;
+            let written = file.write(ring.span(head, n))?;
+            ring.head = (head + written) % ring.capacity();
+            ring.len -= written;
+            let writers = ring.write_wait.take();
+            drop(ring);
+            wake_up(writers);
+            return Some(written);
+        }
+    }
+    /// Read up to `len` bytes from `file` straight into the buffer, blocking
+    /// while it is full as a write does, and while `file` has nothing to
+    /// read; the bytes moved. `None` if every read end is closed, a signal
+    /// interrupts the wait or `file` fails.
+    ///
+    /// `file` must not be a pipe.
+    pub fn splice_in(&self, file: &dyn File, len: usize) -> Option<usize> {
+        loop {
+            if current_task_interrupted() {
+                return None;
+            }
+            let mut ring = self.buffer.exclusive_access();
+            if ring.readers == 0 {
+                return None;
+            }
+            if len == 0 {
+                return Some(0);
+            }
+            if ring.len == ring.capacity() {
+                drop(ring);
+                self.wait(|ring| &mut ring.write_wait);
+                continue;
+            }
+            // its read could block, with the buffer borrowed
+            if !file.read_ready() {
+                drop(ring);
+                let task = current_task().unwrap();
+                block_current_and_run_next(|task| file.poll_wait(&task));
+                file.poll_cancel(&task);
+                continue;
+            }
+            let n = len.min(ring.capacity() - ring.len);
+            let tail = (ring.head + ring.len) % ring.capacity();
+            let read = file.read(ring.span(tail, n))?;
+            ring.len += read;
+            let readers = ring.read_wait.take();
+            drop(ring);
+            wake_up(readers);
+            return Some(read);
+        }
+    }
+    /// Bytes the buffer holds at most
+    pub fn capacity(&self) -> usize {
+        self.buffer.exclusive_access().capacity()
+    }
+    /// Make the buffer hold `size` bytes, at least [`PIPE_MIN_SIZE`], and
+    /// return the new size; `None` if `size` is above [`PIPE_MAX_SIZE`] or
+    /// below what is buffered now
+    pub fn set_capacity(&self, size: usize) -> Option<usize> {
+        let size = size.max(PIPE_MIN_SIZE);
+        let mut ring = self.buffer.exclusive_access();
+        if size > PIPE_MAX_SIZE || size < ring.len {
+            return None;
+        }
+        ring.resize(size);
+        // there may be room now
+        let writers = ring.write_wait.take();
+        drop(ring);
+        wake_up(writers);
+        Some(size)
+    }
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        !self.writable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, buf: UserBuffer) -> Option<usize> {
+        if !self.readable() {
+            return None;
+        }
+        if buf.is_empty() {
+            return Some(0);
+        }
+        loop {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.len == 0 {
+                if ring.writers == 0 {
+                    return Some(0);
+                }
+                // the signal is dealt with on the way back to user mode
+                if current_task_interrupted() {
+                    return None;
+                }
+                drop(ring);
+                self.wait(|ring| &mut ring.read_wait);
+                continue;
+            }
+            let mut read = 0;
+            for byte in buf.bytes().take(ring.len) {
+                *byte = ring.pop();
+                read += 1;
+            }
+            let writers = ring.write_wait.take();
+            drop(ring);
+            wake_up(writers);
+            return Some(read);
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> Option<usize> {
+        if !self.writable() {
+            return None;
+        }
+        let len = buf.len();
+        let mut bytes = buf.bytes().peekable();
+        let mut written = 0;
+        while bytes.peek().is_some() {
+            let mut ring = self.buffer.exclusive_access();
+            if ring.readers == 0 || current_task_interrupted() {
+                return (written > 0).then_some(written);
+            }
+            if ring.len == ring.capacity() {
+                drop(ring);
+                self.wait(|ring| &mut ring.write_wait);
+                continue;
+            }
+            while ring.len < ring.capacity() {
+                match bytes.next() {
+                    Some(byte) => ring.push(*byte),
+                    None => break,
+                }
+                written += 1;
+            }
+            let readers = ring.read_wait.take();
+            drop(ring);
+            wake_up(readers);
+        }
+        debug_assert_eq!(written, len);
+        Some(written)
+    }
+    fn read_ready(&self) -> bool {
+        let ring = self.buffer.exclusive_access();
+        ring.len > 0 || ring.writers == 0
+    }
+    fn write_ready(&self) -> bool {
+        let ring = self.buffer.exclusive_access();
+        ring.len < ring.capacity() || ring.readers == 0
+    }
+    fn poll_wait(&self, task: &Arc<TaskControlBlock>) {
+        let mut ring = self.buffer.exclusive_access();
+        if self.writable {
+            ring.write_wait.push(task.clone());
+        } else {
+            ring.read_wait.push(task.clone());
+        }
+    }
+    fn poll_cancel(&self, task: &Arc<TaskControlBlock>) {
+        let mut ring = self.buffer.exclusive_access();
+        ring.read_wait.remove(task);
+        ring.write_wait.remove(task);
+    }
+    fn interrupt(&self) {
+        let mut ring = self.buffer.exclusive_access();
+        let mut waiters = ring.read_wait.take();
+        waiters.extend(ring.write_wait.take());
+        drop(ring);
+        wake_up(waiters);
+    }
+    fn io_class(&self) -> IoClass {
+        IoClass::Pipe
+    }
+    fn buffered(&self) -> Option<usize> {
+        Some(self.buffer.exclusive_access().len)
+    }
+    fn as_pipe(&self) -> Option<&Pipe> {
+        Some(self)
+    }
+}
+
+impl Drop for Pipe {
+    /// Close this end, waking whoever waits on the other one
+    fn drop(&mut self) {
+        let mut ring = self.buffer.exclusive_access();
+        let waiters = if self.writable {
+            ring.writers -= 1;
+            ring.read_wait.take()
+        } else {
+            ring.readers -= 1;
+            ring.write_wait.take()
+        };
+        drop(ring);
+        wake_up(waiters);
+    }
+}
@@ -0,0 +1,219 @@
+//! Read-only files about processes and the kernel under `/proc`
+//!
+//! - `/proc/<pid>/status`: name, state, ids and counters of a process
+//! - `/proc/<pid>/maps`: its areas, one per line, as `sys_get_maps` has them
+//! - `/proc/stat`: memory, timer and disk queue statistics of the kernel
+//! - `/proc/mounts`: the mount table, a mount point, filesystem and `ro` or
+//!   `rw` a line
+//! - `/proc/partitions`: first block, length and kind of each partition
+//! - `/proc/interrupts`: each interrupt source, its name, the hart it goes
+//!   to and the interrupts handled on each hart
+//!
+//! `self` stands for the pid of the calling process. The text of a file is
+//! taken when it is opened, reading it again needs another `open`.
+
+use super::inode::read_ahead_blocks;
+use super::mount::{mounts, FileSystem};
+use super::{File, OpenFlags};
+use crate::drivers::{disk_queue_stats, irq_routes, PARTITIONS};
+use crate::mm::{frames_free, lazy_pages, AreaInfo, MapPermission, UserBuffer};
+use crate::sync::UPSafeCell;
+use crate::task::{current_task, pid2task, process_leader, TaskControlBlock};
+use crate::timer::{get_time_us, timer_stats};
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::fmt::Write;
+
+pub struct ProcFs;
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+    fn read_only(&self) -> bool {
+        true
+    }
+    /// The files can only be read
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<dyn File>> {
+        if flags.read_write() != (true, false) || flags.contains(OpenFlags::CREATE) {
+            return None;
+        }
+        let text = match path {
+            "stat" => kernel_stat(),
+            "mounts" => mount_table(),
+            "partitions" => partition_table(),
+            "interrupts" => interrupts(),
+            _ => {
+                let (pid, file) = path.split_once('/')?;
+                let task = process_by_pid(pid)?;
+                match file {
+                    "status" => status(&task),
+                    "maps" => maps(&task),
+                    _ => return None,
+                }
+            }
+        };
+        Some(Arc::new(ProcFile {
+            text,
+            offset: unsafe { UPSafeCell::new(0) },
+        }))
+    }
+}
+
+/// A snapshot of one of the files
+pub struct ProcFile {
+    text: String,
+    /// where the next read starts
+    offset: UPSafeCell<usize>,
+}
+
+/// The main thread of the process `pid` names, a decimal pid or `self`
+fn process_by_pid(pid: &str) -> Option<Arc<TaskControlBlock>> {
+    if pid == "self" {
+        return Some(process_leader(&current_task()?));
+    }
+    let pid = pid.parse().ok()?;
+    // a thread id is not a process
+    pid2task(pid).filter(|task| process_leader(task).getpid() == pid)
+}
+
+fn status(task: &Arc<TaskControlBlock>) -> String {
+    let inner = task.inner_exclusive_access();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.getpid());
+    let frames = inner.memory_set.exclusive_access().frames_in_use();
+    let mut text = String::new();
+    writeln!(text, "Name:\t{}", inner.name).unwrap();
+    writeln!(text, "State:\t{:?}", inner.task_status).unwrap();
+    writeln!(text, "Pid:\t{}", task.getpid()).unwrap();
+    writeln!(text, "PPid:\t{}", ppid).unwrap();
+    writeln!(text, "Pgid:\t{}", inner.pgid).unwrap();
+    writeln!(text, "Threads:\t{}", 1 + inner.threads.len()).unwrap();
+    writeln!(text, "Priority:\t{}", inner.priority).unwrap();
+    writeln!(text, "CpuTimeUs:\t{}", inner.cpu_time_us).unwrap();
+    writeln!(text, "Frames:\t{}", frames).unwrap();
+    writeln!(text, "MinFlt:\t{}", inner.faults.minor).unwrap();
+    writeln!(text, "MajFlt:\t{}", inner.faults.major).unwrap();
+    text
+}
+
+/// `start-end perms kind`, `perms` being `rwxu` with `-` for what is missing
+fn maps(task: &Arc<TaskControlBlock>) -> String {
+    let infos = task
+        .inner_exclusive_access()
+        .memory_set
+        .exclusive_access()
+        .area_infos();
+    let mut text = String::new();
+    for AreaInfo {
+        start,
+        end,
+        perm,
+        kind,
+    } in infos
+    {
+        let perm = MapPermission::from_bits_truncate(perm as u8);
+        let flag = |bit, c| if perm.contains(bit) { c } else { '-' };
+        writeln!(
+            text,
+            "{:016x}-{:016x} {}{}{}{} {:?}",
+            start,
+            end,
+            flag(MapPermission::R, 'r'),
+            flag(MapPermission::W, 'w'),
+            flag(MapPermission::X, 'x'),
+            flag(MapPermission::U, 'u'),
+            kind
+        )
+        .unwrap();
+    }
+    text
+}
+
+fn kernel_stat() -> String {
+    let timer = timer_stats();
+    let mut text = String::new();
+    writeln!(text, "uptime_us {}", get_time_us()).unwrap();
+    writeln!(text, "frames_free {}", frames_free()).unwrap();
+    writeln!(text, "lazy_pages {}", lazy_pages()).unwrap();
+    writeln!(text, "timer_programmed {}", timer.programmed).unwrap();
+    writeln!(text, "timer_delivered {}", timer.delivered).unwrap();
+    writeln!(text, "timer_max_drift_us {}", timer.max_drift_us).unwrap();
+    writeln!(text, "readahead_blocks {}", read_ahead_blocks()).unwrap();
+    if let Some(queue) = disk_queue_stats() {
+        writeln!(text, "blk_queued {}", queue.queued).unwrap();
+        writeln!(text, "blk_merged {}", queue.merged).unwrap();
+        writeln!(text, "blk_dispatched {}", queue.dispatched).unwrap();
+        writeln!(text, "blk_seeks {}", queue.seeks).unwrap();
+        writeln!(text, "blk_read_hits {}", queue.read_hits).unwrap();
+    }
+    text
+}
+
+fn mount_table() -> String {
+    let mut text = String::new();
+    for (point, name, read_only) in mounts() {
+        let mode = if read_only { "ro" } else { "rw" };
+        writeln!(text, "{} {} {}", point, name, mode).unwrap();
+    }
+    text
+}
+
+fn partition_table() -> String {
+    let mut text = String::new();
+    for partition in PARTITIONS.iter().flatten() {
+        writeln!(
+            text,
+            "{} {} {:?}",
+            partition.start, partition.blocks, partition.kind
+        )
+        .unwrap();
+    }
+    text
+}
+
+/// `irq: name hart <target>` and the count of each hart
+fn interrupts() -> String {
+    let mut text = String::new();
+    for (irq, name, target, counts) in irq_routes() {
+        write!(text, "{}: {} hart {}", irq, name, target).unwrap();
+        for count in counts {
+            write!(text, " {}", count).unwrap();
+        }
+        writeln!(text).unwrap();
+    }
+    text
+}
+
+impl File for ProcFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, mut buf: UserBuffer) -> Option<usize> {
+        let mut offset = self.offset.exclusive_access();
+        let mut total = 0;
+        for slice in buf.buffers.iter_mut() {
+            let rest = &self.text.as_bytes()[*offset..];
+            let len = rest.len().min(slice.len());
+            slice[..len].copy_from_slice(&rest[..len]);
+            *offset += len;
+            total += len;
+            if len < slice.len() {
+                break;
+            }
+        }
+        Some(total)
+    }
+    fn write(&self, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    fn buffered(&self) -> Option<usize> {
+        Some(self.text.len() - *self.offset.exclusive_access())
+    }
+}
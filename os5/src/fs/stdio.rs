@@ -0,0 +1,83 @@
+//! The console as a [`File`]
+
+use super::{File, IoClass};
+use crate::drivers::uart;
+use crate::mm::UserBuffer;
+use crate::task::{current_task, TaskControlBlock};
+use alloc::sync::Arc;
+
+/// Console input, read through [`uart`]
+pub struct Stdin;
+
+/// Console output, buffered per task, see [`crate::console::LineBuffer`]
+pub struct Stdout;
+
+impl File for Stdin {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    fn read(&self, buf: UserBuffer) -> Option<usize> {
+        if buf.is_empty() {
+            return Some(0);
+        }
+        // a prompt written without a newline must show up before we wait
+        current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .stdout
+            .flush();
+        let input = uart::read(buf.len())?;
+        for (byte, c) in buf.bytes().zip(input.iter()) {
+            *byte = *c;
+        }
+        Some(input.len())
+    }
+    fn write(&self, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    fn read_ready(&self) -> bool {
+        uart::input_ready()
+    }
+    fn buffered(&self) -> Option<usize> {
+        Some(uart::input_len())
+    }
+    fn poll_wait(&self, task: &Arc<TaskControlBlock>) {
+        uart::poll_wait(task);
+    }
+    fn poll_cancel(&self, task: &Arc<TaskControlBlock>) {
+        uart::poll_cancel(task);
+    }
+    fn interrupt(&self) {
+        uart::interrupt_readers();
+    }
+    fn io_class(&self) -> IoClass {
+        IoClass::Console
+    }
+}
+
+impl File for Stdout {
+    fn readable(&self) -> bool {
+        false
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, _buf: UserBuffer) -> Option<usize> {
+        None
+    }
+    fn write(&self, buf: UserBuffer) -> Option<usize> {
+        let len = buf.len();
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        for buffer in buf.buffers {
+            inner.stdout.write(buffer);
+        }
+        Some(len)
+    }
+    fn io_class(&self) -> IoClass {
+        IoClass::Console
+    }
+}
@@ -0,0 +1,173 @@
+//! Harts, and bringing up the secondary ones
+//!
+//! The boot hart initializes the kernel alone and then, with the `smp`
+//! feature, starts every other hart, up to [`MAX_HARTS`], at
+//! `_start_secondary` through the SBI. Each
+//! hart keeps its id in `tp` while in the kernel, `__alltraps` restores it
+//! from the trap context on entry.
+//!
+//! A secondary hart can be taken offline with [`stop`] and brought back
+//! with [`start`]. It stops itself the next time it is between two tasks,
+//! see [`stop_this_hart`], so whatever it ran goes on on the other harts,
+//! and starts over at `_start_secondary` when started again. The boot hart,
+//! which takes the interrupts routed to offline harts, always stays.
+
+use crate::config::{IDLE_STACK_SIZE, MAX_HARTS};
+use crate::drivers::reroute_irqs;
+use crate::sbi::{hart_get_status, hart_start, hart_stop};
+use crate::sync::KERNEL_LOCK;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Harts that have come up, one bit per hart id
+static ONLINE: AtomicUsize = AtomicUsize::new(0);
+/// Harts asked to stop that have not yet, one bit per hart id
+static STOP_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+/// Id of the hart that booted the kernel
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+#[repr(usize)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// State of a hart, numbered as the HSM extension of the SBI does
+pub enum HartState {
+    Started = 0,
+    Stopped = 1,
+    StartPending = 2,
+    /// asked to stop, by [`stop`] or through the SBI
+    StopPending = 3,
+}
+
+/// Id of the hart running this
+#[inline]
+pub fn hart_id() -> usize {
+    let id;
+    unsafe {
+        core::arch::asm!("mv {}, tp", out(reg) id);
+    }
+    id
+}
+
+/// Count the calling hart among the online ones
+pub fn set_online() {
+    let id = hart_id();
+    assert!(id < MAX_HARTS, "hart {} is beyond MAX_HARTS", id);
+    check_idle_stack(id);
+    ONLINE.fetch_or(1 << id, Ordering::Release);
+}
+
+/// Check that hart `id` runs on its own boot stack, the one its idle loop
+/// keeps, as laid out by `entry.asm`, which spells out [`IDLE_STACK_SIZE`]
+/// and [`MAX_HARTS`] instead of taking them from here
+fn check_idle_stack(id: usize) {
+    extern "C" {
+        fn boot_stack();
+        fn boot_stack_top();
+    }
+    assert_eq!(
+        boot_stack_top as usize - boot_stack as usize,
+        IDLE_STACK_SIZE * MAX_HARTS,
+        "entry.asm and config.rs disagree on the boot stacks"
+    );
+    let sp: usize;
+    unsafe {
+        core::arch::asm!("mv {}, sp", out(reg) sp);
+    }
+    let top = boot_stack_top as usize - id * IDLE_STACK_SIZE;
+    assert!((top - IDLE_STACK_SIZE..top).contains(&sp), "hart {} is off its boot stack", id);
+}
+
+/// Take the calling hart for the one that booted the kernel
+pub fn set_boot_hart() {
+    BOOT_HART.store(hart_id(), Ordering::Relaxed);
+}
+
+/// The hart that booted the kernel
+pub fn boot_hart() -> usize {
+    BOOT_HART.load(Ordering::Relaxed)
+}
+
+/// Whether hart `id` is online
+pub fn is_online(id: usize) -> bool {
+    id < MAX_HARTS && ONLINE.load(Ordering::Acquire) & 1 << id != 0
+}
+
+/// Online harts other than the calling one, one bit per hart id
+pub fn other_harts() -> usize {
+    ONLINE.load(Ordering::Acquire) & !(1 << hart_id())
+}
+
+/// Start every hart other than the boot one; they wait for the kernel lock
+/// before touching anything
+#[cfg(feature = "smp")]
+pub fn start_secondary_harts() {
+    extern "C" {
+        fn _start_secondary();
+    }
+    for id in (0..MAX_HARTS).filter(|&id| id != hart_id()) {
+        // ids the machine does not have are refused
+        if hart_start(id, _start_secondary as usize, 0) {
+            info!("starting hart {}", id);
+        }
+    }
+}
+
+/// Ask the online secondary hart `id` to stop; `false` for the boot hart
+/// or a hart that is not online
+pub fn stop(id: usize) -> bool {
+    if id == boot_hart() || !is_online(id) {
+        return false;
+    }
+    STOP_REQUESTS.fetch_or(1 << id, Ordering::AcqRel);
+    true
+}
+
+/// Start the stopped hart `id` again; `false` if it is not stopped, or the
+/// kernel is built to run on one hart
+pub fn start(id: usize) -> bool {
+    extern "C" {
+        fn _start_secondary();
+    }
+    cfg!(feature = "smp")
+        && hart_state(id) == Some(HartState::Stopped)
+        && hart_start(id, _start_secondary as usize, 0)
+}
+
+/// Whether the calling hart has been asked to stop
+pub fn stop_requested() -> bool {
+    STOP_REQUESTS.load(Ordering::Acquire) & 1 << hart_id() != 0
+}
+
+/// Stop the calling hart, which holds the kernel lock and runs no task
+///
+/// It is no longer online from here on, so TLB shootdowns leave it out,
+/// and the interrupts routed to it go to the boot hart until it is back.
+/// If the SBI refuses to stop it, it comes back online and carries on.
+pub fn stop_this_hart() {
+    let id = hart_id();
+    ONLINE.fetch_and(!(1 << id), Ordering::AcqRel);
+    STOP_REQUESTS.fetch_and(!(1 << id), Ordering::AcqRel);
+    reroute_irqs();
+    println!("[kernel] hart {} offline", id);
+    KERNEL_LOCK.unlock();
+    hart_stop();
+    KERNEL_LOCK.lock();
+    warn!("[kernel] the SBI did not stop hart {}", id);
+    set_online();
+    reroute_irqs();
+}
+
+/// State of hart `id`, `None` if the machine has no such hart
+pub fn hart_state(id: usize) -> Option<HartState> {
+    if id >= MAX_HARTS {
+        return None;
+    }
+    if STOP_REQUESTS.load(Ordering::Acquire) & 1 << id != 0 {
+        return Some(HartState::StopPending);
+    }
+    Some(match hart_get_status(id)? {
+        1 => HartState::Stopped,
+        2 => HartState::StartPending,
+        3 => HartState::StopPending,
+        // suspended, which this kernel never asks for, are started too
+        _ => HartState::Started,
+    })
+}
@@ -4,6 +4,11 @@ use crate::console::ANSICON;
 use crate::sbi::shutdown;
 
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by the first panic, so that a panic while dumping state shuts down
+/// right away
+static PANICKING: AtomicBool = AtomicBool::new(false);
 
 #[panic_handler]
 /// panic handler
@@ -25,5 +30,9 @@ fn panic(info: &PanicInfo) -> ! {
             info.message().unwrap()
         );
     }
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        crate::task::dump_panic_state();
+        crate::trace::dump();
+    }
     shutdown()
 }
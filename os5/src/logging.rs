@@ -1,5 +1,7 @@
 //! Global logger
 
+use crate::console::ensure_line_start;
+use crate::timer::get_time_us;
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
 /// a simple logger
@@ -20,16 +22,36 @@ impl Log for SimpleLogger {
             Level::Debug => 32, // Green
             Level::Trace => 90, // BrightBlack
         };
+        // never glue a log line to the tail of unterminated user output
+        ensure_line_start();
+        let us = get_time_us();
         println!(
-            "\u{1B}[{}m[{:>5}] {}\u{1B}[0m",
+            "\u{1B}[{}m[{:>5}][{:>5}.{:06}] {}\u{1B}[0m",
             color,
             record.level(),
+            us / 1_000_000,
+            us % 1_000_000,
             record.args(),
         );
     }
     fn flush(&self) {}
 }
 
+/// Change the log level at runtime: 0 turns kernel logging off, 1 to 5 let
+/// through ERROR up to TRACE
+pub fn set_level(level: usize) -> Option<()> {
+    log::set_max_level(match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        5 => LevelFilter::Trace,
+        _ => return None,
+    });
+    Some(())
+}
+
 /// initiate logger
 pub fn init() {
     static LOGGER: SimpleLogger = SimpleLogger;
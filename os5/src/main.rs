@@ -30,11 +30,21 @@ extern crate alloc;
 
 #[macro_use]
 mod console;
+#[macro_use]
+mod trace;
+#[macro_use]
+mod shadow_stack;
+mod audit;
+mod bootargs;
 mod config;
+mod drivers;
+mod fs;
+mod hart;
 mod lang_items;
 mod loader;
 mod logging;
 mod mm;
+mod random;
 mod sbi;
 mod sync;
 mod syscall;
@@ -59,18 +69,61 @@ fn clear_bss() {
 
 #[no_mangle]
 /// the rust entry-point of os
-pub fn rust_main() -> ! {
+pub fn rust_main(_hart_id: usize, dtb: usize) -> ! {
     clear_bss();
+    // before mm::init, which may give the pages of the device tree away
+    bootargs::init(dtb);
+    // the boot hart holds the kernel lock from here on, the others wait
+    // for it once started
+    sync::KERNEL_LOCK.lock();
+    hart::set_boot_hart();
+    hart::set_online();
     logging::init();
     println!("[kernel] Hello, world!");
+    if !bootargs::bootargs().is_empty() {
+        println!("[kernel] bootargs: {}", bootargs::bootargs());
+    }
     mm::init();
-    mm::remap_test();
+    // the self-tests take a while, and lazy_oom_test takes every free frame
+    // for a moment, so they only run when asked for
+    if cfg!(feature = "selftest") {
+        mm::remap_test();
+        mm::cow_test();
+        mm::cached_text_test();
+        mm::munmap_test();
+        mm::sbrk_test();
+        mm::lazy_oom_test();
+        mm::overcommit_test();
+        mm::page_state_test();
+        mm::page_fault_test();
+        task::stride_test();
+        task::kernel_stack_test();
+    }
+    task::init_perturbation();
     task::add_initproc();
     info!("after initproc!");
     trap::init();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
-    loader::list_apps();
+    drivers::init();
+    fs::list_apps();
+    #[cfg(feature = "smp")]
+    hart::start_secondary_harts();
     task::run_tasks();
     panic!("Unreachable in rust_main!");
 }
+
+#[no_mangle]
+/// the rust entry-point of the other harts, once the boot one is done
+pub fn rust_main_secondary() -> ! {
+    sync::KERNEL_LOCK.lock();
+    hart::set_online();
+    mm::activate_kernel_space();
+    trap::init();
+    drivers::init_hart();
+    trap::enable_timer_interrupt();
+    timer::set_next_trigger();
+    println!("[kernel] hart {} online", hart::hart_id());
+    task::run_tasks();
+    panic!("Unreachable in rust_main_secondary!");
+}
@@ -0,0 +1,96 @@
+//! Files mapped into address spaces
+//!
+//! A file area is a lazy area whose pages are read from the file instead of
+//! being zeroed when they get their frame. Pages get it in
+//! `PageTable::populate`, on a page fault as well as when the kernel
+//! accesses user memory with nothing but a token, which does not lead to
+//! the areas of the [`MemorySet`](super::MemorySet). So every address space
+//! publishes its file areas here, by the root of its page table, for
+//! [`fill`] to find from the page table alone.
+//!
+//! Pages the user wrote, or the kernel wrote on its behalf, are marked
+//! dirty in their PTE and go back to the file on `msync`, `munmap` or when
+//! the address space goes away. A forked child gets a copy of the pages
+//! that have their frame, like a private mapping, and writes its own copy
+//! back like a shared one.
+
+use super::{PhysPageNum, VPNRange, VirtPageNum};
+use crate::config::PAGE_SIZE;
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// What a file area is mapped from
+pub trait FileBacking: Send + Sync {
+    /// Read from `offset` into `buf`, returning the bytes read
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    /// Write `buf` at `offset`, returning the bytes written
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    /// Bytes in the file; pages past its end read as zeros and are never
+    /// written back
+    fn size(&self) -> usize;
+}
+
+#[derive(Clone)]
+/// The file a file area maps, from the byte at `offset` on
+pub struct FileMap {
+    pub backing: Arc<dyn FileBacking>,
+    pub offset: usize,
+}
+
+impl FileMap {
+    /// Offset in the file of page `index` of the area
+    pub fn page_offset(&self, index: usize) -> usize {
+        self.offset + index * PAGE_SIZE
+    }
+    /// Fill the zeroed `frame`, page `index` of the area, from the file
+    pub fn read_page(&self, index: usize, frame: PhysPageNum) {
+        self.backing
+            .read_at(self.page_offset(index), frame.get_bytes_array());
+    }
+    /// Write `frame`, page `index` of the area, back to the file, as far as
+    /// the file goes
+    pub fn write_page(&self, index: usize, frame: PhysPageNum) {
+        let offset = self.page_offset(index);
+        let len = self.backing.size().saturating_sub(offset).min(PAGE_SIZE);
+        if len > 0 {
+            self.backing
+                .write_at(offset, &frame.get_bytes_array()[..len]);
+        }
+    }
+}
+
+lazy_static! {
+    /// File areas of each address space, by the root of its page table
+    static ref FILE_AREAS: UPSafeCell<BTreeMap<PhysPageNum, Vec<(VPNRange, FileMap)>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Replace the file areas published for the page table at `root`
+pub fn publish(root: PhysPageNum, areas: Vec<(VPNRange, FileMap)>) {
+    let mut published = FILE_AREAS.exclusive_access();
+    if areas.is_empty() {
+        published.remove(&root);
+    } else {
+        published.insert(root, areas);
+    }
+}
+
+/// Fill `frame`, just given to `vpn` of the page table at `root`, from the
+/// file mapped there, if any
+pub fn fill(root: PhysPageNum, vpn: VirtPageNum, frame: PhysPageNum) {
+    let published = FILE_AREAS.exclusive_access();
+    let found = published.get(&root).and_then(|areas| {
+        areas
+            .iter()
+            .find(|(range, _)| range.get_start() <= vpn && vpn < range.get_end())
+            .map(|(range, map)| (vpn.0 - range.get_start().0, map.clone()))
+    });
+    // not holding the table while the file system reads
+    drop(published);
+    if let Some((index, map)) = found {
+        map.read_page(index, frame);
+    }
+}
@@ -1,11 +1,23 @@
 //! Implementation of [`FrameAllocator`] which
 //! controls all the frames in the operating system.
+//!
+//! Every frame handed out is zeroed. While the processor idles, the scrubber
+//! kernel thread calls [`scrub_free_frames`], which zeroes free frames ahead
+//! of time into a pool that [`frame_alloc`] takes from first, so faults
+//! needing a fresh page usually skip the clearing.
+//!
+//! Debug builds also remember where each frame was last allocated, so
+//! freeing a frame that is not allocated panics right there, naming the
+//! frame and the site that last allocated it, instead of corrupting the
+//! free list.
 
 use super::{PhysAddr, PhysPageNum};
 use crate::config::MEMORY_END;
 use crate::sync::UPSafeCell;
 use alloc::vec::Vec;
 use core::fmt::{self, Debug, Formatter};
+#[cfg(debug_assertions)]
+use core::panic::Location;
 use lazy_static::*;
 
 /// manage a frame which has the same lifecycle as the tracker
@@ -16,14 +28,17 @@ pub struct FrameTracker {
 impl FrameTracker {
     pub fn new(ppn: PhysPageNum) -> Self {
         // page cleaning
-        let bytes_array = ppn.get_bytes_array();
-        for i in bytes_array {
-            *i = 0;
-        }
+        clear_frame(ppn);
         Self { ppn }
     }
 }
 
+fn clear_frame(ppn: PhysPageNum) {
+    for i in ppn.get_bytes_array() {
+        *i = 0;
+    }
+}
+
 impl Debug for FrameTracker {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("FrameTracker:PPN={:#x}", self.ppn.0))
@@ -42,24 +57,89 @@ trait FrameAllocator {
     fn dealloc(&mut self, ppn: PhysPageNum);
 }
 
+/// Most free frames kept zeroed ahead of time
+pub const ZEROED_POOL_FRAMES: usize = 64;
+
 /// an implementation for frame allocator
 pub struct StackFrameAllocator {
     current: usize,
     end: usize,
     recycled: Vec<usize>,
+    /// free frames already cleared, see [`scrub_free_frames`]
+    zeroed: Vec<usize>,
+    #[cfg(debug_assertions)]
+    owners: FrameOwners,
+}
+
+/// Bit of [`FrameOwners::owners`] set while the frame is allocated
+#[cfg(debug_assertions)]
+const LIVE: u16 = 1 << 15;
+
+/// Allocation sites of the frames, kept in debug builds
+#[cfg(debug_assertions)]
+struct FrameOwners {
+    /// first frame managed
+    base: usize,
+    /// allocation sites seen so far
+    sites: Vec<&'static Location<'static>>,
+    /// for each frame from `base`, its last allocation site as an index
+    /// into `sites` plus one, 0 if never allocated, with [`LIVE`] set while
+    /// allocated
+    owners: Vec<u16>,
+}
+
+#[cfg(debug_assertions)]
+impl FrameOwners {
+    fn init(&mut self, l: usize, r: usize) {
+        self.base = l;
+        self.owners = alloc::vec![0; r - l];
+    }
+    fn allocated(&mut self, ppn: usize, site: &'static Location<'static>) {
+        let index = match self.sites.iter().position(|s| *s == site) {
+            Some(index) => index,
+            None => {
+                self.sites.push(site);
+                self.sites.len() - 1
+            }
+        };
+        // frame_alloc has nowhere near that many callers
+        assert!(index + 1 < LIVE as usize);
+        self.owners[ppn - self.base] = LIVE | (index as u16 + 1);
+    }
+    /// Mark `ppn` free, panicking if it is not allocated
+    fn freed(&mut self, ppn: usize) {
+        let owner = match ppn.checked_sub(self.base) {
+            Some(i) if i < self.owners.len() => &mut self.owners[i],
+            _ => panic!(
+                "Frame ppn={:#x} is not managed by the frame allocator!",
+                ppn
+            ),
+        };
+        if *owner & LIVE == 0 {
+            match *owner {
+                0 => panic!("Frame ppn={:#x} has never been allocated!", ppn),
+                site => panic!(
+                    "Frame ppn={:#x} is already free, it was last allocated at {}",
+                    ppn,
+                    self.sites[site as usize - 1]
+                ),
+            }
+        }
+        *owner &= !LIVE;
+    }
 }
 
 impl StackFrameAllocator {
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
         self.current = l.0;
         self.end = r.0;
+        #[cfg(debug_assertions)]
+        self.owners.init(l.0, r.0);
         info!("last {} Physical Frames.", self.end - self.current);
     }
-}
-
-impl Debug for StackFrameAllocator {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "FrameAllocator")
+    /// Number of frames that can still be allocated
+    pub fn free_count(&self) -> usize {
+        self.end - self.current + self.recycled.len() + self.zeroed.len()
     }
 }
 
@@ -69,13 +149,21 @@ impl FrameAllocator for StackFrameAllocator {
             current: 0,
             end: 0,
             recycled: Vec::new(),
+            zeroed: Vec::new(),
+            #[cfg(debug_assertions)]
+            owners: FrameOwners {
+                base: 0,
+                sites: Vec::new(),
+                owners: Vec::new(),
+            },
         }
     }
     fn alloc(&mut self) -> Option<PhysPageNum> {
         if let Some(ppn) = self.recycled.pop() {
             Some(ppn.into())
         } else if self.current == self.end {
-            None
+            // a zeroed frame is still a free one
+            self.zeroed.pop().map(PhysPageNum::from)
         } else {
             self.current += 1;
             Some((self.current - 1).into())
@@ -83,8 +171,13 @@ impl FrameAllocator for StackFrameAllocator {
     }
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
+        #[cfg(debug_assertions)]
+        self.owners.freed(ppn);
         // validity check
-        if ppn >= self.current || self.recycled.iter().any(|v| *v == ppn) {
+        if ppn >= self.current
+            || self.recycled.iter().any(|v| *v == ppn)
+            || self.zeroed.iter().any(|v| *v == ppn)
+        {
             panic!("Frame ppn={:#x} has not been allocated!", ppn);
         }
         // recycle
@@ -110,12 +203,71 @@ pub fn init_frame_allocator() {
     );
 }
 
-/// initiate the frame allocator using `ekernel` and `MEMORY_END`
+/// allocate a zeroed frame, from the pool of frames cleared while idle if
+/// it has any
+#[track_caller]
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .exclusive_access()
-        .alloc()
-        .map(FrameTracker::new)
+    let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+    if let Some(ppn) = allocator.zeroed.pop() {
+        #[cfg(debug_assertions)]
+        allocator.owners.allocated(ppn, Location::caller());
+        return Some(FrameTracker { ppn: ppn.into() });
+    }
+    let ppn = allocator.alloc();
+    #[cfg(debug_assertions)]
+    if let Some(ppn) = ppn {
+        allocator.owners.allocated(ppn.0, Location::caller());
+    }
+    drop(allocator);
+    ppn.map(FrameTracker::new)
+}
+
+/// allocate `pages` zeroed frames of consecutive physical addresses, for
+/// devices that take a physical range; they are taken from the frames never
+/// handed out, the free lists are not ordered
+#[cfg(feature = "fs")]
+#[track_caller]
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
+    let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+    if allocator.end - allocator.current < pages {
+        return None;
+    }
+    let first = allocator.current;
+    allocator.current += pages;
+    #[cfg(debug_assertions)]
+    for ppn in first..first + pages {
+        allocator.owners.allocated(ppn, Location::caller());
+    }
+    drop(allocator);
+    Some(
+        (first..first + pages)
+            .map(|ppn| FrameTracker::new(ppn.into()))
+            .collect(),
+    )
+}
+
+/// Zero up to `batch` free frames into the pool [`frame_alloc`] takes from
+/// first, returning how many were zeroed. Called by the scrubber thread.
+pub fn scrub_free_frames(batch: usize) -> usize {
+    let mut scrubbed = 0;
+    while scrubbed < batch {
+        let mut allocator = FRAME_ALLOCATOR.exclusive_access();
+        let dirty = allocator.end - allocator.current + allocator.recycled.len();
+        if allocator.zeroed.len() >= ZEROED_POOL_FRAMES || dirty == 0 {
+            break;
+        }
+        let ppn = allocator.alloc().unwrap();
+        drop(allocator);
+        clear_frame(ppn);
+        FRAME_ALLOCATOR.exclusive_access().zeroed.push(ppn.0);
+        scrubbed += 1;
+    }
+    scrubbed
+}
+
+/// number of physical frames not handed out
+pub fn frames_free() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().free_count()
 }
 
 /// deallocate a frame
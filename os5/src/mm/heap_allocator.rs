@@ -1,12 +1,21 @@
 //! The global allocator
 
+#[cfg(feature = "heap-sanitizer")]
+use super::heap_sanitizer::SanitizedHeap;
 use crate::config::KERNEL_HEAP_SIZE;
+#[cfg(not(feature = "heap-sanitizer"))]
 use buddy_system_allocator::LockedHeap;
 
+#[cfg(not(feature = "heap-sanitizer"))]
 #[global_allocator]
 /// heap allocator instance
 static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
 
+#[cfg(feature = "heap-sanitizer")]
+#[global_allocator]
+/// heap allocator instance, checking for out-of-bounds writes and use after free
+static HEAP_ALLOCATOR: SanitizedHeap = SanitizedHeap::empty();
+
 #[alloc_error_handler]
 /// panic when heap allocation error occurs
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -0,0 +1,181 @@
+//! Red zones and a quarantine around kernel heap objects, the global
+//! allocator of the `heap-sanitizer` feature
+//!
+//! Every object is allocated with [`REDZONE`] bytes of a guard pattern on
+//! both sides, and a header at the end of the front red zone records its
+//! size and a serial number. A freed object is filled with another pattern
+//! and held back in a quarantine of [`QUARANTINE_SLOTS`] objects before it
+//! really goes back to the heap. The red zones are checked when an object
+//! is freed and again, along with the free pattern, when it leaves the
+//! quarantine, so an out-of-bounds write or a write after free ends in a
+//! panic naming the object rather than in a random fault later on. Reads
+//! out of bounds or after free are not caught, they only see the patterns.
+
+use buddy_system_allocator::{Heap, LockedHeap};
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::size_of;
+use core::ops::DerefMut;
+use spin::Mutex;
+
+/// Bytes of guard pattern on either side of an object
+const REDZONE: usize = 32;
+const REDZONE_BYTE: u8 = 0xfb;
+/// What a freed object is filled with while in quarantine
+const FREED_BYTE: u8 = 0xfd;
+/// Freed objects held back from reuse
+const QUARANTINE_SLOTS: usize = 256;
+
+const LIVE_MAGIC: usize = 0x1176_e0b1;
+const FREED_MAGIC: usize = 0xf7ee_d0b1;
+
+/// Kept in the last bytes of the front red zone
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Header {
+    size: usize,
+    serial: usize,
+    magic: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Quarantined {
+    ptr: usize,
+    layout: Layout,
+}
+
+struct State {
+    /// Objects allocated so far, the serial of the next one
+    serial: usize,
+    /// Ring of freed objects, the oldest at `head`
+    quarantine: [Option<Quarantined>; QUARANTINE_SLOTS],
+    head: usize,
+}
+
+pub struct SanitizedHeap {
+    heap: LockedHeap,
+    state: Mutex<State>,
+}
+
+impl SanitizedHeap {
+    pub const fn empty() -> Self {
+        Self {
+            heap: LockedHeap::empty(),
+            state: Mutex::new(State {
+                serial: 0,
+                quarantine: [None; QUARANTINE_SLOTS],
+                head: 0,
+            }),
+        }
+    }
+    /// The underlying heap, to be initialized through
+    pub fn lock(&self) -> impl DerefMut<Target = Heap> + '_ {
+        self.heap.lock()
+    }
+}
+
+/// Bytes before an object of `layout`: at least a red zone, and a multiple
+/// of its alignment
+fn front(layout: Layout) -> usize {
+    REDZONE.max(layout.align())
+}
+
+/// The allocation an object of `layout` is carved from
+fn outer(layout: Layout) -> Option<Layout> {
+    let size = front(layout)
+        .checked_add(layout.size())?
+        .checked_add(REDZONE)?;
+    Layout::from_size_align(size, layout.align().max(size_of::<usize>())).ok()
+}
+
+unsafe fn header_of(ptr: *mut u8) -> *mut Header {
+    ptr.sub(size_of::<Header>()) as *mut Header
+}
+
+/// Panic with what went wrong with the object at `ptr`
+fn report(what: &str, ptr: *mut u8, header: &Header, offset: isize) -> ! {
+    panic!(
+        "[kernel] heap sanitizer: {} in object #{} of {} bytes at {:#x}, offset {}",
+        what, header.serial, header.size, ptr as usize, offset
+    );
+}
+
+/// Check both red zones of the object at `ptr`, the front one up to the
+/// header
+unsafe fn check_redzones(ptr: *mut u8, header: &Header) {
+    for offset in 1..=REDZONE - size_of::<Header>() {
+        if *ptr.sub(size_of::<Header>() + offset) != REDZONE_BYTE {
+            let offset = -((size_of::<Header>() + offset) as isize);
+            report("write before the start", ptr, header, offset);
+        }
+    }
+    for offset in header.size..header.size + REDZONE {
+        if *ptr.add(offset) != REDZONE_BYTE {
+            report("write past the end", ptr, header, offset as isize);
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for SanitizedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let outer = match outer(layout) {
+            Some(outer) => outer,
+            None => return core::ptr::null_mut(),
+        };
+        let base = self.heap.alloc(outer);
+        if base.is_null() {
+            return base;
+        }
+        let ptr = base.add(front(layout));
+        base.write_bytes(REDZONE_BYTE, front(layout));
+        ptr.add(layout.size()).write_bytes(REDZONE_BYTE, REDZONE);
+        let mut state = self.state.lock();
+        header_of(ptr).write(Header {
+            size: layout.size(),
+            serial: state.serial,
+            magic: LIVE_MAGIC,
+        });
+        state.serial += 1;
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let header_ptr = header_of(ptr);
+        let header = header_ptr.read();
+        match header.magic {
+            LIVE_MAGIC => {}
+            FREED_MAGIC => report("double free", ptr, &header, 0),
+            _ => report("free of a corrupted or foreign pointer", ptr, &header, 0),
+        }
+        if header.size != layout.size() {
+            report(
+                "free with the wrong size",
+                ptr,
+                &header,
+                layout.size() as isize,
+            );
+        }
+        check_redzones(ptr, &header);
+        ptr.write_bytes(FREED_BYTE, layout.size());
+        (*header_ptr).magic = FREED_MAGIC;
+        let evicted = {
+            let mut state = self.state.lock();
+            let head = state.head;
+            state.head = (head + 1) % QUARANTINE_SLOTS;
+            state.quarantine[head].replace(Quarantined {
+                ptr: ptr as usize,
+                layout,
+            })
+        };
+        if let Some(evicted) = evicted {
+            let ptr = evicted.ptr as *mut u8;
+            let header = header_of(ptr).read();
+            for offset in 0..header.size {
+                if *ptr.add(offset) != FREED_BYTE {
+                    report("write after free", ptr, &header, offset as isize);
+                }
+            }
+            check_redzones(ptr, &header);
+            let base = ptr.sub(front(evicted.layout));
+            self.heap.dealloc(base, outer(evicted.layout).unwrap());
+        }
+    }
+}
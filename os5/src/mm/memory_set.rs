@@ -1,15 +1,22 @@
 //! Implementation of [`MapArea`] and [`MemorySet`].
 
-use super::{frame_alloc, FrameTracker};
+use super::{cached_page, frame_alloc, FrameTracker, ShmObject};
+use super::file_map::{self, FileBacking, FileMap};
 use super::{PTEFlags, PageTable, PageTableEntry};
 use super::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 use super::{StepByOne, VPNRange};
-use crate::config::{MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_SIZE};
+use super::tlb;
+use super::page_table::lazy_pages;
+use crate::bootargs::bootarg;
+use crate::config::{MAX_THREADS, MEMORY_END, MMIO, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT, USER_STACK_LIMIT, USER_STACK_SIZE};
 use crate::sync::UPSafeCell;
+#[cfg(feature = "kstack-trap-cx")]
+use crate::trap::trap_scratch_ppn;
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::fmt::{Debug, Formatter};
+use core::cmp::Ordering;
+use core::fmt::Debug;
 use lazy_static::*;
 use riscv::register::satp;
 
@@ -32,10 +39,29 @@ lazy_static! {
         Arc::new(unsafe { UPSafeCell::new(MemorySet::new_kernel()) });
 }
 
+/// Where the TrapContext of thread `slot` of a process is mapped, slot 0
+/// being the main thread
+pub fn thread_trap_cx(slot: usize) -> usize {
+    TRAP_CONTEXT - slot * PAGE_SIZE
+}
+
+/// Top of the user stack of thread `slot` other than the main one; the
+/// stacks lie below the TrapContexts with a guard page above each
+fn thread_stack_top(slot: usize) -> usize {
+    thread_trap_cx(MAX_THREADS - 1) - PAGE_SIZE - (slot - 1) * (USER_STACK_SIZE + PAGE_SIZE)
+}
+
 /// memory set structure, controls virtual-memory space
 pub struct MemorySet {
     page_table: PageTable,
     areas: Vec<MapArea>,
+    /// Lowest address of the heap, 0 if the space has none
+    heap_bottom: usize,
+    /// Current program break, the end of the heap
+    brk: usize,
+    /// Lowest address the main user stack may grow down to, 0 if the space
+    /// has none; the page below is left unmapped as a guard
+    stack_limit: usize,
 }
 
 impl MemorySet {
@@ -43,6 +69,9 @@ impl MemorySet {
         Self {
             page_table: PageTable::new(),
             areas: Vec::new(),
+            heap_bottom: 0,
+            brk: 0,
+            stack_limit: 0,
         }
     }
     pub fn token(&self) -> usize {
@@ -60,17 +89,192 @@ impl MemorySet {
         }
         false
     }
+    /// Unmap the page-aligned range `[start_va, end_va)`, which must lie
+    /// within one area. Only user framed areas can lose part of their range;
+    /// what is left of them on either side stays mapped as separate areas.
     pub fn unmap_area(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> Option<()> {
-        for (i, area) in self.areas.iter_mut().enumerate() {
-            let start: VirtAddr = area.vpn_range.get_start().into();
-            let end: VirtAddr = area.vpn_range.get_end().into();
-            if start_va == start && end_va == end {
-                area.unmap(&mut self.page_table);
-                self.areas.remove(i);
-                return Some(());
+        if start_va.page_offset() != 0 || end_va.page_offset() != 0 {
+            return None;
+        }
+        let start_vpn = start_va.floor();
+        let end_vpn = end_va.floor();
+        if start_vpn >= end_vpn {
+            return None;
+        }
+        let index = self.areas.iter().position(|area| {
+            area.vpn_range.get_start() <= start_vpn && area.vpn_range.get_end() >= end_vpn
+        })?;
+        let area = &self.areas[index];
+        let whole = area.vpn_range.get_start() == start_vpn && area.vpn_range.get_end() == end_vpn;
+        if area.sealed
+            || !whole
+                && (area.map_type != MapType::Framed || !area.map_perm.contains(MapPermission::U))
+        {
+            return None;
+        }
+        let mut target = self.areas.remove(index);
+        if target.vpn_range.get_start() < start_vpn {
+            let tail = target.split_off(start_vpn);
+            self.areas.push(target);
+            target = tail;
+        }
+        if target.vpn_range.get_end() > end_vpn {
+            let rest = target.split_off(end_vpn);
+            self.areas.push(rest);
+        }
+        target.write_back(&mut self.page_table, target.vpn_range);
+        target.unmap(&mut self.page_table);
+        tlb::shootdown(VPNRange::new(start_vpn, end_vpn));
+        self.publish_files();
+        Some(())
+    }
+    /// Seal the user area spanning exactly `[start_va, end_va)` so that
+    /// [`MemorySet::unmap_area`] and [`MemorySet::protect`] refuse it from now on
+    pub fn seal_area(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> Option<()> {
+        let area = self.areas.iter_mut().find(|area| {
+            VirtAddr::from(area.vpn_range.get_start()) == start_va
+                && VirtAddr::from(area.vpn_range.get_end()) == end_va
+        })?;
+        if !area.map_perm.contains(MapPermission::U) {
+            return None;
+        }
+        area.sealed = true;
+        Some(())
+    }
+    /// Change the permission of every page in `[start_va, end_va)` to
+    /// `permission`, splitting areas that stick out of the range
+    ///
+    /// The range must be fully mapped by user areas that are not sealed and
+    /// were created with at least `permission`. Shared memory areas can only
+    /// be changed as a whole.
+    pub fn protect(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+    ) -> Result<(), MapError> {
+        let start_vpn = start_va.floor();
+        let end_vpn = end_va.ceil();
+        let mut overlapping: Vec<&MapArea> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                area.vpn_range.get_start() < end_vpn && area.vpn_range.get_end() > start_vpn
+            })
+            .collect();
+        overlapping.sort_by_key(|area| area.vpn_range.get_start());
+        let mut covered = start_vpn;
+        for area in overlapping {
+            if area.vpn_range.get_start() > covered {
+                return Err(MapError::NotMapped);
+            }
+            covered = area.vpn_range.get_end();
+            if area.sealed
+                || !area.map_perm.contains(MapPermission::U)
+                || !area.max_perm.contains(permission)
+            {
+                return Err(MapError::PermissionDenied);
+            }
+            if area.map_type == MapType::Shared
+                && (area.vpn_range.get_start() < start_vpn || area.vpn_range.get_end() > end_vpn)
+            {
+                return Err(MapError::InvalidArgument);
+            }
+        }
+        if covered < end_vpn {
+            return Err(MapError::NotMapped);
+        }
+        for split_at in [start_vpn, end_vpn] {
+            if let Some(area) = self.areas.iter_mut().find(|area| {
+                area.vpn_range.get_start() < split_at && area.vpn_range.get_end() > split_at
+            }) {
+                let tail = area.split_off(split_at);
+                self.areas.push(tail);
             }
         }
-        None
+        for area in self.areas.iter_mut() {
+            if area.vpn_range.get_start() >= start_vpn && area.vpn_range.get_end() <= end_vpn {
+                area.protect(&mut self.page_table, permission);
+            }
+        }
+        tlb::shootdown(VPNRange::new(start_vpn, end_vpn));
+        self.publish_files();
+        Ok(())
+    }
+    /// Write the pages of `[start_va, end_va)` that were written back to
+    /// the files mapped there; `None` if part of the range is not mapped
+    pub fn msync(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> Option<()> {
+        let start_vpn = start_va.floor();
+        let end_vpn = end_va.ceil();
+        let mut overlapping: Vec<&MapArea> = self
+            .areas
+            .iter()
+            .filter(|area| {
+                area.vpn_range.get_start() < end_vpn && area.vpn_range.get_end() > start_vpn
+            })
+            .collect();
+        overlapping.sort_by_key(|area| area.vpn_range.get_start());
+        let mut covered = start_vpn;
+        for area in overlapping {
+            if area.vpn_range.get_start() > covered {
+                return None;
+            }
+            covered = area.vpn_range.get_end();
+        }
+        if covered < end_vpn {
+            return None;
+        }
+        let range = VPNRange::new(start_vpn, end_vpn);
+        for area in self.areas.iter() {
+            area.write_back(&mut self.page_table, range);
+        }
+        tlb::shootdown(range);
+        Some(())
+    }
+    /// Map all of `object` from `start_va` on. Assume that no conflicts.
+    pub fn insert_shared_area(
+        &mut self,
+        start_va: VirtAddr,
+        object: Arc<ShmObject>,
+        permission: MapPermission,
+    ) -> Option<()> {
+        let end_va = VirtAddr::from(usize::from(start_va) + object.pages() * PAGE_SIZE);
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Shared, permission);
+        map_area.kind = AreaKind::Shm;
+        map_area.shm = Some(object);
+        self.push(map_area, None)
+    }
+    /// Map the first `len` bytes of `backing` from `start_va` on, its pages
+    /// read from the file on first access. Assume that no conflicts.
+    ///
+    /// `None` as well if the pages would pass the overcommit limit.
+    pub fn insert_file_area(
+        &mut self,
+        start_va: VirtAddr,
+        len: usize,
+        backing: Arc<dyn FileBacking>,
+        permission: MapPermission,
+    ) -> Option<()> {
+        let end_va = VirtAddr::from(usize::from(start_va) + len);
+        if end_va.ceil().0 - start_va.floor().0 > commit_headroom() {
+            return None;
+        }
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        map_area.kind = AreaKind::File;
+        map_area.lazy = true;
+        map_area.file = Some(FileMap { backing, offset: 0 });
+        self.push(map_area, None)?;
+        self.publish_files();
+        Some(())
+    }
+    /// Tell [`file_map`] where the file areas are now
+    fn publish_files(&self) {
+        let areas = self
+            .areas
+            .iter()
+            .filter_map(|area| area.file.clone().map(|file| (area.vpn_range, file)))
+            .collect();
+        file_map::publish(self.page_table.root_ppn(), areas);
     }
     /// Assume that no conflicts.
     pub fn insert_framed_area(
@@ -84,6 +288,215 @@ impl MemorySet {
             None,
         )
     }
+    /// Like [`MemorySet::insert_framed_area`], but map the pages to `frames`,
+    /// one for each page in order, instead of newly allocated ones
+    pub fn insert_area_with_frames(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        frames: Vec<FrameTracker>,
+    ) -> Option<()> {
+        let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        assert_eq!(frames.len(), map_area.vpn_range.into_iter().count());
+        map_area.data_frames = map_area.vpn_range.into_iter().zip(frames).collect();
+        self.push(map_area, None)
+    }
+    /// Allocate the page tables for `[start_va, end_va)`, so that mapping
+    /// it with frames at hand cannot fail later
+    pub fn reserve_page_tables(&mut self, start_va: VirtAddr, end_va: VirtAddr) -> Option<()> {
+        for vpn in VPNRange::new(start_va.floor(), end_va.ceil()) {
+            self.page_table.reserve(vpn)?;
+        }
+        Some(())
+    }
+    /// Move the program break by `increment` bytes, returning the old one
+    ///
+    /// The heap grows in lazily populated areas, so a page gets its frame on
+    /// first access; shrinking unmaps the heap pages above the new break.
+    /// The break never goes below where the heap starts, and growing fails
+    /// if the new pages overlap another area.
+    pub fn sbrk(&mut self, increment: isize) -> Option<usize> {
+        let old_brk = self.brk;
+        if self.heap_bottom == 0 {
+            return None;
+        }
+        let new_brk = if increment >= 0 {
+            old_brk.checked_add(increment as usize)?
+        } else {
+            old_brk.checked_sub(increment.unsigned_abs())?
+        };
+        if new_brk < self.heap_bottom || new_brk > TRAP_CONTEXT {
+            return None;
+        }
+        let old_end = VirtAddr::from(old_brk).ceil();
+        let new_end = VirtAddr::from(new_brk).ceil();
+        match new_end.cmp(&old_end) {
+            Ordering::Greater => {
+                if self.is_conflict(old_end.into(), new_end.into())
+                    || new_end.0 - old_end.0 > commit_headroom()
+                {
+                    return None;
+                }
+                let mut area = MapArea::new(
+                    old_end.into(),
+                    new_end.into(),
+                    MapType::Framed,
+                    MapPermission::R | MapPermission::W | MapPermission::U,
+                );
+                area.kind = AreaKind::Heap;
+                area.lazy = true;
+                area.map(&mut self.page_table)?;
+                // lazy areas own no frames, so the top one can simply grow
+                if let Some(top) = self.areas.iter_mut().find(|top| {
+                    top.kind == AreaKind::Heap
+                        && top.vpn_range.get_end() == old_end
+                        && top.map_perm == area.map_perm
+                        && !top.sealed
+                }) {
+                    top.vpn_range = VPNRange::new(top.vpn_range.get_start(), new_end);
+                } else {
+                    self.areas.push(area);
+                }
+            }
+            Ordering::Less => {
+                let above = |area: &MapArea| {
+                    area.kind == AreaKind::Heap && area.vpn_range.get_end() > new_end
+                };
+                if self.areas.iter().any(|area| above(area) && area.sealed) {
+                    return None;
+                }
+                if let Some(area) = self.areas.iter_mut().find(|area| {
+                    above(area) && area.vpn_range.get_start() < new_end
+                }) {
+                    let tail = area.split_off(new_end);
+                    self.areas.push(tail);
+                }
+                let (gone, kept): (Vec<MapArea>, Vec<MapArea>) =
+                    core::mem::take(&mut self.areas).into_iter().partition(above);
+                self.areas = kept;
+                for mut area in gone {
+                    area.unmap(&mut self.page_table);
+                }
+                tlb::shootdown(VPNRange::new(new_end, old_end));
+            }
+            Ordering::Equal => {}
+        }
+        self.brk = new_brk;
+        Some(old_brk)
+    }
+    /// Index of the main user stack in `areas`
+    ///
+    /// It is the lowest stack area within [`USER_STACK_LIMIT`] of
+    /// `stack_limit`, the stacks of other threads lie far above.
+    fn main_stack(&self) -> Option<usize> {
+        if self.stack_limit == 0 {
+            return None;
+        }
+        let limit = VirtAddr::from(self.stack_limit).floor();
+        let top = VirtAddr::from(self.stack_limit + USER_STACK_LIMIT).floor();
+        self.areas
+            .iter()
+            .enumerate()
+            .filter(|(_, area)| {
+                let start = area.vpn_range.get_start();
+                area.kind == AreaKind::Stack && limit <= start && start < top
+            })
+            .min_by_key(|(_, area)| area.vpn_range.get_start())
+            .map(|(idx, _)| idx)
+    }
+    /// Whether an `access` at `va` should grow the main stack down to it:
+    /// `va` lies between the limit and the stack, nothing else is mapped
+    /// in between, and the stack allows the access
+    fn fault_grows_stack(&self, va: VirtAddr, access: FaultAccess) -> bool {
+        self.main_stack().map_or(false, |idx| {
+            let stack = &self.areas[idx];
+            let start = stack.vpn_range.get_start();
+            usize::from(va) >= self.stack_limit
+                && va.floor() < start
+                && !self.is_conflict(va.floor().into(), start.into())
+                && access.allowed(PTEFlags::from_bits(stack.map_perm.bits).unwrap())
+        })
+    }
+    /// Grow the main stack down to `vpn`, returning the pages added
+    ///
+    /// The new pages get their frames at once, but not the frames promised
+    /// to lazy pages past the overcommit limit.
+    fn grow_stack(&mut self, vpn: VirtPageNum) -> Option<VPNRange> {
+        let idx = self.main_stack()?;
+        let stack = &mut self.areas[idx];
+        let start = stack.vpn_range.get_start();
+        if start.0 - vpn.0 > commit_headroom() {
+            return None;
+        }
+        stack.grow_down(&mut self.page_table, vpn)?;
+        Some(VPNRange::new(vpn, start))
+    }
+    /// Whether the stack of thread `slot` is free to map, and its
+    /// TrapContext unless that is on the kernel stack
+    fn thread_slot_free(&self, slot: usize) -> bool {
+        let stack_top = thread_stack_top(slot);
+        if self.is_conflict((stack_top - USER_STACK_SIZE).into(), stack_top.into()) {
+            return false;
+        }
+        let trap_cx = thread_trap_cx(slot);
+        cfg!(feature = "kstack-trap-cx") || !self.is_conflict(trap_cx.into(), (trap_cx + PAGE_SIZE).into())
+    }
+    /// Map a user stack and a TrapContext for a new thread in the first
+    /// free slot, returning the slot and the top of the stack
+    pub fn insert_thread(&mut self) -> Option<(usize, usize)> {
+        let slot = (1..MAX_THREADS).find(|slot| self.thread_slot_free(*slot))?;
+        let stack_top = thread_stack_top(slot);
+        let stack_bottom = stack_top - USER_STACK_SIZE;
+        let mut stack_area = MapArea::new(
+            stack_bottom.into(),
+            stack_top.into(),
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
+        );
+        stack_area.kind = AreaKind::Stack;
+        self.push(stack_area, None)?;
+        #[cfg(not(feature = "kstack-trap-cx"))]
+        {
+            let trap_cx = thread_trap_cx(slot);
+            let mut trap_cx_area = MapArea::new(
+                trap_cx.into(),
+                (trap_cx + PAGE_SIZE).into(),
+                MapType::Framed,
+                MapPermission::R | MapPermission::W,
+            );
+            trap_cx_area.kind = AreaKind::TrapContext;
+            if self.push(trap_cx_area, None).is_none() {
+                self.remove_area_with_start_vpn(VirtAddr::from(stack_bottom).floor());
+                return None;
+            }
+        }
+        Some((slot, stack_top))
+    }
+    /// Unmap the user stack and the TrapContext of thread `slot`, if mapped
+    pub fn remove_thread(&mut self, slot: usize) {
+        self.remove_area_with_start_vpn(VirtAddr::from(thread_trap_cx(slot)).floor());
+        let stack_bottom = thread_stack_top(slot) - USER_STACK_SIZE;
+        self.remove_area_with_start_vpn(VirtAddr::from(stack_bottom).floor());
+    }
+    /// Unmap the framed area starting at `start_vpn` as
+    /// [`MemorySet::remove_area_with_start_vpn`] does, but hand its frames
+    /// over in page order instead of freeing them
+    pub fn take_area_frames(&mut self, start_vpn: VirtPageNum) -> Vec<FrameTracker> {
+        let idx = match self
+            .areas
+            .iter()
+            .position(|area| area.vpn_range.get_start() == start_vpn)
+        {
+            Some(idx) => idx,
+            None => return Vec::new(),
+        };
+        let mut area = self.areas.remove(idx);
+        let frames = core::mem::take(&mut area.data_frames);
+        area.unmap(&mut self.page_table);
+        tlb::shootdown(VPNRange::new(area.vpn_range.get_start(), area.vpn_range.get_end()));
+        frames.into_values().collect()
+    }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
             .areas
@@ -92,6 +505,7 @@ impl MemorySet {
             .find(|(_, area)| area.vpn_range.get_start() == start_vpn)
         {
             area.unmap(&mut self.page_table);
+            tlb::shootdown(VPNRange::new(area.vpn_range.get_start(), area.vpn_range.get_end()));
             self.areas.remove(idx);
         }
     }
@@ -104,12 +518,21 @@ impl MemorySet {
         Some(())
     }
     /// Mention that trampoline is not collected by areas.
+    ///
+    /// With `kstack-trap-cx` the page of trap scratch below it is mapped
+    /// alike, into every address space, see `trap/scratch.rs`.
     fn map_trampoline(&mut self) {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
         );
+        #[cfg(feature = "kstack-trap-cx")]
+        self.page_table.map(
+            VirtAddr::from(TRAP_CONTEXT).into(),
+            trap_scratch_ppn(),
+            PTEFlags::R | PTEFlags::W,
+        );
     }
     /// Without kernel stacks.
     pub fn new_kernel() -> Self {
@@ -174,24 +597,44 @@ impl MemorySet {
             ),
             None,
         );
+        info!("mapping memory-mapped registers");
+        for &(start, len) in MMIO {
+            memory_set.push(
+                MapArea::new(
+                    start.into(),
+                    (start + len).into(),
+                    MapType::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                None,
+            );
+        }
         memory_set
     }
     /// Include sections in elf and trampoline and TrapContext and user stack,
     /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    ///
+    /// The segments come from the page cache under `name`, see
+    /// [`super::page_cache`].
+    pub fn from_elf(elf_data: &[u8], name: &str) -> Result<(Self, usize, usize), ElfError> {
         let mut memory_set = Self::new_bare();
         // map trampoline
         memory_set.map_trampoline();
         // map program headers of elf, with U flag
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
+        let elf = xmas_elf::ElfFile::new(elf_data).map_err(ElfError::Malformed)?;
         let elf_header = elf.header;
         let magic = elf_header.pt1.magic;
-        assert_eq!(magic, [0x7f, 0x45, 0x4c, 0x46], "invalid elf!");
+        if magic != [0x7f, 0x45, 0x4c, 0x46] {
+            return Err(ElfError::Malformed("invalid elf magic"));
+        }
         let ph_count = elf_header.pt2.ph_count();
         let mut max_end_vpn = VirtPageNum(0);
         for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
+            let ph = elf.program_header(i).map_err(ElfError::Malformed)?;
+            if ph.get_type().map_err(ElfError::Malformed)? == xmas_elf::program::Type::Load {
+                if ph.file_size() > ph.mem_size() {
+                    return Err(ElfError::Malformed("segment file size exceeds memory size"));
+                }
                 let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
                 let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
                 let mut map_perm = MapPermission::U;
@@ -205,64 +648,131 @@ impl MemorySet {
                 if ph_flags.is_execute() {
                     map_perm |= MapPermission::X;
                 }
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                let data = elf
+                    .input
+                    .get(ph.offset() as usize..(ph.offset() + ph.file_size()) as usize)
+                    .ok_or(ElfError::Malformed("segment outside of the file"))?;
+                let mut map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
+                map_area.kind = AreaKind::Elf;
                 max_end_vpn = map_area.vpn_range.get_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                for (i, vpn) in map_area.vpn_range.into_iter().enumerate() {
+                    let page = data
+                        .get(i * PAGE_SIZE..)
+                        .map_or(&[][..], |rest| &rest[..rest.len().min(PAGE_SIZE)]);
+                    let frame = cached_page(name, vpn, page).ok_or(ElfError::OutOfMemory)?;
+                    map_area.cached_frames.insert(vpn, frame);
+                }
+                memory_set
+                    .push(map_area, None)
+                    .ok_or(ElfError::OutOfMemory)?;
             }
         }
         // map user stack with U flags
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push(
-            MapArea::new(
-                user_stack_bottom.into(),
-                user_stack_top.into(),
-                MapType::Framed,
-                MapPermission::R | MapPermission::W | MapPermission::U,
-            ),
-            None,
+        // guard page, then room for the stack to grow down into
+        memory_set.stack_limit = usize::from(max_end_va) + PAGE_SIZE;
+        let user_stack_top = memory_set.stack_limit + USER_STACK_LIMIT;
+        let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
+        // the heap starts empty above another guard page
+        memory_set.heap_bottom = user_stack_top + PAGE_SIZE;
+        memory_set.brk = memory_set.heap_bottom;
+        let mut stack_area = MapArea::new(
+            user_stack_bottom.into(),
+            user_stack_top.into(),
+            MapType::Framed,
+            MapPermission::R | MapPermission::W | MapPermission::U,
         );
-        // map TrapContext
-        memory_set.push(
-            MapArea::new(
+        stack_area.kind = AreaKind::Stack;
+        memory_set
+            .push(stack_area, None)
+            .ok_or(ElfError::OutOfMemory)?;
+        // map TrapContext, which is on the kernel stack with kstack-trap-cx
+        #[cfg(not(feature = "kstack-trap-cx"))]
+        {
+            let mut trap_cx_area = MapArea::new(
                 TRAP_CONTEXT.into(),
                 TRAMPOLINE.into(),
                 MapType::Framed,
                 MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
-        (
+            );
+            trap_cx_area.kind = AreaKind::TrapContext;
+            memory_set
+                .push(trap_cx_area, None)
+                .ok_or(ElfError::OutOfMemory)?;
+        }
+        Ok((
             memory_set,
             user_stack_top,
             elf.header.pt2.entry_point() as usize,
-        )
+        ))
     }
-    /// Copy an identical user_space
-    pub fn from_existed_user(user_space: &MemorySet) -> MemorySet {
-        let mut memory_set = Self::new_bare();
+    /// Copy an identical user_space, or `None` when frames run out or the
+    /// copy would pass the overcommit limit
+    pub fn from_existed_user(user_space: &MemorySet) -> Option<MemorySet> {
+        let data_frames = user_space.frames_in_use() - user_space.page_table_frames();
+        if user_space.promised_pages() + data_frames > commit_headroom() {
+            return None;
+        }
+        let mut memory_set = Self {
+            page_table: PageTable::try_new()?,
+            areas: Vec::new(),
+            heap_bottom: user_space.heap_bottom,
+            brk: user_space.brk,
+            stack_limit: user_space.stack_limit,
+        };
         // map trampoline
-        memory_set.map_trampoline();
+        memory_set.page_table.try_map(
+            VirtAddr::from(TRAMPOLINE).into(),
+            PhysAddr::from(strampoline as usize).into(),
+            PTEFlags::R | PTEFlags::X,
+        )?;
+        #[cfg(feature = "kstack-trap-cx")]
+        memory_set.page_table.try_map(
+            VirtAddr::from(TRAP_CONTEXT).into(),
+            trap_scratch_ppn(),
+            PTEFlags::R | PTEFlags::W,
+        )?;
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
-            let new_area = MapArea::from_another(area);
-            memory_set.push(new_area, None);
+            let mut new_area = MapArea::from_another(area);
+            // pages the parent has not written yet stay shared
+            new_area.cached_frames = area
+                .cached_frames
+                .iter()
+                .filter(|(vpn, frame)| {
+                    user_space.page_table.private_copy(**vpn, frame.ppn).is_none()
+                })
+                .map(|(vpn, frame)| (*vpn, frame.clone()))
+                .collect();
+            memory_set.push(new_area, None)?;
+            // shared areas map the very same frames
+            if area.map_type == MapType::Shared {
+                continue;
+            }
             // copy data from another space
             for vpn in area.vpn_range {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
-                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                let src_pte = user_space.translate(vpn)?;
+                // lazy pages never touched stay so in both
+                if src_pte.is_lazy() {
+                    continue;
+                }
+                if area.lazy {
+                    memory_set.page_table.populate(vpn)?;
+                }
+                let src_ppn = src_pte.ppn();
+                let dst_ppn = memory_set.translate(vpn)?.ppn();
+                // still copy-on-write in both
+                if src_ppn == dst_ppn {
+                    continue;
+                }
                 dst_ppn
                     .get_bytes_array()
                     .copy_from_slice(src_ppn.get_bytes_array());
             }
         }
-        memory_set
+        // only now, as the pages copied above are not to be read from files
+        memory_set.publish_files();
+        Some(memory_set)
     }
     pub fn activate(&self) {
         let satp = self.page_table.token();
@@ -274,15 +784,177 @@ impl MemorySet {
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.translate(vpn)
     }
+    /// Number of frames owned by this address space, page tables included
+    ///
+    /// Frames shared with the page cache do not count, their private copies
+    /// and the populated pages of lazy areas do.
+    pub fn frames_in_use(&self) -> usize {
+        self.page_table.frame_count()
+            + self
+                .areas
+                .iter()
+                .map(|area| {
+                    area.data_frames.len()
+                        + area.private_copies(&self.page_table)
+                        + area.populated(&self.page_table)
+                })
+                .sum::<usize>()
+    }
+    /// What a page fault at `va` on `access` calls for, see [`PageFault`]
+    ///
+    /// A lazy page only gets its frame for an access its permission allows,
+    /// so a store to a read-only one is a segfault rather than a frame
+    /// wasted before the store faults again. The same goes for growing the
+    /// stack, which is never executed from.
+    pub fn classify_fault(&self, va: VirtAddr, access: FaultAccess) -> PageFault {
+        match self.page_table.translate(va.floor()) {
+            Some(pte) if pte.is_lazy() && access.allowed(pte.flags()) => {
+                let vpn = va.floor();
+                let in_file = self.areas.iter().any(|area| {
+                    area.file.is_some()
+                        && area.vpn_range.get_start() <= vpn
+                        && vpn < area.vpn_range.get_end()
+                });
+                if in_file {
+                    PageFault::File
+                } else {
+                    PageFault::Lazy
+                }
+            }
+            Some(pte) if access == FaultAccess::Store && pte.is_cow() => PageFault::Cow,
+            _ if self.fault_grows_stack(va, access) => PageFault::StackGrowth,
+            _ => PageFault::Segfault,
+        }
+    }
+    /// Resolve a page fault at `va` that [`MemorySet::classify_fault`] took
+    /// for `fault`. `false` for a segfault, or if no frame is left.
+    pub fn resolve_fault(&mut self, va: VirtAddr, fault: PageFault) -> bool {
+        let vpn = va.floor();
+        let one_page = |_| VPNRange::new(vpn, VirtPageNum(vpn.0 + 1));
+        let resolved = match fault {
+            PageFault::Lazy | PageFault::File => self.page_table.populate(vpn).map(one_page),
+            PageFault::Cow => self.page_table.break_cow(vpn).map(one_page),
+            PageFault::StackGrowth => self.grow_stack(vpn),
+            PageFault::Segfault => None,
+        };
+        match resolved {
+            Some(pages) => {
+                tlb::shootdown(pages);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Resolve a page fault at `va` on `access` if it is not a segfault,
+    /// returning what it was taken for and whether that worked
+    pub fn handle_page_fault(&mut self, va: VirtAddr, access: FaultAccess) -> (PageFault, bool) {
+        let fault = self.classify_fault(va, access);
+        (fault, self.resolve_fault(va, fault))
+    }
+    /// Describe every area plus the trampoline, and the trap scratch with
+    /// `kstack-trap-cx`, sorted by address
+    pub fn area_infos(&self) -> Vec<AreaInfo> {
+        let mut infos: Vec<AreaInfo> = self
+            .areas
+            .iter()
+            .map(|area| AreaInfo {
+                start: VirtAddr::from(area.vpn_range.get_start()).into(),
+                end: VirtAddr::from(area.vpn_range.get_end()).into(),
+                perm: area.map_perm.bits as usize,
+                kind: area.kind,
+            })
+            .collect();
+        infos.push(AreaInfo {
+            start: TRAMPOLINE,
+            end: TRAMPOLINE.wrapping_add(PAGE_SIZE),
+            perm: (MapPermission::R | MapPermission::X).bits as usize,
+            kind: AreaKind::Trampoline,
+        });
+        #[cfg(feature = "kstack-trap-cx")]
+        infos.push(AreaInfo {
+            start: TRAP_CONTEXT,
+            end: TRAMPOLINE,
+            perm: (MapPermission::R | MapPermission::W).bits as usize,
+            kind: AreaKind::TrapContext,
+        });
+        infos.sort_by_key(|info| info.start);
+        infos
+    }
+    /// Residency of the page `vpn`, see [`PageState`]
+    pub fn page_state(&self, vpn: VirtPageNum) -> PageState {
+        let in_area = self
+            .areas
+            .iter()
+            .any(|area| area.vpn_range.get_start() <= vpn && vpn < area.vpn_range.get_end());
+        let mut state = if in_area {
+            PageState::MAPPED
+        } else {
+            PageState::empty()
+        };
+        match self.page_table.translate(vpn) {
+            Some(pte) if pte.is_cow() => {
+                state |= PageState::MAPPED | PageState::RESIDENT | PageState::COW
+            }
+            Some(pte) if pte.is_valid() => state |= PageState::MAPPED | PageState::RESIDENT,
+            _ => {}
+        }
+        state
+    }
+    /// Frames held by the page table itself
+    pub fn page_table_frames(&self) -> usize {
+        self.page_table.frame_count()
+    }
+    /// Number of pages of lazy areas still waiting for a frame
+    fn promised_pages(&self) -> usize {
+        self.areas
+            .iter()
+            .filter(|area| area.lazy)
+            .map(|area| {
+                area.vpn_range.get_end().0 - area.vpn_range.get_start().0
+                    - area.populated(&self.page_table)
+            })
+            .sum()
+    }
     pub fn recycle_data_pages(&mut self) {
         //*self = Self::new_bare();
+        self.write_back_files();
+        self.release_private_copies();
         self.areas.clear();
+        self.publish_files();
+    }
+    /// Write every page written since the last time back to its file
+    fn write_back_files(&mut self) {
+        for area in self.areas.iter() {
+            area.write_back(&mut self.page_table, area.vpn_range);
+        }
+    }
+    /// Free the copy-on-write copies and the populated pages of lazy areas,
+    /// which only the page table refers to, and give up the pages of lazy
+    /// areas still waiting for a frame
+    fn release_private_copies(&mut self) {
+        for area in self.areas.iter().filter(|area| area.lazy) {
+            for vpn in area.vpn_range {
+                if let Some(pte) = self.page_table.translate(vpn).filter(|pte| pte.is_valid()) {
+                    drop(FrameTracker { ppn: pte.ppn() });
+                }
+                self.page_table.clear(vpn);
+            }
+        }
+        for area in self.areas.iter_mut() {
+            for (vpn, frame) in core::mem::take(&mut area.cached_frames) {
+                if let Some(copy) = self.page_table.private_copy(vpn, frame.ppn) {
+                    drop(FrameTracker { ppn: copy });
+                }
+            }
+        }
     }
 }
 
-impl Debug for MemorySet {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        write!(f, "MemorySet")
+impl Drop for MemorySet {
+    fn drop(&mut self) {
+        self.write_back_files();
+        self.release_private_copies();
+        file_map::publish(self.page_table.root_ppn(), Vec::new());
     }
 }
 
@@ -292,6 +964,22 @@ pub struct MapArea {
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// The permission the area was created with, the most `protect` grants
+    max_perm: MapPermission,
+    /// Sealed areas cannot be unmapped or reprotected until the address
+    /// space goes away
+    sealed: bool,
+    kind: AreaKind,
+    /// Backing object of a [`MapType::Shared`] area
+    shm: Option<Arc<ShmObject>>,
+    /// Pages of a [`MapType::Framed`] area mapped from the page cache instead
+    /// of `data_frames`, copy-on-write if the area is writable
+    cached_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
+    /// Pages of a lazy [`MapType::Framed`] area get a zeroed frame on first
+    /// access, owned by the PTE like a copy-on-write copy
+    lazy: bool,
+    /// File a lazy area reads its pages from instead, see [`file_map`]
+    file: Option<FileMap>,
 }
 
 impl MapArea {
@@ -308,6 +996,13 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            max_perm: map_perm,
+            sealed: false,
+            kind: AreaKind::Mmap,
+            shm: None,
+            cached_frames: BTreeMap::new(),
+            lazy: false,
+            file: None,
         }
     }
     pub fn from_another(another: &MapArea) -> Self {
@@ -316,6 +1011,13 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type: another.map_type,
             map_perm: another.map_perm,
+            max_perm: another.max_perm,
+            sealed: another.sealed,
+            kind: another.kind,
+            shm: another.shm.clone(),
+            cached_frames: BTreeMap::new(),
+            lazy: another.lazy,
+            file: another.file.clone(),
         }
     }
     pub fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Option<()> {
@@ -325,29 +1027,70 @@ impl MapArea {
                 ppn = PhysPageNum(vpn.0);
             }
             MapType::Framed => {
-                let frame = frame_alloc()?;
-                ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                if self.lazy {
+                    let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+                    return page_table.try_map_lazy(vpn, pte_flags);
+                }
+                if let Some(frame) = self.cached_frames.get(&vpn) {
+                    let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
+                    return if self.map_perm.contains(MapPermission::W) {
+                        page_table.try_map_cow(vpn, frame.ppn, pte_flags)
+                    } else {
+                        page_table.try_map(vpn, frame.ppn, pte_flags)
+                    };
+                }
+                // frames handed in by insert_area_with_frames
+                if let Some(frame) = self.data_frames.get(&vpn) {
+                    ppn = frame.ppn;
+                } else {
+                    let frame = frame_alloc()?;
+                    ppn = frame.ppn;
+                    self.data_frames.insert(vpn, frame);
+                }
+            }
+            MapType::Shared => {
+                let index = vpn.0 - self.vpn_range.get_start().0;
+                ppn = self.shm.as_ref().unwrap().ppn(index);
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
-        page_table.map(vpn, ppn, pte_flags);
-        Some(())
+        page_table.try_map(vpn, ppn, pte_flags)
     }
 
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        if self.lazy {
+            if let Some(pte) = page_table.translate(vpn).filter(|pte| pte.is_valid()) {
+                // the PTE owned it since the first access
+                drop(FrameTracker { ppn: pte.ppn() });
+            }
+            page_table.clear(vpn);
+            return;
+        }
         #[allow(clippy::single_match)]
         match self.map_type {
             MapType::Framed => {
                 self.data_frames.remove(&vpn);
+                if let Some(frame) = self.cached_frames.remove(&vpn) {
+                    if let Some(copy) = page_table.private_copy(vpn, frame.ppn) {
+                        // the PTE owned it since the copy-on-write fault
+                        drop(FrameTracker { ppn: copy });
+                    }
+                }
             }
             _ => {}
         }
         page_table.unmap(vpn);
     }
+    /// Map every page of the area, rolling back the pages already mapped if
+    /// frames run out halfway.
     pub fn map(&mut self, page_table: &mut PageTable) -> Option<()> {
         for vpn in self.vpn_range {
-            self.map_one(page_table, vpn)?;
+            if self.map_one(page_table, vpn).is_none() {
+                for mapped in VPNRange::new(self.vpn_range.get_start(), vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return None;
+            }
         }
         Some(())
     }
@@ -356,6 +1099,89 @@ impl MapArea {
             self.unmap_one(page_table, vpn);
         }
     }
+    /// Extend the area down to `start`, mapping the new pages; nothing
+    /// changes if frames run out
+    fn grow_down(&mut self, page_table: &mut PageTable, start: VirtPageNum) -> Option<()> {
+        let old_start = self.vpn_range.get_start();
+        for vpn in VPNRange::new(start, old_start) {
+            if self.map_one(page_table, vpn).is_none() {
+                for mapped in VPNRange::new(start, vpn) {
+                    self.unmap_one(page_table, mapped);
+                }
+                return None;
+            }
+        }
+        self.vpn_range = VPNRange::new(start, self.vpn_range.get_end());
+        Some(())
+    }
+    /// Cut the area at `at`, returning the part from `at` on with its frames
+    fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let start = self.vpn_range.get_start();
+        let end = self.vpn_range.get_end();
+        self.vpn_range = VPNRange::new(start, at);
+        Self {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            max_perm: self.max_perm,
+            sealed: self.sealed,
+            kind: self.kind,
+            shm: self.shm.clone(),
+            cached_frames: self.cached_frames.split_off(&at),
+            lazy: self.lazy,
+            file: self.file.as_ref().map(|file| FileMap {
+                backing: file.backing.clone(),
+                offset: file.page_offset(at.0 - start.0),
+            }),
+        }
+    }
+    /// Write the pages of the area in `range` that were written back to the
+    /// file mapped there, if any
+    fn write_back(&self, page_table: &mut PageTable, range: VPNRange) {
+        let file = match self.file.as_ref() {
+            Some(file) => file,
+            None => return,
+        };
+        let start = self.vpn_range.get_start().max(range.get_start());
+        let end = self.vpn_range.get_end().min(range.get_end());
+        for vpn in VPNRange::new(start, end.max(start)) {
+            if let Some(frame) = page_table.take_dirty(vpn) {
+                file.write_page(vpn.0 - self.vpn_range.get_start().0, frame);
+            }
+        }
+    }
+    /// Rewrite the PTEs of the area for `permission`; pages still shared with
+    /// the page cache stay copy-on-write if it includes `W`
+    fn protect(&mut self, page_table: &mut PageTable, permission: MapPermission) {
+        self.map_perm = permission;
+        let pte_flags = PTEFlags::from_bits(permission.bits).unwrap();
+        for vpn in self.vpn_range {
+            let cow = permission.contains(MapPermission::W)
+                && self
+                    .cached_frames
+                    .get(&vpn)
+                    .map_or(false, |frame| page_table.private_copy(vpn, frame.ppn).is_none());
+            page_table.set_flags(vpn, pte_flags, cow);
+        }
+    }
+    /// Number of cached pages that have been replaced by a private copy
+    fn private_copies(&self, page_table: &PageTable) -> usize {
+        self.cached_frames
+            .iter()
+            .filter(|(vpn, frame)| page_table.private_copy(**vpn, frame.ppn).is_some())
+            .count()
+    }
+    /// Number of pages of a lazy area that have got their frame
+    fn populated(&self, page_table: &PageTable) -> usize {
+        if !self.lazy {
+            return 0;
+        }
+        self.vpn_range
+            .into_iter()
+            .filter(|vpn| page_table.translate(*vpn).map_or(false, |pte| pte.is_valid()))
+            .count()
+    }
     /// data: start-aligned but maybe with shorter length
     /// assume that all frames were cleared before
     pub fn copy_data(&mut self, page_table: &mut PageTable, data: &[u8]) {
@@ -385,6 +1211,152 @@ impl MapArea {
 pub enum MapType {
     Identical,
     Framed,
+    /// frames of a shared memory object, owned by the object
+    Shared,
+}
+
+#[repr(usize)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// what a user area is used for
+pub enum AreaKind {
+    /// a loadable segment of the program
+    Elf = 0,
+    /// a user stack; the main one grows down on faults below it
+    Stack = 1,
+    /// added by `sys_mmap`
+    Mmap = 2,
+    /// the page holding the TrapContext, or with `kstack-trap-cx` the trap
+    /// scratch of every hart
+    TrapContext = 3,
+    /// the trampoline page shared with the kernel
+    Trampoline = 4,
+    /// a shared memory object
+    Shm = 5,
+    /// grown and shrunk by `sys_sbrk`
+    Heap = 6,
+    /// a file mapped by `sys_mmap_file`
+    File = 7,
+}
+
+bitflags! {
+    /// State of one page, as reported by `sys_mincore`
+    pub struct PageState: u8 {
+        /// in one of the areas; without `RESIDENT`, a lazy page not touched yet
+        const MAPPED = 1 << 0;
+        /// backed by a frame
+        const RESIDENT = 1 << 1;
+        /// the frame is shared copy-on-write, a store gets a private copy
+        const COW = 1 << 2;
+        /// paged out; never set, as there is no swap
+        const SWAPPED = 1 << 3;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// the access a page fault was raised for
+pub enum FaultAccess {
+    Load,
+    Store,
+    /// instruction fetch
+    Fetch,
+}
+
+impl FaultAccess {
+    /// Whether a page mapped with `flags` allows the access
+    fn allowed(self, flags: PTEFlags) -> bool {
+        flags.contains(match self {
+            FaultAccess::Load => PTEFlags::R,
+            FaultAccess::Store => PTEFlags::W,
+            FaultAccess::Fetch => PTEFlags::X,
+        })
+    }
+}
+
+/// Percentage of the free frames that the pages of lazy areas may add up
+/// to, unless the `overcommit_ratio` boot argument says otherwise
+const DEFAULT_OVERCOMMIT_RATIO: usize = 100;
+
+/// More pages that can be promised before reaching the overcommit limit,
+/// a share of the free frames as there is no swap to count
+///
+/// Growing the heap or the stack, mapping memory or a file and fork are
+/// refused past it; fork counts both the pages it copies and the lazy pages
+/// the child inherits.
+pub fn commit_headroom() -> usize {
+    let ratio = bootarg("overcommit_ratio")
+        .and_then(|ratio| ratio.parse().ok())
+        .unwrap_or(DEFAULT_OVERCOMMIT_RATIO);
+    (super::frames_free() * ratio / 100).saturating_sub(lazy_pages())
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// what a user page fault calls for, decided by [`MemorySet::classify_fault`]
+pub enum PageFault {
+    /// first access to a page of a lazy area, which gets a zeroed frame
+    Lazy,
+    /// first access to a page of a file area, which gets a frame read from
+    /// the file
+    File,
+    /// store to a copy-on-write page, which gets a private copy
+    Cow,
+    /// access between the main stack and its limit, which grows the stack
+    /// down to the faulting page
+    StackGrowth,
+    /// no area there, or one the access is not allowed in: the task dies
+    Segfault,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// one line of the address space layout reported by `sys_get_maps`
+pub struct AreaInfo {
+    pub start: usize,
+    /// exclusive; 0 for the trampoline, which is the top page of the address space
+    pub end: usize,
+    /// [`MapPermission`] bits
+    pub perm: usize,
+    pub kind: AreaKind,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// reasons for refusing a user mapping request
+pub enum MapError {
+    /// unaligned start, empty or overflowing range, or illegal `port` bits (EINVAL)
+    InvalidArgument,
+    /// the range overlaps an existing area (EEXIST)
+    AlreadyMapped,
+    /// not enough frames to back the range (ENOMEM)
+    OutOfMemory,
+    /// there is no shared memory object of that name (ENOENT)
+    NotFound,
+    /// part of the range is not mapped (EFAULT, as ENOMEM is taken)
+    NotMapped,
+    /// the area is sealed or never had the requested permissions (EACCES)
+    PermissionDenied,
+}
+
+impl MapError {
+    /// The negated errno a syscall returns for it, a different one for
+    /// each variant
+    pub fn errno(self) -> isize {
+        -match self {
+            MapError::InvalidArgument => 22,
+            MapError::AlreadyMapped => 17,
+            MapError::OutOfMemory => 12,
+            MapError::NotFound => 2,
+            MapError::NotMapped => 14,
+            MapError::PermissionDenied => 13,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// reasons for failing to build an address space from an ELF image
+pub enum ElfError {
+    /// the image cannot be parsed or describes impossible segments (ENOEXEC)
+    Malformed(&'static str),
+    /// not enough frames for the segments, user stack or trap context (ENOMEM)
+    OutOfMemory,
 }
 
 bitflags! {
@@ -420,3 +1392,287 @@ pub fn remap_test() {
         .executable());
     info!("remap_test passed!");
 }
+
+/// Fork a space mapping a cached page copy-on-write, write to it from the
+/// kernel and check that only the writer's copy changed and nothing leaks
+pub fn cow_test() {
+    let free_before = super::frames_free();
+    let shared = Arc::new(frame_alloc().unwrap());
+    shared.ppn.get_bytes_array()[0] = 1;
+    let vpn = VirtPageNum(1);
+    let mut parent = MemorySet::new_bare();
+    let mut area = MapArea::new(
+        vpn.into(),
+        VirtPageNum(2).into(),
+        MapType::Framed,
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    );
+    area.cached_frames.insert(vpn, shared.clone());
+    parent.push(area, None).unwrap();
+    let child = MemorySet::from_existed_user(&parent).unwrap();
+    assert_eq!(child.translate(vpn).unwrap().ppn(), shared.ppn);
+    assert!(!child.translate(vpn).unwrap().writable());
+    *super::translated_refmut(parent.token(), usize::from(VirtAddr::from(vpn)) as *mut u8).unwrap() = 2;
+    let parent_pte = parent.translate(vpn).unwrap();
+    assert!(parent_pte.writable() && !parent_pte.is_cow());
+    assert_eq!(parent_pte.ppn().get_bytes_array()[0], 2);
+    assert_eq!(shared.ppn.get_bytes_array()[0], 1);
+    assert_eq!(child.translate(vpn).unwrap().ppn(), shared.ppn);
+    drop(parent);
+    drop(child);
+    drop(shared);
+    assert_eq!(super::frames_free(), free_before);
+    info!("cow_test passed!");
+}
+
+/// Map a cached page read-only, as program text is, and check that the
+/// kernel refuses to write it for user mode, leaving the cache frame as it was
+pub fn cached_text_test() {
+    let free_before = super::frames_free();
+    let shared = Arc::new(frame_alloc().unwrap());
+    shared.ppn.get_bytes_array()[0] = 1;
+    let vpn = VirtPageNum(1);
+    let va = usize::from(VirtAddr::from(vpn));
+    let mut memory_set = MemorySet::new_bare();
+    let mut area = MapArea::new(
+        vpn.into(),
+        VirtPageNum(2).into(),
+        MapType::Framed,
+        MapPermission::R | MapPermission::X | MapPermission::U,
+    );
+    area.cached_frames.insert(vpn, shared.clone());
+    memory_set.push(area, None).unwrap();
+    let token = memory_set.token();
+    // as `read(fd, <own text>, n)` would
+    assert!(super::translated_byte_buffer(token, va as *const u8, 16, true).is_none());
+    assert!(super::translated_refmut(token, va as *mut u8).is_none());
+    assert!(unsafe { super::copy_data_into_space(&2u8, token, va as *const u8) }.is_none());
+    assert!(!super::user_range_accessible(token, va, 16, true));
+    // still readable, as `write` needs
+    let buffers = super::translated_byte_buffer(token, va as *const u8, 16, false).unwrap();
+    assert_eq!(buffers[0][0], 1);
+    assert_eq!(memory_set.translate(vpn).unwrap().ppn(), shared.ppn);
+    assert_eq!(shared.ppn.get_bytes_array()[0], 1);
+    drop(memory_set);
+    drop(shared);
+    assert_eq!(super::frames_free(), free_before);
+    info!("cached_text_test passed!");
+}
+
+/// Unmap the head, the middle and the tail of a framed area and check that
+/// exactly the frames inside each range are freed and the rest stays mapped
+pub fn munmap_test() {
+    let free_before = super::frames_free();
+    let page = |i: usize| VirtAddr::from(VirtPageNum(i));
+    let mut memory_set = MemorySet::new_bare();
+    let permission = MapPermission::R | MapPermission::W | MapPermission::U;
+    memory_set
+        .insert_framed_area(page(0x10), page(0x18), permission)
+        .unwrap();
+    let unmap = |memory_set: &mut MemorySet, start: usize, end: usize| {
+        let free = super::frames_free();
+        memory_set.unmap_area(page(start), page(end)).unwrap();
+        assert_eq!(super::frames_free() - free, end - start);
+    };
+    let mapped = |memory_set: &MemorySet, i: usize| {
+        memory_set.translate(VirtPageNum(i)).map_or(false, |pte| pte.is_valid())
+    };
+    // head, middle, tail
+    unmap(&mut memory_set, 0x10, 0x12);
+    unmap(&mut memory_set, 0x13, 0x15);
+    unmap(&mut memory_set, 0x16, 0x18);
+    let left: Vec<bool> = (0x10..0x18).map(|i| mapped(&memory_set, i)).collect();
+    assert_eq!(left, [false, false, true, false, false, true, false, false]);
+    assert_eq!(memory_set.areas.len(), 2);
+    // neither a range across the hole nor an unaligned one
+    assert!(memory_set.unmap_area(page(0x12), page(0x16)).is_none());
+    assert!(memory_set.unmap_area(VirtAddr::from(0x12001), page(0x13)).is_none());
+    unmap(&mut memory_set, 0x12, 0x13);
+    unmap(&mut memory_set, 0x15, 0x16);
+    assert!(memory_set.areas.is_empty());
+    drop(memory_set);
+    assert_eq!(super::frames_free(), free_before);
+    info!("munmap_test passed!");
+}
+
+/// Grow a heap, touch one page from the kernel, fork, then shrink it and
+/// check that only touched pages ever get a frame and all of them are freed
+pub fn sbrk_test() {
+    let free_before = super::frames_free();
+    let bottom = usize::from(VirtAddr::from(VirtPageNum(0x20)));
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.heap_bottom = bottom;
+    memory_set.brk = bottom;
+    assert!(memory_set.sbrk(-1).is_none());
+    assert_eq!(memory_set.sbrk(PAGE_SIZE as isize + 1), Some(bottom));
+    assert_eq!(memory_set.sbrk(PAGE_SIZE as isize), Some(bottom + PAGE_SIZE + 1));
+    assert_eq!(memory_set.areas.len(), 1);
+    let frames = memory_set.frames_in_use();
+    let lazy = |memory_set: &MemorySet, i: usize| {
+        memory_set.translate(VirtPageNum(i)).map_or(false, |pte| pte.is_lazy())
+    };
+    assert!((0x20..0x23).all(|i| lazy(&memory_set, i)));
+    *super::translated_refmut(memory_set.token(), (bottom + PAGE_SIZE) as *mut u8).unwrap() = 7;
+    assert!(!lazy(&memory_set, 0x21));
+    assert_eq!(memory_set.frames_in_use(), frames + 1);
+    let child = MemorySet::from_existed_user(&memory_set).unwrap();
+    assert!(lazy(&child, 0x20) && lazy(&child, 0x22));
+    assert_eq!(child.translate(VirtPageNum(0x21)).unwrap().ppn().get_bytes_array()[0], 7);
+    // down into the first page, the touched one goes away
+    let brk = memory_set.brk;
+    assert_eq!(memory_set.sbrk(-((2 * PAGE_SIZE) as isize)), Some(brk));
+    assert!(lazy(&memory_set, 0x20));
+    assert!(memory_set.translate(VirtPageNum(0x21)).map_or(true, |pte| !pte.is_valid() && !pte.is_lazy()));
+    assert_eq!(memory_set.frames_in_use(), frames);
+    drop(memory_set);
+    drop(child);
+    assert_eq!(super::frames_free(), free_before);
+    info!("sbrk_test passed!");
+}
+
+/// Check that lazy heap pages count as promised until they get a frame or
+/// go away, and that neither the heap nor a copy of the space can grow past
+/// the overcommit limit
+pub fn overcommit_test() {
+    let promised = lazy_pages();
+    let bottom = usize::from(VirtAddr::from(VirtPageNum(0x20)));
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.heap_bottom = bottom;
+    memory_set.brk = bottom;
+    memory_set.sbrk(3 * PAGE_SIZE as isize).unwrap();
+    assert_eq!(lazy_pages(), promised + 3);
+    *super::translated_refmut(memory_set.token(), bottom as *mut u8).unwrap() = 1;
+    assert_eq!(lazy_pages(), promised + 2);
+    let brk = memory_set.brk;
+    let too_many = commit_headroom() + 1;
+    assert!(memory_set.sbrk((too_many * PAGE_SIZE) as isize).is_none());
+    assert_eq!(memory_set.brk, brk);
+    let child = MemorySet::from_existed_user(&memory_set).unwrap();
+    assert_eq!(lazy_pages(), promised + 4);
+    drop(child);
+    // promise all that is left, and there is none for a copy
+    memory_set.sbrk((commit_headroom() * PAGE_SIZE) as isize).unwrap();
+    assert!(MemorySet::from_existed_user(&memory_set).is_none());
+    drop(memory_set);
+    assert_eq!(lazy_pages(), promised);
+    info!("overcommit_test passed!");
+}
+
+/// Touch a lazy heap page from the kernel with no frame left for it, and
+/// check that the access fails and leaves the page lazy until frames return,
+/// and that a copy of the space fails rather than panics
+pub fn lazy_oom_test() {
+    let free_before = super::frames_free();
+    let bottom = usize::from(VirtAddr::from(VirtPageNum(0x20)));
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.heap_bottom = bottom;
+    memory_set.brk = bottom;
+    memory_set.sbrk(PAGE_SIZE as isize).unwrap();
+    let token = memory_set.token();
+    let mut hoard = Vec::new();
+    while let Some(frame) = frame_alloc() {
+        hoard.push(frame);
+    }
+    assert!(super::translated_refmut(token, bottom as *mut u8).is_none());
+    assert!(memory_set.translate(VirtPageNum(0x20)).unwrap().is_lazy());
+    assert!(MemorySet::from_existed_user(&memory_set).is_none());
+    drop(hoard);
+    *super::translated_refmut(token, bottom as *mut u8).unwrap() = 1;
+    assert!(memory_set.translate(VirtPageNum(0x20)).unwrap().is_valid());
+    drop(memory_set);
+    assert_eq!(super::frames_free(), free_before);
+    info!("lazy_oom_test passed!");
+}
+
+/// Classify faults on a lazy heap page, a cached copy-on-write page, below
+/// a stack and on an unmapped page, and resolve the first three
+pub fn page_fault_test() {
+    let free_before = super::frames_free();
+    let page = |i: usize| VirtAddr::from(VirtPageNum(i));
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.heap_bottom = page(0x20).into();
+    memory_set.brk = page(0x20).into();
+    memory_set.sbrk(PAGE_SIZE as isize).unwrap();
+    let shared = Arc::new(frame_alloc().unwrap());
+    let mut area = MapArea::new(
+        page(0x30),
+        page(0x31),
+        MapType::Framed,
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    );
+    area.cached_frames.insert(VirtPageNum(0x30), shared.clone());
+    memory_set.push(area, None).unwrap();
+    memory_set.stack_limit = page(0x4c).into();
+    let mut stack = MapArea::new(
+        page(0x50),
+        page(0x52),
+        MapType::Framed,
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    );
+    stack.kind = AreaKind::Stack;
+    memory_set.push(stack, None).unwrap();
+    let classify = |memory_set: &MemorySet, i: usize, access: FaultAccess| {
+        memory_set.classify_fault(page(i), access)
+    };
+    assert_eq!(classify(&memory_set, 0x20, FaultAccess::Store), PageFault::Lazy);
+    // the heap is not executable
+    assert_eq!(classify(&memory_set, 0x20, FaultAccess::Fetch), PageFault::Segfault);
+    assert_eq!(classify(&memory_set, 0x30, FaultAccess::Store), PageFault::Cow);
+    assert_eq!(classify(&memory_set, 0x30, FaultAccess::Load), PageFault::Segfault);
+    assert_eq!(classify(&memory_set, 0x40, FaultAccess::Load), PageFault::Segfault);
+    assert_eq!(classify(&memory_set, 0x4c, FaultAccess::Store), PageFault::StackGrowth);
+    assert_eq!(classify(&memory_set, 0x4c, FaultAccess::Fetch), PageFault::Segfault);
+    // the guard page below the limit
+    assert_eq!(classify(&memory_set, 0x4b, FaultAccess::Store), PageFault::Segfault);
+    let stack_frames = memory_set.frames_in_use();
+    assert_eq!(
+        memory_set.handle_page_fault(page(0x4e), FaultAccess::Store),
+        (PageFault::StackGrowth, true)
+    );
+    assert!((0x4e..0x52).all(|i| memory_set.translate(VirtPageNum(i)).map_or(false, |pte| pte.is_valid())));
+    assert_eq!(memory_set.frames_in_use(), stack_frames + 2);
+    assert_eq!(classify(&memory_set, 0x4e, FaultAccess::Store), PageFault::Segfault);
+    assert_eq!(classify(&memory_set, 0x4d, FaultAccess::Load), PageFault::StackGrowth);
+    assert_eq!(memory_set.handle_page_fault(page(0x20), FaultAccess::Load), (PageFault::Lazy, true));
+    assert_eq!(memory_set.handle_page_fault(page(0x30), FaultAccess::Store), (PageFault::Cow, true));
+    // both resolved for good
+    assert_eq!(classify(&memory_set, 0x20, FaultAccess::Store), PageFault::Segfault);
+    assert_eq!(classify(&memory_set, 0x30, FaultAccess::Store), PageFault::Segfault);
+    drop(memory_set);
+    drop(shared);
+    assert_eq!(super::frames_free(), free_before);
+    info!("page_fault_test passed!");
+}
+
+/// Check the page states of a heap with a touched and an untouched page,
+/// and of a cached page before and after its copy-on-write fault
+pub fn page_state_test() {
+    let bottom = usize::from(VirtAddr::from(VirtPageNum(0x20)));
+    let mut memory_set = MemorySet::new_bare();
+    memory_set.heap_bottom = bottom;
+    memory_set.brk = bottom;
+    memory_set.sbrk(2 * PAGE_SIZE as isize).unwrap();
+    *super::translated_refmut(memory_set.token(), (bottom + PAGE_SIZE) as *mut u8).unwrap() = 1;
+    assert_eq!(memory_set.page_state(VirtPageNum(0x20)), PageState::MAPPED);
+    assert_eq!(
+        memory_set.page_state(VirtPageNum(0x21)),
+        PageState::MAPPED | PageState::RESIDENT
+    );
+    assert_eq!(memory_set.page_state(VirtPageNum(0x22)), PageState::empty());
+    let vpn = VirtPageNum(1);
+    let mut area = MapArea::new(
+        vpn.into(),
+        VirtPageNum(2).into(),
+        MapType::Framed,
+        MapPermission::R | MapPermission::W | MapPermission::U,
+    );
+    area.cached_frames.insert(vpn, Arc::new(frame_alloc().unwrap()));
+    memory_set.push(area, None).unwrap();
+    assert_eq!(
+        memory_set.page_state(vpn),
+        PageState::MAPPED | PageState::RESIDENT | PageState::COW
+    );
+    *super::translated_refmut(memory_set.token(), usize::from(VirtAddr::from(vpn)) as *mut u8).unwrap() = 2;
+    assert_eq!(memory_set.page_state(vpn), PageState::MAPPED | PageState::RESIDENT);
+    info!("page_state_test passed!");
+}
@@ -8,18 +8,35 @@
 
 
 mod address;
+mod file_map;
 mod frame_allocator;
 mod heap_allocator;
+#[cfg(feature = "heap-sanitizer")]
+mod heap_sanitizer;
 mod memory_set;
+mod page_cache;
 mod page_table;
+mod shm;
+mod tlb;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+pub use file_map::FileBacking;
 use address::{StepByOne, VPNRange};
-pub use frame_allocator::{frame_alloc, FrameTracker};
-pub use memory_set::remap_test;
-pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
-pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTableEntry, copy_data_into_space};
+#[cfg(feature = "fs")]
+pub use frame_allocator::frame_alloc_contiguous;
+pub use frame_allocator::{frame_alloc, frames_free, scrub_free_frames, FrameTracker};
+pub use memory_set::{cached_text_test, cow_test, munmap_test, lazy_oom_test, overcommit_test, page_fault_test, page_state_test, remap_test, sbrk_test};
+pub use memory_set::{AreaInfo, AreaKind, ElfError, FaultAccess, MapError, MapPermission, MemorySet, PageFault, PageState, KERNEL_SPACE};
+pub use memory_set::{commit_headroom, thread_trap_cx};
+pub use page_table::translated_str_array;
+pub use page_table::UserBuffer;
+pub use page_table::lazy_pages;
+pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTableEntry, copy_data_from_space, copy_data_into_space, user_range_accessible, UserStrError};
 use page_table::{PTEFlags, PageTable};
+use page_cache::cached_page;
+#[cfg(feature = "fs")]
+pub use page_cache::evict_program;
+pub use shm::{shm_open, shm_unlink, ShmObject, SHM_MAX_PAGES};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
@@ -27,3 +44,8 @@ pub fn init() {
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.exclusive_access().activate();
 }
+
+/// Switch a hart other than the boot one to kernel space
+pub fn activate_kernel_space() {
+    KERNEL_SPACE.exclusive_access().activate();
+}
@@ -0,0 +1,62 @@
+//! Page cache for program images
+//!
+//! Loading a program copies every page of its loadable segments out of the
+//! ELF image. Shells start the same programs over and over, so the loaded
+//! pages are kept here keyed by (app name, virtual page) and every process
+//! running the program maps the same frames: read-only segments share them
+//! for good, writable ones map them copy-on-write and get a private copy of
+//! a page on the first store to it.
+
+use super::{frame_alloc, FrameTracker, VirtPageNum};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Most pages the cache keeps
+pub const PAGE_CACHE_MAX_PAGES: usize = 256;
+
+lazy_static! {
+    static ref PAGE_CACHE: UPSafeCell<BTreeMap<String, BTreeMap<VirtPageNum, Arc<FrameTracker>>>> =
+        unsafe { UPSafeCell::new_labeled("PAGE_CACHE", BTreeMap::new()) };
+}
+
+/// Page `vpn` of program `name` as loaded, where `data` is its contents in
+/// the ELF image: at most a page, shorter at the end of the file part of a
+/// segment, the rest is zero
+///
+/// A full cache first drops the pages no process maps any more; if that does
+/// not make room, the page is loaded into a frame the cache does not keep.
+pub fn cached_page(name: &str, vpn: VirtPageNum, data: &[u8]) -> Option<Arc<FrameTracker>> {
+    let mut cache = PAGE_CACHE.exclusive_access();
+    if let Some(frame) = cache.get(name).and_then(|pages| pages.get(&vpn)) {
+        return Some(frame.clone());
+    }
+    let frame = Arc::new(frame_alloc()?);
+    frame.ppn.get_bytes_array()[..data.len()].copy_from_slice(data);
+    if cached_pages(&cache) >= PAGE_CACHE_MAX_PAGES {
+        for pages in cache.values_mut() {
+            pages.retain(|_, frame| Arc::strong_count(frame) > 1);
+        }
+        cache.retain(|_, pages| !pages.is_empty());
+    }
+    if cached_pages(&cache) < PAGE_CACHE_MAX_PAGES {
+        cache
+            .entry(String::from(name))
+            .or_default()
+            .insert(vpn, frame.clone());
+    }
+    Some(frame)
+}
+
+/// Drop the pages of program `name`, whose image has changed; processes
+/// mapping them keep them
+#[cfg(feature = "fs")]
+pub fn evict_program(name: &str) {
+    PAGE_CACHE.exclusive_access().remove(name);
+}
+
+fn cached_pages(cache: &BTreeMap<String, BTreeMap<VirtPageNum, Arc<FrameTracker>>>) -> usize {
+    cache.values().map(|pages| pages.len()).sum()
+}
@@ -1,11 +1,12 @@
 //! Implementation of [`PageTableEntry`] and [`PageTable`].
 
-use super::{frame_alloc, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
+use super::{file_map, frame_alloc, tlb, FrameTracker, PhysAddr, PhysPageNum, StepByOne, VPNRange, VirtAddr, VirtPageNum};
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::intrinsics::size_of;
 use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use bitflags::*;
 
 bitflags! {
@@ -22,6 +23,24 @@ bitflags! {
     }
 }
 
+/// Software (RSW) bit marking a read-only mapping of a shared frame that
+/// gets a private, writable copy on the first store, see [`PageTable::break_cow`]
+const PTE_COW: usize = 1 << 8;
+
+/// Software bit of an invalid PTE: the page is mapped, but only gets its
+/// zeroed frame on first access, see [`PageTable::populate`]
+const PTE_LAZY: usize = 1 << 9;
+
+/// Kept by [`PageTable::try_map_lazy`], [`PageTable::populate`] and
+/// [`PageTable::clear`]
+static LAZY_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Pages of lazy areas, in all address spaces, that have not got their
+/// frame yet
+pub fn lazy_pages() -> usize {
+    LAZY_PAGES.load(Ordering::Relaxed)
+}
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 /// page table entry structure
@@ -56,6 +75,15 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+    pub fn is_cow(&self) -> bool {
+        self.is_valid() && self.bits & PTE_COW != 0
+    }
+    pub fn is_lazy(&self) -> bool {
+        !self.is_valid() && self.bits & PTE_LAZY != 0
+    }
 }
 
 /// page table structure
@@ -67,11 +95,15 @@ pub struct PageTable {
 /// Assume that it won't oom when creating/mapping.
 impl PageTable {
     pub fn new() -> Self {
-        let frame = frame_alloc().unwrap();
-        PageTable {
+        Self::try_new().unwrap()
+    }
+    /// Like [`PageTable::new`], but `None` when no frame is left for the root
+    pub fn try_new() -> Option<Self> {
+        let frame = frame_alloc()?;
+        Some(PageTable {
             root_ppn: frame.ppn,
             frames: vec![frame],
-        }
+        })
     }
     /// Temporarily used to get arguments from user space.
     pub fn from_token(satp: usize) -> Self {
@@ -91,7 +123,7 @@ impl PageTable {
                 break;
             }
             if !pte.is_valid() {
-                let frame = frame_alloc().unwrap();
+                let frame = frame_alloc()?;
                 *pte = PageTableEntry::new(frame.ppn, PTEFlags::V);
                 self.frames.push(frame);
             }
@@ -116,11 +148,136 @@ impl PageTable {
         }
         result
     }
+    fn find_pte_mut(&mut self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let idxs = vpn.indexes();
+        let mut ppn = self.root_ppn;
+        for (i, idx) in idxs.iter().enumerate() {
+            let pte = &mut ppn.get_pte_array()[*idx];
+            if i == 2 {
+                return Some(pte);
+            }
+            if !pte.is_valid() {
+                return None;
+            }
+            ppn = pte.ppn();
+        }
+        unreachable!()
+    }
     #[allow(unused)]
     pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_pte_create(vpn).unwrap();
+        self.try_map(vpn, ppn, flags)
+            .expect("out of frames for page table");
+    }
+    /// Like [`PageTable::map`], but `None` when a page table frame cannot be allocated
+    pub fn try_map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Option<()> {
+        let pte = self.find_pte_create(vpn)?;
         assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        Some(())
+    }
+    /// Like [`PageTable::try_map`], but map `ppn` without `W` and marked
+    /// copy-on-write
+    pub fn try_map_cow(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) -> Option<()> {
+        self.try_map(vpn, ppn, flags - PTEFlags::W)?;
+        self.find_pte_mut(vpn).unwrap().bits |= PTE_COW;
+        Some(())
+    }
+    /// Allocate the page tables down to the PTE of `vpn` without mapping it,
+    /// so that a later [`PageTable::try_map`] of it cannot fail
+    pub fn reserve(&mut self, vpn: VirtPageNum) -> Option<()> {
+        self.find_pte_create(vpn).map(|_| ())
+    }
+    /// Map `vpn` with `flags` but without a frame yet, see [`PageTable::populate`]
+    pub fn try_map_lazy(&mut self, vpn: VirtPageNum, flags: PTEFlags) -> Option<()> {
+        let pte = self.find_pte_create(vpn)?;
+        assert!(!pte.is_valid(), "vpn {:?} is mapped before mapping", vpn);
+        *pte = PageTableEntry {
+            bits: (flags - PTEFlags::V).bits as usize | PTE_LAZY,
+        };
+        LAZY_PAGES.fetch_add(1, Ordering::Relaxed);
+        Some(())
+    }
+    /// Give the lazily mapped `vpn` a zeroed frame, or one read from the
+    /// file mapped there, see [`file_map`]. `None` if `vpn` is not waiting
+    /// for one or no frame is left.
+    ///
+    /// Like a copy-on-write copy, the frame is owned by the PTE from then on.
+    pub fn populate(&mut self, vpn: VirtPageNum) -> Option<()> {
+        let root = self.root_ppn;
+        let pte = self.find_pte_mut(vpn).filter(|pte| pte.is_lazy())?;
+        let frame = frame_alloc()?;
+        file_map::fill(root, vpn, frame.ppn);
+        *pte = PageTableEntry::new(frame.ppn, pte.flags() | PTEFlags::V);
+        core::mem::forget(frame);
+        LAZY_PAGES.fetch_sub(1, Ordering::Relaxed);
+        Some(())
+    }
+    /// Clear the PTE of `vpn` whether it is valid or lazy
+    pub fn clear(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_pte_mut(vpn) {
+            if pte.is_lazy() {
+                LAZY_PAGES.fetch_sub(1, Ordering::Relaxed);
+            }
+            *pte = PageTableEntry::empty();
+        }
+    }
+    /// Replace the copy-on-write mapping of `vpn` by a private, writable copy
+    /// of its frame. `None` if `vpn` is not copy-on-write or no frame is left.
+    ///
+    /// The copy is owned by the PTE from then on: whoever owns the mapping has
+    /// to free it on unmap, see [`PageTable::private_copy`].
+    pub fn break_cow(&mut self, vpn: VirtPageNum) -> Option<()> {
+        let pte = self.find_pte_mut(vpn).filter(|pte| pte.is_cow())?;
+        let frame = frame_alloc()?;
+        frame
+            .ppn
+            .get_bytes_array()
+            .copy_from_slice(pte.ppn().get_bytes_array());
+        *pte = PageTableEntry::new(frame.ppn, pte.flags() | PTEFlags::W);
+        core::mem::forget(frame);
+        Some(())
+    }
+    /// Replace the flags of the mapped page `vpn`, keeping its frame; `cow`
+    /// maps it copy-on-write like [`PageTable::try_map_cow`]
+    pub fn set_flags(&mut self, vpn: VirtPageNum, flags: PTEFlags, cow: bool) {
+        let pte = self
+            .find_pte_mut(vpn)
+            .filter(|pte| pte.is_valid() || pte.is_lazy())
+            .unwrap();
+        if pte.is_lazy() {
+            pte.bits = (flags - PTEFlags::V).bits as usize | PTE_LAZY;
+        } else if cow {
+            *pte = PageTableEntry::new(pte.ppn(), (flags - PTEFlags::W) | PTEFlags::V);
+            pte.bits |= PTE_COW;
+        } else {
+            *pte = PageTableEntry::new(pte.ppn(), flags | PTEFlags::V);
+        }
+    }
+    /// The frame [`PageTable::break_cow`] gave `vpn`, if it replaced `shared`
+    pub fn private_copy(&self, vpn: VirtPageNum, shared: PhysPageNum) -> Option<PhysPageNum> {
+        self.translate(vpn)
+            .filter(|pte| pte.is_valid() && pte.ppn() != shared)
+            .map(|pte| pte.ppn())
+    }
+    /// Mark the mapped page `vpn` written, as the hardware does on a store
+    /// from user mode, for a store of the kernel
+    fn mark_dirty(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_pte_mut(vpn).filter(|pte| pte.is_valid()) {
+            pte.bits |= PTEFlags::D.bits as usize;
+        }
+    }
+    /// The frame of `vpn` if it was written since it got it or since the
+    /// last call, clearing its dirty bit; the caller shoots down the TLB
+    pub fn take_dirty(&mut self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        let pte = self
+            .find_pte_mut(vpn)
+            .filter(|pte| pte.is_valid() && pte.flags().contains(PTEFlags::D))?;
+        pte.bits &= !(PTEFlags::D.bits as usize);
+        Some(pte.ppn())
+    }
+    /// The root of the page table, which [`file_map`] knows it by
+    pub fn root_ppn(&self) -> PhysPageNum {
+        self.root_ppn
     }
     #[allow(unused)]
     pub fn unmap(&mut self, vpn: VirtPageNum) {
@@ -144,18 +301,62 @@ impl PageTable {
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
+    /// Number of frames holding the page table itself
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
+/// Whether the kernel may access the page of `pte` on behalf of user mode,
+/// see [`user_range_accessible`]
+fn user_accessible(pte: &PageTableEntry, write: bool) -> bool {
+    (pte.is_valid() || pte.is_lazy())
+        && pte.is_user()
+        && pte.readable()
+        && (!write || pte.writable() || pte.is_cow())
+}
+
+/// Make sure the kernel may access `vpn` on behalf of user mode, and
+/// writing to it too if `write`, and return its frame; `None` if it may
+/// not, or there is no frame to give the page
+///
+/// The kernel is not stopped by a missing `U` or `W` itself, so both are
+/// checked here: a read-only page may well be a frame of the page cache
+/// shared by every process running that program. Lazy pages get their
+/// frame, and copy-on-write pages about to be written their private copy.
+fn prepare_access(page_table: &mut PageTable, vpn: VirtPageNum, write: bool) -> Option<PhysPageNum> {
+    let pte = page_table.translate(vpn).filter(|pte| user_accessible(pte, write))?;
+    if pte.is_lazy() {
+        page_table.populate(vpn)?;
+    }
+    if write && page_table.translate(vpn).map_or(false, |pte| pte.is_cow()) {
+        page_table.break_cow(vpn)?;
+        tlb::shootdown(VPNRange::new(vpn, VirtPageNum(vpn.0 + 1)));
+    }
+    if write {
+        // a file mapped there has to get what the kernel writes
+        page_table.mark_dirty(vpn);
+    }
+    page_table.translate(vpn).map(|pte| pte.ppn())
+}
+
+/// translate a pointer to a mutable u8 Vec through page table, checking
+/// every page as [`user_range_accessible`] does; `write` if the kernel is
+/// going to write the buffer, as for `read`
+pub fn translated_byte_buffer(
+    token: usize,
+    ptr: *const u8,
+    len: usize,
+    write: bool,
+) -> Option<Vec<&'static mut [u8]>> {
+    let mut page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
-    let end = start + len;
+    let end = start.checked_add(len)?;
     let mut v = Vec::new();
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.floor();
-        let ppn = page_table.translate(vpn).unwrap().ppn();
+        let ppn = prepare_access(&mut page_table, vpn, write)?;
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -166,45 +367,162 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Vec<&
         }
         start = end_va.into();
     }
-    v
+    Some(v)
 }
 
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
-    let page_table = PageTable::from_token(token);
+/// A user-space buffer, as its pieces in each page
+pub struct UserBuffer {
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        Self { buffers }
+    }
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Its bytes, in order
+    pub fn bytes(self) -> impl Iterator<Item = &'static mut u8> {
+        self.buffers.into_iter().flatten()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// reasons for failing to copy a NUL-terminated string out of user space
+pub enum UserStrError {
+    /// part of the string lies in an unmapped page
+    Unmapped,
+    /// the page is mapped, but not readable from user mode
+    NoPermission,
+    /// no NUL terminator within the allowed length
+    TooLong,
+}
+
+/// Copy a NUL-terminated string out of user space.
+///
+/// At most `max_len` bytes (excluding the terminator) are read, and only
+/// through valid, user-readable mappings, so a bogus pointer can never make
+/// the kernel walk off into unmapped or kernel-only memory.
+pub fn translated_str(token: usize, ptr: *const u8, max_len: usize) -> Result<String, UserStrError> {
+    let mut page_table = PageTable::from_token(token);
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
-        } else {
+        let start_va = VirtAddr::from(va);
+        if page_table.translate(start_va.floor()).map_or(false, |pte| pte.is_lazy()) {
+            page_table.populate(start_va.floor()).ok_or(UserStrError::Unmapped)?;
+        }
+        let pte = page_table
+            .translate(start_va.floor())
+            .filter(|pte| pte.is_valid())
+            .ok_or(UserStrError::Unmapped)?;
+        if !pte.readable() || !pte.is_user() {
+            return Err(UserStrError::NoPermission);
+        }
+        for &ch in &pte.ppn().get_bytes_array()[start_va.page_offset()..] {
+            if ch == 0 {
+                return Ok(string);
+            }
+            if string.len() >= max_len {
+                return Err(UserStrError::TooLong);
+            }
             string.push(ch as char);
             va += 1;
         }
     }
-    string
 }
 
-pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> &'static mut T {
-    //println!("into translated_refmut!");
+/// Copy a null-terminated array of pointers to NUL-terminated strings, as
+/// `argv` is passed, out of user space; a null `ptr` is an empty array
+///
+/// At most `max_count` strings of at most `max_len` bytes each are read,
+/// each checked as [`translated_str`] does.
+pub fn translated_str_array(
+    token: usize,
+    ptr: *const usize,
+    max_count: usize,
+    max_len: usize,
+) -> Result<Vec<String>, UserStrError> {
+    let mut strings = Vec::new();
+    if ptr.is_null() {
+        return Ok(strings);
+    }
+    loop {
+        let slot = (ptr as usize).wrapping_add(strings.len() * size_of::<usize>());
+        if !user_range_accessible(token, slot, size_of::<usize>(), false) {
+            return Err(UserStrError::Unmapped);
+        }
+        let string = unsafe { copy_data_from_space(token, slot as *const usize) }
+            .ok_or(UserStrError::Unmapped)?;
+        if string == 0 {
+            return Ok(strings);
+        }
+        if strings.len() == max_count {
+            return Err(UserStrError::TooLong);
+        }
+        strings.push(translated_str(token, string as *const u8, max_len)?);
+    }
+}
+
+/// Whether the kernel may read `[ptr, ptr + len)` of user space through the
+/// helpers here, and write it too if `write`
+///
+/// Every page must be mapped for user mode, or be waiting for its frame;
+/// copy-on-write pages count as writable. Only faults of user mode grow
+/// the stack, so a buffer in the part of it not grown into yet is refused
+/// like any other unmapped one.
+pub fn user_range_accessible(token: usize, ptr: usize, len: usize, write: bool) -> bool {
+    // user space is the lower half of the Sv39 address space
+    const USER_SPACE_END: usize = 1 << 38;
+    let end = match ptr.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => end,
+        _ => return false,
+    };
     let page_table = PageTable::from_token(token);
-    let va = ptr as usize;
-    //println!("translated_refmut: before translate_va");
-    page_table
-        .translate_va(VirtAddr::from(va))
-        .unwrap()
-        .get_mut()
+    let mut vpn = VirtAddr::from(ptr).floor();
+    while usize::from(VirtAddr::from(vpn)) < end {
+        if !page_table.translate(vpn).map_or(false, |pte| user_accessible(&pte, write)) {
+            return false;
+        }
+        vpn.step();
+    }
+    true
+}
+
+/// A `T` of user space the kernel is going to write, if it may
+pub fn translated_refmut<T>(token: usize, ptr: *mut T) -> Option<&'static mut T> {
+    let mut page_table = PageTable::from_token(token);
+    let va = VirtAddr::from(ptr as usize);
+    prepare_access(&mut page_table, va.floor(), true)?;
+    page_table.translate_va(va).map(|pa| pa.get_mut())
 }
 
-pub unsafe fn copy_data_into_space<T>(data: &T, token: usize, ptr: *const T) {
+/// Copy `data` into user space, even if it straddles a page boundary;
+/// `None` if the pages are not all writable from user mode
+pub unsafe fn copy_data_into_space<T>(data: &T, token: usize, ptr: *const T) -> Option<()> {
     let mut i = 0;
     let data = slice::from_raw_parts(data as *const _ as *const u8, size_of::<T>());
-    let buffers = translated_byte_buffer(token, ptr as *const u8, size_of::<T>());
+    let buffers = translated_byte_buffer(token, ptr as *const u8, size_of::<T>(), true)?;
     for buffer in buffers {
-        buffer.copy_from_slice(&data[i..]);
+        buffer.copy_from_slice(&data[i..i + buffer.len()]);
+        i += buffer.len();
+    }
+    Some(())
+}
+
+/// Copy a `T` out of user space, even if it straddles a page boundary;
+/// `None` if the pages are not all readable from user mode
+pub unsafe fn copy_data_from_space<T>(token: usize, ptr: *const T) -> Option<T> {
+    let mut data = core::mem::MaybeUninit::<T>::uninit();
+    let dst = slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, size_of::<T>());
+    let mut i = 0;
+    for buffer in translated_byte_buffer(token, ptr as *const u8, size_of::<T>(), false)? {
+        dst[i..i + buffer.len()].copy_from_slice(buffer);
         i += buffer.len();
     }
+    Some(data.assume_init())
 }
@@ -0,0 +1,63 @@
+//! Named shared memory objects
+//!
+//! An object is a set of frames registered under a name. Any process can
+//! open it by name and map it with [`MemorySet::insert_shared_area`]; every
+//! mapping holds an [`Arc`] to the object, so the frames live until the name
+//! is unlinked and the last mapping is gone.
+//!
+//! [`MemorySet::insert_shared_area`]: super::MemorySet::insert_shared_area
+
+use super::{frame_alloc, FrameTracker, PhysPageNum};
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Most pages one object may have
+pub const SHM_MAX_PAGES: usize = 256;
+
+#[derive(Debug)]
+pub struct ShmObject {
+    frames: Vec<FrameTracker>,
+}
+
+impl ShmObject {
+    fn new(pages: usize) -> Option<Self> {
+        let frames = (0..pages).map(|_| frame_alloc()).collect::<Option<Vec<_>>>()?;
+        Some(Self { frames })
+    }
+    pub fn pages(&self) -> usize {
+        self.frames.len()
+    }
+    /// Frame backing page `index` of the object
+    pub fn ppn(&self, index: usize) -> PhysPageNum {
+        self.frames[index].ppn
+    }
+}
+
+lazy_static! {
+    static ref SHM_OBJECTS: UPSafeCell<BTreeMap<String, Arc<ShmObject>>> =
+        unsafe { UPSafeCell::new_labeled("SHM_OBJECTS", BTreeMap::new()) };
+}
+
+/// Look up the object called `name`, creating it with `pages` zeroed pages
+/// if it does not exist and `create` is set
+pub fn shm_open(name: &str, pages: usize, create: bool) -> Option<Arc<ShmObject>> {
+    let mut objects = SHM_OBJECTS.exclusive_access();
+    if let Some(object) = objects.get(name) {
+        return Some(object.clone());
+    }
+    if !create || pages == 0 || pages > SHM_MAX_PAGES {
+        return None;
+    }
+    let object = Arc::new(ShmObject::new(pages)?);
+    objects.insert(String::from(name), object.clone());
+    Some(object)
+}
+
+/// Remove `name`; existing mappings keep the object alive
+pub fn shm_unlink(name: &str) -> Option<()> {
+    SHM_OBJECTS.exclusive_access().remove(name).map(|_| ())
+}
@@ -0,0 +1,31 @@
+//! TLB shootdown after a mapping is changed or removed.
+//!
+//! Every call site that invalidates a PTE must go through [`shootdown`]
+//! before the frames behind it can be reused, so that no hart keeps using the
+//! stale translation.
+
+use super::{VPNRange, VirtAddr};
+use crate::hart::other_harts;
+use crate::sbi::remote_sfence_vma;
+
+/// Invalidate the translations of `vpn_range` on every hart that may cache them.
+///
+/// `__alltraps`/`__restore` already flush the whole TLB whenever `satp`
+/// changes, so a targeted local `sfence.vma` per page covers the
+/// kernel-space case and is cheap for user spaces. Other harts may be
+/// running a thread of the same address space, so every other online hart
+/// is made to flush the range through the SBI, which waits for them.
+pub fn shootdown(vpn_range: VPNRange) {
+    for vpn in vpn_range {
+        let va: usize = VirtAddr::from(vpn).into();
+        unsafe {
+            core::arch::asm!("sfence.vma {va}, zero", va = in(reg) va);
+        }
+    }
+    let others = other_harts();
+    if others != 0 {
+        let start: usize = VirtAddr::from(vpn_range.get_start()).into();
+        let end: usize = VirtAddr::from(vpn_range.get_end()).into();
+        remote_sfence_vma(others, start, end - start);
+    }
+}
@@ -0,0 +1,45 @@
+//! Kernel randomness, for the `AT_RANDOM` bytes of new processes
+//!
+//! There is no hardware generator to read. A single 64-bit state is seeded
+//! from the `rng-seed` property of `/chosen` when the device tree has one,
+//! and every draw stirs the cycle counter in, so the bytes depend on when
+//! each process starts as well. That is unpredictable enough for stack
+//! canaries and hash seeds in user space, but it is not cryptographic.
+
+use crate::timer::get_time;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Only touched with the kernel lock held
+static STATE: AtomicU64 = AtomicU64::new(GOLDEN_GAMMA);
+
+/// The splitmix64 finalizer: every output bit depends on every input bit
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Stir `bytes` into the state, on top of what is already there
+pub fn add_entropy(bytes: &[u8]) {
+    for chunk in bytes.chunks(8) {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let state = STATE.load(Ordering::Relaxed);
+        STATE.store(mix(state ^ u64::from_le_bytes(word)), Ordering::Relaxed);
+    }
+}
+
+fn next() -> u64 {
+    let state = STATE.load(Ordering::Relaxed).wrapping_add(GOLDEN_GAMMA) ^ get_time() as u64;
+    STATE.store(state, Ordering::Relaxed);
+    mix(state)
+}
+
+/// Fill `buf` with random bytes
+pub fn fill_random(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        chunk.copy_from_slice(&next().to_le_bytes()[..chunk.len()]);
+    }
+}
@@ -5,7 +5,14 @@
 const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
+const SBI_REMOTE_FENCE_I: usize = 5;
+const SBI_REMOTE_SFENCE_VMA: usize = 6;
 const SBI_SHUTDOWN: usize = 8;
+/// Hart state management extension and its functions
+const SBI_EXT_HSM: usize = 0x48534d;
+const SBI_HSM_HART_START: usize = 0;
+const SBI_HSM_HART_STOP: usize = 1;
+const SBI_HSM_HART_GET_STATUS: usize = 2;
 
 #[inline(always)]
 /// general sbi call
@@ -24,6 +31,24 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     ret
 }
 
+#[inline(always)]
+/// sbi call of function `fid` of the extension `which`, returning the
+/// error and the value
+fn sbi_call_fid(which: usize, fid: usize, arg0: usize, arg1: usize, arg2: usize) -> (isize, usize) {
+    let (error, value);
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("x10") arg0 => error,
+            inlateout("x11") arg1 => value,
+            in("x12") arg2,
+            in("x16") fid,
+            in("x17") which,
+        );
+    }
+    (error, value)
+}
+
 /// use sbi call to set timer
 pub fn set_timer(timer: usize) {
     sbi_call(SBI_SET_TIMER, timer, 0, 0);
@@ -39,6 +64,43 @@ pub fn console_getchar() -> usize {
     sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
 }
 
+/// use sbi call to start hart `hartid` in supervisor mode at `start`,
+/// returning whether it was started
+pub fn hart_start(hartid: usize, start: usize, opaque: usize) -> bool {
+    sbi_call_fid(SBI_EXT_HSM, SBI_HSM_HART_START, hartid, start, opaque).0 == 0
+}
+
+/// use sbi call to stop the calling hart, which only returns if the SBI
+/// refuses
+pub fn hart_stop() {
+    sbi_call_fid(SBI_EXT_HSM, SBI_HSM_HART_STOP, 0, 0, 0);
+}
+
+/// use sbi call to get the HSM state of hart `hartid`, `None` if the
+/// machine has no such hart
+pub fn hart_get_status(hartid: usize) -> Option<usize> {
+    match sbi_call_fid(SBI_EXT_HSM, SBI_HSM_HART_GET_STATUS, hartid, 0, 0) {
+        (0, state) => Some(state),
+        _ => None,
+    }
+}
+
+/// use sbi call to run `fence.i` on the harts in `hart_mask`
+pub fn remote_fence_i(hart_mask: usize) {
+    sbi_call(SBI_REMOTE_FENCE_I, &hart_mask as *const _ as usize, 0, 0);
+}
+
+/// use sbi call to run `sfence.vma` for `[start, start + size)` on the harts
+/// in `hart_mask`
+pub fn remote_sfence_vma(hart_mask: usize, start: usize, size: usize) {
+    sbi_call(
+        SBI_REMOTE_SFENCE_VMA,
+        &hart_mask as *const _ as usize,
+        start,
+        size,
+    );
+}
+
 /// use sbi call to shutdown the kernel
 pub fn shutdown() -> ! {
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
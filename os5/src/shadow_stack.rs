@@ -0,0 +1,125 @@
+//! A software shadow stack of kernel return addresses
+//!
+//! With the `shadow-stack` feature, [`shadow_guard!`] at the top of a
+//! function records the return address saved in its frame on a shadow
+//! stack of the current task, and checks on the way out that the saved one
+//! has not changed meanwhile. A mismatch means the kernel stack was written
+//! over, and panics naming the guarded function rather than returning to
+//! wherever the overwritten address points. Without the feature the guard
+//! expands to nothing.
+//!
+//! Kernel code is built with frame pointers, so the return address of a
+//! function sits right below the address `s0` holds. Only the hot trap
+//! paths are guarded.
+
+#[cfg(feature = "shadow-stack")]
+use core::panic::Location;
+#[cfg(feature = "shadow-stack")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "shadow-stack")]
+/// Nested guarded frames recorded per task, deeper ones go unchecked
+const SHADOW_DEPTH: usize = 16;
+
+/// Guard the return address of the enclosing function until it returns
+#[cfg(feature = "shadow-stack")]
+#[macro_export]
+macro_rules! shadow_guard {
+    () => {
+        let _shadow_guard = {
+            let fp: usize;
+            unsafe {
+                core::arch::asm!("mv {}, s0", out(reg) fp);
+                $crate::shadow_stack::ShadowGuard::enter(fp)
+            }
+        };
+    };
+}
+
+#[cfg(not(feature = "shadow-stack"))]
+#[macro_export]
+macro_rules! shadow_guard {
+    () => {};
+}
+
+#[cfg(feature = "shadow-stack")]
+/// Return addresses of the guarded frames of one task, only ever touched by
+/// the task itself, atomic so that no borrow is needed
+pub struct ShadowStack {
+    /// Guarded frames entered, possibly more than are recorded
+    depth: AtomicUsize,
+    frames: [AtomicUsize; SHADOW_DEPTH],
+    return_addrs: [AtomicUsize; SHADOW_DEPTH],
+}
+
+#[cfg(feature = "shadow-stack")]
+impl ShadowStack {
+    pub fn new() -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            frames: core::array::from_fn(|_| AtomicUsize::new(0)),
+            return_addrs: core::array::from_fn(|_| AtomicUsize::new(0)),
+        }
+    }
+}
+
+#[cfg(feature = "shadow-stack")]
+/// Where a frame with frame pointer `fp` keeps its return address
+fn saved_ra(fp: usize) -> usize {
+    unsafe { ((fp - 8) as *const usize).read_volatile() }
+}
+
+#[cfg(feature = "shadow-stack")]
+/// A guarded frame, checked when dropped at the end of its function
+pub struct ShadowGuard {
+    fp: usize,
+    site: &'static Location<'static>,
+}
+
+#[cfg(feature = "shadow-stack")]
+impl ShadowGuard {
+    /// Record the frame with frame pointer `fp`, `None` outside of a task
+    ///
+    /// # Safety
+    ///
+    /// `fp` is the frame pointer of the caller, see [`shadow_guard!`].
+    #[track_caller]
+    pub unsafe fn enter(fp: usize) -> Option<Self> {
+        let stack = crate::task::current_shadow_stack()?;
+        let depth = stack.depth.fetch_add(1, Ordering::Relaxed);
+        if depth < SHADOW_DEPTH {
+            stack.frames[depth].store(fp, Ordering::Relaxed);
+            stack.return_addrs[depth].store(saved_ra(fp), Ordering::Relaxed);
+        }
+        Some(Self {
+            fp,
+            site: Location::caller(),
+        })
+    }
+}
+
+#[cfg(feature = "shadow-stack")]
+impl Drop for ShadowGuard {
+    fn drop(&mut self) {
+        let stack = crate::task::current_shadow_stack().unwrap();
+        let depth = stack.depth.fetch_sub(1, Ordering::Relaxed) - 1;
+        if depth >= SHADOW_DEPTH {
+            return;
+        }
+        let fp = stack.frames[depth].load(Ordering::Relaxed);
+        if fp != self.fp {
+            panic!(
+                "[kernel] shadow stack: frame guarded at {} is at {:#x}, expected {:#x}",
+                self.site, self.fp, fp
+            );
+        }
+        let expected = stack.return_addrs[depth].load(Ordering::Relaxed);
+        let ra = saved_ra(fp);
+        if ra != expected {
+            panic!(
+                "[kernel] shadow stack: return address of the frame guarded at {} was overwritten: {:#x}, expected {:#x}",
+                self.site, ra, expected
+            );
+        }
+    }
+}
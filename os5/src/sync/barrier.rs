@@ -0,0 +1,31 @@
+//! Hart-wide memory barriers
+
+use crate::hart::other_harts;
+use crate::sbi::{remote_fence_i, remote_sfence_vma};
+
+bitflags! {
+    /// commands accepted by `sys_membarrier`, numbered as in Linux
+    pub struct MembarrierCmd: u32 {
+        /// make all harts observe preceding memory accesses and page-table updates
+        const GLOBAL = 1 << 0;
+        /// same as `GLOBAL`, limited to harts running the caller's address space
+        const PRIVATE_EXPEDITED = 1 << 3;
+        /// additionally resynchronize the instruction stream (for JITs)
+        const PRIVATE_EXPEDITED_SYNC_CORE = 1 << 5;
+    }
+}
+
+/// Execute a full `fence`, `fence.i` and `sfence.vma` on every hart.
+///
+/// The calling hart fences itself, the other online ones are made to by
+/// the SBI, which returns once they have.
+pub fn membarrier() {
+    unsafe {
+        core::arch::asm!("fence rw, rw", "fence.i", "sfence.vma");
+    }
+    let others = other_harts();
+    if others != 0 {
+        remote_fence_i(others);
+        remote_sfence_vma(others, 0, usize::MAX);
+    }
+}
@@ -0,0 +1,58 @@
+//! Keeping supervisor interrupts off while kernel state is borrowed
+//!
+//! An [`InterruptGuard`] clears `sstatus.SIE` when it is made and sets it
+//! again when the last one alive on the hart is dropped, if it was set
+//! before the first. Guards may nest and be dropped in any order, only the
+//! count on each hart matters.
+
+use crate::config::MAX_HARTS;
+use crate::hart::hart_id;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use riscv::register::sstatus;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const FALSE: AtomicBool = AtomicBool::new(false);
+
+/// Guards alive on each hart
+static DEPTH: [AtomicUsize; MAX_HARTS] = [ZERO; MAX_HARTS];
+/// Whether interrupts were on before the outermost guard of each hart
+static WERE_ENABLED: [AtomicBool; MAX_HARTS] = [FALSE; MAX_HARTS];
+
+/// Supervisor interrupts stay off on this hart while one is alive
+pub struct InterruptGuard {
+    /// it belongs to the hart it was made on
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        let enabled = sstatus::read().sie();
+        unsafe {
+            sstatus::clear_sie();
+        }
+        // nothing can get in between now, the hart is ours
+        let hart = hart_id();
+        if DEPTH[hart].fetch_add(1, Ordering::Relaxed) == 0 {
+            WERE_ENABLED[hart].store(enabled, Ordering::Relaxed);
+        }
+        Self {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        let hart = hart_id();
+        if DEPTH[hart].fetch_sub(1, Ordering::Relaxed) == 1
+            && WERE_ENABLED[hart].load(Ordering::Relaxed)
+        {
+            unsafe {
+                sstatus::set_sie();
+            }
+        }
+    }
+}
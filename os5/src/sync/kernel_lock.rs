@@ -0,0 +1,56 @@
+//! The big kernel lock, which keeps one hart at a time in the kernel
+//!
+//! Most kernel state lives in [`UPSafeCell`](super::UPSafeCell)s, which are
+//! only sound while a single hart runs kernel code. With several harts up
+//! that stays true by having each of them hold [`KERNEL_LOCK`] whenever it is
+//! in the kernel: `trap_handler` takes it on entry, `trap_return` gives it up
+//! just before going back to user space, and the idle loop holds it except
+//! while it waits for work. User code runs on all harts in parallel.
+//!
+//! A hart switches tasks holding the lock, so it belongs to the hart rather
+//! than to a guard; a ticket lock lets waiting harts in in the order they
+//! came.
+
+use crate::hart::hart_id;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// `holder` of a lock no hart holds
+const NO_HART: usize = usize::MAX;
+
+pub struct KernelLock {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    /// hart holding the lock, for checking `unlock`
+    holder: AtomicUsize,
+}
+
+impl KernelLock {
+    const fn new() -> Self {
+        Self {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            holder: AtomicUsize::new(NO_HART),
+        }
+    }
+
+    pub fn lock(&self) {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+        self.holder.store(hart_id(), Ordering::Relaxed);
+    }
+
+    pub fn unlock(&self) {
+        let holder = self.holder.swap(NO_HART, Ordering::Relaxed);
+        assert_eq!(
+            holder,
+            hart_id(),
+            "the kernel lock is not held by this hart"
+        );
+        self.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+pub static KERNEL_LOCK: KernelLock = KernelLock::new();
@@ -1,5 +1,13 @@
 //! Synchronization and interior mutability primitives
 
+mod barrier;
+mod interrupt;
+mod kernel_lock;
+mod mutex;
 mod up;
 
+pub use barrier::{membarrier, MembarrierCmd};
+pub use interrupt::InterruptGuard;
+pub use kernel_lock::KERNEL_LOCK;
+pub use mutex::Mutex;
 pub use up::{UPSafeCell, RefMutWrapper};
@@ -0,0 +1,119 @@
+//! Mutexes the threads of a process lock with `sys_mutex_lock`
+//!
+//! A process keeps its mutexes by id with its main thread, like its files.
+//! A thread holds one across user space, in between `sys_mutex_lock` and
+//! `sys_mutex_unlock`. A spinning [`Mutex`] has a locker wanting it yield
+//! until it is free; a blocking one puts the locker on a wait queue, and
+//! `unlock` wakes the first waiter, which then tries again with the others.
+//!
+//! Before blocking, an SMP build spins for up to [`SPIN_LIMIT_US`] while
+//! the owner is the current task of another hart: a short critical section
+//! there is likely to end sooner than switching away and back again takes.
+//! The spin gives up the kernel lock, so that the owner can come in to
+//! unlock, and only reads the owner, which is atomic for that reason.
+
+use super::{UPSafeCell, KERNEL_LOCK};
+use crate::task::{
+    block_current_and_run_next, current_task, current_task_interrupted, running_elsewhere,
+    suspend_current_and_run_next, wake_up, WaitQueue, WakeOrder, ERESTARTSYS,
+};
+use crate::timer::get_time_us;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// `owner` of a mutex nobody holds
+const NO_OWNER: usize = usize::MAX;
+
+/// Longest a locker spins for an owner running on another hart before
+/// blocking, about as long as a switch away and back
+const SPIN_LIMIT_US: usize = 50;
+
+pub struct Mutex {
+    /// Whether the lockers wait on `waiters` rather than yield
+    blocking: bool,
+    /// Thread id of the owner
+    owner: AtomicUsize,
+    waiters: UPSafeCell<WaitQueue>,
+}
+
+impl Mutex {
+    pub fn new(blocking: bool) -> Self {
+        Self {
+            blocking,
+            owner: AtomicUsize::new(NO_OWNER),
+            waiters: unsafe { UPSafeCell::new(WaitQueue::new(WakeOrder::Fifo)) },
+        }
+    }
+
+    /// An unlocked mutex of the same kind, for a forked process
+    pub fn fork(&self) -> Self {
+        Self::new(self.blocking)
+    }
+
+    /// Lock the mutex for the current thread: 0 once it has, -1 if it
+    /// already holds it, [`ERESTARTSYS`] if a signal interrupts the wait
+    pub fn lock(&self) -> isize {
+        let task = current_task().unwrap();
+        let tid = task.getpid();
+        let mut may_spin = self.blocking && cfg!(feature = "smp");
+        loop {
+            let owner = self.owner.load(Ordering::Relaxed);
+            if owner == NO_OWNER {
+                self.owner.store(tid, Ordering::Relaxed);
+                return 0;
+            }
+            if owner == tid {
+                return -1;
+            }
+            if may_spin && running_elsewhere(owner) {
+                may_spin = false;
+                self.spin(owner);
+                continue;
+            }
+            if current_task_interrupted() {
+                return ERESTARTSYS;
+            }
+            if !self.blocking {
+                suspend_current_and_run_next();
+                continue;
+            }
+            block_current_and_run_next(|waiter| self.waiters.exclusive_access().push(waiter));
+            // woken by `unlock`, or by a signal along with every waiter
+            self.waiters.exclusive_access().remove(&task);
+            may_spin = cfg!(feature = "smp");
+        }
+    }
+
+    /// Spin without the kernel lock while `owner` holds the mutex and is
+    /// the current task of another hart, for at most [`SPIN_LIMIT_US`]
+    fn spin(&self, owner: usize) {
+        let deadline_us = get_time_us() + SPIN_LIMIT_US;
+        KERNEL_LOCK.unlock();
+        while self.owner.load(Ordering::Acquire) == owner
+            && running_elsewhere(owner)
+            && get_time_us() < deadline_us
+        {
+            spin_loop();
+        }
+        KERNEL_LOCK.lock();
+    }
+
+    /// Unlock the mutex the current thread holds and wake the first
+    /// waiter; -1 if the current thread does not hold it
+    pub fn unlock(&self) -> isize {
+        let tid = current_task().unwrap().getpid();
+        if self.owner.load(Ordering::Relaxed) != tid {
+            return -1;
+        }
+        self.owner.store(NO_OWNER, Ordering::Release);
+        let waiter = self.waiters.exclusive_access().pop();
+        wake_up(waiter.into_iter().collect());
+        0
+    }
+
+    /// Wake every waiter, for an interrupted one to notice
+    pub fn interrupt(&self) {
+        let waiters = self.waiters.exclusive_access().take();
+        wake_up(waiters);
+    }
+}
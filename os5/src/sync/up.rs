@@ -1,56 +1,176 @@
 //! Uniprocessor interior mutability primitives
 
-use alloc::format;
-use alloc::string::String;
-use core::cell::{BorrowMutError, RefCell, RefMut};
-use core::fmt::Debug;
+use super::InterruptGuard;
+#[cfg(feature = "cell-tracking")]
+use core::cell::Cell;
+use core::cell::{RefCell, RefMut};
 use core::ops::{Deref, DerefMut};
+#[cfg(feature = "cell-tracking")]
+use core::panic::Location;
+
+/// Who last borrowed a cell
+#[cfg(feature = "cell-tracking")]
+#[derive(Clone, Copy)]
+struct Borrower {
+    site: &'static Location<'static>,
+    /// address `exclusive_access` returned to
+    #[cfg(feature = "debug_lock")]
+    ra: usize,
+    /// pid of the task running then, `None` while idle or booting
+    #[cfg(feature = "debug_lock")]
+    pid: Option<usize>,
+}
+
+/// Return address of the function calling this, which has to be its first
+/// statement, before any call of its own overwrites `ra`
+#[cfg(feature = "debug_lock")]
+macro_rules! return_address {
+    () => {{
+        let ra: usize;
+        unsafe {
+            core::arch::asm!("mv {}, ra", out(reg) ra);
+        }
+        ra
+    }};
+}
 
 /// Wrap a static data structure inside it so that we are
 /// able to access it without any `unsafe`.
 ///
-/// We should only use it in uniprocessor.
+/// We should only use it in uniprocessor, or under the kernel lock, which
+/// keeps all other harts out.
 ///
 /// In order to get mutable reference of inner data, call
-/// `exclusive_access`.
-pub struct UPSafeCell<T: Debug> {
+/// `exclusive_access`. Supervisor interrupts are off while the borrow
+/// lives, so an interrupt handler never finds the cell borrowed.
+///
+/// A cell borrowed twice panics with the type it holds. With the
+/// `cell-tracking` feature it also has a label, the type unless made with
+/// [`UPSafeCell::new_labeled`], and remembers where it was last borrowed,
+/// which the panic reports as the holder of the first borrow. With
+/// `debug_lock` on top the panic also has the return address and pid of
+/// that borrow, and the pid of the task that ran into it.
+pub struct UPSafeCell<T> {
     /// inner data
-    name: String,
     inner: RefCell<T>,
+    #[cfg(feature = "cell-tracking")]
+    label: &'static str,
+    #[cfg(feature = "cell-tracking")]
+    borrowed_by: Cell<Option<Borrower>>,
 }
 
-unsafe impl<T: Debug> Sync for UPSafeCell<T> {}
+// Sound because one hart at a time runs the kernel and the borrow guard
+// keeps interrupts off on it
+unsafe impl<T> Sync for UPSafeCell<T> {}
 
-impl<T: Debug> UPSafeCell<T> {
+impl<T> UPSafeCell<T> {
     /// User is responsible to guarantee that inner struct is only used in
     /// uniprocessor.
     pub unsafe fn new(value: T) -> Self {
-        let name = format!("{:?}", value);
+        Self::new_labeled(core::any::type_name::<T>(), value)
+    }
+
+    /// Like [`UPSafeCell::new`], naming the cell `label` in borrow panics
+    /// when built with `cell-tracking`
+    #[cfg_attr(not(feature = "cell-tracking"), allow(unused_variables))]
+    pub unsafe fn new_labeled(label: &'static str, value: T) -> Self {
         Self {
-            name,
             inner: RefCell::new(value),
+            #[cfg(feature = "cell-tracking")]
+            label,
+            #[cfg(feature = "cell-tracking")]
+            borrowed_by: Cell::new(None),
         }
     }
 
+    /// Like [`UPSafeCell::exclusive_access`], but `None` if the cell is
+    /// borrowed already
+    ///
+    /// This is for the panic path: the kernel aborts on panic, so a cell
+    /// borrowed when the panic hit is never released again. It is poisoned,
+    /// and `exclusive_access` on it would only panic a second time.
+    #[cfg_attr(feature = "cell-tracking", track_caller)]
+    #[cfg_attr(feature = "debug_lock", inline(never))]
+    pub fn try_exclusive_access(&self) -> Option<RefMutWrapper<'_, T>> {
+        #[cfg(feature = "debug_lock")]
+        let ra = return_address!();
+        let guard = InterruptGuard::new();
+        let inner = self.inner.try_borrow_mut().ok()?;
+        self.borrowed(
+            #[cfg(feature = "debug_lock")]
+            ra,
+        );
+        Some(RefMutWrapper(inner, guard))
+    }
+
+    #[cfg_attr(feature = "cell-tracking", track_caller)]
+    #[cfg_attr(feature = "debug_lock", inline(never))]
     pub fn exclusive_access(&self) -> RefMutWrapper<'_, T> {
+        #[cfg(feature = "debug_lock")]
+        let ra = return_address!();
+        let guard = InterruptGuard::new();
         let inner = self.inner.try_borrow_mut();
         if let Ok(inner) = inner {
-            if self.name == "TCB_Inner(ch5_usertest)" {
-                // println!("borrowed");
-            }
-            RefMutWrapper(inner, self.name.clone())
+            self.borrowed(
+                #[cfg(feature = "debug_lock")]
+                ra,
+            );
+            RefMutWrapper(inner, guard)
         } else {
-            panic!("[{}] has been borrowed", self.name);
+            self.borrow_conflict();
+        }
+    }
+
+    #[cfg(not(feature = "cell-tracking"))]
+    fn borrowed(&self) {}
+
+    #[cfg(feature = "cell-tracking")]
+    #[track_caller]
+    fn borrowed(&self, #[cfg(feature = "debug_lock")] ra: usize) {
+        self.borrowed_by.set(Some(Borrower {
+            site: Location::caller(),
+            #[cfg(feature = "debug_lock")]
+            ra,
+            #[cfg(feature = "debug_lock")]
+            pid: crate::task::current_pid(),
+        }));
+    }
+
+    #[cfg(not(feature = "cell-tracking"))]
+    fn borrow_conflict(&self) -> ! {
+        panic!("[{}] has been borrowed", core::any::type_name::<T>());
+    }
+
+    #[cfg(all(feature = "cell-tracking", not(feature = "debug_lock")))]
+    #[track_caller]
+    fn borrow_conflict(&self) -> ! {
+        match self.borrowed_by.get() {
+            Some(by) => panic!("[{}] has been borrowed at {}", self.label, by.site),
+            None => panic!("[{}] has been borrowed", self.label),
+        }
+    }
+
+    #[cfg(feature = "debug_lock")]
+    #[track_caller]
+    fn borrow_conflict(&self) -> ! {
+        let current = crate::task::current_pid();
+        match self.borrowed_by.get() {
+            Some(by) => panic!(
+                "[{}] has been borrowed at {} (ra {:#x}, pid {:?}), borrowing again in pid {:?}",
+                self.label, by.site, by.ra, by.pid, current
+            ),
+            None => panic!(
+                "[{}] has been borrowed, borrowing again in pid {:?}",
+                self.label, current
+            ),
         }
     }
 }
 
-pub struct RefMutWrapper<'a, T: Debug>(
-    RefMut<'a, T>,
-    String,
-);
+/// The borrow, released before interrupts are let back on
+pub struct RefMutWrapper<'a, T>(RefMut<'a, T>, InterruptGuard);
 
-impl<T: Debug> Deref for RefMutWrapper<'_, T> {
+impl<T> Deref for RefMutWrapper<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -58,16 +178,8 @@ impl<T: Debug> Deref for RefMutWrapper<'_, T> {
     }
 }
 
-impl<T: Debug> DerefMut for RefMutWrapper<'_, T> {
+impl<T> DerefMut for RefMutWrapper<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         self.0.deref_mut()
     }
 }
-
-impl<T: Debug> Drop for RefMutWrapper<'_, T> {
-    fn drop(&mut self) {
-        if self.1 == "TCB_Inner(ch5_usertest)" {
-            // println!("release");
-        }
-    }
-}
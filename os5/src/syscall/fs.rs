@@ -1,50 +1,606 @@
 //! File and filesystem-related syscalls
 
-use crate::mm::translated_byte_buffer;
-use crate::sbi::console_getchar;
-use crate::task::{current_user_token, suspend_current_and_run_next};
+use super::process::TimeSpec;
+use crate::config::{MAX_FDS, MAX_PATH_LEN};
+use crate::console::report;
+use crate::fs::{is_dir, make_pipe, resolve_path, File, Stat};
+#[cfg(feature = "fs")]
+use crate::fs::{open, OpenFlags};
+use crate::mm::{
+    copy_data_from_space, copy_data_into_space, translated_byte_buffer, translated_refmut,
+    translated_str, user_range_accessible, UserBuffer,
+};
+use crate::task::{
+    block_current_until, cancel_sleep, current_task, current_task_interrupted, current_user_token,
+    process_leader,
+    ERESTARTNOHAND, ERESTARTSYS,
+};
+use crate::timer::get_time_us;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
 
-const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
+/// `dirfd` of `sys_openat` for paths relative to the working directory,
+/// the only kind of directory descriptor there is
+#[cfg(feature = "fs")]
+const AT_FDCWD: usize = -100isize as usize;
 
+/// The file open as `fd` in the current process
+fn file_of(fd: usize) -> Option<Arc<dyn File>> {
+    let leader = process_leader(&current_task().unwrap());
+    let inner = leader.inner_exclusive_access();
+    inner.fd_table.get(fd)?.clone()
+}
+
+/// Write `buf` to `fd`, counting the bytes written in the I/O of the
+/// current task; -1 if `buf` is not readable. A signal interrupting the
+/// write before anything is written restarts it, see
+/// [`ERESTARTSYS`](crate::task::ERESTARTSYS).
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDOUT => {
-            let buffers = translated_byte_buffer(current_user_token(), buf, len);
-            for buffer in buffers {
-                print!("{}", core::str::from_utf8(buffer).unwrap());
+    let token = current_user_token();
+    if !user_range_accessible(token, buf as usize, len, false) {
+        return -1;
+    }
+    let file = match file_of(fd) {
+        Some(file) if file.writable() => file,
+        _ => return -1,
+    };
+    let buffer = match translated_byte_buffer(token, buf, len, false) {
+        Some(buffers) => UserBuffer::new(buffers),
+        None => return -1,
+    };
+    let written = match file.write(buffer) {
+        Some(written) => written,
+        None if current_task_interrupted() => return ERESTARTSYS,
+        None => return -1,
+    };
+    let task = current_task().unwrap();
+    task.inner_exclusive_access()
+        .io
+        .record_write(file.io_class(), written);
+    written as isize
+}
+
+/// Read from `fd` into `buf`, counting the bytes read in the I/O of the
+/// current task; -1 if `buf` is not writable, before anything is read. A
+/// signal interrupting the read restarts it, like a write.
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+    let token = current_user_token();
+    if !user_range_accessible(token, buf as usize, len, true) {
+        return -1;
+    }
+    let file = match file_of(fd) {
+        Some(file) if file.readable() => file,
+        _ => return -1,
+    };
+    let buffer = match translated_byte_buffer(token, buf, len, true) {
+        Some(buffers) => UserBuffer::new(buffers),
+        None => return -1,
+    };
+    let read = match file.read(buffer) {
+        Some(read) => read,
+        None if current_task_interrupted() => return ERESTARTSYS,
+        None => return -1,
+    };
+    let task = current_task().unwrap();
+    task.inner_exclusive_access()
+        .io
+        .record_read(file.io_class(), read);
+    read as isize
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    let leader = process_leader(&current_task().unwrap());
+    let mut inner = leader.inner_exclusive_access();
+    let file = match inner.fd_table.get_mut(fd).and_then(Option::take) {
+        Some(file) => file,
+        None => return -1,
+    };
+    drop(inner);
+    // the last end of a pipe closing wakes its waiters
+    drop(file);
+    0
+}
+
+/// Open the file at `path`, in whichever filesystem it is mounted on, as
+/// the lowest free descriptor
+#[cfg(feature = "fs")]
+pub fn sys_openat(dirfd: usize, path: *const u8, flags: u32) -> isize {
+    if dirfd != AT_FDCWD {
+        return -1;
+    }
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) if flags.is_valid() => flags,
+        _ => return -1,
+    };
+    let path = match translated_str(current_user_token(), path, MAX_PATH_LEN) {
+        Ok(path) => path,
+        Err(err) => {
+            debug!("[kernel] sys_openat: bad path: {:?}", err);
+            return -1;
+        }
+    };
+    let task = current_task().unwrap();
+    let path = resolve_path(&task.inner_exclusive_access().cwd, &path);
+    let file = match open(&path, flags) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let leader = process_leader(&task);
+    let mut inner = leader.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    inner.fd_table[fd] = Some(file);
+    fd as isize
+}
+
+/// Store the status of the file open as `fd` at `st`, which must be writable
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
+    let token = current_user_token();
+    if !user_range_accessible(token, st as usize, size_of::<Stat>(), true) {
+        return -1;
+    }
+    let stat = match file_of(fd).and_then(|file| file.stat()) {
+        Some(stat) => stat,
+        None => return -1,
+    };
+    match unsafe { copy_data_into_space(&stat, token, st) } {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Open `fd` again as the lowest free descriptor, sharing the file
+pub fn sys_dup(fd: usize) -> isize {
+    let leader = process_leader(&current_task().unwrap());
+    let mut inner = leader.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    let new_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    inner.fd_table[new_fd] = Some(file);
+    new_fd as isize
+}
+
+/// Make `new_fd` refer to the file of `old_fd`, closing what it referred
+/// to before
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    if new_fd >= MAX_FDS {
+        return -1;
+    }
+    let leader = process_leader(&current_task().unwrap());
+    let mut inner = leader.inner_exclusive_access();
+    let file = match inner.fd_table.get(old_fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return -1,
+    };
+    if inner.fd_table.len() <= new_fd {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    let closed = inner.fd_table[new_fd].replace(file);
+    drop(inner);
+    // the last end of a pipe closing wakes its waiters
+    drop(closed);
+    new_fd as isize
+}
+
+/// Create a pipe and store the descriptors of its read end and its write
+/// end at `pipe[0]` and `pipe[1]`; -1 without allocating any descriptor if
+/// `pipe` is not writable
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    let token = current_user_token();
+    if !user_range_accessible(token, pipe as usize, 2 * size_of::<usize>(), true) {
+        return -1;
+    }
+    let (read_slot, write_slot) = match (
+        translated_refmut(token, pipe),
+        translated_refmut(token, pipe.wrapping_add(1)),
+    ) {
+        (Some(read_slot), Some(write_slot)) => (read_slot, write_slot),
+        _ => return -1,
+    };
+    let leader = process_leader(&current_task().unwrap());
+    let mut inner = leader.inner_exclusive_access();
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    let (read_end, write_end) = make_pipe();
+    inner.fd_table[read_fd] = Some(read_end);
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            let read_end = inner.fd_table[read_fd].take();
+            drop(inner);
+            drop((read_end, write_end));
+            return -1;
+        }
+    };
+    inner.fd_table[write_fd] = Some(write_end);
+    drop(inner);
+    *read_slot = read_fd;
+    *write_slot = write_fd;
+    0
+}
+
+/// `sys_ioctl` request on stdout: `arg` 0 disables line buffering, anything
+/// else enables it
+pub const CONSOLE_SET_BUFFERED: usize = 0x5401;
+/// `sys_ioctl` request: store the bytes a read could take right away at
+/// the `i32` at `arg`
+pub const FIONREAD: usize = 0x541b;
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    match (fd, cmd) {
+        (FD_STDOUT, CONSOLE_SET_BUFFERED) => {
+            let task = current_task().unwrap();
+            task.inner_exclusive_access().stdout.set_buffered(arg != 0);
+            0
+        }
+        (_, FIONREAD) => {
+            let token = current_user_token();
+            if !user_range_accessible(token, arg, size_of::<i32>(), true) {
+                return -1;
             }
-            len as isize
+            let buffered = match file_of(fd).and_then(|file| file.buffered()) {
+                Some(buffered) => buffered as i32,
+                None => return -1,
+            };
+            unsafe { copy_data_into_space(&buffered, token, arg as *mut i32) }.map_or(-1, |()| 0)
+        }
+        _ => -1,
+    }
+}
+
+/// `sys_fcntl` command on a pipe end: resize its buffer to `arg` bytes and
+/// return the size it got, see [`Pipe::set_capacity`](crate::fs::Pipe::set_capacity)
+pub const F_SETPIPE_SZ: usize = 1031;
+/// `sys_fcntl` command on a pipe end: the size of its buffer
+pub const F_GETPIPE_SZ: usize = 1032;
+
+/// Control `fd`; only the pipe buffer size is there to control
+///
+/// A pipe resized to below [`PIPE_MIN_SIZE`](crate::fs::PIPE_MIN_SIZE)
+/// gets that size; above [`PIPE_MAX_SIZE`](crate::fs::PIPE_MAX_SIZE), or
+/// below what it holds, is refused.
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let file = match file_of(fd) {
+        Some(file) => file,
+        None => return -1,
+    };
+    let pipe = match file.as_pipe() {
+        Some(pipe) => pipe,
+        None => return -1,
+    };
+    match cmd {
+        F_GETPIPE_SZ => pipe.capacity() as isize,
+        F_SETPIPE_SZ => pipe.set_capacity(arg).map_or(-1, |size| size as isize),
+        _ => -1,
+    }
+}
+
+/// Most bytes one `sys_report` call may carry
+const MAX_REPORT_LEN: usize = 4096;
+
+/// Write machine-readable test results to the result channel, see
+/// [`crate::console::report`]. Pending stdout of the caller goes out first.
+pub fn sys_report(buf: *const u8, len: usize) -> isize {
+    if len > MAX_REPORT_LEN {
+        return -1;
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.stdout.flush();
+    let text: Vec<u8> = match translated_byte_buffer(inner.get_user_token(), buf, len, false) {
+        Some(buffers) => buffers.concat(),
+        None => return -1,
+    };
+    report(&text);
+    len as isize
+}
+
+/// User-space buffer descriptor for `sys_readv`/`sys_writev`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct IoVec {
+    pub base: *mut u8,
+    pub len: usize,
+}
+
+/// Most buffers one vectored I/O call may take
+const IOV_MAX: usize = 1024;
+
+/// Whether the `iovcnt` descriptors at `iov` can all be read, checked
+/// before any of the I/O they describe
+fn iovecs_accessible(iov: *const IoVec, iovcnt: usize) -> bool {
+    let size = iovcnt * size_of::<IoVec>();
+    user_range_accessible(current_user_token(), iov as usize, size, false)
+}
+
+fn iovec_at(iov: *const IoVec, i: usize) -> Option<IoVec> {
+    unsafe { copy_data_from_space(current_user_token(), iov.wrapping_add(i)) }
+}
+
+/// Write the `iovcnt` buffers described by `iov` to `fd`, in order
+pub fn sys_writev(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    if iovcnt > IOV_MAX || !iovecs_accessible(iov, iovcnt) {
+        return -1;
+    }
+    let mut total = 0;
+    for i in 0..iovcnt {
+        let vec = match iovec_at(iov, i) {
+            Some(vec) => vec,
+            None => return if total == 0 { -1 } else { total },
+        };
+        let written = sys_write(fd, vec.base, vec.len);
+        if written < 0 {
+            return if total == 0 { written } else { total };
         }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+        total += written;
+        if (written as usize) < vec.len {
+            break;
         }
     }
+    total
 }
 
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
-    match fd {
-        FD_STDIN => {
-            assert_eq!(len, 1, "Only support len = 1 in sys_read!");
-            let mut c: usize;
-            loop {
-                c = console_getchar();
-                if c == 0 {
-                    suspend_current_and_run_next();
-                    continue;
-                } else {
-                    break;
-                }
+/// Fill the `iovcnt` buffers described by `iov` from `fd`, stopping at the
+/// first short read
+pub fn sys_readv(fd: usize, iov: *const IoVec, iovcnt: usize) -> isize {
+    if iovcnt > IOV_MAX || !iovecs_accessible(iov, iovcnt) {
+        return -1;
+    }
+    let mut total = 0;
+    for i in 0..iovcnt {
+        let vec = match iovec_at(iov, i) {
+            Some(vec) => vec,
+            None => return if total == 0 { -1 } else { total },
+        };
+        let read = sys_read(fd, vec.base, vec.len);
+        if read < 0 {
+            return if total == 0 { read } else { total };
+        }
+        total += read;
+        if (read as usize) < vec.len {
+            break;
+        }
+    }
+    total
+}
+
+/// Bytes `sys_sendfile` moves per read
+const SENDFILE_CHUNK: usize = 4096;
+
+/// `buf` as the buffer of a [`File`] read or write
+///
+/// The lifetime is a lie: the result must be dropped before `buf` is.
+fn kernel_buffer(buf: &mut [u8]) -> UserBuffer {
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+    UserBuffer::new(alloc::vec![buf])
+}
+
+/// Copy up to `count` bytes from `in_fd` to `out_fd` through a kernel
+/// buffer, without a bounce through user memory; the bytes copied, stopping
+/// early at the first short read or write, or -1 if either descriptor can
+/// not be used
+///
+/// There is no offset argument: reads start at, and advance, the offset of
+/// `in_fd`.
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, count: usize) -> isize {
+    let (input, output) = match (file_of(in_fd), file_of(out_fd)) {
+        (Some(input), Some(output)) if input.readable() && output.writable() => (input, output),
+        _ => return -1,
+    };
+    let mut chunk = alloc::vec![0u8; SENDFILE_CHUNK.min(count)];
+    let mut total = 0;
+    while total < count {
+        let want = chunk.len().min(count - total);
+        let read = match input.read(kernel_buffer(&mut chunk[..want])) {
+            Some(read) if read > 0 => read,
+            _ => break,
+        };
+        let written = output.write(kernel_buffer(&mut chunk[..read])).unwrap_or(0);
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        inner.io.record_read(input.io_class(), read);
+        inner.io.record_write(output.io_class(), written);
+        drop(inner);
+        total += written;
+        if written < read || read < want {
+            break;
+        }
+    }
+    total as isize
+}
+
+/// Move up to `len` bytes between a pipe and another file with no copy in
+/// between: from `fd_in`, the read end of a pipe, to `fd_out`, or from
+/// `fd_in` to `fd_out`, the write end of a pipe; the bytes moved, or -1
+/// if neither or both are pipes or either descriptor cannot be used
+///
+/// Unlike Linux's `splice`, there are no offset arguments and no flags:
+/// the file is read or written at, and advances, its own offset. An
+/// empty pipe, a full one, or a file with nothing to read blocks the call
+/// until there is something to move, and a signal interrupting the wait
+/// restarts it, see [`ERESTARTSYS`](crate::task::ERESTARTSYS).
+pub fn sys_splice(fd_in: usize, fd_out: usize, len: usize) -> isize {
+    let (input, output) = match (file_of(fd_in), file_of(fd_out)) {
+        (Some(input), Some(output)) if input.readable() && output.writable() => (input, output),
+        _ => return -1,
+    };
+    let moved = match (input.as_pipe(), output.as_pipe()) {
+        (Some(pipe), None) => pipe.splice_out(output.as_ref(), len),
+        (None, Some(pipe)) => pipe.splice_in(input.as_ref(), len),
+        _ => return -1,
+    };
+    let moved = match moved {
+        Some(moved) => moved,
+        None if current_task_interrupted() => return ERESTARTSYS,
+        None => return -1,
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.io.record_read(input.io_class(), moved);
+    inner.io.record_write(output.io_class(), moved);
+    moved as isize
+}
+
+/// Entry of `sys_ppoll`: the descriptor, the events asked for, and those
+/// that happened
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+/// `sys_ppoll` event: reading would not block
+pub const POLLIN: i16 = 0x1;
+/// `sys_ppoll` event: writing would not block
+pub const POLLOUT: i16 = 0x4;
+/// `sys_ppoll` event, always reported: the descriptor is not open
+pub const POLLNVAL: i16 = 0x20;
+
+/// The events of `pollfd` that have happened, none for a negative fd, and
+/// the file to wait on for them
+fn poll_events(pollfd: &PollFd) -> (i16, Option<Arc<dyn File>>) {
+    if pollfd.fd < 0 {
+        return (0, None);
+    }
+    let file = match file_of(pollfd.fd as usize) {
+        Some(file) => file,
+        None => return (POLLNVAL, None),
+    };
+    let mut revents = 0;
+    if pollfd.events & POLLIN != 0 && file.readable() && file.read_ready() {
+        revents |= POLLIN;
+    }
+    if pollfd.events & POLLOUT != 0 && file.writable() && file.write_ready() {
+        revents |= POLLOUT;
+    }
+    (revents, Some(file))
+}
+
+/// Wait until one of the `nfds` descriptors at `fds` has an event, or for
+/// at most `timeout`, forever if it is null; the number of descriptors
+/// with events, their `revents` set
+///
+/// The poller waits on the wait queues of all the files at once, see
+/// [`File::poll_wait`], and looks again whenever one of them wakes it.
+/// The signal mask argument of Linux's `ppoll` is not taken, as syscalls
+/// have three arguments here. A signal ends the wait, which fails with
+/// EINTR if a handler runs, whatever its flags.
+pub fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout: *const TimeSpec) -> isize {
+    let token = current_user_token();
+    if nfds > MAX_FDS
+        || !user_range_accessible(token, fds as usize, nfds * size_of::<PollFd>(), true)
+    {
+        return -1;
+    }
+    let deadline_us = if timeout.is_null() {
+        usize::MAX
+    } else {
+        if !user_range_accessible(token, timeout as usize, size_of::<TimeSpec>(), false) {
+            return -1;
+        }
+        let timeout: TimeSpec = match unsafe { copy_data_from_space(token, timeout) } {
+            Some(timeout) => timeout,
+            None => return -1,
+        };
+        if timeout.nsec >= 1_000_000_000 {
+            return -1;
+        }
+        let us = timeout
+            .sec
+            .saturating_mul(1_000_000)
+            .saturating_add((timeout.nsec + 999) / 1000);
+        get_time_us().saturating_add(us)
+    };
+    loop {
+        let mut ready = 0;
+        let mut files = Vec::new();
+        for i in 0..nfds {
+            let slot = fds.wrapping_add(i);
+            let mut pollfd: PollFd = match unsafe { copy_data_from_space(token, slot) } {
+                Some(pollfd) => pollfd,
+                None => return -1,
+            };
+            let (revents, file) = poll_events(&pollfd);
+            pollfd.revents = revents;
+            if pollfd.revents != 0 {
+                ready += 1;
+            }
+            files.extend(file);
+            if unsafe { copy_data_into_space(&pollfd, token, slot) }.is_none() {
+                return -1;
             }
-            let ch = c as u8;
-            let mut buffers = translated_byte_buffer(current_user_token(), buf, len);
-            unsafe {
-                buffers[0].as_mut_ptr().write_volatile(ch);
+        }
+        let now_us = get_time_us();
+        if ready > 0 || now_us >= deadline_us {
+            return ready;
+        }
+        if current_task_interrupted() {
+            return ERESTARTNOHAND;
+        }
+        // a signal wakes the poller from the sleepers even without a timeout
+        block_current_until(deadline_us, |task| {
+            for file in files.iter() {
+                file.poll_wait(&task);
             }
-            1
+        });
+        let task = current_task().unwrap();
+        for file in files.iter() {
+            file.poll_cancel(&task);
         }
-        _ => {
-            panic!("Unsupported fd in sys_read!");
+        cancel_sleep(&task);
+    }
+}
+
+/// Change the current working directory, resolving `path` against the old one
+pub fn sys_chdir(path: *const u8) -> isize {
+    let path = match translated_str(current_user_token(), path, MAX_PATH_LEN) {
+        Ok(path) => path,
+        Err(err) => {
+            debug!("[kernel] sys_chdir: bad path: {:?}", err);
+            return -1;
+        }
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let cwd = resolve_path(&inner.cwd, &path);
+    if !is_dir(&cwd) {
+        return -1;
+    }
+    inner.cwd = cwd;
+    0
+}
+
+/// Copy the NUL-terminated current working directory to `buf` and return its
+/// size including the terminator, or -1 if it does not fit in `len` bytes
+pub fn sys_getcwd(buf: *mut u8, len: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let size = inner.cwd.len() + 1;
+    if len < size {
+        return -1;
+    }
+    let buffers = match translated_byte_buffer(inner.get_user_token(), buf, size, true) {
+        Some(buffers) => buffers,
+        None => return -1,
+    };
+    let mut bytes = inner.cwd.bytes().chain(core::iter::once(0));
+    for buffer in buffers {
+        for byte in buffer.iter_mut() {
+            *byte = bytes.next().unwrap();
         }
     }
+    size as isize
 }
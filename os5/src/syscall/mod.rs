@@ -10,50 +10,229 @@
 //! `sys_` then the name of the syscall. You can find functions like this in
 //! submodules, and you should also implement syscalls this way.
 
+const SYSCALL_GETCWD: usize = 17;
+const SYSCALL_DUP: usize = 24;
+const SYSCALL_FCNTL: usize = 25;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_CHDIR: usize = 49;
+#[cfg(feature = "fs")]
+const SYSCALL_OPENAT: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE: usize = 59;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_READV: usize = 65;
+const SYSCALL_WRITEV: usize = 66;
+const SYSCALL_SENDFILE: usize = 71;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_SPLICE: usize = 76;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_CAPGET: usize = 90;
+const SYSCALL_CAPSET: usize = 91;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_GETITIMER: usize = 102;
+const SYSCALL_SETITIMER: usize = 103;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_CLOCK_NANOSLEEP: usize = 115;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+#[cfg(feature = "signals")]
+const SYSCALL_SIGACTION: usize = 134;
+#[cfg(feature = "signals")]
+const SYSCALL_SIGPROCMASK: usize = 135;
+#[cfg(feature = "signals")]
+const SYSCALL_SIGRETURN: usize = 139;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETTID: usize = 178;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_SBRK: usize = 214;
 const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MSYNC: usize = 227;
+const SYSCALL_MINCORE: usize = 232;
+const SYSCALL_THREAD_CREATE: usize = 460;
+const SYSCALL_MSEAL: usize = 462;
+const SYSCALL_MUTEX_CREATE: usize = 463;
+const SYSCALL_MUTEX_LOCK: usize = 464;
+const SYSCALL_MUTEX_UNLOCK: usize = 466;
 const SYSCALL_SET_PRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_TIMES: usize = 153;
+const SYSCALL_GETRUSAGE: usize = 165;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
 const SYSCALL_TASK_INFO: usize = 410;
+const SYSCALL_MEMBARRIER: usize = 283;
+const SYSCALL_PIDFD_OPEN: usize = 434;
+const SYSCALL_IRQ_ROUTE: usize = 474;
+const SYSCALL_HART_CTL: usize = 475;
+const SYSCALL_HART_STATUS: usize = 476;
+const SYSCALL_MMAP_FILE: usize = 477;
+const SYSCALL_SHUTDOWN: usize = 478;
+/// Below [`crate::config::MAX_SYSCALL_NUM`], as a call has to be for
+/// `sys_task_info` to count it
+const SYSCALL_GET_CYCLES: usize = 479;
+const SYSCALL_KBENCH: usize = 480;
+const SYSCALL_KSTAT: usize = 481;
+const SYSCALL_GET_CHILDREN: usize = 482;
+const SYSCALL_DUMP_PROC_TREE: usize = 483;
+const SYSCALL_SET_LOG_LEVEL: usize = 484;
+const SYSCALL_CHECK_PROC_TREE: usize = 485;
+const SYSCALL_GET_MAPS: usize = 486;
+const SYSCALL_REPORT: usize = 487;
+const SYSCALL_SHM_OPEN: usize = 488;
+const SYSCALL_SHM_MAP: usize = 489;
+const SYSCALL_SHM_UNLINK: usize = 490;
+const SYSCALL_TRACE: usize = 491;
+const SYSCALL_DUMP_TIMERS: usize = 492;
+/// Linux's number for it, 140, is taken by the lab's `set_priority`
+const SYSCALL_SETPRIORITY: usize = 493;
+/// The user library's number for it, 462, is taken by `mseal`
+const SYSCALL_WAITTID: usize = 494;
+/// Linux has no `dup2` on RISC-V, and its `dup3` number, 24, is `dup` here
+const SYSCALL_DUP2: usize = 495;
+const SYSCALL_PANIC: usize = 496;
+const SYSCALL_AUDIT_READ: usize = 497;
+const SYSCALL_TASK_STAT: usize = 498;
+const SYSCALL_TASK_LIST: usize = 499;
+const SYSCALL_SCHED_WORKLOAD: usize = 500;
 
 mod fs;
 mod process;
+mod sync;
+mod thread;
 
 use fs::*;
 use process::*;
-pub use process::TaskInfo;
-use crate::task::{decrease_syscall_times, increase_syscall_times};
+use sync::*;
+use thread::*;
+pub use process::{TaskInfo, TaskStat, TASK_STAT_NAME_LEN};
+use crate::fs::Stat;
+use crate::audit::AuditRecord;
+use crate::mm::AreaInfo;
+#[cfg(feature = "signals")]
+use crate::task::SignalAction;
+use crate::task::{decrease_syscall_times, increase_syscall_times, CapSet};
 
 /// handle syscall exception with `syscall_id` and other arguments
 pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+    shadow_guard!();
     increase_syscall_times(syscall_id);
-    match syscall_id {
+    let ret = match syscall_id {
+        SYSCALL_GETCWD => sys_getcwd(args[0] as *mut u8, args[1]),
+        SYSCALL_DUP => sys_dup(args[0]),
+        SYSCALL_DUP2 => sys_dup2(args[0], args[1]),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_CHDIR => sys_chdir(args[0] as *const u8),
+        #[cfg(feature = "fs")]
+        SYSCALL_OPENAT => sys_openat(args[0], args[1] as *const u8, args[2] as u32),
+        SYSCALL_CLOSE => sys_close(args[0]),
+        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_READV => sys_readv(args[0], args[1] as *const IoVec, args[2]),
+        SYSCALL_WRITEV => sys_writev(args[0], args[1] as *const IoVec, args[2]),
+        SYSCALL_SENDFILE => sys_sendfile(args[0], args[1], args[2]),
+        SYSCALL_SPLICE => sys_splice(args[0], args[1], args[2]),
+        SYSCALL_PPOLL => sys_ppoll(args[0] as *mut PollFd, args[1], args[2] as *const TimeSpec),
+        SYSCALL_FSTAT => sys_fstat(args[0], args[1] as *mut Stat),
+        SYSCALL_CAPGET => sys_capget(args[0] as *mut CapSet),
+        SYSCALL_CAPSET => sys_capset(args[0] as u32, args[1] as u32),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_PANIC => sys_panic(args[0] as *const u8, args[1]),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_GETTID => sys_gettid(),
+        SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
+        SYSCALL_WAITTID => sys_waittid(args[0]),
+        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] != 0),
+        SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
+        SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
         SYSCALL_FORK => sys_fork(),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32, args[2]),
+        SYSCALL_KILL => sys_kill(args[0] as isize, args[1]),
+        SYSCALL_PIDFD_OPEN => sys_pidfd_open(args[0], args[1]),
+        #[cfg(feature = "signals")]
+        SYSCALL_SIGACTION => sys_sigaction(
+            args[0],
+            args[1] as *const SignalAction,
+            args[2] as *mut SignalAction,
+        ),
+        #[cfg(feature = "signals")]
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0], args[1]),
+        #[cfg(feature = "signals")]
+        SYSCALL_SIGRETURN => sys_sigreturn(),
         SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
+        SYSCALL_GET_CYCLES => sys_get_cycles(args[0] as *mut Cycles),
+        SYSCALL_NANOSLEEP => sys_nanosleep(args[0] as *const TimeSpec, args[1] as *mut TimeSpec),
+        SYSCALL_GETITIMER => sys_getitimer(args[0], args[1] as *mut ITimerVal),
+        SYSCALL_SETITIMER => sys_setitimer(
+            args[0],
+            args[1] as *const ITimerVal,
+            args[2] as *mut ITimerVal,
+        ),
+        SYSCALL_CLOCK_GETTIME => sys_clock_gettime(args[0], args[1] as *mut TimeSpec),
+        SYSCALL_CLOCK_NANOSLEEP => sys_clock_nanosleep(args[0], args[1], args[2] as *const TimeSpec),
+        SYSCALL_TIMES => sys_times(args[0] as *mut Tms),
+        SYSCALL_GETRUSAGE => sys_getrusage(args[0] as isize, args[1] as *mut Rusage),
+        SYSCALL_SETPGID => sys_setpgid(args[0], args[1]),
+        SYSCALL_GETPGID => sys_getpgid(args[0]),
         SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_MSYNC => sys_msync(args[0], args[1], args[2]),
+        SYSCALL_SBRK => sys_sbrk(args[0] as isize),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
+        SYSCALL_MINCORE => sys_mincore(args[0], args[1], args[2] as *mut u8),
+        SYSCALL_MSEAL => sys_mseal(args[0], args[1], args[2]),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
+        SYSCALL_GETPRIORITY => sys_getpriority(args[0], args[1]),
         SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
-        SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
+        SYSCALL_SPAWN => sys_spawn(args[0] as *const u8, args[1] as *const usize),
+        SYSCALL_MEMBARRIER => sys_membarrier(args[0] as u32, args[1] as u32),
+        SYSCALL_KBENCH => sys_kbench(args[0], args[1]),
+        SYSCALL_KSTAT => sys_kstat(args[0], args[1] as *mut u8, args[2]),
+        SYSCALL_GET_CHILDREN => sys_get_children(args[0] as *mut ChildInfo, args[1]),
+        SYSCALL_TASK_STAT => sys_task_stat(args[0], args[1] as *mut TaskStat),
+        SYSCALL_TASK_LIST => sys_task_list(args[0] as *mut usize, args[1]),
+        SYSCALL_SCHED_WORKLOAD => sys_sched_workload(args[0] as *mut SchedWorker, args[1], args[2]),
+        SYSCALL_DUMP_PROC_TREE => sys_dump_proc_tree(),
+        SYSCALL_SET_LOG_LEVEL => sys_set_log_level(args[0]),
+        SYSCALL_CHECK_PROC_TREE => sys_check_proc_tree(),
+        SYSCALL_GET_MAPS => sys_get_maps(args[0] as *mut AreaInfo, args[1]),
+        SYSCALL_REPORT => sys_report(args[0] as *const u8, args[1]),
+        SYSCALL_SHM_OPEN => sys_shm_open(args[0] as *const u8, args[1], args[2]),
+        SYSCALL_SHM_MAP => sys_shm_map(args[0] as *const u8, args[1], args[2]),
+        SYSCALL_MMAP_FILE => sys_mmap_file(args[0], args[1], args[2]),
+        SYSCALL_HART_CTL => sys_hart_ctl(args[0], args[1]),
+        SYSCALL_IRQ_ROUTE => sys_irq_route(args[0], args[1]),
+        SYSCALL_HART_STATUS => sys_hart_status(args[0] as *mut usize, args[1]),
+        SYSCALL_SHM_UNLINK => sys_shm_unlink(args[0] as *const u8),
+        SYSCALL_TRACE => sys_trace(args[0], args[1]),
+        SYSCALL_AUDIT_READ => sys_audit_read(args[0] as *mut AuditRecord, args[1]),
+        SYSCALL_DUMP_TIMERS => sys_dump_timers(),
+        SYSCALL_SHUTDOWN => sys_shutdown(),
+        SYSCALL_SETPRIORITY => sys_setpriority(args[0], args[1], args[2] as isize),
         _ => {
             decrease_syscall_times(syscall_id);
             panic!("Unsupported syscall_id: {}", syscall_id)
         },
-    }
+    };
+    trace_event!(
+        SYSCALL,
+        "syscall {}({:#x}, {:#x}, {:#x}) = {}",
+        syscall_id,
+        args[0],
+        args[1],
+        args[2],
+        ret
+    );
+    ret
 }
@@ -1,15 +1,26 @@
 //! Process management syscalls
 
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
-use crate::config::MAX_SYSCALL_NUM;
-use crate::loader::get_app_data_by_name;
-use crate::mm::{copy_data_into_space, translated_refmut, translated_str};
-use crate::task::{add_task, current_task, current_task_mmap, current_task_munmap, current_user_token, exit_current_and_run_next, get_current_task_info, set_current_task_priority, suspend_current_and_run_next, TaskStatus};
-use crate::timer::get_time_us;
+use crate::audit::{audit, audit_named, take_records, AuditKind, AuditRecord, AUDIT_RECORDS};
+use crate::config::{ARG_MAX, CLOCK_FREQ, MAX_ARGS, MAX_ARG_LEN, MAX_HARTS, MAX_PATH_LEN, MAX_SYSCALL_NUM, PAGE_SIZE};
+use crate::drivers::route_irq;
+use crate::hart;
+use crate::fs::{lookup_app, resolve_path, PidFd};
+use crate::mm::{shm_open, shm_unlink, AreaInfo, copy_data_from_space, copy_data_into_space, translated_byte_buffer, translated_refmut, translated_str, translated_str_array, user_range_accessible, UserStrError, MapPermission, MemorySet, VirtAddr, VirtPageNum};
+use crate::task::{add_task, args_size, block_current_and_run_next, FaultStats, IoStats, check_process_tree, current_task, current_task_capable, Capabilities, CapSet, dump_process_tree, dump_timers, get_alarm, set_alarm, release_zombie, current_task_mmap, current_task_mmap_file, current_task_msync, current_task_munmap, current_task_mprotect, current_task_mseal, current_task_sbrk, current_task_shm_map, current_user_token, exit_current_and_run_next, send_signal, get_current_task_info, get_nice, get_task_stat, pid2task, process_pids, get_pgid, process_leader, run_workload, TaskControlBlock, set_current_task_priority, set_nice, set_pgid, sleep_current_until, suspend_current_and_run_next, TaskStatus, WorkerSpec, ERESTARTNOHAND, ERESTARTSYS, MIN_PRIORITY};
+#[cfg(feature = "signals")]
+use crate::task::{current_task_sigaction, current_task_sigprocmask, current_task_sigreturn, SignalAction, SignalFlags};
+use crate::sbi::shutdown;
+use crate::sync::{membarrier, MembarrierCmd};
+use crate::timer::{get_time, get_time_us, timer_stats};
+use crate::trace::{self, TraceSubsys};
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 pub struct TimeVal {
     pub sec: usize,
     pub usec: usize,
@@ -22,26 +33,155 @@ pub struct TaskInfo {
     pub time: usize,
 }
 
+/// CPU usage reported by `sys_times`, in microseconds
+///
+/// The kernel does not tell user from kernel time apart, so everything is
+/// counted as user time. The `c` fields cover every reaped descendant,
+/// including grandchildren reaped by children (or by initproc once orphaned).
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Tms {
+    pub utime: usize,
+    pub stime: usize,
+    pub cutime: usize,
+    pub cstime: usize,
+}
+
+/// `sys_getrusage` target: the calling task
+pub const RUSAGE_SELF: isize = 0;
+/// `sys_getrusage` target: every reaped descendant, as the `c` fields of
+/// [`Tms`] count them
+pub const RUSAGE_CHILDREN: isize = -1;
+
+/// Resource usage reported by `sys_getrusage`
+///
+/// Not Linux's layout: past the times it has the bytes read and written
+/// per file class and the page faults, and none of the fields this kernel
+/// does not keep track of. As with [`Tms`], all CPU time is user time.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Rusage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    pub io: IoStats,
+    pub faults: FaultStats,
+}
+
+/// One worker of `sys_sched_workload`: its duty cycle in, what it got of
+/// the CPU out
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SchedWorker {
+    /// At least [`MIN_PRIORITY`]
+    pub priority: usize,
+    /// CPU time used between two sleeps, more than 0
+    pub cpu_us: usize,
+    /// 0 for a worker that never sleeps
+    pub sleep_us: usize,
+    /// Out: the CPU time it got
+    pub ran_us: usize,
+    /// Out: that in thousandths of what all the workers got
+    pub share: usize,
+}
+
+/// Snapshot of one child reported by `sys_get_children`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ChildInfo {
+    pub pid: usize,
+    /// a [`TaskStatus`] as its discriminant, which has a fixed size unlike
+    /// the enum
+    pub status: usize,
+    /// only meaningful once `status` is `Zombie`
+    pub exit_code: i32,
+}
+
+/// Bytes of the program name in a [`TaskStat`]
+pub const TASK_STAT_NAME_LEN: usize = 16;
+
+/// What `sys_task_stat` reports about a process
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TaskStat {
+    pub pid: usize,
+    /// 0 for a process without a parent
+    pub ppid: usize,
+    /// a [`TaskStatus`] as its discriminant, as in [`ChildInfo`]
+    pub status: usize,
+    pub priority: u64,
+    pub stride: u64,
+    /// frames its address space holds, see [`MemorySet::frames_in_use`]
+    pub frames: usize,
+    /// milliseconds since it first ran, 0 if it never has
+    pub time: usize,
+    /// NUL-padded program name, cut off at [`TASK_STAT_NAME_LEN`] bytes
+    pub name: [u8; TASK_STAT_NAME_LEN],
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    pub io: IoStats,
+    /// the page faults of the process, which `sys_task_info` cannot carry
+    /// as its layout is fixed by the lab
+    pub faults: FaultStats,
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     debug!("[kernel] Application exited with code {}", exit_code);
     exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
+/// Most bytes of a panic message `sys_panic` logs, the rest is cut off
+const MAX_PANIC_MSG_LEN: usize = 256;
+
+/// Exit code of a program that panicked, as Rust programs use
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// Log the panic message of the calling program on the console, naming the
+/// process, then exit with [`PANIC_EXIT_CODE`]. Pending stdout of the
+/// caller goes out first. An unreadable message is logged as
+/// `<bad message>`, the program exits all the same.
+pub fn sys_panic(buf: *const u8, len: usize) -> ! {
+    let token = current_user_token();
+    let len = len.min(MAX_PANIC_MSG_LEN);
+    let msg: Vec<u8> = user_range_accessible(token, buf as usize, len, false)
+        .then(|| translated_byte_buffer(token, buf, len, false))
+        .flatten()
+        .map_or_else(|| b"<bad message>".to_vec(), |buffers| buffers.concat());
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.stdout.flush();
+    println!(
+        "[kernel] pid {} ({}) {}",
+        task.getpid(),
+        inner.name,
+        String::from_utf8_lossy(&msg)
+    );
+    drop(inner);
+    drop(task);
+    exit_current_and_run_next(PANIC_EXIT_CODE);
+    panic!("Unreachable in sys_panic!");
+}
+
 /// current task gives up resources for other tasks
 pub fn sys_yield() -> isize {
     suspend_current_and_run_next();
     0
 }
 
+/// The pid of the process, which for a thread is that of its main thread
 pub fn sys_getpid() -> isize {
-    current_task().unwrap().pid.0 as isize
+    process_leader(&current_task().unwrap()).pid.0 as isize
 }
 
 /// Syscall Fork which returns 0 for child process and child_pid for parent process
 pub fn sys_fork() -> isize {
     let current_task = current_task().unwrap();
-    let new_task = current_task.fork();
+    if current_task.inner_exclusive_access().thread_slot != 0 {
+        return -1;
+    }
+    let new_task = match current_task.fork() {
+        Some(task) => task,
+        None => return -1,
+    };
     let new_pid = new_task.pid.0;
     // modify trap context of new_task, because it returns immediately after switching
     let trap_cx = new_task.inner_exclusive_access().get_trap_cx();
@@ -53,55 +193,186 @@ pub fn sys_fork() -> isize {
     new_pid as isize
 }
 
-/// Syscall Exec which accepts the elf path
-pub fn sys_exec(path: *const u8) -> isize {
+/// Whether `task` is a thread or has threads that have not exited
+fn is_multithreaded(task: &TaskControlBlock) -> bool {
+    let inner = task.inner_exclusive_access();
+    inner.thread_slot != 0
+        || inner
+            .threads
+            .iter()
+            .any(|thread| !thread.inner_exclusive_access().is_zombie())
+}
+
+/// Error of `sys_exec` and `sys_spawn` for arguments over the limits
+const E2BIG: isize = -7;
+
+/// Copy the `argv` of `sys_exec` or `sys_spawn` out of user space; -7
+/// (E2BIG) for more than [`MAX_ARGS`] arguments, one longer than
+/// [`MAX_ARG_LEN`] or ones that take more than [`ARG_MAX`] bytes on the
+/// new stack, -1 for an unreadable one
+fn translated_args(token: usize, args: *const usize) -> Result<Vec<String>, isize> {
+    let args = translated_str_array(token, args, MAX_ARGS, MAX_ARG_LEN).map_err(|err| {
+        debug!("[kernel] bad argv: {:?}", err);
+        match err {
+            UserStrError::TooLong => E2BIG,
+            _ => -1,
+        }
+    })?;
+    if args_size(&args) > ARG_MAX {
+        debug!("[kernel] argv of {} bytes", args_size(&args));
+        return Err(E2BIG);
+    }
+    Ok(args)
+}
+
+/// Syscall Exec which accepts the elf path and a null-terminated argument
+/// vector, which may be null for no arguments
+///
+/// The program gets argc in a0 and argv in a1. Arguments over the limits
+/// fail it with -7 (E2BIG), see [`translated_args`].
+pub fn sys_exec(path: *const u8, args: *const usize) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match translated_str(token, path, MAX_PATH_LEN) {
+        Ok(path) => path,
+        Err(err) => {
+            debug!("[kernel] sys_exec: bad path: {:?}", err);
+            return -1;
+        }
+    };
+    let args = match translated_args(token, args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
     // println!("[sys_exec] path:{}", path);
-    if let Some(data) = get_app_data_by_name(path.as_str()) {
-        let task = current_task().unwrap();
-        task.exec(data, path.as_str());
-        0
-    } else {
-        -1
+    let task = current_task().unwrap();
+    if is_multithreaded(&task) {
+        return -1;
     }
+    let path = resolve_path(&task.inner_exclusive_access().cwd, &path);
+    let name = app_name(&path);
+    let result = match lookup_app(&path) {
+        Some(data) => task.exec(&data, name, &args).map_err(|err| {
+            debug!("[kernel] sys_exec: cannot load {}: {:?}", path, err);
+        }),
+        None => Err(()),
+    };
+    audit_named(AuditKind::Exec, result.is_ok(), task.getpid(), 0, name);
+    result.map_or(-1, |()| 0)
+}
+
+/// The application name of an absolute path, i.e. its last component
+fn app_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap()
 }
 
+/// `sys_waitpid` option: return 0 instead of blocking if no child has exited
+pub const WNOHANG: usize = 1;
+
 /// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Else if there is a child process but it is still running, block until it
+/// exits (woken from `exit_current_and_run_next`), or return 0 at once with
+/// [`WNOHANG`]. A signal ends the wait, which is restarted after the
+/// handler unless that has no `SA_RESTART`, then -4 (EINTR).
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
+    if options & !WNOHANG != 0 {
+        return -1;
+    }
+    loop {
+        let task = current_task().unwrap();
+        // find a child process
+
+        // ---- access current TCB exclusively
+        let mut inner = task.inner_exclusive_access();
+        if !inner
+            .children
+            .iter()
+            .any(|p| pid == -1 || pid as usize == p.getpid())
+        {
+            return -1;
+            // ---- release current PCB
+        }
+        let pair = inner.children.iter().enumerate().find(|(_, p)| {
+            // ++++ temporarily access child PCB lock exclusively
+            p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
+            // ++++ release child PCB
+        });
+        if let Some((idx, _)) = pair {
+            let token = inner.memory_set.exclusive_access().token();
+            let exit_code_slot = match translated_refmut(token, exit_code_ptr) {
+                Some(slot) => slot,
+                None => return -1,
+            };
+            let child = inner.children.remove(idx);
+            let found_pid = child.getpid();
+            // ++++ temporarily access child TCB exclusively
+            let child_inner = child.inner_exclusive_access();
+            let exit_code = child_inner.exit_code;
+            // the subtree's usage moves up as each level is reaped
+            inner.children_cpu_us += child_inner.cpu_time_us + child_inner.children_cpu_us;
+            inner.children_io.add(&child_inner.io);
+            inner.children_io.add(&child_inner.children_io);
+            inner.children_faults.add(&child_inner.faults);
+            inner.children_faults.add(&child_inner.children_faults);
+            drop(child_inner);
+            // ++++ release child PCB
+            // confirm that child will be deallocated after removing from children list
+            release_zombie(child);
+            *exit_code_slot = exit_code;
+            return found_pid as isize;
+        }
+        if options & WNOHANG != 0 {
+            return 0;
+        }
+        if inner.signals.interrupted() {
+            return ERESTARTSYS;
+        }
+        drop(inner);
+        drop(task);
+        // ---- release current PCB lock
+        block_current_and_run_next(|task| {
+            let waiter = task.clone();
+            task.inner_exclusive_access().child_exit.push(waiter);
+        });
+    }
+}
+
+pub fn sys_times(buf: *mut Tms) -> isize {
     let task = current_task().unwrap();
-    // find a child process
+    let inner = task.inner_exclusive_access();
+    let tms = Tms {
+        utime: inner.running_cpu_time_us(),
+        stime: 0,
+        cutime: inner.children_cpu_us,
+        cstime: 0,
+    };
+    unsafe { copy_data_into_space(&tms, inner.get_user_token(), buf) }.map_or(-1, |()| 0)
+}
 
-    // ---- access current TCB exclusively
-    let mut inner = task.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
+/// Report the CPU time and I/O of the caller or of its reaped descendants,
+/// see [`RUSAGE_SELF`] and [`RUSAGE_CHILDREN`]
+pub fn sys_getrusage(who: isize, usage: *mut Rusage) -> isize {
+    let token = current_user_token();
+    if !user_range_accessible(token, usage as usize, core::mem::size_of::<Rusage>(), true) {
         return -1;
-        // ---- release current PCB
-    }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie() && (pid == -1 || pid as usize == p.getpid())
-        // ++++ release child PCB
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
     }
-    // ---- release current PCB lock automatically
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let (us, io, faults) = match who {
+        RUSAGE_SELF => (inner.running_cpu_time_us(), inner.io, inner.faults),
+        RUSAGE_CHILDREN => (inner.children_cpu_us, inner.children_io, inner.children_faults),
+        _ => return -1,
+    };
+    drop(inner);
+    let rusage = Rusage {
+        utime: TimeVal {
+            sec: us / 1_000_000,
+            usec: us % 1_000_000,
+        },
+        stime: TimeVal { sec: 0, usec: 0 },
+        io,
+        faults,
+    };
+    unsafe { copy_data_into_space(&rusage, token, usage) }.map_or(-1, |()| 0)
 }
 
 pub fn sys_get_time(ts_ptr: *mut TimeVal, _tz: usize) -> isize {
@@ -110,55 +381,957 @@ pub fn sys_get_time(ts_ptr: *mut TimeVal, _tz: usize) -> isize {
         sec: us / 1_000_000,
         usec: us % 1_000_000,
     };
-    unsafe { copy_data_into_space(&ts, current_user_token(), ts_ptr) };
+    unsafe { copy_data_into_space(&ts, current_user_token(), ts_ptr) }.map_or(-1, |()| 0)
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// What `sys_get_cycles` reports: the raw `time` counter and the ticks it
+/// counts per second
+pub struct Cycles {
+    pub cycles: u64,
+    pub freq: u64,
+}
+
+/// Read the `time` counter into `cycles`, for measurements finer than the
+/// microseconds of `sys_get_time`
+///
+/// `freq` is the [`CLOCK_FREQ`] the kernel is configured with, qemu's, as
+/// nothing calibrates it at boot. There is no vDSO page mapping the counter
+/// into user space, so every read is a syscall.
+pub fn sys_get_cycles(cycles: *mut Cycles) -> isize {
+    let token = current_user_token();
+    if !user_range_accessible(token, cycles as usize, core::mem::size_of::<Cycles>(), true) {
+        return -1;
+    }
+    let now = Cycles {
+        cycles: get_time() as u64,
+        freq: CLOCK_FREQ as u64,
+    };
+    unsafe { copy_data_into_space(&now, token, cycles) }.map_or(-1, |()| 0)
+}
+
+/// `sys_setitimer` timer: counts down in real time and sends `SIGALRM`
+pub const ITIMER_REAL: usize = 0;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// What `sys_setitimer` sets and `sys_getitimer` reports: the time until
+/// the timer expires (zero when disarmed) and the period it then rearms with
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+fn timeval_us(tv: &TimeVal) -> Option<usize> {
+    if tv.usec >= 1_000_000 {
+        return None;
+    }
+    Some(tv.sec.saturating_mul(1_000_000).saturating_add(tv.usec))
+}
+
+fn us_timeval(us: usize) -> TimeVal {
+    TimeVal {
+        sec: us / 1_000_000,
+        usec: us % 1_000_000,
+    }
+}
+
+/// Report the interval timer `which` of the caller in `curr`
+pub fn sys_getitimer(which: usize, curr: *mut ITimerVal) -> isize {
+    if which != ITIMER_REAL {
+        return -1;
+    }
+    let token = current_user_token();
+    if !user_range_accessible(token, curr as usize, core::mem::size_of::<ITimerVal>(), true) {
+        return -1;
+    }
+    let (value_us, interval_us) = get_alarm(current_task().unwrap().getpid());
+    let itv = ITimerVal {
+        interval: us_timeval(interval_us),
+        value: us_timeval(value_us),
+    };
+    unsafe { copy_data_into_space(&itv, token, curr) }.map_or(-1, |()| 0)
+}
+
+/// Arm the interval timer `which` from `new`, or disarm it with a zero
+/// `value`; the old setting goes to `old` unless it is null. Both are
+/// checked before the timer changes.
+pub fn sys_setitimer(which: usize, new: *const ITimerVal, old: *mut ITimerVal) -> isize {
+    if which != ITIMER_REAL {
+        return -1;
+    }
+    let token = current_user_token();
+    let size = core::mem::size_of::<ITimerVal>();
+    if !user_range_accessible(token, new as usize, size, false)
+        || !old.is_null() && !user_range_accessible(token, old as usize, size, true)
+    {
+        return -1;
+    }
+    let new: ITimerVal = match unsafe { copy_data_from_space(token, new) } {
+        Some(new) => new,
+        None => return -1,
+    };
+    let (value_us, interval_us) = match (timeval_us(&new.value), timeval_us(&new.interval)) {
+        (Some(value_us), Some(interval_us)) => (value_us, interval_us),
+        _ => return -1,
+    };
+    let (old_value_us, old_interval_us) =
+        set_alarm(current_task().unwrap().getpid(), value_us, interval_us);
+    if !old.is_null() {
+        let itv = ITimerVal {
+            interval: us_timeval(old_interval_us),
+            value: us_timeval(old_value_us),
+        };
+        return unsafe { copy_data_into_space(&itv, token, old) }.map_or(-1, |()| 0);
+    }
     0
 }
 
-pub fn sys_task_info(ti_ptr: *mut TaskInfo) -> isize {
-    if let Some(task_info) = get_current_task_info() {
-        unsafe { copy_data_into_space(&task_info, current_user_token(), ti_ptr) };
+/// Print every armed kernel timer with its owner and deadline to the
+/// kernel console
+pub fn sys_dump_timers() -> isize {
+    if !current_task_capable(Capabilities::KLOG) {
+        return -1;
+    }
+    dump_timers();
+    0
+}
+
+/// Power the machine off, after the caller's pending stdout; needs
+/// [`Capabilities::SHUTDOWN`], and returns -1 without it
+pub fn sys_shutdown() -> isize {
+    if !current_task_capable(Capabilities::SHUTDOWN) {
+        return -1;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().stdout.flush();
+    println!("[kernel] pid {} shut the machine down", task.getpid());
+    #[cfg(feature = "fs")]
+    crate::drivers::flush_disk();
+    shutdown()
+}
+
+/// `sys_hart_ctl` operations
+pub const HART_STOP: usize = 0;
+pub const HART_START: usize = 1;
+/// What `sys_hart_status` reports for a hart the machine does not have
+pub const HART_ABSENT: usize = usize::MAX;
+
+/// Stop the secondary hart `hart`, or start it again, as `op` says; needs
+/// [`Capabilities::HOTPLUG`]
+///
+/// A hart asked to stop does so once it is between two tasks, which may be
+/// after this returns; `sys_hart_status` tells when it has.
+pub fn sys_hart_ctl(hart: usize, op: usize) -> isize {
+    if !current_task_capable(Capabilities::HOTPLUG) {
+        return -1;
+    }
+    let done = match op {
+        HART_STOP => hart::stop(hart),
+        HART_START => hart::start(hart),
+        _ => return -1,
+    };
+    if done {
         0
     } else {
         -1
     }
 }
 
+/// Route the interrupt source `irq` to the online hart `hart`; needs
+/// [`Capabilities::HOTPLUG`]
+///
+/// While `hart` is stopped, the source goes to the boot hart, and back to
+/// `hart` once it is started again. `/proc/interrupts` shows the routes.
+pub fn sys_irq_route(irq: usize, hart: usize) -> isize {
+    if !current_task_capable(Capabilities::HOTPLUG) {
+        return -1;
+    }
+    if irq <= u32::MAX as usize && route_irq(irq as u32, hart) {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Write the [`HartState`](hart::HartState) of the first `len` harts to
+/// `states`, [`HART_ABSENT`] for those the machine does not have, and
+/// return the number of harts the kernel supports
+pub fn sys_hart_status(states: *mut usize, len: usize) -> isize {
+    let token = current_user_token();
+    let len = len.min(MAX_HARTS);
+    if !user_range_accessible(token, states as usize, len * core::mem::size_of::<usize>(), true) {
+        return -1;
+    }
+    for id in 0..len {
+        let state = hart::hart_state(id).map_or(HART_ABSENT, |state| state as usize);
+        if unsafe { copy_data_into_space(&state, token, states.wrapping_add(id)) }.is_none() {
+            return -1;
+        }
+    }
+    MAX_HARTS as isize
+}
+
+/// Clock: time since boot, as `sys_get_time` reports it
+pub const CLOCK_MONOTONIC: usize = 1;
+/// `sys_clock_gettime` clock: CPU time used by the calling process
+pub const CLOCK_PROCESS_CPUTIME_ID: usize = 2;
+/// `sys_clock_gettime` clock: CPU time used by the calling thread, which
+/// is the process as there is one thread per process
+pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
+/// `sys_clock_nanosleep` flag: `req` is a deadline, not a duration
+pub const TIMER_ABSTIME: usize = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+/// Read `clock` into `tp`, at microsecond resolution
+///
+/// The CPU-time clocks count the time the process was running, not the
+/// time it waited in the ready queue or slept, so benchmarks can measure
+/// themselves without the scheduling delays in wall-clock time.
+pub fn sys_clock_gettime(clock: usize, tp: *mut TimeSpec) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let us = match clock {
+        CLOCK_MONOTONIC => get_time_us(),
+        CLOCK_PROCESS_CPUTIME_ID | CLOCK_THREAD_CPUTIME_ID => inner.running_cpu_time_us(),
+        _ => return -1,
+    };
+    let ts = TimeSpec {
+        sec: us / 1_000_000,
+        nsec: us % 1_000_000 * 1000,
+    };
+    unsafe { copy_data_into_space(&ts, inner.get_user_token(), tp) }.map_or(-1, |()| 0)
+}
+
+/// Sleep for `req`, or until the time `req` with [`TIMER_ABSTIME`]; -1 at
+/// once if `req` cannot be read
+///
+/// Sleepers are woken on timer ticks, so the sleep ends up to one time
+/// slice after the deadline, unless a signal cuts it short. The sleep then
+/// fails with -4 (EINTR) if a handler runs, whatever its flags, and there
+/// is no remaining time to report: three arguments leave no room for it.
+pub fn sys_clock_nanosleep(clock: usize, flags: usize, req: *const TimeSpec) -> isize {
+    if clock != CLOCK_MONOTONIC || flags & !TIMER_ABSTIME != 0 {
+        return -1;
+    }
+    let token = current_user_token();
+    if !user_range_accessible(token, req as usize, core::mem::size_of::<TimeSpec>(), false) {
+        return -1;
+    }
+    let req: TimeSpec = match unsafe { copy_data_from_space(token, req) } {
+        Some(req) => req,
+        None => return -1,
+    };
+    if req.nsec >= 1_000_000_000 {
+        return -1;
+    }
+    let us = match req.sec.checked_mul(1_000_000) {
+        Some(us) => us.saturating_add((req.nsec + 999) / 1000),
+        None => usize::MAX,
+    };
+    let now_us = get_time_us();
+    let deadline_us = if flags & TIMER_ABSTIME != 0 {
+        us
+    } else {
+        now_us.saturating_add(us)
+    };
+    if deadline_us > now_us {
+        sleep_current_until(deadline_us);
+        // only a signal wakes a sleeper early
+        if get_time_us() < deadline_us {
+            return ERESTARTNOHAND;
+        }
+    }
+    0
+}
+
+/// Sleep for `req`, `sys_clock_nanosleep` on [`CLOCK_MONOTONIC`], so an
+/// unreadable `req` or a signal fails it the same. `rem` is never written.
+pub fn sys_nanosleep(req: *const TimeSpec, _rem: *mut TimeSpec) -> isize {
+    sys_clock_nanosleep(CLOCK_MONOTONIC, 0, req)
+}
+
+/// Send signal `signum` to process `pid`, or to a process group if `pid`
+/// is 0 or negative; signal 0 only checks that it could be sent
+pub fn sys_kill(pid: isize, signum: usize) -> isize {
+    let sent = send_signal(pid, signum);
+    audit(AuditKind::Kill, sent.is_some(), pid as usize, signum as u64);
+    match sent {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Open a descriptor of process `pid` that [`sys_ppoll`](super::fs::sys_ppoll)
+/// reports readable once the process exits; `flags` must be 0
+///
+/// It does not reap the process, that is still up to its parent.
+pub fn sys_pidfd_open(pid: usize, flags: usize) -> isize {
+    if flags != 0 {
+        return -1;
+    }
+    // a thread id is not a process
+    let task = match pid2task(pid) {
+        Some(task) if process_leader(&task).getpid() == pid => task,
+        _ => return -1,
+    };
+    let leader = process_leader(&current_task().unwrap());
+    let mut inner = leader.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return -1,
+    };
+    inner.fd_table[fd] = Some(Arc::new(PidFd::new(&task)));
+    fd as isize
+}
+
+/// Move process `pid` (0 for the caller) into group `pgid` (0 for a new
+/// group after it)
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    match set_pgid(pid, pgid) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Process group of `pid`, 0 for the caller
+pub fn sys_getpgid(pid: usize) -> isize {
+    match get_pgid(pid) {
+        Some(pgid) => pgid as isize,
+        None => -1,
+    }
+}
+
+/// Set the action of `signum` from `action` and report the old one in
+/// `old_action`; either may be null
+#[cfg(feature = "signals")]
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    let token = current_user_token();
+    let size = core::mem::size_of::<SignalAction>();
+    if !action.is_null() && !user_range_accessible(token, action as usize, size, false)
+        || !old_action.is_null() && !user_range_accessible(token, old_action as usize, size, true)
+    {
+        return -1;
+    }
+    let action = if action.is_null() {
+        None
+    } else {
+        match unsafe { copy_data_from_space(token, action) } {
+            Some(action) => Some(action),
+            None => return -1,
+        }
+    };
+    match current_task_sigaction(signum, action) {
+        Some(old) if !old_action.is_null() => {
+            unsafe { copy_data_into_space(&old, token, old_action) }.map_or(-1, |()| 0)
+        }
+        Some(_) => 0,
+        None => -1,
+    }
+}
+
+/// Block, unblock or set (`how`) the signals in `set`, returning the old
+/// mask. `SIGKILL` and `SIGSTOP` cannot be blocked.
+#[cfg(feature = "signals")]
+pub fn sys_sigprocmask(how: usize, set: usize) -> isize {
+    let set = SignalFlags::from_bits_truncate(set as u32);
+    match current_task_sigprocmask(how, set) {
+        Some(old) => old.bits() as isize,
+        None => -1,
+    }
+}
+
+/// Return from a signal handler to where the signal interrupted the task
+#[cfg(feature = "signals")]
+pub fn sys_sigreturn() -> isize {
+    current_task_sigreturn().unwrap_or(-1)
+}
+
+pub fn sys_task_info(ti_ptr: *mut TaskInfo) -> isize {
+    match get_current_task_info() {
+        Some(task_info) => {
+            unsafe { copy_data_into_space(&task_info, current_user_token(), ti_ptr) }.map_or(-1, |()| 0)
+        }
+        None => -1,
+    }
+}
+
 pub fn sys_set_priority(prio: isize) -> isize {
-    if prio <= 1 { return -1; }
-    if set_current_task_priority(prio).is_some() {
+    if prio < MIN_PRIORITY as isize {
+        return -1;
+    }
+    if set_current_task_priority(prio as u64).is_some() {
         prio
     } else {
         -1
     }
 }
 
+/// Set the nice value of the processes `which` and `who` name, see
+/// [`crate::task::set_nice`]; values beyond `-20..=19` are clamped
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> isize {
+    match set_nice(which, who, nice) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// The lowest nice value of the processes `which` and `who` name, returned
+/// as `20 - nice` like Linux does so that it never looks like an error
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    match get_nice(which, who) {
+        Some(nice) => 20 - nice,
+        None => -1,
+    }
+}
+
+/// Map `[start, start + len)` with the permissions `port`; a failure
+/// returns the [`MapError::errno`](crate::mm::MapError::errno) of its cause,
+/// -22 (EINVAL) for bad arguments, -17 (EEXIST) for an overlap and -12
+/// (ENOMEM) when out of frames.
 pub fn sys_mmap(start: usize, len: usize, port: usize) -> isize {
-    if current_task_mmap(start, len, port).is_some() {
+    match current_task_mmap(start, len, port) {
+        Ok(()) => 0,
+        Err(err) => {
+            debug!("[kernel] sys_mmap({:#x}, {:#x}, {:#b}) failed: {:?}", start, len, port, err);
+            err.errno()
+        }
+    }
+}
+
+/// Move the program break by `increment` bytes, returning the old break.
+/// `sys_sbrk(0)` just reads it.
+pub fn sys_sbrk(increment: isize) -> isize {
+    match current_task_sbrk(increment) {
+        Some(old_brk) => old_brk as isize,
+        None => -1,
+    }
+}
+
+pub fn sys_munmap(start: usize, len: usize) -> isize {
+    if current_task_munmap(start, len).is_some() {
         0
     } else {
         -1
     }
 }
 
-pub fn sys_munmap(start: usize, len: usize) -> isize {
-    if current_task_munmap(start, len).is_some() {
+/// Change the permission of the pages in `[start, start + len)`, with
+/// `port` as in `sys_mmap`
+pub fn sys_mprotect(start: usize, len: usize, port: usize) -> isize {
+    match current_task_mprotect(start, len, port) {
+        Ok(()) => 0,
+        Err(err) => {
+            debug!("[kernel] sys_mprotect({:#x}, {:#x}, {:#b}) failed: {:?}", start, len, port, err);
+            -1
+        }
+    }
+}
+
+/// Seal the mapping `[start, start + len)` against `munmap` and
+/// `mprotect`. The range must be exactly one mapping, and no flags are
+/// defined yet.
+pub fn sys_mseal(start: usize, len: usize, flags: usize) -> isize {
+    if flags != 0 || start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    if current_task_mseal(start, len).is_some() {
         0
     } else {
         -1
     }
 }
 
-pub fn sys_spawn(path: *const u8) -> isize {
+/// Store the [`PageState`](crate::mm::PageState) bits of each page of `[start, start + len)` in
+/// the byte array `vec`, one byte per page. `start` must be page-aligned.
+/// Returns the number of pages.
+pub fn sys_mincore(start: usize, len: usize, vec: *mut u8) -> isize {
+    if start % PAGE_SIZE != 0 {
+        return -1;
+    }
+    let pages = match start.checked_add(len) {
+        Some(end) => VirtAddr::from(end).ceil().0 - VirtAddr::from(start).floor().0,
+        None => return -1,
+    };
+    let token = current_user_token();
+    if !user_range_accessible(token, vec as usize, pages, true) {
+        return -1;
+    }
+    // gets `vec` its frames before the query, should it lie in the range
+    let buffer = match translated_byte_buffer(token, vec, pages, true) {
+        Some(buffer) => buffer,
+        None => return -1,
+    };
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let memory_set = inner.memory_set.exclusive_access();
+    let first = VirtAddr::from(start).floor().0;
+    for (i, byte) in buffer.into_iter().flatten().enumerate() {
+        *byte = memory_set.page_state(VirtPageNum(first + i)).bits();
+    }
+    pages as isize
+}
+
+/// `sys_shm_open` flag: create the object if it does not exist
+pub const SHM_CREATE: usize = 1;
+
+/// Open (or with [`SHM_CREATE`], create with `pages` zeroed pages) the
+/// shared memory object `name`, returning its size in pages
+pub fn sys_shm_open(name: *const u8, pages: usize, flags: usize) -> isize {
+    if flags & !SHM_CREATE != 0 {
+        return -1;
+    }
+    let name = match translated_str(current_user_token(), name, MAX_PATH_LEN) {
+        Ok(name) => name,
+        Err(_) => return -1,
+    };
+    match shm_open(&name, pages, flags & SHM_CREATE != 0) {
+        Some(object) => object.pages() as isize,
+        None => -1,
+    }
+}
+
+/// Map all of the shared memory object `name` at `start`, with `port` as in
+/// `sys_mmap`; `sys_munmap` of the whole range unmaps it again
+pub fn sys_shm_map(name: *const u8, start: usize, port: usize) -> isize {
+    let name = match translated_str(current_user_token(), name, MAX_PATH_LEN) {
+        Ok(name) => name,
+        Err(_) => return -1,
+    };
+    match current_task_shm_map(start, &name, port) {
+        Ok(()) => 0,
+        Err(err) => {
+            debug!("[kernel] sys_shm_map({}, {:#x}) failed: {:?}", name, start, err);
+            -1
+        }
+    }
+}
+
+/// Map all of the file open as `fd` from `start` on, with `port` as in
+/// `sys_mmap`, returning its size. Pages are read from the file on first
+/// access and written back by `sys_msync`, `sys_munmap` and exit.
+pub fn sys_mmap_file(fd: usize, start: usize, port: usize) -> isize {
+    match current_task_mmap_file(fd, start, port) {
+        Ok(size) => size as isize,
+        Err(err) => {
+            debug!("[kernel] sys_mmap_file({}, {:#x}) failed: {:?}", fd, start, err);
+            -1
+        }
+    }
+}
+
+/// Write the pages of `[start, start + len)` written since they were last
+/// written back to the files mapped there. `start` must be page-aligned.
+/// `flags` may have the bits of `MS_ASYNC` (1), `MS_INVALIDATE` (2) and
+/// `MS_SYNC` (4), but writing back is always synchronous.
+pub fn sys_msync(start: usize, len: usize, flags: usize) -> isize {
+    if flags & !0b111 != 0 {
+        return -1;
+    }
+    match current_task_msync(start, len) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Remove the name of a shared memory object; mappings stay valid
+pub fn sys_shm_unlink(name: *const u8) -> isize {
+    let name = match translated_str(current_user_token(), name, MAX_PATH_LEN) {
+        Ok(name) => name,
+        Err(_) => return -1,
+    };
+    match shm_unlink(&name) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// Like fork and exec in one, taking the arguments of [`sys_exec`]
+pub fn sys_spawn(path: *const u8, args: *const usize) -> isize {
     let token = current_user_token();
-    let path = translated_str(token, path);
+    let path = match translated_str(token, path, MAX_PATH_LEN) {
+        Ok(path) => path,
+        Err(err) => {
+            debug!("[kernel] sys_spawn: bad path: {:?}", err);
+            return -1;
+        }
+    };
+    let args = match translated_args(token, args) {
+        Ok(args) => args,
+        Err(err) => return err,
+    };
     // println!("[sys_spawn] path:{}", path);
-    if let Some(data) = get_app_data_by_name(path.as_str()) {
-        let task = current_task().unwrap();
-        let new_task = task.spawn(data, path.as_str());
-        let new_pid = new_task.pid.0;
-        add_task(new_task);
-        new_pid as isize
+    let task = current_task().unwrap();
+    let path = resolve_path(&task.inner_exclusive_access().cwd, &path);
+    let name = app_name(&path);
+    let new_task = match lookup_app(&path).map(|data| task.spawn(&data, name, &args)) {
+        Some(Ok(new_task)) => new_task,
+        Some(Err(err)) => {
+            debug!("[kernel] sys_spawn: cannot load {}: {:?}", path, err);
+            audit_named(AuditKind::Spawn, false, 0, 0, name);
+            return -1;
+        }
+        None => {
+            audit_named(AuditKind::Spawn, false, 0, 0, name);
+            return -1;
+        }
+    };
+    let new_pid = new_task.pid.0;
+    audit_named(AuditKind::Spawn, true, new_pid, 0, name);
+    add_task(new_task);
+    new_pid as isize
+}
+
+/// Membarrier query command: report the supported command mask
+const MEMBARRIER_CMD_QUERY: u32 = 0;
+
+pub fn sys_membarrier(cmd: u32, _flags: u32) -> isize {
+    if cmd == MEMBARRIER_CMD_QUERY {
+        return MembarrierCmd::all().bits() as isize;
+    }
+    match MembarrierCmd::from_bits(cmd) {
+        Some(cmd) if cmd.bits().count_ones() == 1 => {
+            membarrier();
+            0
+        }
+        _ => -1,
+    }
+}
+
+const KBENCH_FORK: usize = 0;
+const KBENCH_EXEC: usize = 1;
+const KBENCH_SWITCH: usize = 2;
+const KBENCH_MMAP: usize = 3;
+/// upper bound on iterations so a benchmark cannot stall the system
+const KBENCH_MAX_ITERS: usize = 1000;
+/// pages mapped and unmapped per `KBENCH_MMAP` iteration
+const KBENCH_MMAP_PAGES: usize = 16;
+
+/// Run an in-kernel micro-benchmark `iters` times and return the average cost
+/// of one iteration in `time` ticks (`CLOCK_FREQ` per second), or -1.
+///
+/// - `KBENCH_FORK`: fork the caller and reap the child before it ever runs
+/// - `KBENCH_EXEC`: build (and drop) the address space of the caller's ELF
+/// - `KBENCH_SWITCH`: a full suspend/schedule round trip back to the caller
+/// - `KBENCH_MMAP`: map and unmap `KBENCH_MMAP_PAGES` framed pages
+///
+/// Needs [`Capabilities::BENCH`], as each run holds the kernel for up to
+/// `KBENCH_MAX_ITERS` iterations.
+pub fn sys_kbench(op: usize, iters: usize) -> isize {
+    if iters == 0 || iters > KBENCH_MAX_ITERS || !current_task_capable(Capabilities::BENCH) {
+        return -1;
+    }
+    let task = current_task().unwrap();
+    let name = task.inner_exclusive_access().name.clone();
+    let elf_data = if op == KBENCH_EXEC {
+        match lookup_app(&format!("/{}", name)) {
+            Some(data) => Some(data),
+            None => return -1,
+        }
+    } else {
+        None
+    };
+    let start = get_time();
+    for _ in 0..iters {
+        match op {
+            KBENCH_FORK => {
+                let child = match task.fork() {
+                    Some(child) => child,
+                    None => return -1,
+                };
+                task.inner_exclusive_access()
+                    .children
+                    .retain(|p| !Arc::ptr_eq(p, &child));
+                release_zombie(child);
+            }
+            KBENCH_EXEC => {
+                drop(MemorySet::from_elf(elf_data.as_deref().unwrap(), &name));
+            }
+            KBENCH_SWITCH => suspend_current_and_run_next(),
+            KBENCH_MMAP => {
+                let mut memory_set = MemorySet::new_bare();
+                let start_va = VirtAddr::from(0);
+                let end_va = VirtAddr::from(KBENCH_MMAP_PAGES * PAGE_SIZE);
+                if memory_set
+                    .insert_framed_area(start_va, end_va, MapPermission::R | MapPermission::W | MapPermission::U)
+                    .is_none()
+                {
+                    return -1;
+                }
+                memory_set.unmap_area(start_va, end_va);
+            }
+            _ => return -1,
+        }
+    }
+    ((get_time() - start) / iters) as isize
+}
+
+/// upper bounds so that a workload cannot stall the system for long
+const SCHED_WORKLOAD_MAX_WORKERS: usize = 16;
+const SCHED_WORKLOAD_MAX_MS: usize = 10_000;
+
+/// Run the `n` workers of `workers` for `duration_ms` as kernel threads,
+/// see [`run_workload`], and fill in what each got of the CPU; 0, or -1
+///
+/// Needs [`Capabilities::BENCH`] like `sys_kbench`: the workers hold the
+/// kernel while they burn CPU time, and the call returns only once the run
+/// is over.
+pub fn sys_sched_workload(workers: *mut SchedWorker, n: usize, duration_ms: usize) -> isize {
+    if n == 0
+        || n > SCHED_WORKLOAD_MAX_WORKERS
+        || duration_ms == 0
+        || duration_ms > SCHED_WORKLOAD_MAX_MS
+        || !current_task_capable(Capabilities::BENCH)
+    {
+        return -1;
+    }
+    let token = current_user_token();
+    if !user_range_accessible(token, workers as usize, n * core::mem::size_of::<SchedWorker>(), true) {
+        return -1;
+    }
+    let mut specs = Vec::new();
+    for i in 0..n {
+        let worker = match unsafe { copy_data_from_space(token, workers.wrapping_add(i)) } {
+            Some(worker) => worker,
+            None => return -1,
+        };
+        if (worker.priority as u64) < MIN_PRIORITY || worker.cpu_us == 0 {
+            return -1;
+        }
+        specs.push(WorkerSpec {
+            priority: worker.priority as u64,
+            cpu_us: worker.cpu_us,
+            sleep_us: worker.sleep_us,
+        });
+    }
+    let cpu_times = match run_workload(&specs, duration_ms * 1000) {
+        Some(cpu_times) => cpu_times,
+        None => return -1,
+    };
+    let total_us = cpu_times.iter().sum::<usize>().max(1);
+    for (i, ran_us) in cpu_times.into_iter().enumerate() {
+        let spec = &specs[i];
+        let worker = SchedWorker {
+            priority: spec.priority as usize,
+            cpu_us: spec.cpu_us,
+            sleep_us: spec.sleep_us,
+            ran_us,
+            share: ran_us * 1000 / total_us,
+        };
+        if unsafe { copy_data_into_space(&worker, token, workers.wrapping_add(i)) }.is_none() {
+            return -1;
+        }
+    }
+    0
+}
+
+/// `sys_kstat` selector: the caller's [`SchedLatencyHistogram`](crate::task::SchedLatencyHistogram)
+const KSTAT_SCHED_LATENCY: usize = 0;
+/// `sys_kstat` selector: kernel-wide [`TimerStats`](crate::timer::TimerStats)
+const KSTAT_TIMER: usize = 1;
+
+/// Copy the kernel statistics selected by `what` to `buf`, returning the
+/// number of bytes written, or -1 for an unknown selector or a short buffer.
+pub fn sys_kstat(what: usize, buf: *mut u8, len: usize) -> isize {
+    match what {
+        KSTAT_SCHED_LATENCY => {
+            let histogram = current_task().unwrap().inner_exclusive_access().sched_latency;
+            copy_kstat(&histogram, buf, len)
+        }
+        KSTAT_TIMER => copy_kstat(&timer_stats(), buf, len),
+        _ => -1,
+    }
+}
+
+fn copy_kstat<T>(stat: &T, buf: *mut u8, len: usize) -> isize {
+    let size = core::mem::size_of::<T>();
+    if len < size {
+        return -1;
+    }
+    unsafe { copy_data_into_space(stat, current_user_token(), buf as *const T) }
+        .map_or(-1, |()| size as isize)
+}
+
+/// Write up to `cap` [`ChildInfo`] entries for the caller's children to `buf`
+/// and return the total number of children (which may exceed `cap`).
+pub fn sys_get_children(buf: *mut ChildInfo, cap: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let token = inner.get_user_token();
+    let size = cap.min(inner.children.len()) * core::mem::size_of::<ChildInfo>();
+    if !user_range_accessible(token, buf as usize, size, true) {
+        return -1;
+    }
+    for (i, child) in inner.children.iter().take(cap).enumerate() {
+        let child_inner = child.inner_exclusive_access();
+        let info = ChildInfo {
+            pid: child.getpid(),
+            status: child_inner.task_status as usize,
+            exit_code: child_inner.exit_code,
+        };
+        if unsafe { copy_data_into_space(&info, token, buf.wrapping_add(i)) }.is_none() {
+            return -1;
+        }
+    }
+    inner.children.len() as isize
+}
+
+/// Copy the [`TaskStat`] of process `pid` (0 for the caller) to `buf`; any
+/// process may be looked at, zombies included
+pub fn sys_task_stat(pid: usize, buf: *mut TaskStat) -> isize {
+    let token = current_user_token();
+    if !user_range_accessible(token, buf as usize, core::mem::size_of::<TaskStat>(), true) {
+        return -1;
+    }
+    match get_task_stat(pid) {
+        Some(stat) => unsafe { copy_data_into_space(&stat, token, buf) }.map_or(-1, |()| 0),
+        None => -1,
+    }
+}
+
+/// Write the pids of up to `cap` processes to `buf`, in increasing order,
+/// and return the total number of processes (which may exceed `cap`)
+pub fn sys_task_list(buf: *mut usize, cap: usize) -> isize {
+    let token = current_user_token();
+    let pids = process_pids();
+    let size = cap.min(pids.len()) * core::mem::size_of::<usize>();
+    if !user_range_accessible(token, buf as usize, size, true) {
+        return -1;
+    }
+    for (i, pid) in pids.iter().take(cap).enumerate() {
+        match translated_refmut(token, buf.wrapping_add(i)) {
+            Some(slot) => *slot = *pid,
+            None => return -1,
+        }
+    }
+    pids.len() as isize
+}
+
+/// Copy up to `cap` [`AreaInfo`]s describing the caller's address space to
+/// `buf` and return the total number of areas (which may exceed `cap`).
+pub fn sys_get_maps(buf: *mut AreaInfo, cap: usize) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let token = inner.get_user_token();
+    let infos = inner.memory_set.exclusive_access().area_infos();
+    for (i, info) in infos.iter().take(cap).enumerate() {
+        if unsafe { copy_data_into_space(info, token, buf.wrapping_add(i)) }.is_none() {
+            return -1;
+        }
+    }
+    infos.len() as isize
+}
+
+/// Print the process tree to the kernel console
+pub fn sys_dump_proc_tree() -> isize {
+    if !current_task_capable(Capabilities::KLOG) {
+        return -1;
+    }
+    dump_process_tree();
+    0
+}
+
+/// Check parent/child links of the whole process tree, returning the number
+/// of problems (each one is printed to the kernel console)
+pub fn sys_check_proc_tree() -> isize {
+    check_process_tree() as isize
+}
+
+/// Set the kernel log level, see [`crate::logging::set_level`]
+pub fn sys_set_log_level(level: usize) -> isize {
+    if !current_task_capable(Capabilities::KLOG) {
+        return -1;
+    }
+    match crate::logging::set_level(level) {
+        Some(()) => 0,
+        None => -1,
+    }
+}
+
+/// `sys_trace` command: trace exactly the subsystems in `arg`, a
+/// [`TraceSubsys`] mask, returning the previous mask
+pub const TRACE_SET_MASK: usize = 0;
+/// `sys_trace` command: print and clear the buffered events, returning
+/// their number
+pub const TRACE_DUMP: usize = 1;
+
+pub fn sys_trace(cmd: usize, arg: usize) -> isize {
+    if !current_task_capable(Capabilities::KLOG) {
+        return -1;
+    }
+    match cmd {
+        TRACE_SET_MASK => match TraceSubsys::from_bits(arg as u32) {
+            Some(mask) if arg <= u32::MAX as usize => trace::set_enabled(mask).bits() as isize,
+            _ => -1,
+        },
+        TRACE_DUMP => trace::dump() as isize,
+        _ => -1,
+    }
+}
+
+/// Copy the caller's [`CapSet`] to `buf`
+pub fn sys_capget(buf: *mut CapSet) -> isize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    unsafe { copy_data_into_space(&inner.caps, inner.get_user_token(), buf) }.map_or(-1, |()| 0)
+}
+
+/// Replace the caller's capabilities; they can only ever shrink
+pub fn sys_capset(effective: u32, inheritable: u32) -> isize {
+    let new = match (
+        Capabilities::from_bits(effective),
+        Capabilities::from_bits(inheritable),
+    ) {
+        (Some(effective), Some(inheritable)) => CapSet {
+            effective,
+            inheritable,
+        },
+        _ => return -1,
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let allowed = inner.caps.can_become(&new);
+    if allowed {
+        inner.caps = new;
+    }
+    drop(inner);
+    let arg = (effective as u64) << 32 | inheritable as u64;
+    audit(AuditKind::CapSet, allowed, task.getpid(), arg);
+    if allowed {
+        0
     } else {
         -1
     }
 }
+
+/// Move up to `count` of the oldest audit records to `buf`, returning how
+/// many were moved; needs [`Capabilities::AUDIT`]
+pub fn sys_audit_read(buf: *mut AuditRecord, count: usize) -> isize {
+    if !current_task_capable(Capabilities::AUDIT) {
+        return -1;
+    }
+    let token = current_user_token();
+    let count = count.min(AUDIT_RECORDS);
+    let size = count * core::mem::size_of::<AuditRecord>();
+    if !user_range_accessible(token, buf as usize, size, true) {
+        return -1;
+    }
+    let records = take_records(count);
+    for (i, record) in records.iter().enumerate() {
+        if unsafe { copy_data_into_space(record, token, buf.wrapping_add(i)) }.is_none() {
+            return -1;
+        }
+    }
+    records.len() as isize
+}
@@ -0,0 +1,43 @@
+//! Mutex syscalls, see [`crate::sync::Mutex`]
+
+use crate::sync::Mutex;
+use crate::task::{current_task, process_leader};
+use alloc::sync::Arc;
+
+/// Create a mutex for the current process and return its id, -1 if the
+/// process has as many as it may; `blocking` for lockers to sleep until it
+/// is free rather than yield
+pub fn sys_mutex_create(blocking: bool) -> isize {
+    let leader = process_leader(&current_task().unwrap());
+    let id = leader.inner_exclusive_access().add_mutex(blocking);
+    match id {
+        Some(id) => id as isize,
+        None => -1,
+    }
+}
+
+/// Mutex `id` of the current process, taken out of the table so as not to
+/// hold the main thread's inner while waiting for it
+fn process_mutex(id: usize) -> Option<Arc<Mutex>> {
+    let leader = process_leader(&current_task().unwrap());
+    let mutex = leader.inner_exclusive_access().mutexes.get(id).cloned();
+    mutex
+}
+
+/// Lock mutex `id`, waiting while another thread holds it; -1 if there is
+/// no such mutex or the caller holds it already
+pub fn sys_mutex_lock(id: usize) -> isize {
+    match process_mutex(id) {
+        Some(mutex) => mutex.lock(),
+        None => -1,
+    }
+}
+
+/// Unlock mutex `id`; -1 if there is no such mutex or the caller does not
+/// hold it
+pub fn sys_mutex_unlock(id: usize) -> isize {
+    match process_mutex(id) {
+        Some(mutex) => mutex.unlock(),
+        None => -1,
+    }
+}
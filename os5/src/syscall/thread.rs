@@ -0,0 +1,25 @@
+//! Thread syscalls, see [`crate::task`] for how threads share a process
+
+use crate::task::{create_thread, current_task, wait_thread};
+
+/// Start a thread running `entry(arg)` in the current process and return
+/// its thread id, or -1 if the process cannot have more threads
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    match create_thread(entry, arg) {
+        Some(tid) => tid as isize,
+        None => -1,
+    }
+}
+
+pub fn sys_gettid() -> isize {
+    current_task().unwrap().getpid() as isize
+}
+
+/// Wait for thread `tid` of the current process to exit and return its exit
+/// code, or -1 if there is no such thread other than the caller
+pub fn sys_waittid(tid: usize) -> isize {
+    match wait_thread(tid) {
+        Some(exit_code) => exit_code as isize,
+        None => -1,
+    }
+}
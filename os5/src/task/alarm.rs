@@ -0,0 +1,110 @@
+//! Real-time interval timers (`ITIMER_REAL`)
+//!
+//! A process has at most one armed interval timer. It sends the process
+//! `SIGALRM` when it expires and rearms itself if it has an interval.
+//! [`fire_alarms`] runs wherever the sleepers are woken, so an alarm fires
+//! at most one timer tick late; a sleeping process sees the signal once
+//! its sleep is over.
+
+use super::{pid2task, SignalFlags};
+use crate::sync::UPSafeCell;
+use crate::timer::get_time_us;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use lazy_static::*;
+
+#[derive(Copy, Clone)]
+struct Alarm {
+    deadline_us: usize,
+    /// 0 for a one-shot alarm
+    interval_us: usize,
+}
+
+impl Debug for Alarm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Alarm({}us every {}us)", self.deadline_us, self.interval_us)
+    }
+}
+
+lazy_static! {
+    /// Armed alarms by pid
+    static ref ALARMS: UPSafeCell<BTreeMap<usize, Alarm>> =
+        unsafe { UPSafeCell::new_labeled("ALARMS", BTreeMap::new()) };
+}
+
+/// Time left and interval of the alarm of `pid`, both 0 if it is not armed
+pub fn get_alarm(pid: usize) -> (usize, usize) {
+    let now_us = get_time_us();
+    ALARMS.exclusive_access().get(&pid).map_or((0, 0), |alarm| {
+        (alarm.deadline_us.saturating_sub(now_us).max(1), alarm.interval_us)
+    })
+}
+
+/// Arm the alarm of `pid` to expire in `value_us` and then every
+/// `interval_us`, or disarm it if `value_us` is 0; returns the old setting
+/// as [`get_alarm`] does
+pub fn set_alarm(pid: usize, value_us: usize, interval_us: usize) -> (usize, usize) {
+    let old = get_alarm(pid);
+    let mut alarms = ALARMS.exclusive_access();
+    if value_us == 0 {
+        alarms.remove(&pid);
+    } else {
+        let alarm = Alarm {
+            deadline_us: get_time_us().saturating_add(value_us),
+            interval_us,
+        };
+        alarms.insert(pid, alarm);
+    }
+    old
+}
+
+/// Disarm the alarm of an exiting process
+pub fn cancel_alarm(pid: usize) {
+    ALARMS.exclusive_access().remove(&pid);
+}
+
+/// Send `SIGALRM` for every expired alarm, rearming the periodic ones
+pub fn fire_alarms() {
+    let now_us = get_time_us();
+    let mut alarms = ALARMS.exclusive_access();
+    let expired: Vec<usize> = alarms
+        .iter()
+        .filter(|(_, alarm)| alarm.deadline_us <= now_us)
+        .map(|(pid, _)| *pid)
+        .collect();
+    for pid in expired.iter() {
+        let alarm = alarms.get_mut(pid).unwrap();
+        if alarm.interval_us == 0 {
+            alarms.remove(pid);
+            continue;
+        }
+        alarm.deadline_us = alarm.deadline_us.saturating_add(alarm.interval_us);
+        // ticks missed altogether are not made up for
+        if alarm.deadline_us <= now_us {
+            alarm.deadline_us = now_us.saturating_add(alarm.interval_us);
+        }
+    }
+    drop(alarms);
+    for pid in expired {
+        if let Some(task) = pid2task(pid) {
+            trace_event!(SCHED, "pid {} alarm", pid);
+            task.inner_exclusive_access()
+                .signals
+                .post(SignalFlags::SIGALRM.number());
+        }
+    }
+}
+
+/// Print every armed alarm
+pub fn dump_alarms() {
+    let now_us = get_time_us();
+    for (pid, alarm) in ALARMS.exclusive_access().iter() {
+        println!(
+            "  alarm    pid {:<5} in {:>10}us, every {}us",
+            pid,
+            alarm.deadline_us as isize - now_us as isize,
+            alarm.interval_us
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Bucketed ready queue, the [`Scheduler`] of the `sched-bands` feature
+//!
+//! Instead of ordering tasks by stride, tasks are kept in one FIFO per
+//! priority band and a bitmap records which bands are nonempty, so both `add`
+//! and `fetch` are O(1). The policy is strict priority: the highest nonempty
+//! band always runs first, and tasks of the same band take turns in the order
+//! they became ready.
+
+use super::scheduler::Scheduler;
+use super::task::TaskControlBlockInner;
+use super::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+
+/// Number of priority bands; priorities above the last band share it
+pub const PRIORITY_BANDS: usize = 64;
+
+pub struct PriorityBands {
+    bands: [VecDeque<Arc<TaskControlBlock>>; PRIORITY_BANDS],
+    /// Bit `i` is set when `bands[i]` is nonempty
+    nonempty: u64,
+}
+
+/// The band a task is queued in
+fn band_of(inner: &TaskControlBlockInner) -> usize {
+    // pinned tasks run before anything of lower priority can starve them
+    if inner.pinned {
+        PRIORITY_BANDS - 1
+    } else {
+        inner.priority.min(PRIORITY_BANDS as u64 - 1) as usize
+    }
+}
+
+impl Scheduler for PriorityBands {
+    fn new() -> Self {
+        Self {
+            bands: [(); PRIORITY_BANDS].map(|_| VecDeque::new()),
+            nonempty: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let band = band_of(&task.inner_exclusive_access());
+        self.bands[band].push_back(task);
+        self.nonempty |= 1 << band;
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        if self.nonempty == 0 {
+            return None;
+        }
+        let band = 63 - self.nonempty.leading_zeros() as usize;
+        let task = self.bands[band].pop_front();
+        if self.bands[band].is_empty() {
+            self.nonempty &= !(1 << band);
+        }
+        task
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        for (band, queue) in self.bands.iter_mut().enumerate() {
+            if let Some(index) = queue.iter().position(|queued| Arc::ptr_eq(queued, task)) {
+                queue.remove(index);
+                if queue.is_empty() {
+                    self.nonempty &= !(1 << band);
+                }
+                return true;
+            }
+        }
+        false
+    }
+    fn len(&self) -> usize {
+        self.bands.iter().map(|queue| queue.len()).sum()
+    }
+    /// The higher band first
+    fn urgency_cmp(a: &TaskControlBlockInner, b: &TaskControlBlockInner) -> Ordering {
+        band_of(b).cmp(&band_of(a))
+    }
+}
@@ -0,0 +1,55 @@
+//! Minimal capability model
+
+bitflags! {
+    /// privileges a task may hold, checked by the privileged syscalls
+    pub struct Capabilities: u32 {
+        /// signal or kill tasks other than its own children
+        const KILL_ANY = 1 << 0;
+        /// change the priority of tasks other than itself
+        const SET_PRIORITY_ANY = 1 << 1;
+        /// power off the machine with `sys_shutdown`
+        const SHUTDOWN = 1 << 2;
+        /// read the kernel log and change its level, dump kernel state
+        const KLOG = 1 << 3;
+        /// read the audit trail
+        const AUDIT = 1 << 4;
+        /// run the in-kernel benchmarks of `sys_kbench`
+        const BENCH = 1 << 5;
+        /// stop and start harts with `sys_hart_ctl`, route interrupts to
+        /// them with `sys_irq_route`
+        const HOTPLUG = 1 << 6;
+    }
+}
+
+/// What `sys_capget` reports and `sys_capset` takes
+///
+/// `effective` is what the task may do now; only the `inheritable` subset
+/// survives `exec`. Both are inherited unchanged by `fork` and `spawn`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CapSet {
+    pub effective: Capabilities,
+    pub inheritable: Capabilities,
+}
+
+impl CapSet {
+    /// Everything, as given to initproc
+    pub fn full() -> Self {
+        Self {
+            effective: Capabilities::all(),
+            inheritable: Capabilities::all(),
+        }
+    }
+    /// Whether the task may switch to `new`: capabilities can only be
+    /// dropped, and nothing can be inheritable without being effective
+    pub fn can_become(&self, new: &CapSet) -> bool {
+        self.effective.contains(new.effective) && new.effective.contains(new.inheritable)
+    }
+    /// The set left after `exec`
+    pub fn after_exec(&self) -> Self {
+        Self {
+            effective: self.effective & self.inheritable,
+            inheritable: self.inheritable,
+        }
+    }
+}
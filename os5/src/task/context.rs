@@ -22,6 +22,18 @@ impl TaskContext {
             s: [0; 12],
         }
     }
+    /// Where the stack pointer will be once switched to
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+    /// Start at `entry` on the stack topped by `kstack_ptr`, for kernel threads
+    pub fn goto(entry: usize, kstack_ptr: usize) -> Self {
+        Self {
+            ra: entry,
+            sp: kstack_ptr,
+            s: [0; 12],
+        }
+    }
     pub fn goto_trap_return(kstack_ptr: usize) -> Self {
         Self {
             ra: trap_return as usize,
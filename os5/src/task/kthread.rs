@@ -0,0 +1,91 @@
+//! Kernel threads
+//!
+//! A kernel thread is a [`TaskControlBlock`] that never leaves the kernel:
+//! it starts at a kernel function on its kernel stack instead of returning
+//! to user mode, but unlike the [scrubber](super::scrubber), which only runs
+//! on an idle hart, it is queued and scheduled like any task, under the same
+//! policy and priorities. Nothing preempts kernel code, so a kernel thread
+//! that keeps the CPU has to call [`kthread_tick`] itself every time slice.
+
+use super::processor::schedule;
+use super::{add_task, current_task, drop_zombie, current_task_tick, suspend_current_and_run_next};
+use super::{take_current_task, wake_sleepers, wake_up, TaskContext, TaskControlBlock, TaskStatus};
+use crate::sync::UPSafeCell;
+use crate::timer::get_time_us;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+type KthreadEntry = Box<dyn FnOnce() + Send>;
+
+lazy_static! {
+    /// What each kernel thread that has not started yet is to run, by pid
+    static ref KTHREAD_ENTRIES: UPSafeCell<BTreeMap<usize, KthreadEntry>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Start a kernel thread `name` of `priority` running `f`, a child of the
+/// current task; `None` if there are no frames for its kernel stack
+///
+/// The thread exits once `f` returns. The caller keeps the handle until
+/// then, waiting on its own `child_exit` for the thread to be a zombie, and
+/// drops it with [`release_kthread`].
+pub fn spawn_kthread(
+    name: &str,
+    priority: u64,
+    f: impl FnOnce() + Send + 'static,
+) -> Option<Arc<TaskControlBlock>> {
+    let kthread = current_task().unwrap().new_kthread(name, kthread_main as usize)?;
+    kthread.inner_exclusive_access().set_priority(priority);
+    KTHREAD_ENTRIES
+        .exclusive_access()
+        .insert(kthread.getpid(), Box::new(f));
+    add_task(kthread.clone());
+    Some(kthread)
+}
+
+/// Account a timer tick to the current kernel thread as the timer interrupt
+/// does for a user task: wake the sleepers whose time has come, and switch
+/// away once the time slice is used up
+pub fn kthread_tick() {
+    wake_sleepers();
+    if current_task_tick() {
+        suspend_current_and_run_next();
+    }
+}
+
+/// Drop a kernel thread that has exited, checking for leaks as
+/// [`release_zombie`](super::release_zombie) does
+pub fn release_kthread(kthread: Arc<TaskControlBlock>) {
+    assert!(kthread.inner_exclusive_access().is_zombie());
+    drop_zombie(kthread);
+}
+
+fn kthread_main() -> ! {
+    let pid = current_task().unwrap().getpid();
+    let f = KTHREAD_ENTRIES.exclusive_access().remove(&pid).unwrap();
+    f();
+    exit_kthread()
+}
+
+/// End the current kernel thread, waking its parent
+///
+/// The parent's handle keeps the kernel stack alive until the switch away
+/// from it is done.
+fn exit_kthread() -> ! {
+    let task = take_current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.set_status(TaskStatus::Zombie);
+    inner.cpu_time_us += get_time_us() - inner.dispatched_at_us;
+    let parent = inner.parent.as_ref().and_then(|parent| parent.upgrade());
+    drop(inner);
+    if let Some(parent) = parent {
+        let waiters = parent.inner_exclusive_access().child_exit.take();
+        wake_up(waiters);
+    }
+    drop(task);
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+    unreachable!("a zombie kernel thread was switched back to")
+}
@@ -4,83 +4,118 @@
 //! Other CPU process monitoring functions are in Processor.
 
 
+#[cfg(feature = "sched-bands")]
+use super::bands::PriorityBands;
+#[cfg(feature = "sched-fifo")]
+use super::scheduler::FifoScheduler;
+use super::scheduler::Scheduler;
+#[cfg(feature = "sched-mlfq")]
+use super::mlfq::Mlfq;
+#[cfg(not(any(feature = "sched-bands", feature = "sched-fifo", feature = "sched-mlfq")))]
+use super::stride::StrideScheduler;
+use super::task::TaskControlBlockInner;
 use super::TaskControlBlock;
 use crate::sync::UPSafeCell;
-use alloc::collections::{BinaryHeap, VecDeque};
+use alloc::collections::BTreeMap;
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cmp::Ordering;
-use core::fmt::{Debug, Formatter};
 use core::iter::Map;
 use lazy_static::*;
-
-struct StrideComparator(Arc<TaskControlBlock>);
-
-impl Eq for StrideComparator {}
-
-impl PartialEq<Self> for StrideComparator {
-    fn eq(&self, other: &Self) -> bool {
-        let stride1 = self.0.inner_exclusive_access().stride;
-        let stride2 =other.0.inner_exclusive_access().stride;
-        stride1 == stride2
-    }
+use spin::Mutex;
+use crate::timer::{get_time_us, TIME_SLICE_US};
+
+/// Pass of a priority-1 task over one full time slice
+///
+/// Priorities are at least [`MIN_PRIORITY`], so no single charge exceeds
+/// `BIG_STRIDE / 2` and the strides of all ready tasks stay within
+/// `BIG_STRIDE / 2` of each other. That keeps [`stride_cmp`] correct after
+/// the counters wrap.
+pub const BIG_STRIDE: u64 = 6469693230;
+
+/// Smallest priority `set_priority` accepts
+pub const MIN_PRIORITY: u64 = 2;
+
+/// Priority of initproc and of tasks that never called `set_priority`
+pub const DEFAULT_PRIORITY: u64 = 16;
+
+/// Longest time slice, in timer ticks
+pub const MAX_SLICE_TICKS: usize = 8;
+
+/// Timer ticks a task of `priority` may run per dispatch: one per
+/// [`DEFAULT_PRIORITY`], at least one and at most [`MAX_SLICE_TICKS`]
+pub fn slice_ticks(priority: u64) -> usize {
+    (priority / DEFAULT_PRIORITY).clamp(1, MAX_SLICE_TICKS as u64) as usize
 }
 
-impl PartialOrd<Self> for StrideComparator {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let stride1 = self.0.inner_exclusive_access().stride;
-        let stride2 =other.0.inner_exclusive_access().stride;
-        // reverse the order for BinaryHeap
-        stride2.partial_cmp(&stride1)
-    }
+/// Charge a task that is being descheduled for the CPU time it actually used
+///
+/// A full time slice costs `BIG_STRIDE / priority`, shorter runs cost
+/// proportionally less (at least one microsecond's worth), so tasks that
+/// block or yield early are not billed a whole pass. Longer runs are billed
+/// as using up their whole budget of [`slice_ticks`]; since that is at most
+/// `priority / DEFAULT_PRIORITY` slices, the charge stays within the bound
+/// [`stride_cmp`] relies on. Every run costs at least 1, so even a task of
+/// a priority beyond `BIG_STRIDE` moves forward.
+pub fn charge_stride(inner: &mut TaskControlBlockInner, now_us: usize) {
+    let budget_us = TIME_SLICE_US * slice_ticks(inner.priority);
+    let ran_us = now_us
+        .saturating_sub(inner.dispatched_at_us)
+        .clamp(1, budget_us) as u64;
+    let pass = (BIG_STRIDE / inner.priority * ran_us / TIME_SLICE_US as u64).max(1);
+    inner.stride = inner.stride.wrapping_add(pass);
 }
 
-impl Ord for StrideComparator {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let stride1 = self.0.inner_exclusive_access().stride;
-        let stride2 = other.0.inner_exclusive_access().stride;
-        // reverse the order for BinaryHeap
-        stride2.cmp(&stride1)
-    }
-
-    fn max(self, other: Self) -> Self where Self: Sized {
-        let stride1 = self.0.inner_exclusive_access().stride;
-        let stride2 = other.0.inner_exclusive_access().stride;
-        // reverse the order for BinaryHeap
-        if stride1 < stride2 {
-            self
-        } else {
-            other
-        }
-    }
-
-    fn min(self, other: Self) -> Self where Self: Sized {
-        let stride1 = self.0.inner_exclusive_access().stride;
-        let stride2 = other.0.inner_exclusive_access().stride;
-        // reverse the order for BinaryHeap
-        if stride1 > stride2 {
-            self
-        } else {
-            other
-        }
-    }
+/// Compare two strides that may have wrapped around `u64::MAX`
+///
+/// Valid as long as the true values are less than `2^63` apart, which
+/// [`BIG_STRIDE`] guarantees by a wide margin.
+pub fn stride_cmp(a: u64, b: u64) -> Ordering {
+    (a.wrapping_sub(b) as i64).cmp(&0)
+}
 
-    fn clamp(self, min: Self, max: Self) -> Self where Self: Sized {
-        let stride = self.0.inner_exclusive_access().stride;
-        let min_stride = min.0.inner_exclusive_access().stride;
-        let max_stride = max.0.inner_exclusive_access().stride;
-        if stride < min_stride {
-            min
-        } else if stride > max_stride {
-            max
-        } else {
-            self
-        }
+/// Check [`stride_cmp`] and the pass arithmetic across the `u64` wrap
+pub fn stride_test() {
+    assert_eq!(stride_cmp(5, 5), Ordering::Equal);
+    assert_eq!(stride_cmp(u64::MAX, 1), Ordering::Less);
+    assert_eq!(stride_cmp(1, u64::MAX), Ordering::Greater);
+    assert_eq!(stride_cmp(u64::MAX - BIG_STRIDE / 2, BIG_STRIDE / 2), Ordering::Less);
+    // two tasks of priority 2 and 4 starting just below the wrap: always
+    // running the smaller stride must give the second twice the CPU
+    let priorities = [2u64, 4];
+    let mut strides = [u64::MAX - 10 * BIG_STRIDE; 2];
+    let mut runs = [0usize; 2];
+    for _ in 0..3000 {
+        let next = if stride_cmp(strides[0], strides[1]) == Ordering::Greater { 1 } else { 0 };
+        runs[next] += 1;
+        strides[next] = strides[next].wrapping_add(BIG_STRIDE / priorities[next]);
+        let gap = strides[0].wrapping_sub(strides[1]) as i64;
+        assert!(gap.unsigned_abs() <= BIG_STRIDE / 2);
     }
+    assert!(strides[0] < u64::MAX - 10 * BIG_STRIDE, "strides did not wrap");
+    assert_eq!(runs, [1000, 2000]);
+    info!("stride_test passed!");
 }
 
+#[cfg(any(
+    all(feature = "sched-bands", feature = "sched-fifo"),
+    all(feature = "sched-bands", feature = "sched-mlfq"),
+    all(feature = "sched-fifo", feature = "sched-mlfq"),
+))]
+compile_error!("at most one of the `sched-*` features may be enabled");
+
+#[cfg(feature = "sched-bands")]
+type SchedulerImpl = PriorityBands;
+#[cfg(feature = "sched-fifo")]
+type SchedulerImpl = FifoScheduler;
+#[cfg(feature = "sched-mlfq")]
+type SchedulerImpl = Mlfq;
+#[cfg(not(any(feature = "sched-bands", feature = "sched-fifo", feature = "sched-mlfq")))]
+type SchedulerImpl = StrideScheduler;
+
 pub struct TaskManager {
-    ready_queue: BinaryHeap<StrideComparator>,
+    ready_queue: SchedulerImpl,
 }
 
 // YOUR JOB: FIFO->Stride
@@ -88,21 +123,19 @@ pub struct TaskManager {
 impl TaskManager {
     pub fn new() -> Self {
         Self {
-            ready_queue: BinaryHeap::new(),
+            ready_queue: SchedulerImpl::new(),
         }
     }
     /// Add process back to ready queue
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
-        let stride = task.inner_exclusive_access().stride;
-        self.ready_queue.push(StrideComparator(task));
+        task.inner_exclusive_access().ready_since_us = get_time_us();
+        self.ready_queue.add(task);
     }
 
-    /// Take a process out of the ready queue
+    /// Take a process out of the ready queue, see [`Scheduler::on_dispatch`]
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        let task = self.ready_queue.pop()?.0;
-        let mut inner = task.inner_exclusive_access();
-        inner.stride += 6469693230 / inner.priority;
-        drop(inner);
+        let task = self.ready_queue.fetch()?;
+        self.ready_queue.on_dispatch(&mut task.inner_exclusive_access());
         Some(task)
 
 
@@ -124,24 +157,79 @@ impl TaskManager {
         //     task
         // })
     }
-}
 
-impl Debug for TaskManager {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        write!(f, "TaskManager")
+    /// Take a specific process out of the ready queue, if it is there
+    #[allow(unused)]
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.ready_queue.remove(task)
+    }
+
+    /// Account a timer tick to the running process, see [`Scheduler::on_tick`]
+    pub fn tick(&mut self, current: &mut TaskControlBlockInner) -> bool {
+        self.ready_queue.on_tick(current)
+    }
+
+    /// Number of ready processes
+    pub fn len(&self) -> usize {
+        self.ready_queue.len()
     }
 }
 
 lazy_static! {
     /// TASK_MANAGER instance through lazy_static!
-    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
-        unsafe { UPSafeCell::new(TaskManager::new()) };
+    ///
+    /// Idle harts look at it without the kernel lock, so it has a lock of
+    /// its own, always taken after the kernel lock.
+    pub static ref TASK_MANAGER: Mutex<TaskManager> = Mutex::new(TaskManager::new());
+    /// Every process from its creation until it is reaped, by pid
+    pub static ref PID2TCB: UPSafeCell<BTreeMap<usize, Arc<TaskControlBlock>>> =
+        unsafe { UPSafeCell::new_labeled("PID2TCB", BTreeMap::new()) };
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
-    TASK_MANAGER.exclusive_access().add(task);
+    TASK_MANAGER.lock().add(task);
+}
+
+/// Which of two tasks the scheduler would rather run, see
+/// [`Scheduler::urgency_cmp`]
+pub fn urgency_cmp(a: &Arc<TaskControlBlock>, b: &Arc<TaskControlBlock>) -> Ordering {
+    SchedulerImpl::urgency_cmp(&a.inner_exclusive_access(), &b.inner_exclusive_access())
 }
 
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
-    TASK_MANAGER.exclusive_access().fetch()
+    TASK_MANAGER.lock().fetch()
+}
+
+/// Number of ready processes, for harts waiting for one
+pub fn ready_count() -> usize {
+    TASK_MANAGER.lock().len()
+}
+
+/// The process `pid`, live or zombie
+pub fn pid2task(pid: usize) -> Option<Arc<TaskControlBlock>> {
+    PID2TCB.exclusive_access().get(&pid).cloned()
+}
+
+/// Every process that has not been reaped yet, by pid
+pub fn tasks() -> Vec<Arc<TaskControlBlock>> {
+    PID2TCB.exclusive_access().values().cloned().collect()
+}
+
+pub fn insert_into_pid2task(pid: usize, task: Arc<TaskControlBlock>) {
+    PID2TCB.exclusive_access().insert(pid, task);
+}
+
+pub fn remove_from_pid2task(pid: usize) {
+    if PID2TCB.exclusive_access().remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2task!", pid);
+    }
+}
+
+/// Account a timer tick to the current task, returning whether it should
+/// give up the CPU, see also [`extra_preemption`](super::extra_preemption)
+pub fn current_task_tick() -> bool {
+    let task = super::current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let preempt = TASK_MANAGER.lock().tick(&mut inner);
+    preempt || super::extra_preemption()
 }
@@ -0,0 +1,102 @@
+//! Multi-level feedback queue, the [`Scheduler`] of the `sched-mlfq` feature
+//!
+//! New tasks start in the top queue. A task that uses up the quantum of its
+//! level is demoted one level; yielding early keeps both its level and the
+//! ticks it has left. Every [`MLFQ_BOOST_TICKS`] ticks all tasks go back to
+//! the top so that long-running tasks cannot starve.
+
+use super::scheduler::Scheduler;
+use super::task::TaskControlBlockInner;
+use super::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+
+/// Number of queues, level 0 runs first
+pub const MLFQ_LEVELS: usize = 4;
+
+/// Timer ticks a task may run at each level before it is demoted
+const MLFQ_QUANTUM: [usize; MLFQ_LEVELS] = [1, 2, 4, 8];
+
+/// Period of the priority boost, in timer ticks
+pub const MLFQ_BOOST_TICKS: usize = 100;
+
+pub struct Mlfq {
+    queues: [VecDeque<Arc<TaskControlBlock>>; MLFQ_LEVELS],
+    /// Timer ticks since the last boost
+    ticks: usize,
+}
+
+impl Mlfq {
+    /// Move every queued task to the top level with a fresh quantum
+    fn boost(&mut self) {
+        for level in 1..MLFQ_LEVELS {
+            while let Some(task) = self.queues[level].pop_front() {
+                self.queues[0].push_back(task);
+            }
+        }
+        for task in self.queues[0].iter() {
+            let mut inner = task.inner_exclusive_access();
+            inner.sched_level = 0;
+            inner.slice_ticks_left = MLFQ_QUANTUM[0];
+        }
+    }
+}
+
+impl Scheduler for Mlfq {
+    fn new() -> Self {
+        Self {
+            queues: [(); MLFQ_LEVELS].map(|_| VecDeque::new()),
+            ticks: 0,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut inner = task.inner_exclusive_access();
+        let level = inner.sched_level.min(MLFQ_LEVELS - 1);
+        if inner.slice_ticks_left == 0 {
+            inner.slice_ticks_left = MLFQ_QUANTUM[level];
+        }
+        drop(inner);
+        self.queues[level].push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.queues.iter_mut().find_map(|queue| queue.pop_front())
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        for queue in self.queues.iter_mut() {
+            if let Some(index) = queue.iter().position(|queued| Arc::ptr_eq(queued, task)) {
+                queue.remove(index);
+                return true;
+            }
+        }
+        false
+    }
+    fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+    /// The quantum is set in `add` and carried over an early yield
+    fn on_dispatch(&mut self, _next: &mut TaskControlBlockInner) {}
+    /// The lower level first, in the order `fetch` looks at the queues
+    fn urgency_cmp(a: &TaskControlBlockInner, b: &TaskControlBlockInner) -> Ordering {
+        a.sched_level.cmp(&b.sched_level)
+    }
+    fn on_tick(&mut self, current: &mut TaskControlBlockInner) -> bool {
+        self.ticks += 1;
+        if self.ticks == MLFQ_BOOST_TICKS {
+            self.ticks = 0;
+            self.boost();
+            current.sched_level = 0;
+            current.slice_ticks_left = MLFQ_QUANTUM[0];
+            return true;
+        }
+        current.slice_ticks_left = current.slice_ticks_left.saturating_sub(1);
+        if current.slice_ticks_left > 0 {
+            return false;
+        }
+        // pinned tasks stay on top
+        if !current.pinned {
+            current.sched_level = (current.sched_level + 1).min(MLFQ_LEVELS - 1);
+        }
+        true
+    }
+}
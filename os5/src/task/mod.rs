@@ -4,51 +4,110 @@
 //! (such as syscall or clock interrupt).
 //! By suspending or exiting the current process, you can
 //! modify the process state, manage the process queue through TASK_MANAGER,
-//! and switch the control flow through PROCESSOR.
+//! and switch the control flow through the PROCESSOR of each hart.
 //!
-//! Be careful when you see [`__switch`]. Control flow around this function
+//! Be careful when you see [`switch`]. Control flow around this function
 //! might not be what you expect.
 
+mod alarm;
+#[cfg(feature = "sched-bands")]
+mod bands;
+mod caps;
 mod context;
+mod kthread;
 mod manager;
+#[cfg(feature = "sched-mlfq")]
+mod mlfq;
+mod nice;
+mod perturb;
+mod pgrp;
 mod pid;
 mod processor;
+mod scheduler;
+mod scrubber;
+mod signal;
+mod sleep;
+#[cfg(not(any(feature = "sched-bands", feature = "sched-fifo", feature = "sched-mlfq")))]
+mod stride;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
+mod thread;
+mod wait_queue;
+mod workload;
 
-use crate::loader::get_app_data_by_name;
+use crate::fs::lookup_app;
+use crate::hart::hart_id;
+use alloc::borrow::Cow;
+use alloc::format;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
-use manager::fetch_task;
-use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus};
+use manager::{charge_stride, fetch_task, TASK_MANAGER};
+pub use manager::{stride_test, MIN_PRIORITY};
+use switch::switch;
+pub use caps::{Capabilities, CapSet};
+pub use task::{args_size, FaultStats, IoStats, SchedLatencyHistogram, SyscallTimes, TaskControlBlock, TaskStatus};
 
 pub use context::TaskContext;
-pub use manager::add_task;
-pub use pid::{pid_alloc, KernelStack, PidHandle};
+pub use manager::{add_task, current_task_tick, pid2task};
+use manager::{insert_into_pid2task, remove_from_pid2task};
+pub use signal::{current_task_catch_fault, current_task_interrupted, current_task_syscall_interrupted, handle_signals, send_signal, SignalFlags, SignalState, ERESTARTNOHAND, ERESTARTSYS};
+#[cfg(feature = "signals")]
+pub use signal::{
+    current_task_sigaction, current_task_sigprocmask, current_task_sigreturn, SignalAction,
+};
+pub use alarm::{fire_alarms, get_alarm, set_alarm};
+pub use nice::{get_nice, set_nice};
+pub use perturb::{extra_preemption, init_perturbation};
+pub use pgrp::{get_pgid, set_pgid};
+pub use sleep::{block_current_until, cancel_sleep, sleep_current_until, wake_sleepers};
+pub use thread::{create_thread, process_leader, wait_thread};
+pub use wait_queue::{block_current_and_run_next, wake_up, WaitQueue, WakeOrder};
+pub use workload::{run_workload, WorkerSpec};
+pub use pid::{check_current_kernel_stack, kernel_stack_test, pid_alloc, pid_is_free, pooled_kernel_stack_frames, KernelStack, PidHandle, StackRole};
 pub use processor::{
-    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+    current_task, current_trap_cx, current_user_token, run_tasks, running_elsewhere, schedule,
+    take_current_task,
 };
+#[cfg(not(feature = "kstack-trap-cx"))]
+pub use processor::current_trap_cx_user_va;
+#[cfg(feature = "shadow-stack")]
+pub use processor::current_shadow_stack;
+#[cfg(feature = "debug_lock")]
+pub use processor::current_pid;
+use crate::audit::{audit, AuditKind};
+use crate::bootargs::bootarg;
 use crate::config::PAGE_SIZE;
-use crate::mm::{MapPermission, VirtAddr};
-use crate::syscall::TaskInfo;
-use crate::task::processor::PROCESSOR;
-use crate::timer::{get_time_ms};
+use crate::mm::{commit_headroom, frames_free, shm_open, FaultAccess, MapError, MapPermission, PageFault, VirtAddr};
+use crate::syscall::{TaskInfo, TaskStat, TASK_STAT_NAME_LEN};
+use crate::task::processor::{current_syscall_times, this_processor};
+use crate::timer::{get_time_ms, get_time_us};
 
-/// Make current task suspended and switch to the next task
-pub fn suspend_current_and_run_next() {
+/// Take the current task off the processor with `status`, charging it for
+/// the CPU time it used; the caller queues it somewhere and calls `schedule`
+/// with the returned context
+fn switch_out_current(status: TaskStatus) -> (Arc<TaskControlBlock>, *mut TaskContext) {
     // There must be an application running.
     let task = take_current_task().unwrap();
 
     // ---- access current TCB exclusively
     let mut task_inner = task.inner_exclusive_access();
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
-    // Change status to Ready
-    task_inner.task_status = TaskStatus::Ready;
+    task_inner.set_status(status);
+    trace_event!(SCHED, "pid {} switched out, {:?}", task.pid.0, status);
+    let now_us = get_time_us();
+    task_inner.cpu_time_us += now_us - task_inner.dispatched_at_us;
+    charge_stride(&mut task_inner, now_us);
+    task_inner.stdout.flush();
     drop(task_inner);
     // ---- release current PCB
+    (task, task_cx_ptr)
+}
 
+/// Make current task suspended and switch to the next task
+pub fn suspend_current_and_run_next() {
+    let (task, task_cx_ptr) = switch_out_current(TaskStatus::Ready);
     // push back to ready queue.
     add_task(task);
     // jump to scheduling cycle
@@ -57,34 +116,65 @@ pub fn suspend_current_and_run_next() {
 
 /// Exit current task, recycle process resources and switch to the next task
 pub fn exit_current_and_run_next(exit_code: i32) {
+    // the other threads run in the address space freed below
+    thread::end_other_threads();
     // take from Processor
     let task = take_current_task().unwrap();
+    alarm::cancel_alarm(task.getpid());
+    if Arc::ptr_eq(&task, &INITPROC) {
+        initproc_exited(task, exit_code);
+        return;
+    }
     // **** access current TCB exclusively
     let mut inner = task.inner_exclusive_access();
     // println!("[exit_current_and_run_next] inner: {:?}", *inner);
     // Change status to Zombie
-    inner.task_status = TaskStatus::Zombie;
+    inner.set_status(TaskStatus::Zombie);
+    inner.cpu_time_us += get_time_us() - inner.dispatched_at_us;
     // Record exit code
     inner.exit_code = exit_code;
+    inner.stdout.flush();
     // do not move to its parent but under initproc
+    let mut waiters = Vec::new();
 
     // ++++++ access initproc TCB exclusively
     {
         // println!("[exit_current_and_run_next] getting initproc inner");
         let mut initproc_inner = INITPROC.inner_exclusive_access();
         // println!("[exit_current_and_run_next] got initproc inner");
+        let mut orphaned_zombie = false;
         for child in inner.children.iter() {
-            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+            let mut child_inner = child.inner_exclusive_access();
+            child_inner.parent = Some(Arc::downgrade(&INITPROC));
+            orphaned_zombie |= child_inner.is_zombie();
+            drop(child_inner);
             initproc_inner.children.push(child.clone());
         }
+        // initproc may be waiting to reap the orphans that already exited
+        if orphaned_zombie {
+            waiters.extend(initproc_inner.child_exit.take());
+        }
     }
+    // pollers of the pidfds of its children, which are now initproc's
+    waiters.extend(inner.child_exit.take());
     // ++++++ release parent PCB
+    if let Some(parent) = inner.parent.as_ref().and_then(|parent| parent.upgrade()) {
+        waiters.extend(parent.inner_exclusive_access().child_exit.take());
+    }
 
     inner.children.clear();
-    // deallocate user space
-    inner.memory_set.recycle_data_pages();
+    // closed once the inner is released, a pipe end wakes its waiters
+    let fd_table = core::mem::take(&mut inner.fd_table);
+    // deallocate user space, or only the slot of a thread
+    if inner.thread_slot == 0 {
+        inner.memory_set.exclusive_access().recycle_data_pages();
+    } else {
+        inner.memory_set.exclusive_access().remove_thread(inner.thread_slot);
+    }
     drop(inner);
     // **** release current PCB
+    drop(fd_table);
+    wake_up(waiters);
     // drop task manually to maintain rc correctly
     drop(task);
     // we do not have to save task context
@@ -92,89 +182,504 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     schedule(&mut _unused as *mut _);
 }
 
-#[inline]
-pub fn get_current_pid() -> Option<usize> {
-    PROCESSOR.exclusive_access().current().map(|task| task.pid.0)
+/// Drop a zombie that its parent has just reaped
+pub fn release_zombie(child: Arc<TaskControlBlock>) {
+    remove_from_pid2task(child.getpid());
+    drop_zombie(child);
+}
+
+/// Drop the last handle to a zombie that is no longer in the pid table
+///
+/// In debug builds, check that its page table frames, kernel stack and pid
+/// all went back to their allocators (or the kernel stack pool), and
+/// report whatever did not.
+fn drop_zombie(child: Arc<TaskControlBlock>) {
+    assert_eq!(Arc::strong_count(&child), 1);
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let pid = child.getpid();
+    let inner = child.inner_exclusive_access();
+    // the address space of a thread stays with the rest of its process
+    let space_frames = if Arc::strong_count(&inner.memory_set) == 1 {
+        let memory_set = inner.memory_set.exclusive_access();
+        let data_frames = memory_set.frames_in_use() - memory_set.page_table_frames();
+        if data_frames != 0 {
+            println!("[kernel] leak: pid {} still maps {} data frames after exit", pid, data_frames);
+        }
+        memory_set.frames_in_use()
+    } else {
+        0
+    };
+    let expected = space_frames + child.kernel_stack.frames_in_use();
+    drop(inner);
+    // a kernel stack may go to the pool rather than back to the allocator
+    let free = || frames_free() + pooled_kernel_stack_frames();
+    let free_before = free();
+    drop(child);
+    let freed = free() - free_before;
+    if freed != expected {
+        println!("[kernel] leak: pid {} freed {} of its {} frames", pid, freed, expected);
+    }
+    if !pid_is_free(pid) {
+        println!("[kernel] leak: pid {} was not recycled", pid);
+    }
+}
+
+/// Apply the initproc exit policy: panic, or with the boot argument
+/// `init_on_exit=respawn` load the init binary afresh into the same pid
+/// (keeping its children) and put it back to the ready queue
+fn initproc_exited(task: Arc<TaskControlBlock>, exit_code: i32) {
+    task.inner_exclusive_access().stdout.flush();
+    if bootarg("init_on_exit") != Some("respawn") {
+        panic!("init exited with code {}", exit_code);
+    }
+    println!("[kernel] init exited with code {}, respawning", exit_code);
+    task.exec(&initproc_image(), INITPROC_NAME, &[])
+        .unwrap_or_else(|err| panic!("cannot respawn init: {:?}", err));
+    let mut inner = task.inner_exclusive_access();
+    inner.task_cx = TaskContext::goto_trap_return(task.kernel_stack.get_trap_sp());
+    inner.set_status(TaskStatus::Ready);
+    drop(inner);
+    add_task(task);
+    // the old kernel stack contents are dead, there is nothing to save
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
 }
 
 pub fn get_current_task_info() -> Option<TaskInfo> {
-    let task = PROCESSOR.exclusive_access().current()?;
+    let task = current_task()?;
     let inner = task.inner_exclusive_access();
     let current_time_ms = get_time_ms();
 
     Some(TaskInfo {
         status: inner.task_status,
-        syscall_times: inner.syscall_times,
+        syscall_times: task.syscall_times.snapshot(),
         time: current_time_ms - inner.start_time_ms,
     })
 }
 
-pub fn set_current_task_priority(priority: isize) -> Option<()> {
-    let task = PROCESSOR.exclusive_access().current()?;
+/// Status of process `pid`, 0 for the caller
+pub fn get_task_stat(pid: usize) -> Option<TaskStat> {
+    let task = match pid {
+        0 => process_leader(&current_task()?),
+        pid => pid2task(pid)?,
+    };
+    let inner = task.inner_exclusive_access();
+    let mut name = [0; TASK_STAT_NAME_LEN];
+    let len = inner.name.len().min(TASK_STAT_NAME_LEN);
+    name[..len].copy_from_slice(&inner.name.as_bytes()[..len]);
+    let frames = inner.memory_set.exclusive_access().frames_in_use();
+    Some(TaskStat {
+        pid: task.getpid(),
+        ppid: inner
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(0, |parent| parent.getpid()),
+        status: inner.task_status as usize,
+        priority: inner.priority,
+        stride: inner.stride,
+        frames,
+        time: match inner.start_time_ms {
+            0 => 0,
+            start => get_time_ms() - start,
+        },
+        name,
+        syscall_times: task.syscall_times.snapshot(),
+        io: inner.io,
+        faults: inner.faults,
+    })
+}
+
+/// Pids of every process not reaped yet, in increasing order; threads
+/// other than the main one are left out
+pub fn process_pids() -> Vec<usize> {
+    manager::tasks()
+        .iter()
+        .filter(|task| Arc::ptr_eq(&process_leader(task), task))
+        .map(|task| task.getpid())
+        .collect()
+}
+
+/// Set the priority of the current task, `None` below [`MIN_PRIORITY`]
+pub fn set_current_task_priority(priority: u64) -> Option<()> {
+    if priority < MIN_PRIORITY {
+        return None;
+    }
+    let task = current_task()?;
     let mut inner = task.inner_exclusive_access();
-    inner.priority = priority;
+    let old = inner.priority;
+    inner.set_priority(priority);
+    let new = inner.priority;
+    drop(inner);
+    if new > old {
+        audit(AuditKind::PriorityRaise, true, task.getpid(), new);
+    }
     Some(())
 }
 
+/// Count a call of `syscall_id` by the current task, without borrowing
+/// its PROCESSOR or its TCB
 pub fn increase_syscall_times(syscall_id: usize) -> Option<()> {
-    let task = PROCESSOR.exclusive_access().current()?;
-    let mut inner = task.inner_exclusive_access();
-    inner.syscall_times[syscall_id] += 1;
+    current_syscall_times()?.increase(syscall_id);
     Some(())
 }
 
 pub fn decrease_syscall_times(syscall_id: usize) -> Option<()> {
-    let task = PROCESSOR.exclusive_access().current()?;
-    let mut inner = task.inner_exclusive_access();
-    inner.syscall_times[syscall_id] -= 1;
+    current_syscall_times()?.decrease(syscall_id);
     Some(())
 }
 
-pub fn current_task_mmap(start: usize, len: usize, port: usize) -> Option<()> {
+pub fn current_task_mmap(start: usize, len: usize, port: usize) -> Result<(), MapError> {
+    let task = current_task().unwrap();
     if start & (PAGE_SIZE - 1) != 0 {
-        debug!("[kernel] [pid {}] start not aligned, mmap failed", get_current_pid()?);
-        return None;
+        debug!("[kernel] [pid {}] start not aligned, mmap failed", task.pid.0);
+        return Err(MapError::InvalidArgument);
     }
+    let permission = user_permission(port).ok_or_else(|| {
+        debug!("[kernel] [pid {}] port `{:#b}` is illegal, mmap failed", task.pid.0, port);
+        MapError::InvalidArgument
+    })?;
+    let end = match start.checked_add(len) {
+        Some(end) if len != 0 => end,
+        _ => {
+            debug!("[kernel] [pid {}] len {:#x} is illegal, mmap failed", task.pid.0, len);
+            return Err(MapError::InvalidArgument);
+        }
+    };
+    let start_va = VirtAddr::from(start);
+    let end_va: VirtAddr = VirtAddr::from(end).ceil().into();
+
+    let inner = task.inner_exclusive_access();
+    let mut memory_set = inner.memory_set.exclusive_access();
+    if memory_set.is_conflict(start_va, end_va) {
+        debug!("[kernel] [pid {:?}] memory conflicted, mmap failed", task.pid);
+        return Err(MapError::AlreadyMapped);
+    }
+    if end_va.floor().0 - start_va.floor().0 > commit_headroom() {
+        debug!("[kernel] [pid {:?}] past the overcommit limit, mmap failed", task.pid);
+        return Err(MapError::OutOfMemory);
+    }
+    memory_set
+        .insert_framed_area(start_va, end_va, permission)
+        .ok_or_else(|| {
+            debug!("[kernel] [pid {:?}] out of frames, mmap failed", task.pid);
+            MapError::OutOfMemory
+        })
+}
+
+/// Turn the `port` argument of mmap-like calls (bit 0 R, bit 1 W, bit 2 X)
+/// into the permission of a user area
+fn user_permission(port: usize) -> Option<MapPermission> {
     if port & !0b111 != 0 || port & 0b111 == 0 {
-        debug!("[kernel] [pid {}] port `{:#b}` is illegal, mmap failed", port, get_current_pid()?);
         return None;
     }
-    let start_va = VirtAddr::from(start);
-    let end_va: VirtAddr = VirtAddr::from(start + len).ceil().into();
+    Some(MapPermission::from_bits((port << 1) as u8).unwrap() | MapPermission::U)
+}
 
-    let task = PROCESSOR.exclusive_access().current()?;
+/// Resolve a page fault of the current task at `va`, see
+/// [`MemorySet::handle_page_fault`]
+///
+/// [`MemorySet::handle_page_fault`]: crate::mm::MemorySet::handle_page_fault
+pub fn current_task_page_fault(va: usize, access: FaultAccess) -> bool {
+    shadow_guard!();
+    let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
-    let memory_set = &mut inner.memory_set;
-    if memory_set.is_conflict(start_va, end_va) {
-        debug!("[kernel] [pid {:?}] memory conflicted, mmap failed", task.pid);
+    let (fault, handled) = inner.memory_set.exclusive_access().handle_page_fault(va.into(), access);
+    if handled && fault == PageFault::File {
+        inner.faults.major += 1;
+    } else if handled {
+        inner.faults.minor += 1;
+    }
+    trace_event!(
+        MM,
+        "pid {} {:?} fault at {:#x}, {:?}, resolved: {}",
+        task.pid.0,
+        access,
+        va,
+        fault,
+        handled
+    );
+    handled
+}
+
+/// Move the program break of the current task, see [`MemorySet::sbrk`]
+///
+/// [`MemorySet::sbrk`]: crate::mm::MemorySet::sbrk
+pub fn current_task_sbrk(increment: isize) -> Option<usize> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let mut memory_set = inner.memory_set.exclusive_access();
+    memory_set.sbrk(increment)
+}
+
+/// Map the whole shared memory object `name` at `start`
+pub fn current_task_shm_map(start: usize, name: &str, port: usize) -> Result<(), MapError> {
+    let task = current_task().unwrap();
+    if start & (PAGE_SIZE - 1) != 0 {
+        return Err(MapError::InvalidArgument);
+    }
+    let permission = user_permission(port).ok_or(MapError::InvalidArgument)?;
+    let object = shm_open(name, 0, false).ok_or(MapError::NotFound)?;
+    let end = start
+        .checked_add(object.pages() * PAGE_SIZE)
+        .ok_or(MapError::InvalidArgument)?;
+    let inner = task.inner_exclusive_access();
+    let mut memory_set = inner.memory_set.exclusive_access();
+    if memory_set.is_conflict(start.into(), end.into()) {
+        return Err(MapError::AlreadyMapped);
+    }
+    memory_set
+        .insert_shared_area(start.into(), object, permission)
+        .ok_or(MapError::OutOfMemory)
+}
+
+/// Map all of the file open as `fd` from `start` on, with `port` as in
+/// mmap, returning its size
+pub fn current_task_mmap_file(fd: usize, start: usize, port: usize) -> Result<usize, MapError> {
+    let task = current_task().unwrap();
+    if start & (PAGE_SIZE - 1) != 0 {
+        return Err(MapError::InvalidArgument);
+    }
+    let permission = user_permission(port).ok_or(MapError::InvalidArgument)?;
+    let inner = task.inner_exclusive_access();
+    let file = inner
+        .fd_table
+        .get(fd)
+        .cloned()
+        .flatten()
+        .ok_or(MapError::InvalidArgument)?;
+    let backing = file.mmap_backing().ok_or(MapError::InvalidArgument)?;
+    if !file.readable() || permission.contains(MapPermission::W) && !file.writable() {
+        return Err(MapError::PermissionDenied);
+    }
+    let size = backing.size();
+    let end = match start.checked_add(size) {
+        Some(end) if size != 0 => end,
+        _ => return Err(MapError::InvalidArgument),
+    };
+    let end_va: VirtAddr = VirtAddr::from(end).ceil().into();
+    let mut memory_set = inner.memory_set.exclusive_access();
+    if memory_set.is_conflict(start.into(), end_va) {
+        return Err(MapError::AlreadyMapped);
+    }
+    memory_set
+        .insert_file_area(start.into(), size, backing, permission)
+        .ok_or(MapError::OutOfMemory)?;
+    Ok(size)
+}
+
+/// Write the written pages of the files mapped in `[start, start + len)`
+/// back to them
+pub fn current_task_msync(start: usize, len: usize) -> Option<()> {
+    if start & (PAGE_SIZE - 1) != 0 {
         return None;
     }
-    let permission = MapPermission::from_bits((port << 1) as u8)? | MapPermission::U;
-    memory_set.insert_framed_area(start_va, end_va, permission)?;
-    Some(())
+    let end_va: VirtAddr = VirtAddr::from(start.checked_add(len)?).ceil().into();
+    let task = current_task()?;
+    let inner = task.inner_exclusive_access();
+    let mut memory_set = inner.memory_set.exclusive_access();
+    memory_set.msync(start.into(), end_va)
 }
 
 pub fn current_task_munmap(start: usize, len: usize) -> Option<()> {
     let start_va = VirtAddr::from(start);
-    let end_va: VirtAddr = VirtAddr::from(start + len).ceil().into();
+    let end_va: VirtAddr = VirtAddr::from(start.checked_add(len)?).ceil().into();
 
-    let task = PROCESSOR.exclusive_access().current()?;
-    let mut inner = task.inner_exclusive_access();
-    let memory_set = &mut inner.memory_set;
+    let task = current_task()?;
+    let inner = task.inner_exclusive_access();
+    let mut memory_set = inner.memory_set.exclusive_access();
     memory_set.unmap_area(start_va, end_va)
 }
 
+/// Change the permission of `[start, start + len)` to `port`, as in mmap
+pub fn current_task_mprotect(start: usize, len: usize, port: usize) -> Result<(), MapError> {
+    if start & (PAGE_SIZE - 1) != 0 {
+        return Err(MapError::InvalidArgument);
+    }
+    let permission = user_permission(port).ok_or(MapError::InvalidArgument)?;
+    let end = match start.checked_add(len) {
+        Some(end) if len != 0 => end,
+        _ => return Err(MapError::InvalidArgument),
+    };
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let mut memory_set = inner.memory_set.exclusive_access();
+    memory_set.protect(start.into(), VirtAddr::from(end).ceil().into(), permission)
+}
+
+pub fn current_task_mseal(start: usize, len: usize) -> Option<()> {
+    let start_va = VirtAddr::from(start);
+    let end_va: VirtAddr = VirtAddr::from(start.checked_add(len)?).ceil().into();
+
+    let task = current_task()?;
+    let inner = task.inner_exclusive_access();
+    let mut memory_set = inner.memory_set.exclusive_access();
+    memory_set.seal_area(start_va, end_va)
+}
+
+/// Whether the current task holds all of `caps`
+pub fn current_task_capable(caps: Capabilities) -> bool {
+    let task = current_task().unwrap();
+    let capable = task.inner_exclusive_access().caps.effective.contains(caps);
+    if !capable {
+        audit(AuditKind::Denied, false, task.getpid(), caps.bits() as u64);
+    }
+    capable
+}
+
+/// Print the scheduler state from the panic handler, skipping every cell
+/// that was borrowed when the panic hit instead of panicking again on it
+pub fn dump_panic_state() {
+    let current = this_processor().try_exclusive_access().map(|processor| processor.current());
+    match current {
+        None => {
+            println!("[kernel] PROCESSOR of hart {} poisoned", hart_id());
+        }
+        Some(None) => {
+            println!("[kernel] current task on hart {}: none", hart_id());
+        }
+        Some(Some(task)) => {
+            if let Some(inner) = task.try_inner_exclusive_access() {
+                println!(
+                    "[kernel] current task on hart {}: pid {} ({}), {:?}",
+                    hart_id(),
+                    task.getpid(),
+                    inner.name,
+                    inner.task_status
+                );
+            } else {
+                println!("[kernel] current task: pid {} (TCB poisoned)", task.getpid());
+            }
+        }
+    }
+    if let Some(manager) = TASK_MANAGER.try_lock() {
+        println!("[kernel] ready tasks: {}", manager.len());
+    } else {
+        println!("[kernel] TASK_MANAGER locked");
+    }
+}
+
+/// Print every armed kernel timer: the sleepers and the interval timers
+pub fn dump_timers() {
+    println!("[kernel] armed timers:");
+    sleep::dump_sleepers();
+    alarm::dump_alarms();
+}
+
+/// Print the whole process tree, starting from [`INITPROC`]
+pub fn dump_process_tree() {
+    println!("[kernel] process tree:");
+    println!("  PID   PPID  STATE    STRIDE       PRIO   PAGES  NAME");
+    dump_subtree(&INITPROC, 0);
+}
+
+fn dump_subtree(task: &Arc<TaskControlBlock>, depth: usize) {
+    let inner = task.inner_exclusive_access();
+    let ppid = inner
+        .parent
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map(|parent| parent.getpid() as isize)
+        .unwrap_or(-1);
+    let state = match inner.task_status {
+        TaskStatus::UnInit => "UnInit",
+        TaskStatus::Ready => "Ready",
+        TaskStatus::Running => "Running",
+        TaskStatus::Blocked => "Blocked",
+        TaskStatus::Zombie => "Zombie",
+    };
+    println!(
+        "  {:<5} {:<5} {:<8} {:<12} {:<6} {:<6} {:indent$}{}",
+        task.getpid(),
+        ppid,
+        state,
+        inner.stride,
+        inner.priority,
+        inner.memory_set.exclusive_access().frames_in_use(),
+        "",
+        inner.name,
+        indent = depth * 2,
+    );
+    for child in inner.children.iter() {
+        dump_subtree(child, depth + 1);
+    }
+}
+
+/// Walk the process tree from [`INITPROC`] and report every inconsistency
+///
+/// Every task but initproc must have a live parent whose `children` holds
+/// it, and no task may show up twice. Returns the number of problems found.
+pub fn check_process_tree() -> usize {
+    let mut seen = Vec::new();
+    let mut problems = 0;
+    if INITPROC.inner_exclusive_access().parent.is_some() {
+        println!("[kernel] proc tree: initproc has a parent");
+        problems += 1;
+    }
+    check_subtree(&INITPROC, &mut seen, &mut problems);
+    problems
+}
+
+fn check_subtree(task: &Arc<TaskControlBlock>, seen: &mut Vec<usize>, problems: &mut usize) {
+    if seen.contains(&task.getpid()) {
+        println!("[kernel] proc tree: pid {} is reachable twice", task.getpid());
+        *problems += 1;
+        return;
+    }
+    seen.push(task.getpid());
+    let inner = task.inner_exclusive_access();
+    for child in inner.children.iter() {
+        let parent = child
+            .inner_exclusive_access()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade());
+        match parent {
+            Some(parent) if Arc::ptr_eq(&parent, task) => {}
+            Some(parent) => {
+                println!(
+                    "[kernel] proc tree: pid {} is a child of {} but points at {}",
+                    child.getpid(),
+                    task.getpid(),
+                    parent.getpid()
+                );
+                *problems += 1;
+            }
+            None => {
+                println!(
+                    "[kernel] proc tree: pid {} is a child of {} but has no live parent",
+                    child.getpid(),
+                    task.getpid()
+                );
+                *problems += 1;
+            }
+        }
+        check_subtree(child, seen, problems);
+    }
+}
+
+/// Application run as initproc
+const INITPROC_NAME: &str = "ch5b_initproc";
+
+/// The ELF image of initproc, from the disk if there is one
+fn initproc_image() -> Cow<'static, [u8]> {
+    lookup_app(&format!("/{}", INITPROC_NAME))
+        .unwrap_or_else(|| panic!("cannot find {}", INITPROC_NAME))
+}
+
 lazy_static! {
     /// Creation of initial process
     ///
     /// the name "initproc" may be changed to any other app name like "usertests",
     /// but we have user_shell, so we don't need to change it.
-    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
-        get_app_data_by_name("ch5b_initproc").unwrap(),
-        "ch5b_initproc"
-    ));
+    pub static ref INITPROC: Arc<TaskControlBlock> =
+        Arc::new(TaskControlBlock::new(&initproc_image(), INITPROC_NAME));
 }
 
 pub fn add_initproc() {
+    INITPROC.inner_exclusive_access().pinned = true;
+    insert_into_pid2task(INITPROC.getpid(), INITPROC.clone());
     add_task(INITPROC.clone());
 }
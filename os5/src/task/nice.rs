@@ -0,0 +1,106 @@
+//! POSIX nice values on top of stride priorities
+//!
+//! Stride scheduling shares the CPU in proportion to priority, so nice `n`
+//! maps to the CFS load weight of `n` scaled down by 64: nice 0 is
+//! [`DEFAULT_PRIORITY`] and every step up gives about 20% less CPU. The
+//! scaled weights are rounded and kept at [`MIN_PRIORITY`] or above, so
+//! nice 9 to 19 all end up at the minimum. Mapping back picks the nice
+//! value whose priority is closest, the lowest one on a tie.
+//!
+//! There are no users: every process belongs to user 0.
+
+use super::manager::{pid2task, tasks};
+use super::pgrp::{get_pgid, group_members};
+use super::{current_task, Capabilities, TaskControlBlock};
+use super::manager::{DEFAULT_PRIORITY, MIN_PRIORITY};
+use crate::audit::{audit, AuditKind};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// `sys_getpriority`/`sys_setpriority` target: a process, 0 for the caller
+pub const PRIO_PROCESS: usize = 0;
+/// target: a process group, 0 for the caller's
+pub const PRIO_PGRP: usize = 1;
+/// target: the processes of a user, 0 for the caller's
+pub const PRIO_USER: usize = 2;
+
+pub const NICE_MIN: isize = -20;
+pub const NICE_MAX: isize = 19;
+
+/// CFS load weight of nice `-20..=19`
+const NICE_WEIGHTS: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916, 9548, 7620, 6100, 4904,
+    3906, 3121, 2501, 1991, 1586, 1277, 1024, 820, 655, 526, 423, 335, 272, 215, 172, 137, 110,
+    87, 70, 56, 45, 36, 29, 23, 18, 15,
+];
+
+/// Priority of `nice`, which is clamped to `NICE_MIN..=NICE_MAX` first
+pub fn nice_to_priority(nice: isize) -> u64 {
+    let weight = NICE_WEIGHTS[(nice.clamp(NICE_MIN, NICE_MAX) - NICE_MIN) as usize];
+    let priority = (weight * DEFAULT_PRIORITY + 512) / 1024;
+    priority.max(MIN_PRIORITY)
+}
+
+/// The nice value closest to `priority`
+pub fn priority_to_nice(priority: u64) -> isize {
+    (NICE_MIN..=NICE_MAX)
+        .min_by_key(|nice| nice_to_priority(*nice).abs_diff(priority))
+        .unwrap()
+}
+
+/// The live processes `which` and `who` name, `None` if there are none
+pub fn priority_targets(which: usize, who: usize) -> Option<Vec<Arc<TaskControlBlock>>> {
+    let targets = match which {
+        PRIO_PROCESS if who == 0 => vec![current_task().unwrap()],
+        PRIO_PROCESS => vec![pid2task(who)?],
+        PRIO_PGRP if who == 0 => group_members(get_pgid(0)?),
+        PRIO_PGRP => group_members(who),
+        PRIO_USER if who == 0 => tasks(),
+        _ => return None,
+    };
+    let targets: Vec<_> = targets
+        .into_iter()
+        .filter(|task| !task.inner_exclusive_access().is_zombie())
+        .collect();
+    (!targets.is_empty()).then_some(targets)
+}
+
+/// The lowest nice value among the targets, see [`priority_targets`]
+pub fn get_nice(which: usize, who: usize) -> Option<isize> {
+    priority_targets(which, who)?
+        .iter()
+        .map(|task| priority_to_nice(task.inner_exclusive_access().priority))
+        .min()
+}
+
+/// Give every target the priority of `nice`; targets other than the caller
+/// need [`Capabilities::SET_PRIORITY_ANY`]
+pub fn set_nice(which: usize, who: usize, nice: isize) -> Option<()> {
+    let targets = priority_targets(which, who)?;
+    let current = current_task().unwrap();
+    let others = targets.iter().any(|task| !Arc::ptr_eq(task, &current));
+    if others
+        && !current
+            .inner_exclusive_access()
+            .caps
+            .effective
+            .contains(Capabilities::SET_PRIORITY_ANY)
+    {
+        let missing = Capabilities::SET_PRIORITY_ANY.bits() as u64;
+        audit(AuditKind::Denied, false, current.getpid(), missing);
+        return None;
+    }
+    let priority = nice_to_priority(nice);
+    for task in targets {
+        let mut inner = task.inner_exclusive_access();
+        let old = inner.priority;
+        inner.set_priority(priority);
+        let new = inner.priority;
+        drop(inner);
+        if new > old {
+            audit(AuditKind::PriorityRaise, true, task.getpid(), new);
+        }
+    }
+    Some(())
+}
@@ -0,0 +1,74 @@
+//! Seeded scheduling perturbation, for shaking out races in user programs
+//!
+//! Built with `SCHED_PERTURB=<seed>`, or `SCHED_PERTURB=random` to take the
+//! seed from the boot time, the kernel preempts the running task at random
+//! extra points (timer ticks within its slice and returns from syscalls) and
+//! breaks ties between equally ranked ready tasks at random. All decisions
+//! come from one xorshift generator seeded at boot, and the seed is printed
+//! so that a failing run can be built again with the same one.
+
+use crate::timer::get_time;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// `SCHED_PERTURB` at build time, unset leaves scheduling alone
+const SCHED_PERTURB: Option<&str> = option_env!("SCHED_PERTURB");
+
+/// One in this many preemption points actually preempts
+const PREEMPT_ONE_IN: u64 = 8;
+
+/// Generator state, 0 while perturbation is off
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Seed the generator from `SCHED_PERTURB` and report the seed
+pub fn init_perturbation() {
+    let seed = match SCHED_PERTURB {
+        None => return,
+        Some("random") => get_time() as u64,
+        Some(seed) => seed.parse().unwrap_or_else(|_| {
+            panic!("SCHED_PERTURB must be a number or `random`, not {:?}", seed)
+        }),
+    };
+    // splitmix the seed so that small seeds give unrelated streams, and
+    // keep the state off 0, which xorshift never leaves
+    let mut state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    state ^= state >> 31;
+    STATE.store(state.max(1), Ordering::Relaxed);
+    println!("[kernel] scheduling perturbation on, seed {}", seed);
+}
+
+/// Whether perturbation is on
+pub fn perturbing() -> bool {
+    STATE.load(Ordering::Relaxed) != 0
+}
+
+/// Next number of the generator; perturbation must be on
+fn next() -> u64 {
+    let mut x = STATE.load(Ordering::Relaxed);
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    STATE.store(x, Ordering::Relaxed);
+    x
+}
+
+/// Whether to preempt at a point where the running task would have gone on
+pub fn extra_preemption() -> bool {
+    perturbing() && next() % PREEMPT_ONE_IN == 0
+}
+
+/// Index of the one to pick out of `n` equally ranked candidates, 0 when
+/// perturbation is off
+#[cfg(not(any(
+    feature = "sched-bands",
+    feature = "sched-fifo",
+    feature = "sched-mlfq"
+)))]
+pub fn pick_tied(n: usize) -> usize {
+    if n > 1 && perturbing() {
+        (next() % n as u64) as usize
+    } else {
+        0
+    }
+}
@@ -0,0 +1,61 @@
+//! Process groups
+//!
+//! The processes sharing a pgid form a group, named after the process that
+//! founded it. initproc founds the first one; every other process starts in
+//! its parent's group and may move with [`set_pgid`], so a shell can give a
+//! pipeline a group of its own and signal it as a whole. A group lasts as
+//! long as any of its processes is left, reaped ones aside.
+
+use super::manager::{pid2task, tasks};
+use super::{current_task, TaskControlBlock};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Processes of group `pgid`, zombies included
+pub fn group_members(pgid: usize) -> Vec<Arc<TaskControlBlock>> {
+    tasks()
+        .into_iter()
+        .filter(|task| task.inner_exclusive_access().pgid == pgid)
+        .collect()
+}
+
+/// Group of process `pid`, 0 for the caller
+pub fn get_pgid(pid: usize) -> Option<usize> {
+    let task = if pid == 0 {
+        current_task().unwrap()
+    } else {
+        pid2task(pid)?
+    };
+    let pgid = task.inner_exclusive_access().pgid;
+    Some(pgid)
+}
+
+/// Move process `pid`, 0 for the caller, into group `pgid`, 0 for a new
+/// group named after it
+///
+/// Only the caller and its children can be moved, and only into a group
+/// that exists already unless they found it.
+pub fn set_pgid(pid: usize, pgid: usize) -> Option<()> {
+    let current = current_task().unwrap();
+    let task = if pid == 0 {
+        current.clone()
+    } else {
+        pid2task(pid)?
+    };
+    let own = Arc::ptr_eq(&task, &current)
+        || task
+            .inner_exclusive_access()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(false, |parent| Arc::ptr_eq(&parent, &current));
+    if !own {
+        return None;
+    }
+    let pgid = if pgid == 0 { task.getpid() } else { pgid };
+    if pgid != task.getpid() && group_members(pgid).is_empty() {
+        return None;
+    }
+    task.inner_exclusive_access().pgid = pgid;
+    Some(())
+}
@@ -1,13 +1,17 @@
 //! Task pid implementation.
 //!
 //! Assign PID to the process here. At the same time, the position of the application KernelStack
-//! is determined according to the PID.
+//! is determined according to the PID and its [`StackRole`].
 
-use crate::config::{KERNEL_STACK_SIZE, PAGE_SIZE, TRAMPOLINE};
-use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use crate::config::{KERNEL_STACK_SIZE, KTHREAD_STACK_SIZE, MEMORY_END, PAGE_SIZE, TRAMPOLINE, TRAP_CONTEXT};
+#[cfg(feature = "kstack-trap-cx")]
+use crate::mm::PhysPageNum;
+use crate::mm::{frame_alloc, frames_free, FrameTracker, MapPermission, VirtAddr, KERNEL_SPACE};
 use crate::sync::UPSafeCell;
+use crate::trap::TrapContext;
 use alloc::vec::Vec;
 use core::fmt::{Debug, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::*;
 
 /// Process identifier allocator using stack allocation
@@ -44,12 +48,6 @@ impl PidAllocator {
     }
 }
 
-impl Debug for PidAllocator {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        write!(f, "PidAllocator")
-    }
-}
-
 lazy_static! {
     /// Pid allocator instance through lazy_static!
     static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
@@ -76,28 +74,190 @@ pub fn pid_alloc() -> PidHandle {
     PID_ALLOCATOR.exclusive_access().alloc()
 }
 
+/// Whether `pid` is currently not held by any [`PidHandle`]
+pub fn pid_is_free(pid: usize) -> bool {
+    let allocator = PID_ALLOCATOR.exclusive_access();
+    pid >= allocator.current || allocator.recycled.contains(&pid)
+}
+
+/// Top of the kernel stacks of kernel threads, halfway down the 256 GiB of
+/// kernel space above physical memory, far below those of the tasks
+const KTHREAD_STACKS_TOP: usize = TRAMPOLINE - (1 << 37);
+
+/// What a kernel stack is for, which decides its size
+///
+/// The stacks of each role are laid out downwards from a region of their
+/// own, one per pid with a guard page below each, so that the role of a
+/// stack follows from its address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackRole {
+    /// A task that runs user code, [`KERNEL_STACK_SIZE`]
+    Task,
+    /// A kernel thread, [`KTHREAD_STACK_SIZE`]
+    Kthread,
+}
+
+impl StackRole {
+    pub fn size(self) -> usize {
+        match self {
+            StackRole::Task => KERNEL_STACK_SIZE,
+            StackRole::Kthread => KTHREAD_STACK_SIZE,
+        }
+    }
+    fn region_top(self) -> usize {
+        match self {
+            // below the trap scratch mapped there with kstack-trap-cx
+            StackRole::Task if cfg!(feature = "kstack-trap-cx") => TRAP_CONTEXT,
+            StackRole::Task => TRAMPOLINE,
+            StackRole::Kthread => KTHREAD_STACKS_TOP,
+        }
+    }
+}
+
 /// Return (bottom, top) of a kernel stack in kernel space.
-pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
-    let top = TRAMPOLINE - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
-    let bottom = top - KERNEL_STACK_SIZE;
+pub fn kernel_stack_position(app_id: usize, role: StackRole) -> (usize, usize) {
+    let top = role.region_top() - app_id * (role.size() + PAGE_SIZE);
+    let bottom = top - role.size();
     (bottom, top)
 }
 
+/// Written at the bottom of every kernel stack when it is mapped; a stack
+/// that ran into the one below has overwritten it
+const STACK_CANARY: usize = 0x57ac_c0de_57ac_c0de;
+
+/// Pid and role of the kernel stack `sp` points into, `None` for the boot
+/// stacks
+///
+/// Kernel stacks lie above all of physical memory, so anything below
+/// `MEMORY_END` is the boot stack of a hart or the stack of the scrubber.
+fn kernel_stack_owner(sp: usize) -> Option<(usize, StackRole)> {
+    if sp <= MEMORY_END {
+        return None;
+    }
+    let role = if sp > KTHREAD_STACKS_TOP {
+        StackRole::Task
+    } else {
+        StackRole::Kthread
+    };
+    let pid = role.region_top().checked_sub(sp)? / (role.size() + PAGE_SIZE);
+    let (bottom, top) = kernel_stack_position(pid, role);
+    (bottom..=top).contains(&sp).then_some((pid, role))
+}
+
+/// Panic with the owning pid if the canary of the kernel stack `sp` points
+/// into has been overwritten
+///
+/// The guard page below each stack only catches frames that touch it, a
+/// large enough one can skip it into the next stack, which the canary
+/// notices instead. `sp` must be in a mapped stack, as it is whenever
+/// something runs or is about to run on it.
+pub fn check_kernel_stack(sp: usize) {
+    if let Some((pid, role)) = kernel_stack_owner(sp) {
+        let (bottom, _) = kernel_stack_position(pid, role);
+        let canary = unsafe { (bottom as *const usize).read_volatile() };
+        assert!(
+            canary == STACK_CANARY,
+            "kernel stack of pid {} overflowed, canary is {:#x}",
+            pid,
+            canary
+        );
+    }
+}
+
+/// [`check_kernel_stack`] for the stack this runs on
+#[inline(always)]
+pub fn check_current_kernel_stack() {
+    let sp: usize;
+    unsafe {
+        core::arch::asm!("mv {}, sp", out(reg) sp);
+    }
+    check_kernel_stack(sp);
+}
+
+/// Most frames of dropped kernel stacks kept for new ones, as many as
+/// eight task stacks take
+const KERNEL_STACK_POOL_FRAMES: usize = 8 * KERNEL_STACK_SIZE / PAGE_SIZE;
+
+lazy_static! {
+    /// Frames of dropped kernel stacks, each set enough for one stack of
+    /// the role it came from
+    static ref KERNEL_STACK_POOL: UPSafeCell<Vec<Vec<FrameTracker>>> =
+        unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+/// Number of frames kept in the kernel stack pool
+pub fn pooled_kernel_stack_frames() -> usize {
+    KERNEL_STACK_POOL.exclusive_access().iter().map(Vec::len).sum()
+}
+
+/// Frames for one kernel stack of `role`, from the pool if it has a set of
+/// the right size
+fn kernel_stack_frames(role: StackRole) -> Option<Vec<FrameTracker>> {
+    let pages = role.size() / PAGE_SIZE;
+    let mut pool = KERNEL_STACK_POOL.exclusive_access();
+    if let Some(index) = pool.iter().position(|frames| frames.len() == pages) {
+        return Some(pool.swap_remove(index));
+    }
+    drop(pool);
+    (0..pages).map(|_| frame_alloc()).collect()
+}
+
 /// KernelStack corresponding to PID
+///
+/// The frames of the stack and the page tables to map them are reserved
+/// up front, but the pages are only mapped by [`KernelStack::ensure_mapped`]
+/// right before the task is switched in for the first time, so children
+/// that are reaped without ever running (fork storms) never touch the
+/// kernel space. Dropped stacks give their frames to a small pool that new
+/// ones take from first.
 pub struct KernelStack {
     pid: usize,
+    role: StackRole,
+    mapped: AtomicBool,
+    /// The reserved frames until the stack is mapped
+    frames: UPSafeCell<Vec<FrameTracker>>,
+    /// Frame of the top page, which holds the TrapContext
+    #[cfg(feature = "kstack-trap-cx")]
+    top_ppn: PhysPageNum,
 }
 
 impl KernelStack {
-    pub fn new(pid_handle: &PidHandle) -> Self {
-        let pid = pid_handle.0;
-        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
-        KERNEL_SPACE.exclusive_access().insert_framed_area(
-            kernel_stack_bottom.into(),
-            kernel_stack_top.into(),
-            MapPermission::R | MapPermission::W,
-        );
-        KernelStack { pid: pid_handle.0 }
+    /// Reserve a stack of `role` for `pid_handle`, `None` if frames run out
+    pub fn new(pid_handle: &PidHandle, role: StackRole) -> Option<Self> {
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid_handle.0, role);
+        KERNEL_SPACE
+            .exclusive_access()
+            .reserve_page_tables(kernel_stack_bottom.into(), kernel_stack_top.into())?;
+        let frames = kernel_stack_frames(role)?;
+        Some(KernelStack {
+            pid: pid_handle.0,
+            role,
+            mapped: AtomicBool::new(false),
+            #[cfg(feature = "kstack-trap-cx")]
+            top_ppn: frames.last().unwrap().ppn,
+            frames: unsafe { UPSafeCell::new(frames) },
+        })
+    }
+    /// Map the stack to its reserved frames if this has not happened yet
+    pub fn ensure_mapped(&self) {
+        if self.mapped.load(Ordering::Relaxed) {
+            return;
+        }
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(self.pid, self.role);
+        let frames = core::mem::take(&mut *self.frames.exclusive_access());
+        KERNEL_SPACE
+            .exclusive_access()
+            .insert_area_with_frames(
+                kernel_stack_bottom.into(),
+                kernel_stack_top.into(),
+                MapPermission::R | MapPermission::W,
+                frames,
+            )
+            .expect("page tables of kernel stack were reserved");
+        unsafe {
+            (kernel_stack_bottom as *mut usize).write_volatile(STACK_CANARY);
+        }
+        self.mapped.store(true, Ordering::Relaxed);
     }
     #[allow(unused)]
     /// Push a variable of type T into the top of the KernelStack and return its raw pointer
@@ -105,6 +265,7 @@ impl KernelStack {
     where
         T: Sized,
     {
+        self.ensure_mapped();
         let kernel_stack_top = self.get_top();
         let ptr_mut = (kernel_stack_top - core::mem::size_of::<T>()) as *mut T;
         unsafe {
@@ -112,18 +273,96 @@ impl KernelStack {
         }
         ptr_mut
     }
+    /// Number of frames reserved for the stack
+    pub fn frames_in_use(&self) -> usize {
+        self.role.size() / PAGE_SIZE
+    }
     pub fn get_top(&self) -> usize {
-        let (_, kernel_stack_top) = kernel_stack_position(self.pid);
+        let (_, kernel_stack_top) = kernel_stack_position(self.pid, self.role);
         kernel_stack_top
     }
+    /// Where the stack starts for a trap from user space: its top, or with
+    /// `kstack-trap-cx` right below the TrapContext kept there
+    pub fn get_trap_sp(&self) -> usize {
+        if cfg!(feature = "kstack-trap-cx") {
+            self.get_top() - core::mem::size_of::<TrapContext>()
+        } else {
+            self.get_top()
+        }
+    }
+    /// Frame of the top page of the stack, where the TrapContext is kept at
+    /// [`KernelStack::get_trap_sp`]; as the frame is at hand from the
+    /// start, the TrapContext is written there before the stack is mapped
+    #[cfg(feature = "kstack-trap-cx")]
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        self.top_ppn
+    }
 }
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
-        let (kernel_stack_bottom, _) = kernel_stack_position(self.pid);
-        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
-        KERNEL_SPACE
-            .exclusive_access()
-            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        let frames = if self.mapped.load(Ordering::Relaxed) {
+            let (kernel_stack_bottom, _) = kernel_stack_position(self.pid, self.role);
+            let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+            KERNEL_SPACE
+                .exclusive_access()
+                .take_area_frames(kernel_stack_bottom_va.into())
+        } else {
+            core::mem::take(&mut *self.frames.exclusive_access())
+        };
+        if pooled_kernel_stack_frames() + frames.len() <= KERNEL_STACK_POOL_FRAMES {
+            KERNEL_STACK_POOL.exclusive_access().push(frames);
+        }
+    }
+}
+
+/// Check that a kernel stack gets mapped without allocating, and that
+/// dropped stacks go to the pool for new ones to take even with no free
+/// frame left
+pub fn kernel_stack_test() {
+    let pid = pid_alloc();
+    let stack = KernelStack::new(&pid, StackRole::Task).unwrap();
+    let free = frames_free();
+    stack.ensure_mapped();
+    assert_eq!(frames_free(), free);
+    check_kernel_stack(stack.get_top());
+    drop(stack);
+    assert_eq!(frames_free(), free);
+    assert!(pooled_kernel_stack_frames() >= KERNEL_STACK_SIZE / PAGE_SIZE);
+    let pooled_before = pooled_kernel_stack_frames();
+    let mut hoard = Vec::new();
+    while let Some(frame) = frame_alloc() {
+        hoard.push(frame);
+    }
+    let mut stacks = Vec::new();
+    while let Some(stack) = KernelStack::new(&pid, StackRole::Task) {
+        stacks.push(stack);
     }
+    assert!(!stacks.is_empty());
+    assert_eq!(pooled_kernel_stack_frames(), 0);
+    drop(stacks);
+    drop(hoard);
+    assert_eq!(frames_free(), free);
+    assert_eq!(pooled_kernel_stack_frames(), pooled_before);
+    // a kernel thread's stack is smaller, lies apart and is pooled apart
+    let stack = KernelStack::new(&pid, StackRole::Kthread).unwrap();
+    assert_eq!(stack.frames_in_use(), KTHREAD_STACK_SIZE / PAGE_SIZE);
+    stack.ensure_mapped();
+    check_kernel_stack(stack.get_top());
+    assert_eq!(
+        kernel_stack_owner(stack.get_top() - 1),
+        Some((pid.0, StackRole::Kthread))
+    );
+    drop(stack);
+    assert_eq!(
+        pooled_kernel_stack_frames(),
+        pooled_before + KTHREAD_STACK_SIZE / PAGE_SIZE
+    );
+    let stack = KernelStack::new(&pid, StackRole::Task).unwrap();
+    assert_eq!(
+        pooled_kernel_stack_frames(),
+        pooled_before - KERNEL_STACK_SIZE / PAGE_SIZE + KTHREAD_STACK_SIZE / PAGE_SIZE
+    );
+    drop(stack);
+    info!("kernel_stack_test passed!");
 }
@@ -6,17 +6,32 @@
 
 
 use alloc::sync::Arc;
-use core::fmt::{Debug, Formatter};
+use core::hint::spin_loop;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
 
 use lazy_static::*;
 
-use crate::sync::UPSafeCell;
-use crate::timer::get_time_ms;
+use crate::config::MAX_HARTS;
+use crate::drivers::poll_irqs;
+use crate::hart::{self, hart_id};
+#[cfg(not(feature = "kstack-trap-cx"))]
+use crate::mm::thread_trap_cx;
+#[cfg(feature = "shadow-stack")]
+use crate::shadow_stack::ShadowStack;
+use crate::sync::{UPSafeCell, KERNEL_LOCK};
+use crate::timer::{get_time_ms, get_time_us};
 use crate::trap::TrapContext;
 
-use super::{fetch_task, TaskStatus};
-use super::{TaskContext, TaskControlBlock};
-use super::__switch;
+use super::manager::ready_count;
+use super::scrubber::run_scrubber;
+use super::{fetch_task, fire_alarms, wake_sleepers, TaskStatus};
+use super::{SyscallTimes, TaskContext, TaskControlBlock};
+use super::switch;
+
+/// Longest an idle hart waits for a ready task without the kernel lock
+/// before it polls the devices and the sleepers again
+const IDLE_WAIT_US: usize = 100;
 
 /// Processor management structure
 pub struct Processor {
@@ -39,6 +54,10 @@ impl Processor {
     }
     #[inline]
     pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        CURRENT_SYSCALL_TIMES[hart_id()].store(ptr::null_mut(), Ordering::Relaxed);
+        CURRENT_PID[hart_id()].store(NO_PID, Ordering::Relaxed);
+        #[cfg(feature = "shadow-stack")]
+        CURRENT_SHADOW_STACK[hart_id()].store(ptr::null_mut(), Ordering::Relaxed);
         self.current.take()
     }
     #[inline]
@@ -47,58 +66,168 @@ impl Processor {
     }
 }
 
-impl Debug for Processor {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        write!(f, "PROCESSOR")
+lazy_static! {
+    /// The [`Processor`] of each hart, by hart id; only ever touched by its
+    /// own hart
+    static ref PROCESSORS: [UPSafeCell<Processor>; MAX_HARTS] =
+        core::array::from_fn(|_| unsafe { UPSafeCell::new(Processor::new()) });
+}
+
+/// The [`Processor`] of the calling hart
+pub fn this_processor() -> &'static UPSafeCell<Processor> {
+    &PROCESSORS[hart_id()]
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_SYSCALL_TIMES: AtomicPtr<SyscallTimes> = AtomicPtr::new(ptr::null_mut());
+
+/// Syscall counters of the current task of each hart, null while there is
+/// none
+///
+/// They are kept outside [`PROCESSORS`] so that counting a syscall takes no
+/// borrow. The pointer is set and cleared together with
+/// `Processor::current`, whose `Arc` keeps the counters alive meanwhile.
+static CURRENT_SYSCALL_TIMES: [AtomicPtr<SyscallTimes>; MAX_HARTS] = [NO_SYSCALL_TIMES; MAX_HARTS];
+
+/// The syscall counters of the current task, see [`CURRENT_SYSCALL_TIMES`];
+/// not to be held past switching it out
+pub fn current_syscall_times() -> Option<&'static SyscallTimes> {
+    unsafe {
+        CURRENT_SYSCALL_TIMES[hart_id()]
+            .load(Ordering::Relaxed)
+            .as_ref()
     }
 }
 
-lazy_static! {
-    /// PROCESSOR instance through lazy_static!
-    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+#[cfg(feature = "shadow-stack")]
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_SHADOW_STACK: AtomicPtr<ShadowStack> = AtomicPtr::new(ptr::null_mut());
+
+/// The shadow stack of the current task of each hart, kept like
+/// [`CURRENT_SYSCALL_TIMES`]
+#[cfg(feature = "shadow-stack")]
+static CURRENT_SHADOW_STACK: [AtomicPtr<ShadowStack>; MAX_HARTS] = [NO_SHADOW_STACK; MAX_HARTS];
+
+#[cfg(feature = "shadow-stack")]
+pub fn current_shadow_stack() -> Option<&'static ShadowStack> {
+    unsafe {
+        CURRENT_SHADOW_STACK[hart_id()]
+            .load(Ordering::Relaxed)
+            .as_ref()
+    }
+}
+
+/// [`CURRENT_PID`] while a hart runs no task
+const NO_PID: usize = usize::MAX;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_CURRENT_PID: AtomicUsize = AtomicUsize::new(NO_PID);
+
+/// Pid of the current task of each hart, kept like [`CURRENT_SYSCALL_TIMES`]
+/// for `UPSafeCell` to report borrowers and the blocking mutex to see
+/// whether its owner runs, without borrowing anything
+static CURRENT_PID: [AtomicUsize; MAX_HARTS] = [NO_CURRENT_PID; MAX_HARTS];
+
+/// Pid of the current task, without borrowing its PROCESSOR
+#[cfg(feature = "debug_lock")]
+pub fn current_pid() -> Option<usize> {
+    let pid = CURRENT_PID[hart_id()].load(Ordering::Relaxed);
+    (pid != NO_PID).then_some(pid)
+}
+
+/// Whether the task `pid` is the current task of a hart other than this
+/// one, in user space or waiting there for the kernel lock; readable
+/// without the kernel lock, but only a hint by the time it returns
+pub fn running_elsewhere(pid: usize) -> bool {
+    let here = hart_id();
+    CURRENT_PID
+        .iter()
+        .enumerate()
+        .any(|(id, current)| id != here && current.load(Ordering::Relaxed) == pid)
 }
 
 /// The main part of process execution and scheduling
 ///
 /// Loop fetch_task to get the process that needs to run,
-/// and switch the process through __switch
+/// and switch the process through [`switch`]. Every hart runs this, holding
+/// the kernel lock.
 pub fn run_tasks() {
     loop {
-        // println!("[kernel] main loop");
-        let mut processor = PROCESSOR.exclusive_access();
-        // println!("[kernel] got processor");
+        if hart::stop_requested() {
+            hart::stop_this_hart();
+        }
+        let mut processor = this_processor().exclusive_access();
         if let Some(task) = fetch_task() {
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            // the kernel stack is mapped lazily on the first switch-in
+            task.kernel_stack.ensure_mapped();
             // access coming task TCB exclusively
             let mut task_inner = task.inner_exclusive_access();
-            // println!("[kernel] running task pid={} name={}", task.pid.0, task_inner.name);
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
-            task_inner.task_status = TaskStatus::Running;
+            task_inner.set_status(TaskStatus::Running);
             if task_inner.start_time_ms == 0 {
                 task_inner.start_time_ms = get_time_ms();
             }
+            let now_us = get_time_us();
+            let latency_us = now_us - task_inner.ready_since_us;
+            task_inner.sched_latency.record(latency_us);
+            trace_event!(
+                SCHED,
+                "run pid {} ({}) after {}us ready",
+                task.pid.0,
+                task_inner.name,
+                latency_us
+            );
+            task_inner.dispatched_at_us = now_us;
             drop(task_inner);
             // release coming task TCB manually
+            let syscall_times = &task.syscall_times as *const SyscallTimes;
+            CURRENT_SYSCALL_TIMES[hart_id()].store(syscall_times as *mut _, Ordering::Relaxed);
+            CURRENT_PID[hart_id()].store(task.pid.0, Ordering::Relaxed);
+            #[cfg(feature = "shadow-stack")]
+            CURRENT_SHADOW_STACK[hart_id()]
+                .store(&task.shadow_stack as *const _ as *mut _, Ordering::Relaxed);
             processor.current = Some(task);
             // release processor manually
             drop(processor);
             unsafe {
-                // println!("start execute");
-                __switch(idle_task_cx_ptr, next_task_cx_ptr);
-                // println!("switch back");
+                switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+        } else {
+            // interrupts are off while idling, poll the devices and the
+            // sleepers
+            poll_irqs();
+            wake_sleepers();
+            fire_alarms();
+            // nothing to run, a good time for the disk to catch up
+            #[cfg(feature = "fs")]
+            {
+                crate::fs::read_ahead();
+                crate::drivers::flush_disk();
             }
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            drop(processor);
+            run_scrubber(idle_task_cx_ptr);
+            // let the other harts into the kernel until there is something
+            // to run here
+            KERNEL_LOCK.unlock();
+            let wait_until = get_time_us() + IDLE_WAIT_US;
+            while ready_count() == 0 && get_time_us() < wait_until {
+                spin_loop();
+            }
+            KERNEL_LOCK.lock();
         }
     }
 }
 
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().take_current()
+    this_processor().exclusive_access().take_current()
 }
 
 /// Get a copy of the current task
 pub fn current_task() -> Option<Arc<TaskControlBlock>> {
-    PROCESSOR.exclusive_access().current()
+    this_processor().exclusive_access().current()
 }
 
 /// Get token of the address space of current task
@@ -116,12 +245,19 @@ pub fn current_trap_cx() -> &'static mut TrapContext {
         .get_trap_cx()
 }
 
+/// Where the trap context of current task is mapped in its address space
+#[cfg(not(feature = "kstack-trap-cx"))]
+pub fn current_trap_cx_user_va() -> usize {
+    let slot = current_task().unwrap().inner_exclusive_access().thread_slot;
+    thread_trap_cx(slot)
+}
+
 /// Return to idle control flow for new scheduling
 pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
-    let mut processor = PROCESSOR.exclusive_access();
+    let mut processor = this_processor().exclusive_access();
     let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
     drop(processor);
     unsafe {
-        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+        switch(switched_task_cx_ptr, idle_task_cx_ptr);
     }
 }
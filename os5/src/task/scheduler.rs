@@ -0,0 +1,76 @@
+//! The [`Scheduler`] interface behind [`TaskManager`](super::manager::TaskManager)
+//!
+//! The policy is chosen at build time: the stride scheduler by default,
+//! `sched-fifo` for the plain round-robin queue, `sched-bands` for
+//! [`PriorityBands`](super::bands::PriorityBands) and `sched-mlfq` for
+//! [`Mlfq`](super::mlfq::Mlfq).
+
+use super::manager::{slice_ticks, stride_cmp};
+use super::task::TaskControlBlockInner;
+use super::TaskControlBlock;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+#[cfg(feature = "sched-fifo")]
+use alloc::collections::VecDeque;
+
+/// A ready queue policy
+pub trait Scheduler {
+    fn new() -> Self;
+    /// Make `task` ready
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    /// Take out the task to run next
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    /// Take out `task` wherever it is queued, returning whether it was
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool;
+    fn len(&self) -> usize;
+    /// Called on the task `fetch` returned, before it runs; by default it
+    /// gets a fresh budget of [`slice_ticks`] for its priority
+    fn on_dispatch(&mut self, next: &mut TaskControlBlockInner) {
+        next.slice_ticks_left = slice_ticks(next.priority);
+    }
+    /// Account a timer tick to the running task and decide whether it
+    /// should be preempted; by default once its budget is used up
+    fn on_tick(&mut self, current: &mut TaskControlBlockInner) -> bool {
+        current.slice_ticks_left = current.slice_ticks_left.saturating_sub(1);
+        current.slice_ticks_left == 0
+    }
+    /// Which of two tasks should run first, `Less` for `a`, for wait
+    /// queues that wake the most urgent waiter first; by default the one of
+    /// the smaller stride, the furthest behind its share of the CPU
+    fn urgency_cmp(a: &TaskControlBlockInner, b: &TaskControlBlockInner) -> Ordering {
+        stride_cmp(a.stride, b.stride)
+    }
+}
+
+/// Round robin in the order tasks became ready, ignoring priorities
+#[cfg(feature = "sched-fifo")]
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+#[cfg(feature = "sched-fifo")]
+impl Scheduler for FifoScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let len = self.ready_queue.len();
+        self.ready_queue.retain(|queued| !Arc::ptr_eq(queued, task));
+        self.ready_queue.len() != len
+    }
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+    /// No task is more urgent than another, they keep their order
+    fn urgency_cmp(_a: &TaskControlBlockInner, _b: &TaskControlBlockInner) -> Ordering {
+        Ordering::Equal
+    }
+}
@@ -0,0 +1,67 @@
+//! The frame scrubber, an idle-priority kernel thread
+//!
+//! It has its own stack and context but no task control block: it is not
+//! in the ready queue, so no pid, signal or scheduler policy ever reaches
+//! it. An idle hart switches to it from [`run_tasks`](super::run_tasks)
+//! when there is nothing else to run, and it switches back after zeroing a
+//! batch of free frames into the pool of [`scrub_free_frames`]. Like all
+//! kernel code it holds the kernel lock meanwhile, hence the small batches.
+
+use super::{switch, TaskContext};
+use crate::config::KTHREAD_STACK_SIZE;
+use crate::mm::scrub_free_frames;
+use crate::sync::UPSafeCell;
+use lazy_static::*;
+
+/// Frames zeroed per switch to the scrubber, few so that waking stays prompt
+const SCRUB_BATCH: usize = 4;
+/// In the kernel's `.bss` like the boot stacks, which the kernel stack
+/// checks leave alone
+static mut SCRUBBER_STACK: [u8; KTHREAD_STACK_SIZE] = [0; KTHREAD_STACK_SIZE];
+
+struct Scrubber {
+    /// Where the scrubber resumes
+    cx: TaskContext,
+    /// The idle context of the hart that last switched to the scrubber
+    idle_cx: *mut TaskContext,
+}
+
+lazy_static! {
+    static ref SCRUBBER: UPSafeCell<Scrubber> = unsafe {
+        UPSafeCell::new(Scrubber {
+            cx: TaskContext::goto(
+                scrubber_main as usize,
+                SCRUBBER_STACK.as_ptr() as usize + KTHREAD_STACK_SIZE,
+            ),
+            idle_cx: core::ptr::null_mut(),
+        })
+    };
+}
+
+/// Run the scrubber for one batch from the idle context `idle_cx` of this
+/// hart, with the kernel lock held
+///
+/// Only one hart at a time idles holding the lock, so the single scrubber
+/// is never switched to twice.
+pub fn run_scrubber(idle_cx: *mut TaskContext) {
+    let mut scrubber = SCRUBBER.exclusive_access();
+    scrubber.idle_cx = idle_cx;
+    let cx = &scrubber.cx as *const TaskContext;
+    drop(scrubber);
+    unsafe {
+        switch(idle_cx, cx);
+    }
+}
+
+fn scrubber_main() -> ! {
+    loop {
+        scrub_free_frames(SCRUB_BATCH);
+        let mut scrubber = SCRUBBER.exclusive_access();
+        let cx = &mut scrubber.cx as *mut TaskContext;
+        let idle_cx = scrubber.idle_cx;
+        drop(scrubber);
+        unsafe {
+            switch(cx, idle_cx);
+        }
+    }
+}
@@ -0,0 +1,600 @@
+//! POSIX-style signals
+//!
+//! Every process has a set of pending and a set of blocked signals, and an
+//! action per signal. Pending signals that are not blocked are acted on by
+//! [`handle_signals`] right before the process returns to user mode. A user
+//! handler runs on the user stack, below a [`SignalFrame`] holding the
+//! interrupted registers, and must end with `sys_sigreturn`, which puts them
+//! back. Handlers and masks are the `signals` feature; without it every
+//! signal takes its default action.
+//!
+//! A signal that is to kill the process or run a handler wakes it from a
+//! blocking syscall, which returns [`ERESTARTSYS`] or [`ERESTARTNOHAND`].
+//! That never reaches user mode: [`handle_signals`] either fails the call
+//! with [`EINTR`] or rewinds `sepc` to its `ecall` and puts `a0` back, so
+//! that it is made again once the signal is dealt with. [`ERESTARTSYS`] is
+//! restarted unless a handler without [`SA_RESTART`] runs, [`ERESTARTNOHAND`]
+//! only if no handler does.
+
+use super::manager::tasks;
+use super::pgrp::group_members;
+use super::sleep::interrupt_sleep;
+use super::thread::process_leader;
+use super::{current_task, exit_current_and_run_next, pid2task, suspend_current_and_run_next};
+use super::{wake_up, Capabilities, TaskControlBlock, TaskStatus, INITPROC};
+#[cfg(feature = "signals")]
+use crate::mm::{copy_data_from_space, copy_data_into_space, user_range_accessible};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "signals")]
+use core::mem::size_of;
+
+/// Highest signal number
+pub const MAX_SIG: usize = 31;
+
+bitflags! {
+    /// A set of signals, bit `n` is signal `n`
+    pub struct SignalFlags: u32 {
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+impl SignalFlags {
+    /// The set holding just `signum`, which must be in `1..=MAX_SIG`
+    pub fn of(signum: usize) -> Self {
+        Self::from_bits_truncate(1 << signum)
+    }
+    /// The number of the lowest signal in the set
+    pub fn number(self) -> usize {
+        self.bits().trailing_zeros() as usize
+    }
+    /// Signals that can be neither caught, ignored nor blocked
+    #[cfg(feature = "signals")]
+    fn unblockable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+    /// Signals that stop the process by default
+    fn stopping() -> Self {
+        Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU
+    }
+}
+
+/// [`SignalAction::handler`]: take the default action
+pub const SIG_DFL: usize = 0;
+/// [`SignalAction::handler`]: discard the signal
+pub const SIG_IGN: usize = 1;
+
+/// `sys_sigprocmask`: add the given signals to the blocked ones
+#[cfg(feature = "signals")]
+pub const SIG_BLOCK: usize = 0;
+/// `sys_sigprocmask`: remove the given signals from the blocked ones
+#[cfg(feature = "signals")]
+pub const SIG_UNBLOCK: usize = 1;
+/// `sys_sigprocmask`: block exactly the given signals
+#[cfg(feature = "signals")]
+pub const SIG_SETMASK: usize = 2;
+
+/// [`SignalAction::flags`]: restart a syscall the signal interrupts once
+/// the handler returns, instead of failing it with [`EINTR`]
+pub const SA_RESTART: u32 = 0x1000_0000;
+
+/// Returned by a blocking syscall a signal interrupted, to be restarted
+/// unless a handler without [`SA_RESTART`] runs
+pub const ERESTARTSYS: isize = -512;
+/// Returned by a blocking syscall a signal interrupted that fails if any
+/// handler runs, like a sleep, which a restart would make longer
+pub const ERESTARTNOHAND: isize = -514;
+/// What an interrupted syscall returns when it is not restarted
+pub const EINTR: isize = -4;
+
+/// Most handlers that may be running at once, nested in each other
+#[cfg(feature = "signals")]
+const MAX_SIGNAL_FRAMES: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// What `sys_sigaction` sets and reports
+pub struct SignalAction {
+    /// Handler entry point, or [`SIG_DFL`] or [`SIG_IGN`]
+    pub handler: usize,
+    /// Signals blocked in addition while the handler runs; the signal being
+    /// handled always is
+    pub mask: SignalFlags,
+    /// [`SA_RESTART`] or nothing
+    pub flags: u32,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            flags: 0,
+        }
+    }
+}
+
+#[cfg(feature = "signals")]
+#[repr(C)]
+#[derive(Copy, Clone)]
+/// Saved on the user stack when a handler is entered
+struct SignalFrame {
+    x: [usize; 32],
+    sepc: usize,
+    blocked: SignalFlags,
+}
+
+/// The signal state of a process
+pub struct SignalState {
+    pub pending: SignalFlags,
+    pub blocked: SignalFlags,
+    actions: [SignalAction; MAX_SIG + 1],
+    /// User addresses of the frames of the handlers currently running,
+    /// innermost last
+    frames: Vec<usize>,
+    /// Stopped by a stop signal until `SIGCONT`
+    pub stopped: bool,
+    /// The syscall the process returns to user mode from, if a signal
+    /// interrupted it: what it returned and its first argument
+    interrupted: Option<(isize, usize)>,
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        Self {
+            pending: SignalFlags::empty(),
+            blocked: SignalFlags::empty(),
+            actions: [SignalAction::default(); MAX_SIG + 1],
+            frames: Vec::new(),
+            stopped: false,
+            interrupted: None,
+        }
+    }
+    /// The state of a forked child: same actions, mask and running
+    /// handlers, nothing pending
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: SignalFlags::empty(),
+            blocked: self.blocked,
+            actions: self.actions,
+            frames: self.frames.clone(),
+            stopped: false,
+            interrupted: None,
+        }
+    }
+    /// The state after `exec`: handlers are gone with the old program,
+    /// ignored signals stay ignored, mask and pending signals are kept
+    pub fn after_exec(&self) -> Self {
+        let mut actions = [SignalAction::default(); MAX_SIG + 1];
+        for (action, old) in actions.iter_mut().zip(self.actions.iter()) {
+            if old.handler == SIG_IGN {
+                action.handler = SIG_IGN;
+            }
+        }
+        Self {
+            pending: self.pending,
+            blocked: self.blocked,
+            actions,
+            frames: Vec::new(),
+            stopped: false,
+            interrupted: None,
+        }
+    }
+    /// Make `signum` pending, with the side effects of sending it: `SIGCONT`
+    /// resumes a stopped process at once and cancels pending stop signals,
+    /// a stop signal cancels a pending `SIGCONT`
+    pub fn post(&mut self, signum: usize) {
+        let signal = SignalFlags::of(signum);
+        if signal == SignalFlags::SIGCONT {
+            self.stopped = false;
+            self.pending -= SignalFlags::stopping();
+        } else if SignalFlags::stopping().contains(signal) {
+            self.pending -= SignalFlags::SIGCONT;
+        }
+        self.pending |= signal;
+    }
+    /// Replace the action of `signum`, returning the old one
+    #[cfg(feature = "signals")]
+    pub fn set_action(&mut self, signum: usize, action: SignalAction) -> SignalAction {
+        core::mem::replace(&mut self.actions[signum], action)
+    }
+    pub fn action(&self, signum: usize) -> SignalAction {
+        self.actions[signum]
+    }
+    /// Change the mask as `sys_sigprocmask` does, returning the old one
+    #[cfg(feature = "signals")]
+    pub fn set_blocked(&mut self, how: usize, set: SignalFlags) -> Option<SignalFlags> {
+        let old = self.blocked;
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old - set,
+            SIG_SETMASK => set,
+            _ => return None,
+        };
+        self.blocked = blocked - SignalFlags::unblockable();
+        Some(old)
+    }
+    /// Whether `signum` arriving ends a blocking syscall: it kills the
+    /// process or runs a handler, and is not blocked
+    fn interrupts(&self, signum: usize) -> bool {
+        let signal = SignalFlags::of(signum);
+        if signal == SignalFlags::SIGKILL {
+            return true;
+        }
+        if self.blocked.contains(signal) || self.stopped {
+            return false;
+        }
+        match self.actions[signum].handler {
+            SIG_IGN => false,
+            SIG_DFL => matches!(default_action(signum), DefaultAction::Terminate),
+            _ => cfg!(feature = "signals"),
+        }
+    }
+    /// Whether a pending signal is to end a blocking syscall, for it to
+    /// give up waiting
+    pub fn interrupted(&self) -> bool {
+        (1..=MAX_SIG).any(|signum| self.pending.contains(SignalFlags::of(signum)) && self.interrupts(signum))
+    }
+    /// Take the lowest pending signal that is not blocked; while stopped,
+    /// only the ones able to end the stop
+    fn take_deliverable(&mut self) -> Option<usize> {
+        let mut deliverable = self.pending - self.blocked;
+        if self.stopped {
+            deliverable &= SignalFlags::SIGKILL | SignalFlags::SIGCONT;
+        }
+        if deliverable.is_empty() {
+            return None;
+        }
+        let signum = deliverable.number();
+        self.pending -= SignalFlags::of(signum);
+        Some(signum)
+    }
+}
+
+/// What a signal does by default
+enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+fn default_action(signum: usize) -> DefaultAction {
+    let signal = SignalFlags::of(signum);
+    if signal == SignalFlags::SIGCONT {
+        DefaultAction::Continue
+    } else if SignalFlags::stopping().contains(signal) {
+        DefaultAction::Stop
+    } else if (SignalFlags::SIGCHLD | SignalFlags::SIGURG | SignalFlags::SIGWINCH).contains(signal) {
+        DefaultAction::Ignore
+    } else {
+        DefaultAction::Terminate
+    }
+}
+
+/// Whether signal `signum` may get a handler or be ignored
+#[cfg(feature = "signals")]
+pub fn signal_catchable(signum: usize) -> bool {
+    (1..=MAX_SIG).contains(&signum) && !SignalFlags::unblockable().contains(SignalFlags::of(signum))
+}
+
+/// Whether the current task has a signal pending that interrupts blocking
+/// calls, see [`SignalState::interrupted`]
+pub fn current_task_interrupted() -> bool {
+    let task = current_task().unwrap();
+    let interrupted = task.inner_exclusive_access().signals.interrupted();
+    interrupted
+}
+
+/// Note that the syscall the current task made with first argument `a0`
+/// returned `code`, [`ERESTARTSYS`] or [`ERESTARTNOHAND`], for
+/// [`handle_signals`] to fail or restart it
+pub fn current_task_syscall_interrupted(code: isize, a0: usize) {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().signals.interrupted = Some((code, a0));
+}
+
+/// Fail the interrupted syscall of `inner` with [`EINTR`], or rewind it to
+/// be made again, now that the handler `action` is about to run, or with
+/// `None` that none is
+fn settle_interrupted(inner: &mut super::task::TaskControlBlockInner, action: Option<SignalAction>) {
+    let (code, a0) = match inner.signals.interrupted.take() {
+        Some(interrupted) => interrupted,
+        None => return,
+    };
+    let restart = match action {
+        Some(action) => code == ERESTARTSYS && action.flags & SA_RESTART != 0,
+        None => true,
+    };
+    let cx = inner.get_trap_cx();
+    if restart {
+        // back to the ecall
+        cx.sepc -= 4;
+        cx.x[10] = a0;
+    } else {
+        cx.x[10] = EINTR as usize;
+    }
+}
+
+/// Post `SIGSEGV` to the current task for a fault it cannot go on from, if
+/// it has a handler for it that is not blocked, and return whether it did;
+/// otherwise the caller kills the task as before
+///
+/// The handler runs on the same stack, so one for a stack overflow finds no
+/// room for its frame and the task dies of the `SIGSEGV` after all.
+pub fn current_task_catch_fault() -> bool {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let signal = SignalFlags::SIGSEGV;
+    let handler = inner.signals.action(signal.number()).handler;
+    if handler == SIG_DFL || handler == SIG_IGN || inner.signals.blocked.contains(signal) {
+        return false;
+    }
+    inner.signals.post(signal.number());
+    true
+}
+
+/// Act on the pending signals of the current task that are not blocked,
+/// before it returns to user mode
+///
+/// A fatal signal ends the task with exit code `-signum`. At most one user
+/// handler is entered per return, the next signal waits for the one after.
+/// A stopped task keeps yielding here until it is continued or killed. An
+/// interrupted syscall is settled before the handler is entered, so that
+/// its frame holds the registers to restart it with.
+pub fn handle_signals() {
+    shadow_guard!();
+    loop {
+        let task = current_task().unwrap();
+        let mut inner = task.inner_exclusive_access();
+        let signum = match inner.signals.take_deliverable() {
+            Some(signum) => signum,
+            None if inner.signals.stopped => {
+                drop(inner);
+                drop(task);
+                suspend_current_and_run_next();
+                continue;
+            }
+            None => {
+                settle_interrupted(&mut inner, None);
+                return;
+            }
+        };
+        let action = inner.signals.action(signum);
+        trace_event!(SCHED, "pid {} signal {}, handler {:#x}", task.pid.0, signum, action.handler);
+        let fatal = match action.handler {
+            SIG_IGN => None,
+            SIG_DFL => match default_action(signum) {
+                DefaultAction::Terminate => Some(signum),
+                DefaultAction::Stop => {
+                    inner.signals.stopped = true;
+                    None
+                }
+                DefaultAction::Continue | DefaultAction::Ignore => None,
+            },
+            #[cfg(feature = "signals")]
+            handler => {
+                settle_interrupted(&mut inner, Some(action));
+                if enter_handler(&mut inner, signum, handler, action.mask).is_some() {
+                    return;
+                }
+                // no room for the frame on the user stack
+                Some(SignalFlags::SIGSEGV.number())
+            }
+            // there is no setting a handler, ignore whatever got there
+            #[cfg(not(feature = "signals"))]
+            _ => None,
+        };
+        if let Some(signum) = fatal {
+            info!("[kernel] pid {} killed by signal {}", task.pid.0, signum);
+            drop(inner);
+            drop(task);
+            exit_current_and_run_next(-(signum as i32));
+            return;
+        }
+    }
+}
+
+/// Push a [`SignalFrame`] onto the user stack and make the trap context
+/// enter `handler(signum)` with the signal and `mask` blocked
+#[cfg(feature = "signals")]
+fn enter_handler(
+    inner: &mut super::task::TaskControlBlockInner,
+    signum: usize,
+    handler: usize,
+    mask: SignalFlags,
+) -> Option<()> {
+    if inner.signals.frames.len() >= MAX_SIGNAL_FRAMES {
+        return None;
+    }
+    let token = inner.get_user_token();
+    let cx = inner.get_trap_cx();
+    let sp = cx.x[2].checked_sub(size_of::<SignalFrame>())? & !0xf;
+    if !user_range_accessible(token, sp, size_of::<SignalFrame>(), true) {
+        return None;
+    }
+    let frame = SignalFrame {
+        x: cx.x,
+        sepc: cx.sepc,
+        blocked: inner.signals.blocked,
+    };
+    unsafe { copy_data_into_space(&frame, token, sp as *const SignalFrame) }?;
+    inner.signals.frames.push(sp);
+    inner.signals.blocked |= (mask | SignalFlags::of(signum)) - SignalFlags::unblockable();
+    cx.x[2] = sp;
+    cx.x[10] = signum;
+    // returning from the handler instead of calling sigreturn faults
+    cx.x[1] = 0;
+    cx.sepc = handler;
+    Some(())
+}
+
+/// Leave the innermost running handler of the current task, restoring the
+/// registers and mask saved on entering it; returns the restored `a0`, so
+/// that the syscall return leaves it as it was
+#[cfg(feature = "signals")]
+pub fn current_task_sigreturn() -> Option<isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let sp = *inner.signals.frames.last()?;
+    let token = inner.get_user_token();
+    if !user_range_accessible(token, sp, size_of::<SignalFrame>(), false) {
+        return None;
+    }
+    let frame: SignalFrame = unsafe { copy_data_from_space(token, sp as *const SignalFrame) }?;
+    inner.signals.frames.pop();
+    inner.signals.blocked = frame.blocked - SignalFlags::unblockable();
+    let cx = inner.get_trap_cx();
+    cx.x = frame.x;
+    cx.sepc = frame.sepc;
+    Some(cx.x[10] as isize)
+}
+
+/// Set the action of `signum` for the current task if `action` is given,
+/// returning the old one
+#[cfg(feature = "signals")]
+pub fn current_task_sigaction(signum: usize, action: Option<SignalAction>) -> Option<SignalAction> {
+    if !signal_catchable(signum) || action.map_or(false, |action| action.flags & !SA_RESTART != 0) {
+        return None;
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let old = inner.signals.action(signum);
+    if let Some(action) = action {
+        inner.signals.set_action(signum, action);
+    }
+    Some(old)
+}
+
+/// Change the blocked signals of the current task, see
+/// [`SignalState::set_blocked`]
+#[cfg(feature = "signals")]
+pub fn current_task_sigprocmask(how: usize, set: SignalFlags) -> Option<SignalFlags> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.signals.set_blocked(how, set)
+}
+
+/// Send `signum` to the processes `pid` names, or with `signum` 0 only
+/// check that it could be sent
+///
+/// A positive `pid` names a process, 0 the caller's process group, -1 every
+/// process but initproc and the caller, and any other negative one the group
+/// `-pid`. A task may signal itself and its children, others only with
+/// [`Capabilities::KILL_ANY`]. A pinned task only takes the signals it has
+/// set an action for, so nobody may kill or stop initproc. The others named
+/// are skipped, and this fails if that leaves none. A signal that kills the
+/// process or runs a handler ends a sleep, a wait or a blocking read or
+/// write at once.
+pub fn send_signal(pid: isize, signum: usize) -> Option<()> {
+    if signum > MAX_SIG {
+        return None;
+    }
+    let current = current_task().unwrap();
+    let targets = match pid {
+        pid if pid > 0 => vec![pid2task(pid as usize)?],
+        0 => group_members(current.inner_exclusive_access().pgid),
+        -1 => tasks()
+            .into_iter()
+            .filter(|task| !Arc::ptr_eq(task, &current) && !Arc::ptr_eq(task, &INITPROC))
+            .collect(),
+        pid => group_members(pid.unsigned_abs()),
+    };
+    let mut sent = false;
+    for target in targets
+        .iter()
+        .filter(|target| may_signal(&current, target) && !refuses(target, signum))
+    {
+        if signum != 0 {
+            deliver(target, signum);
+        }
+        sent = true;
+    }
+    sent.then_some(())
+}
+
+fn may_signal(current: &Arc<TaskControlBlock>, target: &Arc<TaskControlBlock>) -> bool {
+    Arc::ptr_eq(current, target)
+        || current.inner_exclusive_access().caps.effective.contains(Capabilities::KILL_ANY)
+        || target
+            .inner_exclusive_access()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(false, |parent| Arc::ptr_eq(&parent, current))
+}
+
+/// Whether `target` is pinned against `signum`, which it has left to the
+/// default action
+fn refuses(target: &Arc<TaskControlBlock>, signum: usize) -> bool {
+    let inner = target.inner_exclusive_access();
+    signum != 0 && inner.pinned && inner.signals.action(signum).handler == SIG_DFL
+}
+
+/// Post `signum` to `target`, waking it from a sleep, a wait, a mutex of
+/// its process or a blocking read or write of one of its files if the
+/// signal interrupts those, see [`SignalState::interrupted`]
+pub(super) fn deliver(target: &Arc<TaskControlBlock>, signum: usize) {
+    let mut inner = target.inner_exclusive_access();
+    if inner.is_zombie() {
+        return;
+    }
+    inner.signals.post(signum);
+    let wake = inner.task_status == TaskStatus::Blocked && inner.signals.interrupts(signum);
+    drop(inner);
+    if wake {
+        interrupt_sleep(target);
+        let mut waiters = target.inner_exclusive_access().child_exit.take();
+        // a thread waits for the others on the queue of the main thread
+        let leader = process_leader(target);
+        let files: Vec<_> = leader
+            .inner_exclusive_access()
+            .fd_table
+            .iter()
+            .flatten()
+            .cloned()
+            .collect();
+        for file in files {
+            file.interrupt();
+        }
+        let mutexes = leader.inner_exclusive_access().mutexes.clone();
+        for mutex in mutexes {
+            mutex.interrupt();
+        }
+        if !Arc::ptr_eq(&leader, target) {
+            waiters.extend(leader.inner_exclusive_access().child_exit.take());
+        }
+        wake_up(waiters);
+    }
+}
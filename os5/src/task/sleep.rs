@@ -0,0 +1,120 @@
+//! Tasks blocked until a deadline
+//!
+//! Sleepers are kept in a min-heap by deadline. [`wake_sleepers`] runs on
+//! every timer interrupt and whenever the processor has nothing to run, so a
+//! sleep ends at most one timer tick after its deadline.
+
+use super::processor::schedule;
+use super::{switch_out_current, wake_up, TaskControlBlock, TaskStatus};
+use crate::sync::UPSafeCell;
+use crate::timer::get_time_us;
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::{Debug, Formatter};
+use lazy_static::*;
+
+struct Sleeper {
+    deadline_us: usize,
+    task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for Sleeper {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline_us == other.deadline_us
+    }
+}
+
+impl Eq for Sleeper {}
+
+impl PartialOrd for Sleeper {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sleeper {
+    /// Reversed, so that the max-heap yields the earliest deadline first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline_us.cmp(&self.deadline_us)
+    }
+}
+
+impl Debug for Sleeper {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Sleeper(pid {} until {}us)", self.task.getpid(), self.deadline_us)
+    }
+}
+
+lazy_static! {
+    static ref SLEEPERS: UPSafeCell<BinaryHeap<Sleeper>> =
+        unsafe { UPSafeCell::new_labeled("SLEEPERS", BinaryHeap::new()) };
+}
+
+/// Block the current task until `get_time_us()` reaches `deadline_us`
+pub fn sleep_current_until(deadline_us: usize) {
+    let (task, task_cx_ptr) = switch_out_current(TaskStatus::Blocked);
+    SLEEPERS.exclusive_access().push(Sleeper { deadline_us, task });
+    schedule(task_cx_ptr);
+}
+
+/// Block the current task, handing it to `enqueue` as
+/// [`block_current_and_run_next`](super::block_current_and_run_next) does,
+/// until it is woken from there or `get_time_us()` reaches `deadline_us`
+///
+/// Woken otherwise, the task must leave the sleepers with [`cancel_sleep`].
+pub fn block_current_until(deadline_us: usize, enqueue: impl FnOnce(Arc<TaskControlBlock>)) {
+    let (task, task_cx_ptr) = switch_out_current(TaskStatus::Blocked);
+    enqueue(task.clone());
+    SLEEPERS.exclusive_access().push(Sleeper { deadline_us, task });
+    schedule(task_cx_ptr);
+}
+
+/// Make every task whose deadline has passed ready again
+pub fn wake_sleepers() {
+    let now_us = get_time_us();
+    let mut sleepers = SLEEPERS.exclusive_access();
+    let mut woken = Vec::new();
+    while sleepers.peek().map_or(false, |sleeper| sleeper.deadline_us <= now_us) {
+        woken.push(sleepers.pop().unwrap().task);
+    }
+    drop(sleepers);
+    wake_up(woken);
+}
+
+/// Take `task` off the sleepers, returning whether it was sleeping
+pub fn cancel_sleep(task: &Arc<TaskControlBlock>) -> bool {
+    let mut sleepers = SLEEPERS.exclusive_access();
+    let (woken, kept): (Vec<Sleeper>, Vec<Sleeper>) = core::mem::take(&mut *sleepers)
+        .into_vec()
+        .into_iter()
+        .partition(|sleeper| Arc::ptr_eq(&sleeper.task, task));
+    *sleepers = BinaryHeap::from(kept);
+    !woken.is_empty()
+}
+
+/// Wake `task` before its deadline, if it is sleeping
+pub fn interrupt_sleep(task: &Arc<TaskControlBlock>) {
+    if cancel_sleep(task) {
+        wake_up(alloc::vec![task.clone()]);
+    }
+}
+
+/// Print every sleeper, earliest deadline first
+pub fn dump_sleepers() {
+    let now_us = get_time_us();
+    let mut sleepers: Vec<(usize, usize)> = SLEEPERS
+        .exclusive_access()
+        .iter()
+        .map(|sleeper| (sleeper.deadline_us, sleeper.task.getpid()))
+        .collect();
+    sleepers.sort_unstable();
+    for (deadline_us, pid) in sleepers {
+        println!(
+            "  sleep    pid {:<5} in {:>10}us",
+            pid,
+            deadline_us as isize - now_us as isize
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! The stride scheduler, the default [`Scheduler`]
+
+use super::manager::stride_cmp;
+use super::perturb::{perturbing, pick_tied};
+use super::scheduler::Scheduler;
+use super::TaskControlBlock;
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::cmp::Ordering;
+
+/// Heap entry: the stride is copied out of the TCB when the task is queued,
+/// so comparisons inside the heap never borrow a TCB inner
+struct StrideEntry {
+    stride: u64,
+    task: Arc<TaskControlBlock>,
+}
+
+impl Eq for StrideEntry {}
+
+impl PartialEq<Self> for StrideEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.stride == other.stride
+    }
+}
+
+impl PartialOrd<Self> for StrideEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for StrideEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reverse the order for BinaryHeap
+        stride_cmp(other.stride, self.stride)
+    }
+}
+
+/// Always runs the ready task with the smallest stride
+pub struct StrideScheduler {
+    heap: BinaryHeap<StrideEntry>,
+}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let stride = task.inner_exclusive_access().stride;
+        self.heap.push(StrideEntry { stride, task });
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let first = self.heap.pop()?;
+        if !perturbing() {
+            return Some(first.task);
+        }
+        // the heap breaks ties between equal strides arbitrarily but always
+        // the same way, pick one of them at random instead
+        let mut tied = vec![first];
+        while self
+            .heap
+            .peek()
+            .map_or(false, |entry| entry.stride == tied[0].stride)
+        {
+            tied.push(self.heap.pop().unwrap());
+        }
+        let picked = tied.swap_remove(pick_tied(tied.len()));
+        self.heap.extend(tied);
+        Some(picked.task)
+    }
+    fn remove(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        let mut entries = core::mem::take(&mut self.heap).into_vec();
+        let len = entries.len();
+        entries.retain(|entry| !Arc::ptr_eq(&entry.task, task));
+        let removed = entries.len() != len;
+        self.heap = entries.into();
+        removed
+    }
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
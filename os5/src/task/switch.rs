@@ -7,10 +7,19 @@
 
 core::arch::global_asm!(include_str!("switch.S"));
 
+use super::pid::{check_current_kernel_stack, check_kernel_stack};
 use super::TaskContext;
 
 extern "C" {
     /// Switch to the context of `next_task_cx_ptr`, saving the current context
     /// in `current_task_cx_ptr`.
-    pub fn __switch(current_task_cx_ptr: *mut TaskContext, next_task_cx_ptr: *const TaskContext);
+    fn __switch(current_task_cx_ptr: *mut TaskContext, next_task_cx_ptr: *const TaskContext);
+}
+
+/// [`__switch`], after checking the canaries of the kernel stacks switched
+/// from and to
+pub unsafe fn switch(current_task_cx_ptr: *mut TaskContext, next_task_cx_ptr: *const TaskContext) {
+    check_current_kernel_stack();
+    check_kernel_stack((*next_task_cx_ptr).sp());
+    __switch(current_task_cx_ptr, next_task_cx_ptr);
 }
@@ -2,21 +2,36 @@
 
 use alloc::string::{String, ToString};
 use alloc::sync::{Arc, Weak};
+use alloc::vec;
 use alloc::vec::Vec;
-use core::arch::asm;
 use core::cell::RefMut;
 use core::fmt::{Debug, Formatter};
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
 use core::usize::MAX;
 
 use xmas_elf::symbol_table::Visibility::Default;
 
-use crate::config::{MAX_SYSCALL_NUM, TRAP_CONTEXT};
-use crate::mm::{KERNEL_SPACE, MemorySet, PhysPageNum, VirtAddr};
-use crate::sync::{RefMutWrapper, UPSafeCell};
+use crate::config::{ARG_MAX, MAX_FDS, MAX_MUTEXES, MAX_SYSCALL_NUM, MAX_THREADS, PAGE_SIZE, USER_STACK_SIZE};
+#[cfg(not(feature = "kstack-trap-cx"))]
+use crate::config::TRAP_CONTEXT;
+use crate::console::LineBuffer;
+use crate::random::fill_random;
+use crate::fs::{File, IoClass, Stdin, Stdout, IO_CLASSES};
+use crate::mm::{ElfError, KERNEL_SPACE, MemorySet, PhysAddr, PhysPageNum};
+#[cfg(not(feature = "kstack-trap-cx"))]
+use crate::mm::{thread_trap_cx, VirtAddr};
+use crate::mm::{copy_data_into_space, translated_byte_buffer};
+#[cfg(feature = "shadow-stack")]
+use crate::shadow_stack::ShadowStack;
+use crate::sync::{Mutex, RefMutWrapper, UPSafeCell};
+use crate::timer::get_time_us;
 use crate::trap::{trap_handler, TrapContext};
 
-use super::{KernelStack, pid_alloc, PidHandle};
-use super::TaskContext;
+use super::{KernelStack, pid_alloc, PidHandle, StackRole};
+use super::{CapSet, SignalState, TaskContext, WaitQueue, WakeOrder};
+use super::manager::{insert_into_pid2task, DEFAULT_PRIORITY};
+use super::thread::process_leader;
 
 /// Task control block structure
 ///
@@ -27,6 +42,11 @@ pub struct TaskControlBlock {
     pub pid: PidHandle,
     /// Kernel stack corresponding to PID
     pub kernel_stack: KernelStack,
+    /// Calls of each syscall, atomic so that counting takes no borrow
+    pub syscall_times: SyscallTimes,
+    /// Return addresses of its guarded kernel frames
+    #[cfg(feature = "shadow-stack")]
+    pub shadow_stack: ShadowStack,
     // mutable
     inner: UPSafeCell<TaskControlBlockInner>,
 }
@@ -43,7 +63,9 @@ impl Debug for TaskControlBlock {
 /// and are wrapped by UPSafeCell to provide mutual exclusion
 pub struct TaskControlBlockInner {
     pub name: String,
-    /// The physical page number of the frame where the trap context is placed
+    /// The physical page number of the frame where the trap context is placed,
+    /// at its start, or with `kstack-trap-cx` at its end as the top page of
+    /// the kernel stack
     pub trap_cx_ppn: PhysPageNum,
     /// Application data can only appear in areas
     /// where the application address space is lower than base_size
@@ -52,8 +74,8 @@ pub struct TaskControlBlockInner {
     pub task_cx: TaskContext,
     /// Maintain the execution status of the current process
     pub task_status: TaskStatus,
-    /// Application address space
-    pub memory_set: MemorySet,
+    /// Application address space, shared by the threads of a process
+    pub memory_set: Arc<UPSafeCell<MemorySet>>,
     /// Parent process of the current process.
     /// Weak will not affect the reference count of the parent
     pub parent: Option<Weak<TaskControlBlock>>,
@@ -62,9 +84,246 @@ pub struct TaskControlBlockInner {
     /// It is set when active exit or execution error occurs
     pub exit_code: i32,
     pub start_time_ms: usize,
-    pub syscall_times: [u32; MAX_SYSCALL_NUM],
-    pub stride: isize,
-    pub priority: isize,
+    /// Wrapping pass counter, compare with [`stride_cmp`](super::manager::stride_cmp)
+    pub stride: u64,
+    /// At least [`MIN_PRIORITY`](super::manager::MIN_PRIORITY)
+    pub priority: u64,
+    /// `get_time_us()` when the task was last switched in
+    pub dispatched_at_us: usize,
+    /// Queue level, used by schedulers with several levels
+    pub sched_level: usize,
+    /// Timer ticks left in the current time slice
+    pub slice_ticks_left: usize,
+    /// CPU time used up to the last time the task was switched out
+    pub cpu_time_us: usize,
+    /// CPU time of all reaped descendants, grandchildren included
+    pub children_cpu_us: usize,
+    /// `get_time_us()` when the task was last put into the ready queue
+    pub ready_since_us: usize,
+    /// Delay between becoming ready and being dispatched
+    pub sched_latency: SchedLatencyHistogram,
+    /// Bytes read and written by the task
+    pub io: IoStats,
+    /// Bytes read and written by all reaped descendants, like `children_cpu_us`
+    pub children_io: IoStats,
+    /// Page faults the task took that were resolved
+    pub faults: FaultStats,
+    /// Page faults of all reaped descendants, like `children_io`
+    pub children_faults: FaultStats,
+    /// Absolute, normalized current working directory
+    pub cwd: String,
+    /// Pending console output of `sys_write(stdout)`
+    pub stdout: LineBuffer,
+    /// Open files by descriptor, kept by the main thread for the whole
+    /// process
+    pub fd_table: Vec<Option<Arc<dyn File>>>,
+    /// Mutexes by id, kept by the main thread like `fd_table`
+    pub mutexes: Vec<Arc<Mutex>>,
+    /// Privileges, see [`CapSet`]
+    pub caps: CapSet,
+    /// Pending and blocked signals and their actions
+    pub signals: SignalState,
+    /// The task itself while it waits in `waitpid` for a child to exit
+    pub child_exit: WaitQueue,
+    /// Process group, see [`pgrp`](super::pgrp)
+    pub pgid: usize,
+    /// 0 for the main thread of a process, see [`thread`](super::thread)
+    pub thread_slot: usize,
+    /// The other threads of the process, kept by its main thread until
+    /// they are waited for or the process exits
+    pub threads: Vec<Arc<TaskControlBlock>>,
+    /// Essential to the system, like initproc: its priority never drops
+    /// below [`DEFAULT_PRIORITY`], the schedulers never let it starve, and
+    /// it only gets the signals it has set an action for
+    ///
+    /// There is no swap and no OOM killer yet; once there is, they are to
+    /// skip pinned tasks too.
+    pub pinned: bool,
+}
+
+/// Per-syscall call counters of a task
+///
+/// Only the task itself counts and reads them, so relaxed ordering is
+/// enough.
+pub struct SyscallTimes([AtomicU32; MAX_SYSCALL_NUM]);
+
+impl SyscallTimes {
+    pub fn new() -> Self {
+        Self(core::array::from_fn(|_| AtomicU32::new(0)))
+    }
+    /// Count a call of `syscall_id`, ignoring ids past [`MAX_SYSCALL_NUM`]
+    pub fn increase(&self, syscall_id: usize) {
+        if let Some(times) = self.0.get(syscall_id) {
+            times.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    /// Take back a call counted with [`SyscallTimes::increase`]
+    pub fn decrease(&self, syscall_id: usize) {
+        if let Some(times) = self.0.get(syscall_id) {
+            times.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+    pub fn snapshot(&self) -> [u32; MAX_SYSCALL_NUM] {
+        core::array::from_fn(|id| self.0[id].load(Ordering::Relaxed))
+    }
+}
+
+/// Number of log2 buckets in a [`SchedLatencyHistogram`]
+pub const SCHED_LATENCY_BUCKETS: usize = 24;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+/// Histogram of ready-queue waiting times
+///
+/// Bucket `i` counts dispatches that waited `[2^i, 2^(i+1))` microseconds
+/// (bucket 0 also takes 0us), the last bucket takes everything longer.
+pub struct SchedLatencyHistogram {
+    pub buckets: [u32; SCHED_LATENCY_BUCKETS],
+    pub samples: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+}
+
+impl SchedLatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: [0; SCHED_LATENCY_BUCKETS],
+            samples: 0,
+            total_us: 0,
+            max_us: 0,
+        }
+    }
+    pub fn record(&mut self, latency_us: usize) {
+        let bucket = (usize::BITS - latency_us.leading_zeros()).saturating_sub(1) as usize;
+        self.buckets[bucket.min(SCHED_LATENCY_BUCKETS - 1)] += 1;
+        self.samples += 1;
+        self.total_us += latency_us as u64;
+        self.max_us = self.max_us.max(latency_us as u64);
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+/// Bytes a task moved through `sys_read` and `sys_write`, indexed by
+/// [`IoClass`]
+pub struct IoStats {
+    pub read: [u64; IO_CLASSES],
+    pub written: [u64; IO_CLASSES],
+}
+
+impl IoStats {
+    pub fn new() -> Self {
+        Self {
+            read: [0; IO_CLASSES],
+            written: [0; IO_CLASSES],
+        }
+    }
+    pub fn record_read(&mut self, class: IoClass, bytes: usize) {
+        self.read[class as usize] += bytes as u64;
+    }
+    pub fn record_write(&mut self, class: IoClass, bytes: usize) {
+        self.written[class as usize] += bytes as u64;
+    }
+    /// Add the counts of `other` to these
+    pub fn add(&mut self, other: &IoStats) {
+        for class in 0..IO_CLASSES {
+            self.read[class] += other.read[class];
+            self.written[class] += other.written[class];
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+/// Page faults of a task that were resolved, by whether they needed I/O
+///
+/// Lazy pages, copy-on-write and stack growth only take a frame and are
+/// minor. The first access to a page of a mapped file reads it from the
+/// disk and is major; with no swap, nothing else is.
+pub struct FaultStats {
+    pub minor: u64,
+    pub major: u64,
+}
+
+impl FaultStats {
+    pub fn new() -> Self {
+        Self { minor: 0, major: 0 }
+    }
+    /// Add the counts of `other` to these
+    pub fn add(&mut self, other: &FaultStats) {
+        self.minor += other.minor;
+        self.major += other.major;
+    }
+}
+
+/// Auxiliary vector entry types, numbered as on Linux
+const AT_NULL: usize = 0;
+const AT_RANDOM: usize = 25;
+/// Random bytes `AT_RANDOM` points to
+const AT_RANDOM_LEN: usize = 16;
+// the arguments always fit in the first page of a fresh user stack, with
+// the random bytes, the empty envp and the auxiliary vector
+#[allow(clippy::assertions_on_constants)]
+const _: () = assert!(ARG_MAX + AT_RANDOM_LEN + 5 * size_of::<usize>() + 16 <= PAGE_SIZE);
+
+/// Bytes [`push_args`] lays `args` out in, before aligning the stack
+pub fn args_size(args: &[String]) -> usize {
+    (args.len() + 1) * size_of::<usize>() + args.iter().map(|arg| arg.len() + 1).sum::<usize>()
+}
+
+/// Lay out `args` on the fresh user stack of the address space `token`,
+/// which ends at `user_sp`; returns the new, 16-byte aligned, stack pointer
+/// and the address of `argv`
+///
+/// As on Linux, the null-terminated `argv` array is followed by an empty
+/// `envp` and by the auxiliary vector, whose `AT_RANDOM` entry points to
+/// 16 random bytes at the very top, for stack canaries and the like. The
+/// strings lie below `argv`. The whole stack is zeroed first, so nothing
+/// else is there to be read. The arguments must take at most [`ARG_MAX`]
+/// bytes, see [`args_size`].
+fn push_args(token: usize, mut user_sp: usize, args: &[String]) -> (usize, usize) {
+    let stack = translated_byte_buffer(token, (user_sp - USER_STACK_SIZE) as *const u8, USER_STACK_SIZE, true)
+        .expect("user stack not mapped");
+    for buffer in stack {
+        buffer.fill(0);
+    }
+    user_sp -= AT_RANDOM_LEN;
+    let random = user_sp;
+    let mut bytes = [0u8; AT_RANDOM_LEN];
+    fill_random(&mut bytes);
+    unsafe { copy_data_into_space(&bytes, token, random as *const [u8; AT_RANDOM_LEN]) }
+        .expect("random bytes outside the stack");
+    // argv and its NULL, envp's NULL, then AT_RANDOM and AT_NULL
+    user_sp -= (args.len() + 1 + 1 + 4) * size_of::<usize>();
+    let argv = user_sp;
+    let auxv = [AT_RANDOM, random, AT_NULL, 0];
+    let auxv_start = (argv + (args.len() + 2) * size_of::<usize>()) as *const [usize; 4];
+    unsafe { copy_data_into_space(&auxv, token, auxv_start) }.expect("auxv outside the stack");
+    for (i, arg) in args.iter().enumerate() {
+        user_sp -= arg.len() + 1;
+        let pointer = (argv + i * size_of::<usize>()) as *const usize;
+        unsafe { copy_data_into_space(&user_sp, token, pointer) }.expect("argv outside the stack");
+        let mut bytes = arg.bytes().chain(core::iter::once(0));
+        let buffers = translated_byte_buffer(token, user_sp as *const u8, arg.len() + 1, true)
+            .expect("arguments outside the stack");
+        for buffer in buffers {
+            buffer.iter_mut().for_each(|byte| *byte = bytes.next().unwrap());
+        }
+    }
+    // argv's and envp's NULL
+    let end = (argv + args.len() * size_of::<usize>()) as *const [usize; 2];
+    unsafe { copy_data_into_space(&[0; 2], token, end) }.expect("argv outside the stack");
+    user_sp -= user_sp % 16;
+    (user_sp, argv)
+}
+
+/// A child starts with an empty buffer but keeps the parent's buffering mode
+fn inherit_stdout(parent: &LineBuffer) -> LineBuffer {
+    let mut stdout = LineBuffer::new();
+    if !parent.is_buffered() {
+        stdout.set_buffered(false);
+    }
+    stdout
 }
 
 /// Simple access to its internal fields
@@ -75,58 +334,114 @@ impl TaskControlBlockInner {
     }
     */
     pub fn get_trap_cx(&self) -> &'static mut TrapContext {
-        self.trap_cx_ppn.get_mut()
+        if cfg!(feature = "kstack-trap-cx") {
+            let top = PhysAddr::from(self.trap_cx_ppn).0 + PAGE_SIZE;
+            unsafe { &mut *((top - size_of::<TrapContext>()) as *mut TrapContext) }
+        } else {
+            self.trap_cx_ppn.get_mut()
+        }
     }
     pub fn get_user_token(&self) -> usize {
-        self.memory_set.token()
+        self.memory_set.exclusive_access().token()
+    }
+    /// Set the priority, keeping that of a pinned task at the default or above
+    pub fn set_priority(&mut self, priority: u64) {
+        self.priority = if self.pinned {
+            priority.max(DEFAULT_PRIORITY)
+        } else {
+            priority
+        };
     }
     fn get_status(&self) -> TaskStatus {
         self.task_status
     }
+    /// Change the status, checking the transition in debug builds
+    #[track_caller]
+    pub fn set_status(&mut self, next: TaskStatus) {
+        debug_assert!(
+            self.task_status.can_become(next),
+            "[kernel] task {}: illegal transition {:?} -> {:?} at {}",
+            self.name,
+            self.task_status,
+            next,
+            core::panic::Location::caller(),
+        );
+        self.task_status = next;
+    }
+    /// CPU time used so far by the running task, its current slice included
+    pub fn running_cpu_time_us(&self) -> usize {
+        self.cpu_time_us + (get_time_us() - self.dispatched_at_us)
+    }
     pub fn is_zombie(&self) -> bool {
         self.get_status() == TaskStatus::Zombie
     }
-}
-
-impl Debug for TaskControlBlockInner {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        write!(f, "TCB_Inner({})", self.name)
+    /// The lowest free file descriptor, `None` if [`MAX_FDS`] are open
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        if let Some(fd) = self.fd_table.iter().position(|file| file.is_none()) {
+            return Some(fd);
+        }
+        if self.fd_table.len() == MAX_FDS {
+            return None;
+        }
+        self.fd_table.push(None);
+        Some(self.fd_table.len() - 1)
+    }
+    /// Add a new mutex and return its id, `None` if there are
+    /// [`MAX_MUTEXES`] already
+    pub fn add_mutex(&mut self, blocking: bool) -> Option<usize> {
+        if self.mutexes.len() == MAX_MUTEXES {
+            return None;
+        }
+        self.mutexes.push(Arc::new(Mutex::new(blocking)));
+        Some(self.mutexes.len() - 1)
     }
 }
 
 impl TaskControlBlock {
     /// Get the mutex to get the RefMut TaskControlBlockInner
+    ///
+    /// Always inlined with `debug_lock`, so the return address recorded for
+    /// a borrow is in the caller of this rather than here.
+    #[cfg_attr(feature = "cell-tracking", track_caller)]
+    #[cfg_attr(feature = "debug_lock", inline(always))]
     pub fn inner_exclusive_access(&self) -> RefMutWrapper<'_, TaskControlBlockInner> {
-        let mut ra: usize;
-
-        unsafe {
-            asm!(
-                "mv {ra}, ra",
-                ra = out(reg) ra,
-            );
-        }
-        // println!("[inner_exclusive_access] ra={:#x}", ra);
         self.inner.exclusive_access()
     }
+    /// `None` if the inner is borrowed, see [`UPSafeCell::try_exclusive_access`]
+    #[cfg_attr(feature = "cell-tracking", track_caller)]
+    pub fn try_inner_exclusive_access(&self) -> Option<RefMutWrapper<'_, TaskControlBlockInner>> {
+        self.inner.try_exclusive_access()
+    }
 
     /// Create a new process
     ///
     /// At present, it is only used for the creation of initproc
     pub fn new(elf_data: &[u8], name: &str) -> Self {
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data, name)
+            .unwrap_or_else(|err| panic!("cannot load {}: {:?}", name, err));
+        // no arguments, but the auxiliary vector
+        let (user_sp, argv) = push_args(memory_set.token(), user_sp, &[]);
+        #[cfg(not(feature = "kstack-trap-cx"))]
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
+        let pid = pid_handle.0;
+        let kernel_stack =
+            KernelStack::new(&pid_handle, StackRole::Task).expect("out of frames for the kernel stack of initproc");
+        let kernel_stack_top = kernel_stack.get_trap_sp();
+        #[cfg(feature = "kstack-trap-cx")]
+        let trap_cx_ppn = kernel_stack.trap_cx_ppn();
         // push a task context which goes to trap_return to the top of kernel stack
         let task_control_block = Self {
             pid: pid_handle,
             kernel_stack,
+            syscall_times: SyscallTimes::new(),
+            #[cfg(feature = "shadow-stack")]
+            shadow_stack: ShadowStack::new(),
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     name: name.to_string(),
@@ -134,14 +449,40 @@ impl TaskControlBlock {
                     base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    memory_set: Arc::new(UPSafeCell::new(memory_set)),
                     parent: None,
                     children: Vec::new(),
                     exit_code: 0,
                     start_time_ms: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
                     stride: 0,
-                    priority: 16,
+                    priority: DEFAULT_PRIORITY,
+                    dispatched_at_us: 0,
+                    sched_level: 0,
+                    slice_ticks_left: 0,
+                    cpu_time_us: 0,
+                    children_cpu_us: 0,
+                    ready_since_us: 0,
+                    sched_latency: SchedLatencyHistogram::new(),
+                    io: IoStats::new(),
+                    children_io: IoStats::new(),
+                    faults: FaultStats::new(),
+                    children_faults: FaultStats::new(),
+                    cwd: String::from("/"),
+                    stdout: LineBuffer::new(),
+                    // stdin, stdout and stderr
+                    fd_table: vec![
+                        Some(Arc::new(Stdin)),
+                        Some(Arc::new(Stdout)),
+                        Some(Arc::new(Stdout)),
+                    ],
+                    mutexes: Vec::new(),
+                    caps: CapSet::full(),
+                    signals: SignalState::new(),
+                    child_exit: WaitQueue::new(WakeOrder::Fifo),
+                    pgid: pid,
+                    thread_slot: 0,
+                    threads: Vec::new(),
+                    pinned: false,
                 })
             },
         };
@@ -154,13 +495,20 @@ impl TaskControlBlock {
             kernel_stack_top,
             trap_handler as usize,
         );
+        trap_cx.x[11] = argv;
         task_control_block
     }
-    /// Load a new elf to replace the original application address space and start execution
-    pub fn exec(&self, elf_data: &[u8], name: &str) {
+    /// Load a new elf to replace the original application address space and
+    /// start execution with `args`, see [`push_args`]
+    ///
+    /// The new address space is built completely before anything of the
+    /// current one is touched, so on error the old program keeps running.
+    pub fn exec(&self, elf_data: &[u8], name: &str, args: &[String]) -> Result<(), ElfError> {
         // println!("[exec]");
         // memory_set with elf program headers/trampoline/trap context/user stack
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data, name)?;
+        let (sp, argv) = push_args(memory_set.token(), user_sp, args);
+        #[cfg(not(feature = "kstack-trap-cx"))]
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
@@ -170,38 +518,59 @@ impl TaskControlBlock {
         let mut inner = self.inner_exclusive_access();
         // println!("[exec] name:{} chang to:{}", inner.name, name);
         inner.name = name.to_string();
+        inner.caps = inner.caps.after_exec();
+        inner.signals = inner.signals.after_exec();
+        inner.mutexes.clear();
         // substitute memory_set
-        inner.memory_set = memory_set;
-        // update trap_cx ppn
-        inner.trap_cx_ppn = trap_cx_ppn;
+        inner.memory_set = Arc::new(unsafe { UPSafeCell::new(memory_set) });
+        // update trap_cx ppn, it stays on the kernel stack with kstack-trap-cx
+        #[cfg(not(feature = "kstack-trap-cx"))]
+        {
+            inner.trap_cx_ppn = trap_cx_ppn;
+        }
+        inner.base_size = user_sp;
         // initialize trap_cx
         let trap_cx = inner.get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
             entry_point,
-            user_sp,
+            sp,
             KERNEL_SPACE.exclusive_access().token(),
-            self.kernel_stack.get_top(),
+            self.kernel_stack.get_trap_sp(),
             trap_handler as usize,
         );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv;
+        Ok(())
         // **** release inner automatically
     }
-    /// Fork from parent to child
-    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    /// Fork from parent to child, or `None` when frames run out
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Option<Arc<TaskControlBlock>> {
         // ---- access parent PCB exclusively
         let mut parent_inner = self.inner_exclusive_access();
         // copy user space(include trap context)
-        let memory_set = MemorySet::from_existed_user(&parent_inner.memory_set);
+        let mut memory_set =
+            MemorySet::from_existed_user(&parent_inner.memory_set.exclusive_access())?;
+        // the child has only the thread that forked it
+        for slot in 1..MAX_THREADS {
+            memory_set.remove_thread(slot);
+        }
+        #[cfg(not(feature = "kstack-trap-cx"))]
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         // alloc a pid and a kernel stack in kernel space
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
+        let kernel_stack = KernelStack::new(&pid_handle, StackRole::Task)?;
+        let kernel_stack_top = kernel_stack.get_trap_sp();
+        #[cfg(feature = "kstack-trap-cx")]
+        let trap_cx_ppn = kernel_stack.trap_cx_ppn();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
+            syscall_times: SyscallTimes::new(),
+            #[cfg(feature = "shadow-stack")]
+            shadow_stack: ShadowStack::new(),
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     name: parent_inner.name.clone(),
@@ -209,70 +578,293 @@ impl TaskControlBlock {
                     base_size: parent_inner.base_size,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    memory_set: Arc::new(UPSafeCell::new(memory_set)),
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: 0,
                     start_time_ms: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    stride: 0,
-                    priority: 16,
+                    stride: parent_inner.stride,
+                    priority: DEFAULT_PRIORITY,
+                    dispatched_at_us: 0,
+                    sched_level: 0,
+                    slice_ticks_left: 0,
+                    cpu_time_us: 0,
+                    children_cpu_us: 0,
+                    ready_since_us: 0,
+                    sched_latency: SchedLatencyHistogram::new(),
+                    io: IoStats::new(),
+                    children_io: IoStats::new(),
+                    faults: FaultStats::new(),
+                    children_faults: FaultStats::new(),
+                    cwd: parent_inner.cwd.clone(),
+                    stdout: inherit_stdout(&parent_inner.stdout),
+                    fd_table: parent_inner.fd_table.clone(),
+                    // unlocked: the threads holding them are not forked
+                    mutexes: parent_inner
+                        .mutexes
+                        .iter()
+                        .map(|mutex| Arc::new(mutex.fork()))
+                        .collect(),
+                    caps: parent_inner.caps,
+                    signals: parent_inner.signals.fork(),
+                    child_exit: WaitQueue::new(WakeOrder::Fifo),
+                    pgid: parent_inner.pgid,
+                    thread_slot: 0,
+                    threads: Vec::new(),
+                    pinned: false,
                 })
             },
         });
         // add child
         parent_inner.children.push(task_control_block.clone());
+        insert_into_pid2task(task_control_block.getpid(), task_control_block.clone());
         // modify kernel_sp in trap_cx
         // **** access children PCB exclusively
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        // copied with the address space unless it is on the kernel stack
+        #[cfg(feature = "kstack-trap-cx")]
+        {
+            *trap_cx = parent_inner.get_trap_cx().clone();
+        }
         trap_cx.kernel_sp = kernel_stack_top;
         // return
-        task_control_block
+        Some(task_control_block)
         // ---- release parent PCB automatically
         // **** release children PCB automatically
     }
 
-    pub fn spawn(self: &Arc<TaskControlBlock>, elf_data: &[u8], name: &str ) -> Arc<TaskControlBlock> {
+    /// Start a child running `elf_data` with `args`, as fork and exec
+    /// would but without copying the parent's address space
+    pub fn spawn(
+        self: &Arc<TaskControlBlock>,
+        elf_data: &[u8],
+        name: &str,
+        args: &[String],
+    ) -> Result<Arc<TaskControlBlock>, ElfError> {
+        // a thread spawning passes on the files of its process
+        let fd_table = process_leader(self).inner_exclusive_access().fd_table.clone();
         let mut parent_inner = self.inner_exclusive_access();
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data, name)?;
+        let (sp, argv) = push_args(memory_set.token(), user_sp, args);
+        #[cfg(not(feature = "kstack-trap-cx"))]
         let trap_cx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT).into())
             .unwrap()
             .ppn();
         let pid_handle = pid_alloc();
-        let kernel_stack = KernelStack::new(&pid_handle);
-        let kernel_stack_top = kernel_stack.get_top();
+        let kernel_stack = KernelStack::new(&pid_handle, StackRole::Task).ok_or(ElfError::OutOfMemory)?;
+        let kernel_stack_top = kernel_stack.get_trap_sp();
+        #[cfg(feature = "kstack-trap-cx")]
+        let trap_cx_ppn = kernel_stack.trap_cx_ppn();
         let task_control_block = Arc::new(TaskControlBlock {
             pid: pid_handle,
             kernel_stack,
+            syscall_times: SyscallTimes::new(),
+            #[cfg(feature = "shadow-stack")]
+            shadow_stack: ShadowStack::new(),
             inner: unsafe {
                 UPSafeCell::new(TaskControlBlockInner {
                     name: name.to_string(),
                     trap_cx_ppn,
-                    base_size: parent_inner.base_size,
+                    base_size: user_sp,
                     task_cx: TaskContext::goto_trap_return(kernel_stack_top),
                     task_status: TaskStatus::Ready,
-                    memory_set,
+                    memory_set: Arc::new(UPSafeCell::new(memory_set)),
                     parent: Some(Arc::downgrade(self)),
                     children: Vec::new(),
                     exit_code: 0,
                     start_time_ms: 0,
-                    syscall_times: [0; MAX_SYSCALL_NUM],
-                    stride: 0,
-                    priority: 16,
+                    stride: parent_inner.stride,
+                    priority: DEFAULT_PRIORITY,
+                    dispatched_at_us: 0,
+                    sched_level: 0,
+                    slice_ticks_left: 0,
+                    cpu_time_us: 0,
+                    children_cpu_us: 0,
+                    ready_since_us: 0,
+                    sched_latency: SchedLatencyHistogram::new(),
+                    io: IoStats::new(),
+                    children_io: IoStats::new(),
+                    faults: FaultStats::new(),
+                    children_faults: FaultStats::new(),
+                    cwd: parent_inner.cwd.clone(),
+                    stdout: inherit_stdout(&parent_inner.stdout),
+                    fd_table,
+                    mutexes: Vec::new(),
+                    caps: parent_inner.caps.after_exec(),
+                    signals: parent_inner.signals.after_exec(),
+                    child_exit: WaitQueue::new(WakeOrder::Fifo),
+                    pgid: parent_inner.pgid,
+                    thread_slot: 0,
+                    threads: Vec::new(),
+                    pinned: false,
                 })
             }
         });
         parent_inner.children.push(task_control_block.clone());
+        insert_into_pid2task(task_control_block.getpid(), task_control_block.clone());
         let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
         *trap_cx = TrapContext::app_init_context(
             entry_point,
+            sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            kernel_stack_top,
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = args.len();
+        trap_cx.x[11] = argv;
+        Ok(task_control_block)
+    }
+
+    /// Start a thread at `entry` with argument `arg` in the address space of
+    /// this process, `None` if it has no free thread slot
+    ///
+    /// `self` is the thread creating it, and the new thread takes after it
+    /// like a forked child does; it belongs to `leader`, the main thread.
+    pub fn new_thread(
+        self: &Arc<TaskControlBlock>,
+        leader: &Arc<TaskControlBlock>,
+        entry: usize,
+        arg: usize,
+    ) -> Option<Arc<TaskControlBlock>> {
+        let inner = self.inner_exclusive_access();
+        let memory_set = inner.memory_set.clone();
+        let (slot, user_sp) = memory_set.exclusive_access().insert_thread()?;
+        #[cfg(not(feature = "kstack-trap-cx"))]
+        let trap_cx_ppn = memory_set
+            .exclusive_access()
+            .translate(VirtAddr::from(thread_trap_cx(slot)).into())
+            .unwrap()
+            .ppn();
+        let pid_handle = pid_alloc();
+        let kernel_stack = match KernelStack::new(&pid_handle, StackRole::Task) {
+            Some(kernel_stack) => kernel_stack,
+            None => {
+                memory_set.exclusive_access().remove_thread(slot);
+                return None;
+            }
+        };
+        let kernel_stack_top = kernel_stack.get_trap_sp();
+        #[cfg(feature = "kstack-trap-cx")]
+        let trap_cx_ppn = kernel_stack.trap_cx_ppn();
+        let task_control_block = Arc::new(TaskControlBlock {
+            pid: pid_handle,
+            kernel_stack,
+            syscall_times: SyscallTimes::new(),
+            #[cfg(feature = "shadow-stack")]
+            shadow_stack: ShadowStack::new(),
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    name: inner.name.clone(),
+                    trap_cx_ppn,
+                    base_size: user_sp,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_status: TaskStatus::Ready,
+                    memory_set,
+                    parent: Some(Arc::downgrade(leader)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    start_time_ms: 0,
+                    stride: inner.stride,
+                    priority: DEFAULT_PRIORITY,
+                    dispatched_at_us: 0,
+                    sched_level: 0,
+                    slice_ticks_left: 0,
+                    cpu_time_us: 0,
+                    children_cpu_us: 0,
+                    ready_since_us: 0,
+                    sched_latency: SchedLatencyHistogram::new(),
+                    io: IoStats::new(),
+                    children_io: IoStats::new(),
+                    faults: FaultStats::new(),
+                    children_faults: FaultStats::new(),
+                    cwd: inner.cwd.clone(),
+                    stdout: inherit_stdout(&inner.stdout),
+                    fd_table: Vec::new(),
+                    mutexes: Vec::new(),
+                    caps: inner.caps,
+                    signals: inner.signals.fork(),
+                    child_exit: WaitQueue::new(WakeOrder::Fifo),
+                    pgid: inner.pgid,
+                    thread_slot: slot,
+                    threads: Vec::new(),
+                    pinned: false,
+                })
+            },
+        });
+        drop(inner);
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry,
             user_sp,
             KERNEL_SPACE.exclusive_access().token(),
             kernel_stack_top,
             trap_handler as usize,
         );
-        task_control_block
+        trap_cx.x[10] = arg;
+        leader.inner_exclusive_access().threads.push(task_control_block.clone());
+        insert_into_pid2task(task_control_block.getpid(), task_control_block.clone());
+        Some(task_control_block)
+    }
+
+    /// A kernel thread named `name` that starts at `entry` on its kernel
+    /// stack, `None` if there are no frames for that stack
+    ///
+    /// It has no user space: no TrapContext, no files, and the kernel's
+    /// address space as its memory set. `self` is its parent, whose
+    /// `child_exit` it wakes on exit, but it is neither among the parent's
+    /// children nor in the pid table. See [`kthread`](super::kthread).
+    pub fn new_kthread(self: &Arc<TaskControlBlock>, name: &str, entry: usize) -> Option<Arc<TaskControlBlock>> {
+        let parent_inner = self.inner_exclusive_access();
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle, StackRole::Kthread)?;
+        let kernel_stack_top = kernel_stack.get_top();
+        Some(Arc::new(TaskControlBlock {
+            pid: pid_handle,
+            kernel_stack,
+            syscall_times: SyscallTimes::new(),
+            #[cfg(feature = "shadow-stack")]
+            shadow_stack: ShadowStack::new(),
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    name: name.to_string(),
+                    // never used, a kernel thread does not trap from user mode
+                    trap_cx_ppn: PhysPageNum(0),
+                    base_size: 0,
+                    task_cx: TaskContext::goto(entry, kernel_stack_top),
+                    task_status: TaskStatus::Ready,
+                    memory_set: KERNEL_SPACE.clone(),
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    start_time_ms: 0,
+                    stride: parent_inner.stride,
+                    priority: DEFAULT_PRIORITY,
+                    dispatched_at_us: 0,
+                    sched_level: 0,
+                    slice_ticks_left: 0,
+                    cpu_time_us: 0,
+                    children_cpu_us: 0,
+                    ready_since_us: 0,
+                    sched_latency: SchedLatencyHistogram::new(),
+                    io: IoStats::new(),
+                    children_io: IoStats::new(),
+                    faults: FaultStats::new(),
+                    children_faults: FaultStats::new(),
+                    cwd: String::from("/"),
+                    stdout: LineBuffer::new(),
+                    fd_table: Vec::new(),
+                    mutexes: Vec::new(),
+                    caps: parent_inner.caps,
+                    signals: SignalState::new(),
+                    child_exit: WaitQueue::new(WakeOrder::Fifo),
+                    pgid: parent_inner.pgid,
+                    thread_slot: 0,
+                    threads: Vec::new(),
+                    pinned: false,
+                })
+            },
+        }))
     }
 
     #[inline]
@@ -281,11 +873,31 @@ impl TaskControlBlock {
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 /// task status: UnInit, Ready, Running, Exited
 pub enum TaskStatus {
     UnInit,
     Ready,
     Running,
     Zombie,
+    /// waiting for an event, in some queue other than the ready queue
+    Blocked,
+}
+
+impl TaskStatus {
+    /// Whether the scheduler may move a task from `self` to `next`
+    ///
+    /// UnInit -> Ready -> Running -> {Ready, Blocked, Zombie}, and a blocked
+    /// task becomes Ready when woken; a zombie never changes.
+    pub fn can_become(self, next: TaskStatus) -> bool {
+        matches!(
+            (self, next),
+            (TaskStatus::UnInit, TaskStatus::Ready)
+                | (TaskStatus::Ready, TaskStatus::Running)
+                | (TaskStatus::Running, TaskStatus::Ready)
+                | (TaskStatus::Running, TaskStatus::Blocked)
+                | (TaskStatus::Running, TaskStatus::Zombie)
+                | (TaskStatus::Blocked, TaskStatus::Ready)
+        )
+    }
 }
@@ -0,0 +1,94 @@
+//! Threads sharing the address space of a process
+//!
+//! A thread is a TCB of its own, with its own pid as thread id, kernel
+//! stack, and a user stack and TrapContext in a slot of the shared
+//! [`MemorySet`](crate::mm::MemorySet). Only the address space is shared:
+//! signals, capabilities and the rest are copied from the creating thread as
+//! on fork. The main thread, slot 0, keeps the other threads, which name it
+//! as their parent but are not among its children.
+//!
+//! A thread that exits ends only itself, but when the main thread exits the
+//! whole process does: the others are sent `SIGKILL` and waited for first,
+//! so that the address space is freed by the last one to go. Only a process
+//! with a single thread can `exec`, and only its main thread can `fork`.
+
+use super::signal::deliver;
+use super::{add_task, block_current_and_run_next, current_task, release_zombie};
+use super::{SignalFlags, TaskControlBlock};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The main thread of the process `task` belongs to
+pub fn process_leader(task: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+    let inner = task.inner_exclusive_access();
+    if inner.thread_slot == 0 {
+        return task.clone();
+    }
+    // the main thread outlives every other thread
+    inner.parent.as_ref().and_then(|parent| parent.upgrade()).unwrap()
+}
+
+/// Start a thread of the current process, returning its thread id
+pub fn create_thread(entry: usize, arg: usize) -> Option<usize> {
+    let task = current_task().unwrap();
+    let leader = process_leader(&task);
+    let thread = task.new_thread(&leader, entry, arg)?;
+    let tid = thread.getpid();
+    add_task(thread);
+    Some(tid)
+}
+
+/// Wait for thread `tid` of the current process to exit and return its exit
+/// code; `None` if it is the caller, the main thread or no thread of the
+/// process, or if the caller got `SIGKILL` meanwhile
+pub fn wait_thread(tid: usize) -> Option<i32> {
+    let task = current_task().unwrap();
+    let leader = process_leader(&task);
+    if tid == task.getpid() {
+        return None;
+    }
+    loop {
+        let mut leader_inner = leader.inner_exclusive_access();
+        let index = leader_inner.threads.iter().position(|thread| thread.getpid() == tid)?;
+        if leader_inner.threads[index].inner_exclusive_access().is_zombie() {
+            let thread = leader_inner.threads.remove(index);
+            drop(leader_inner);
+            let exit_code = thread.inner_exclusive_access().exit_code;
+            release_zombie(thread);
+            return Some(exit_code);
+        }
+        drop(leader_inner);
+        if task.inner_exclusive_access().signals.pending.contains(SignalFlags::SIGKILL) {
+            return None;
+        }
+        // an exiting thread wakes whatever waits on its parent, the main thread
+        block_current_and_run_next(|task| leader.inner_exclusive_access().child_exit.push(task));
+    }
+}
+
+/// If the current task is the main thread of its process, kill the other
+/// threads, wait until they have all exited and release them
+pub fn end_other_threads() {
+    let task = current_task().unwrap();
+    loop {
+        let live: Vec<_> = task
+            .inner_exclusive_access()
+            .threads
+            .iter()
+            .filter(|thread| !thread.inner_exclusive_access().is_zombie())
+            .cloned()
+            .collect();
+        if live.is_empty() {
+            break;
+        }
+        for thread in live.iter() {
+            deliver(thread, SignalFlags::SIGKILL.number());
+        }
+        drop(live);
+        block_current_and_run_next(|current| task.inner_exclusive_access().child_exit.push(current));
+    }
+    let threads = core::mem::take(&mut task.inner_exclusive_access().threads);
+    for thread in threads {
+        release_zombie(thread);
+    }
+}
@@ -0,0 +1,95 @@
+//! Tasks blocked until an event
+//!
+//! A [`WaitQueue`] lives inside whatever cell guards the event, usually a
+//! TCB inner. Waking takes the waiters out with [`WaitQueue::take`] and
+//! hands them to [`wake_up`] once that cell is released, since making a
+//! task ready needs its own inner.
+//!
+//! The waiters are woken in the [`WakeOrder`] the queue was made with, so
+//! with [`WakeOrder::Urgency`] the one the scheduler would pick first
+//! becomes ready first, and runs first under a policy that goes by the
+//! order tasks become ready, or on the first hart to look for a task.
+
+use super::manager::urgency_cmp;
+use super::processor::schedule;
+use super::{add_task, switch_out_current, TaskControlBlock, TaskStatus};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// The order a [`WaitQueue`] wakes its waiters in
+pub enum WakeOrder {
+    /// the order they started waiting in
+    Fifo,
+    /// by [`Scheduler::urgency_cmp`](super::scheduler::Scheduler::urgency_cmp),
+    /// the most starved or highest priority first, ties in the order they
+    /// started waiting in
+    Urgency,
+}
+
+pub struct WaitQueue {
+    /// in the order they are to be woken
+    waiters: Vec<Arc<TaskControlBlock>>,
+    order: WakeOrder,
+}
+
+impl WaitQueue {
+    pub fn new(order: WakeOrder) -> Self {
+        Self {
+            waiters: Vec::new(),
+            order,
+        }
+    }
+    /// Add `task`; with [`WakeOrder::Urgency`] it is ranked by how urgent
+    /// it is as it starts waiting, and its inner must not be borrowed
+    pub fn push(&mut self, task: Arc<TaskControlBlock>) {
+        let index = match self.order {
+            WakeOrder::Fifo => self.waiters.len(),
+            WakeOrder::Urgency => self
+                .waiters
+                .partition_point(|waiter| urgency_cmp(waiter, &task) != Ordering::Greater),
+        };
+        self.waiters.insert(index, task);
+    }
+    /// Remove every waiter, in the order to wake them in, to be passed to
+    /// [`wake_up`]
+    pub fn take(&mut self) -> Vec<Arc<TaskControlBlock>> {
+        core::mem::take(&mut self.waiters)
+    }
+    /// Take out the waiter to be woken first, for an event only one of
+    /// them can have
+    pub fn pop(&mut self) -> Option<Arc<TaskControlBlock>> {
+        (!self.waiters.is_empty()).then(|| self.waiters.remove(0))
+    }
+    /// Take `task` off the queue if it is on it, for a task that waited on
+    /// several queues and was woken from another
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.waiters.retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+}
+
+/// Make the tasks taken from a [`WaitQueue`] ready again
+///
+/// A task waiting on several queues, as `sys_ppoll` does, may be woken
+/// from one of them before it gets to leave the others, so tasks that are
+/// not blocked any more are left alone.
+pub fn wake_up(tasks: Vec<Arc<TaskControlBlock>>) {
+    for task in tasks {
+        let mut inner = task.inner_exclusive_access();
+        if inner.task_status != TaskStatus::Blocked {
+            continue;
+        }
+        inner.set_status(TaskStatus::Ready);
+        drop(inner);
+        add_task(task);
+    }
+}
+
+/// Block the current task, handing it to `enqueue` to be put on the wait
+/// queue it is to be woken from, and run the next task
+pub fn block_current_and_run_next(enqueue: impl FnOnce(Arc<TaskControlBlock>)) {
+    let (task, task_cx_ptr) = switch_out_current(TaskStatus::Blocked);
+    enqueue(task);
+    schedule(task_cx_ptr);
+}
@@ -0,0 +1,87 @@
+//! A synthetic scheduler workload, for `sys_sched_workload`
+//!
+//! Each worker is a [kernel thread](super::kthread) of its own priority that
+//! uses the CPU for `cpu_us`, sleeps for `sleep_us`, and so on until the run
+//! ends. The CPU time each got, as the stride charges count it, shows how
+//! the scheduler shares the CPU out: under the stride scheduler two workers
+//! that never sleep, one of twice the priority of the other, get 2/3 and
+//! 1/3 of it.
+
+use super::kthread::{kthread_tick, release_kthread, spawn_kthread};
+use super::{block_current_and_run_next, current_task, sleep_current_until};
+use crate::timer::{get_time_us, TIME_SLICE_US};
+use alloc::format;
+use alloc::vec::Vec;
+use core::hint::spin_loop;
+
+/// Duty cycle of one worker
+#[derive(Clone, Copy)]
+pub struct WorkerSpec {
+    pub priority: u64,
+    /// CPU time used between two sleeps, more than 0
+    pub cpu_us: usize,
+    /// 0 for a worker that never sleeps
+    pub sleep_us: usize,
+}
+
+/// Run a worker per spec for `duration_us` and return the CPU time each
+/// got, in the same order; `None` if out of frames for their kernel stacks
+///
+/// The caller blocks until the last worker has exited, and the workers
+/// exit on their own at most a time slice after the run ends, so this takes
+/// `duration_us` and a little.
+pub fn run_workload(specs: &[WorkerSpec], duration_us: usize) -> Option<Vec<usize>> {
+    let task = current_task().unwrap();
+    let end_us = get_time_us() + duration_us;
+    let mut workers = Vec::new();
+    for (i, &spec) in specs.iter().enumerate() {
+        match spawn_kthread(&format!("worker{}", i), spec.priority, move || work(spec, end_us)) {
+            Some(worker) => workers.push(worker),
+            // the ones already started end on their own as well
+            None => break,
+        }
+    }
+    while workers
+        .iter()
+        .any(|worker| !worker.inner_exclusive_access().is_zombie())
+    {
+        block_current_and_run_next(|current| task.inner_exclusive_access().child_exit.push(current));
+    }
+    let spawned = workers.len();
+    let cpu_times = workers
+        .into_iter()
+        .map(|worker| {
+            let cpu_time_us = worker.inner_exclusive_access().cpu_time_us;
+            release_kthread(worker);
+            cpu_time_us
+        })
+        .collect();
+    (spawned == specs.len()).then_some(cpu_times)
+}
+
+/// Body of a worker: spin through its CPU time a tick at a time, as if the
+/// timer interrupted it, then sleep, until `end_us`
+fn work(spec: WorkerSpec, end_us: usize) {
+    loop {
+        let mut left_us = spec.cpu_us;
+        while left_us > 0 {
+            let now_us = get_time_us();
+            if now_us >= end_us {
+                return;
+            }
+            let tick_us = left_us.min(TIME_SLICE_US);
+            while get_time_us() < now_us + tick_us {
+                spin_loop();
+            }
+            left_us -= tick_us;
+            kthread_tick();
+        }
+        let now_us = get_time_us();
+        if now_us >= end_us {
+            return;
+        }
+        if spec.sleep_us > 0 {
+            sleep_current_until((now_us + spec.sleep_us).min(end_us));
+        }
+    }
+}
@@ -1,12 +1,17 @@
 //! RISC-V timer-related functionality
 
-use crate::config::CLOCK_FREQ;
+use crate::config::{CLOCK_FREQ, MAX_HARTS};
+use crate::hart::hart_id;
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use lazy_static::*;
 use riscv::register::time;
 
 const TICKS_PER_SEC: usize = 100;
 const MILLI_PER_SEC: usize = 1_000;
 const MICRO_PER_SEC: usize = 1_000_000;
+/// Length of one scheduling time slice
+pub const TIME_SLICE_US: usize = MICRO_PER_SEC / TICKS_PER_SEC;
 
 /// read the `mtime` register
 pub fn get_time() -> usize {
@@ -22,7 +27,62 @@ pub fn get_time_ms() -> usize {
     time::read() / (CLOCK_FREQ / MILLI_PER_SEC)
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+/// Timer interrupt accounting, reported by `sys_kstat`
+///
+/// `programmed - delivered` is the number of deadlines that were replaced
+/// before they fired (at most one per hart is ever pending). Drift is how late a
+/// delivered interrupt was handled compared to its deadline.
+pub struct TimerStats {
+    pub programmed: u64,
+    pub delivered: u64,
+    pub total_drift_us: u64,
+    pub max_drift_us: u64,
+}
+
+struct TimerState {
+    stats: TimerStats,
+    /// `mtime` value the pending interrupt of each hart was requested for
+    deadline: [Option<usize>; MAX_HARTS],
+}
+
+lazy_static! {
+    static ref TIMER_STATE: UPSafeCell<TimerState> = unsafe {
+        UPSafeCell::new(TimerState {
+            stats: TimerStats {
+                programmed: 0,
+                delivered: 0,
+                total_drift_us: 0,
+                max_drift_us: 0,
+            },
+            deadline: [None; MAX_HARTS],
+        })
+    };
+}
+
 /// set the next timer interrupt
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    let deadline = get_time() + CLOCK_FREQ / TICKS_PER_SEC;
+    let mut state = TIMER_STATE.exclusive_access();
+    state.stats.programmed += 1;
+    state.deadline[hart_id()] = Some(deadline);
+    drop(state);
+    set_timer(deadline);
+}
+
+/// Account for a timer interrupt that has just been taken
+pub fn timer_interrupt_delivered() {
+    let now = get_time();
+    let mut state = TIMER_STATE.exclusive_access();
+    state.stats.delivered += 1;
+    if let Some(deadline) = state.deadline[hart_id()].take() {
+        let drift_us = (now.saturating_sub(deadline) * MICRO_PER_SEC / CLOCK_FREQ) as u64;
+        state.stats.total_drift_us += drift_us;
+        state.stats.max_drift_us = state.stats.max_drift_us.max(drift_us);
+    }
+}
+
+pub fn timer_stats() -> TimerStats {
+    TIMER_STATE.exclusive_access().stats
 }
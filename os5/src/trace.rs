@@ -0,0 +1,118 @@
+//! Structured kernel trace points
+//!
+//! [`trace_event!`] records a formatted event in a ring buffer holding the
+//! last [`TRACE_EVENTS`] events, if the subsystem of the trace point is
+//! enabled. Subsystems are switched on and off at runtime with `sys_trace`
+//! and all start off; a disabled trace point costs one atomic load and does
+//! not evaluate its arguments.
+
+use crate::sync::UPSafeCell;
+use crate::timer::get_time_us;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+use lazy_static::*;
+
+bitflags! {
+    /// Subsystems of the trace points
+    pub struct TraceSubsys: u32 {
+        const SCHED = 1 << 0;
+        const MM = 1 << 1;
+        const SYSCALL = 1 << 2;
+        const IRQ = 1 << 3;
+    }
+}
+
+/// Number of events kept, older ones are overwritten
+pub const TRACE_EVENTS: usize = 256;
+
+static ENABLED: AtomicU32 = AtomicU32::new(0);
+
+struct TraceEvent {
+    time_us: usize,
+    subsys: TraceSubsys,
+    text: String,
+}
+
+struct TraceRing {
+    events: VecDeque<TraceEvent>,
+    /// Events lost to the ring wrapping since the last dump
+    overwritten: usize,
+}
+
+lazy_static! {
+    static ref TRACE_RING: UPSafeCell<TraceRing> = unsafe {
+        UPSafeCell::new(TraceRing {
+            events: VecDeque::with_capacity(TRACE_EVENTS),
+            overwritten: 0,
+        })
+    };
+}
+
+/// Record an event for the subsystem `TraceSubsys::$subsys` if it is
+/// enabled, e.g. `trace_event!(SCHED, "run pid {}", pid)`
+#[macro_export]
+macro_rules! trace_event {
+    ($subsys: ident, $fmt: literal $(, $($arg: tt)+)?) => {
+        if $crate::trace::enabled($crate::trace::TraceSubsys::$subsys) {
+            $crate::trace::record(
+                $crate::trace::TraceSubsys::$subsys,
+                format_args!($fmt $(, $($arg)+)?),
+            );
+        }
+    };
+}
+
+pub fn enabled(subsys: TraceSubsys) -> bool {
+    ENABLED.load(Ordering::Relaxed) & subsys.bits() != 0
+}
+
+/// Enable exactly the subsystems in `mask`, returning the previous mask
+pub fn set_enabled(mask: TraceSubsys) -> TraceSubsys {
+    TraceSubsys::from_bits_truncate(ENABLED.swap(mask.bits(), Ordering::Relaxed))
+}
+
+/// Append an event to the ring, use [`trace_event!`] instead
+pub fn record(subsys: TraceSubsys, args: fmt::Arguments) {
+    let event = TraceEvent {
+        time_us: get_time_us(),
+        subsys,
+        text: alloc::fmt::format(args),
+    };
+    let mut ring = TRACE_RING.exclusive_access();
+    if ring.events.len() == TRACE_EVENTS {
+        ring.events.pop_front();
+        ring.overwritten += 1;
+    }
+    ring.events.push_back(event);
+}
+
+/// Print and clear the buffered events, returning how many there were
+///
+/// Also called on panic, so a poisoned ring is reported rather than taken.
+pub fn dump() -> usize {
+    let mut ring = match TRACE_RING.try_exclusive_access() {
+        Some(ring) => ring,
+        None => {
+            println!("[trace] ring poisoned");
+            return 0;
+        }
+    };
+    let events = core::mem::take(&mut ring.events);
+    let overwritten = core::mem::take(&mut ring.overwritten);
+    drop(ring);
+    if overwritten != 0 {
+        println!("[trace] {} older events were overwritten", overwritten);
+    }
+    for event in events.iter() {
+        println!(
+            "[trace][{:>5}.{:06}] {:?}: {}",
+            event.time_us / 1_000_000,
+            event.time_us % 1_000_000,
+            event.subsys,
+            event.text,
+        );
+    }
+    events.len()
+}
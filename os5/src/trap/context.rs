@@ -3,6 +3,7 @@
 use riscv::register::sstatus::{self, Sstatus, SPP};
 
 #[repr(C)]
+#[derive(Clone)]
 /// trap context structure containing sstatus, sepc and registers
 pub struct TrapContext {
     /// General-Purpose Register x0-31
@@ -17,6 +18,8 @@ pub struct TrapContext {
     pub kernel_sp: usize,
     /// Virtual address of trap handler entry point in kernel
     pub trap_handler: usize,
+    /// Hart the task last returned to user space on, for `tp` on the next trap
+    pub hart_id: usize,
 }
 
 impl TrapContext {
@@ -40,6 +43,8 @@ impl TrapContext {
             kernel_satp,
             kernel_sp,
             trap_handler,
+            // set by every trap_return
+            hart_id: 0,
         };
         cx.set_sp(sp);
         cx
@@ -11,22 +11,39 @@
 //! It then calls different functionality based on what exactly the exception
 //! was. For example, timer interrupts trigger task preemption, and syscalls go
 //! to [`syscall()`].
+//!
+//! With the `kstack-trap-cx` feature the TrapContext is kept on top of the
+//! kernel stack rather than in a page of the user address space, see
+//! `scratch.rs` for how the trampoline gets to it.
 
 mod context;
+#[cfg(feature = "kstack-trap-cx")]
+mod scratch;
 
-use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
+use crate::config::TRAMPOLINE;
+use crate::drivers::irq_handler;
+use crate::hart::hart_id;
+use crate::mm::FaultAccess;
+use crate::sync::KERNEL_LOCK;
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next,
+    check_current_kernel_stack, current_task_catch_fault, current_task_page_fault, current_task_tick, extra_preemption, handle_signals, current_trap_cx,
+    current_task_syscall_interrupted, current_user_token, exit_current_and_run_next, fire_alarms,
+    suspend_current_and_run_next, wake_sleepers, ERESTARTNOHAND, ERESTARTSYS,
 };
-use crate::timer::set_next_trigger;
+#[cfg(not(feature = "kstack-trap-cx"))]
+use crate::task::current_trap_cx_user_va;
+use crate::timer::{set_next_trigger, timer_interrupt_delivered};
 use riscv::register::{
     mtvec::TrapMode,
     scause::{self, Exception, Interrupt, Trap},
     sie, stval, stvec,
 };
 
-core::arch::global_asm!(include_str!("trap.S"));
+#[cfg(not(feature = "kstack-trap-cx"))]
+core::arch::global_asm!(concat!(".equ KSTACK_TRAP_CX, 0\n", include_str!("trap.S")));
+#[cfg(feature = "kstack-trap-cx")]
+core::arch::global_asm!(concat!(".equ KSTACK_TRAP_CX, 1\n", include_str!("trap.S")));
 
 pub fn init() {
     set_kernel_trap_entry();
@@ -53,6 +70,8 @@ pub fn enable_timer_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    KERNEL_LOCK.lock();
+    check_current_kernel_stack();
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
@@ -60,26 +79,42 @@ pub fn trap_handler() -> ! {
             // jump to next instruction anyway
             let mut cx = current_trap_cx();
             cx.sepc += 4;
+            // kept for a restart, see `handle_signals`
+            let a0 = cx.x[10];
             // get system call return value
             let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
+            if result == ERESTARTSYS || result == ERESTARTNOHAND {
+                current_task_syscall_interrupted(result, a0);
+            }
+            if extra_preemption() {
+                suspend_current_and_run_next();
+            }
         }
+        Trap::Exception(
+            fault @ (Exception::StorePageFault
+            | Exception::LoadPageFault
+            | Exception::InstructionPageFault),
+        ) if current_task_page_fault(stval, fault_access(fault)) => {}
         Trap::Exception(Exception::StoreFault)
         | Trap::Exception(Exception::StorePageFault)
         | Trap::Exception(Exception::InstructionFault)
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
-                scause.cause(),
-                stval,
-                current_trap_cx().sepc,
-            );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            // a handler for SIGSEGV gets to deal with it first
+            if !current_task_catch_fault() {
+                println!(
+                    "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                    scause.cause(),
+                    stval,
+                    current_trap_cx().sepc,
+                );
+                // page fault exit code
+                exit_current_and_run_next(-2);
+            }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             println!("[kernel] IllegalInstruction in application, core dumped.");
@@ -87,8 +122,18 @@ pub fn trap_handler() -> ! {
             exit_current_and_run_next(-3);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            timer_interrupt_delivered();
+            trace_event!(IRQ, "timer, sepc {:#x}", current_trap_cx().sepc);
             set_next_trigger();
-            suspend_current_and_run_next();
+            wake_sleepers();
+            fire_alarms();
+            if current_task_tick() {
+                suspend_current_and_run_next();
+            }
+        }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            trace_event!(IRQ, "external, sepc {:#x}", current_trap_cx().sepc);
+            irq_handler();
         }
         _ => {
             panic!(
@@ -98,19 +143,38 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    handle_signals();
     trap_return();
 }
 
+/// The access a page fault exception was raised for
+fn fault_access(fault: Exception) -> FaultAccess {
+    match fault {
+        Exception::StorePageFault => FaultAccess::Store,
+        Exception::InstructionPageFault => FaultAccess::Fetch,
+        _ => FaultAccess::Load,
+    }
+}
+
 #[no_mangle]
 pub fn trap_return() -> ! {
     set_user_trap_entry();
-    let trap_cx_ptr = TRAP_CONTEXT;
     let user_satp = current_user_token();
+    current_trap_cx().hart_id = hart_id();
+    #[cfg(not(feature = "kstack-trap-cx"))]
+    let (trap_cx_ptr, scratch) = (current_trap_cx_user_va(), 0);
+    #[cfg(feature = "kstack-trap-cx")]
+    let (trap_cx_ptr, scratch) = {
+        let trap_cx = current_trap_cx();
+        (trap_cx as *mut TrapContext as usize, prepare_trap_scratch(hart_id(), trap_cx))
+    };
     extern "C" {
         fn __alltraps();
         fn __restore();
     }
     let restore_va = __restore as usize - __alltraps as usize + TRAMPOLINE;
+    // nothing of the kernel is touched from here on
+    KERNEL_LOCK.unlock();
     unsafe {
         core::arch::asm!(
             "fence.i",
@@ -118,6 +182,8 @@ pub fn trap_return() -> ! {
             restore_va = in(reg) restore_va,
             in("a0") trap_cx_ptr,
             in("a1") user_satp,
+            // only used with kstack-trap-cx
+            in("a2") scratch,
             options(noreturn)
         );
     }
@@ -129,3 +195,7 @@ pub fn trap_from_kernel() -> ! {
 }
 
 pub use context::TrapContext;
+#[cfg(feature = "kstack-trap-cx")]
+pub use scratch::trap_scratch_ppn;
+#[cfg(feature = "kstack-trap-cx")]
+use scratch::prepare_trap_scratch;
@@ -0,0 +1,68 @@
+//! The per-hart scratch the trampoline needs to keep the TrapContext on
+//! the kernel stack, with the `kstack-trap-cx` feature
+//!
+//! By default the TrapContext of a thread has a page of its own in the
+//! user address space, so `__alltraps` can save the user registers there
+//! before switching to the kernel page table. On the kernel stack it is out
+//! of reach until then, and switching needs a register to hold the kernel
+//! satp, with only `sp` to spare through `sscratch`.
+//!
+//! So `sscratch` points to a [`TrapScratch`] of the hart instead, in a page
+//! shared by all address spaces and mapped at `TRAP_CONTEXT` in each, the
+//! kernel space too, like the trampoline. `__alltraps` parks `t0` in it,
+//! switches to the kernel satp it finds there, then saves the registers
+//! into the TrapContext it points to. `__restore` leaves there what it
+//! reloads after switching back. No thread needs a page for its
+//! TrapContext then, nor `exec` to look up where the new one is.
+
+use crate::config::{MAX_HARTS, PAGE_SIZE, TRAP_CONTEXT};
+use crate::mm::{PhysAddr, PhysPageNum};
+use core::mem::size_of;
+
+use super::TrapContext;
+
+/// What the trampoline keeps for a hart, laid out as `trap.S` expects
+#[repr(C)]
+struct TrapScratch {
+    /// TrapContext of the task the hart returned to user space with, at
+    /// its address in the kernel space
+    trap_cx: usize,
+    kernel_satp: usize,
+    /// `t0` of the user, parked on the way in and out
+    t0: usize,
+    /// `sp` of the user on the way out
+    user_sp: usize,
+    /// User space token on the way out
+    user_satp: usize,
+}
+
+#[repr(C, align(4096))]
+struct TrapScratchPage([TrapScratch; MAX_HARTS]);
+
+const _: () = assert!(size_of::<TrapScratchPage>() == PAGE_SIZE);
+
+const NO_SCRATCH: TrapScratch = TrapScratch {
+    trap_cx: 0,
+    kernel_satp: 0,
+    t0: 0,
+    user_sp: 0,
+    user_satp: 0,
+};
+
+/// Written by each hart at its own slot only
+static mut TRAP_SCRATCH: TrapScratchPage = TrapScratchPage([NO_SCRATCH; MAX_HARTS]);
+
+/// The page to map at `TRAP_CONTEXT`, in the kernel image and so at its
+/// physical address in the kernel space
+pub fn trap_scratch_ppn() -> PhysPageNum {
+    PhysAddr::from(unsafe { &TRAP_SCRATCH as *const _ as usize }).floor()
+}
+
+/// Point the scratch of hart `hart` to `trap_cx` before it returns to
+/// user space, and return where `sscratch` is to point to
+pub fn prepare_trap_scratch(hart: usize, trap_cx: &mut TrapContext) -> usize {
+    let scratch = unsafe { &mut TRAP_SCRATCH.0[hart] };
+    scratch.trap_cx = trap_cx as *mut TrapContext as usize;
+    scratch.kernel_satp = trap_cx.kernel_satp;
+    TRAP_CONTEXT + hart * size_of::<TrapScratch>()
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{exec, exit, fork, waitpid, ARG_MAX, E2BIG, MAX_ARGS, MAX_ARG_LEN};
+
+/*
+理想结果：参数个数超过 MAX_ARGS、单个参数超过 MAX_ARG_LEN、参数总大小超过 ARG_MAX 时 exec 返回 E2BIG，
+没有超过限制的参数完整地传给新程序。
+输出 Test ARG_MAX OK!
+*/
+
+const NAME: &str = "ch5_arg_max\0";
+
+/// 长 `len` 个字节、以 NUL 结尾的参数
+fn arg(len: usize) -> Vec<u8> {
+    let mut arg = alloc::vec![b'a'; len];
+    arg.push(0);
+    arg
+}
+
+/// 程序名、"child" 和 `count` 个长 `len` 的参数
+fn child_args(count: usize, len: usize) -> Vec<Vec<u8>> {
+    let mut args = alloc::vec![NAME.as_bytes().to_vec(), b"child\0".to_vec()];
+    args.extend((0..count).map(|_| arg(len)));
+    args
+}
+
+/// 在子进程里以 `args` 为参数 exec 自己，返回子进程的退出码
+fn exec_with(args: &[Vec<u8>]) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let mut argv: Vec<*const u8> = args.iter().map(|arg| arg.as_ptr()).collect();
+        argv.push(core::ptr::null());
+        exit(exec(NAME, &argv) as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+/// 参数在新栈上占的字节数，NUL 和 argv 数组都算上
+fn size(args: &[Vec<u8>]) -> usize {
+    (args.len() + 1) * core::mem::size_of::<usize>() + args.iter().map(Vec::len).sum::<usize>()
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc >= 2 && argv[1] == "child" {
+        // 新程序拿到的参数和传的一样
+        assert!(argv[2..].iter().all(|arg| arg.bytes().all(|byte| byte == b'a')));
+        return 100 + argc as i32;
+    }
+    // 程序名和 "child" 之外最多的参数个数
+    let free = MAX_ARGS - 2;
+    let most = child_args(free, MAX_ARG_LEN);
+    assert!(size(&most) <= ARG_MAX);
+    assert_eq!(exec_with(&most), 100 + MAX_ARGS as i32);
+    assert_eq!(exec_with(&child_args(1, MAX_ARG_LEN + 1)), E2BIG as i32);
+    assert_eq!(exec_with(&child_args(free + 1, 0)), E2BIG as i32);
+    // 每个参数都不超过 MAX_ARG_LEN，合起来超过 ARG_MAX
+    let big: Vec<_> = (0..MAX_ARGS).map(|_| arg(MAX_ARG_LEN)).collect();
+    assert!(size(&big) > ARG_MAX);
+    assert_eq!(exec_with(&big), E2BIG as i32);
+    println!("Test ARG_MAX OK!");
+    0
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use user_lib::{close, exec, exit, fork, getauxval, pipe, read, waitpid, write, AT_RANDOM};
+
+/*
+理想结果：auxv 里的 AT_RANDOM 指向栈上 16 个随机字节，fork 出的子进程和父进程相同，exec 之后换成新的一组，
+栈上参数以外的地方都是 0。
+输出 Test AT_RANDOM OK!
+*/
+
+const NAME: &str = "ch5_at_random\0";
+/// 内核 config.rs 里的 USER_STACK_SIZE
+const USER_STACK_SIZE: usize = 4096 * 2;
+
+fn random_bytes() -> [u8; 16] {
+    let at = getauxval(AT_RANDOM).expect("no AT_RANDOM in auxv");
+    unsafe { (at as *const [u8; 16]).read() }
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc == 3 && argv[1] == "child" {
+        // exec 之后: 把自己的随机字节交给父进程
+        let fd: usize = argv[2].parse().unwrap();
+        assert_eq!(write(fd, &random_bytes()), 16);
+        return 0;
+    }
+    let mine = random_bytes();
+    assert!(mine.iter().any(|&byte| byte != 0));
+    // 随机字节在栈顶, 8KiB 的初始栈最底下还没用到的地方全是 0
+    let stack_bottom = getauxval(AT_RANDOM).unwrap() + 16 - USER_STACK_SIZE;
+    let untouched = unsafe { core::slice::from_raw_parts(stack_bottom as *const u8, 512) };
+    assert!(untouched.iter().all(|&byte| byte == 0));
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        // fork 复制了栈, 随机字节也一样
+        assert_eq!(random_bytes(), mine);
+        close(fds[0]);
+        let fd = format!("{}\0", fds[1]);
+        let argv = [NAME.as_ptr(), "child\0".as_ptr(), fd.as_ptr(), core::ptr::null()];
+        exit(exec(NAME, &argv) as i32);
+    }
+    close(fds[1]);
+    let mut theirs = [0u8; 16];
+    assert_eq!(read(fds[0], &mut theirs), 16);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_ne!(theirs, mine);
+    println!("Test AT_RANDOM OK!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, write, OpenFlags};
+
+/*
+理想结果：写文件的块经过磁盘请求队列，/proc/stat 的 blk_* 计数随之增长，读回的数据不变，输出 Test blk queue OK!
+*/
+
+/// /proc/stat 中 `name` 一行的值
+fn stat(name: &str) -> u64 {
+    let mut buf = [0u8; 1024];
+    let fd = open("/proc/stat\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .and_then(|value| value.parse().ok())
+        .unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let queued = stat("blk_queued");
+    let fd = open("blk_queue\0", OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    let block = [0x5au8; 512];
+    for _ in 0..5 {
+        assert_eq!(write(fd as usize, &block), 512);
+    }
+    close(fd as usize);
+    let fd = open("blk_queue\0", OpenFlags::RDONLY);
+    let mut buf = [0u8; 512];
+    for _ in 0..5 {
+        assert_eq!(read(fd as usize, &mut buf), 512);
+        assert!(buf.iter().all(|&b| b == 0x5a));
+    }
+    close(fd as usize);
+    // 每次写至少写出一个数据块
+    assert!(stat("blk_queued") - queued >= 5);
+    // 每个写请求要么被合并, 要么已经发出, 要么还在队列里
+    assert!(stat("blk_merged") + stat("blk_dispatched") <= stat("blk_queued"));
+    println!("Test blk queue OK!");
+    0
+}
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    hart_ctl, hart_status, sleep_blocking, HART_ABSENT, HART_START, HART_STARTED, HART_STOP,
+    HART_STOPPED,
+};
+
+/*
+理想结果：启动核不能停止；有第二个核时（make run SMP=2）它能停下再启动，输出 Test hotplug OK!
+*/
+
+/// 等待 `hart` 进入 `state`
+fn wait_for(hart: usize, state: usize) {
+    for _ in 0..100 {
+        let mut states = [0usize; 4];
+        hart_status(&mut states);
+        if states[hart] == state {
+            return;
+        }
+        sleep_blocking(10);
+    }
+    panic!("hart {} never reached state {}", hart, state);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut states = [0usize; 4];
+    assert_eq!(hart_status(&mut states), 4);
+    assert_eq!(states[0], HART_STARTED);
+    assert_eq!(hart_ctl(0, HART_STOP), -1);
+    assert_eq!(hart_ctl(0, HART_START), -1);
+    if states[1] != HART_ABSENT {
+        assert_eq!(hart_ctl(1, HART_STOP), 0);
+        wait_for(1, HART_STOPPED);
+        // 停下的核不能再停
+        assert_eq!(hart_ctl(1, HART_STOP), -1);
+        assert_eq!(hart_ctl(1, HART_START), 0);
+        wait_for(1, HART_STARTED);
+    }
+    println!("Test hotplug OK!");
+    0
+}
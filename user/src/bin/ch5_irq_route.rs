@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, hart_ctl, hart_status, irq_route, open, read, sleep_blocking, OpenFlags, HART_ABSENT,
+    HART_START, HART_STARTED, HART_STOP, HART_STOPPED,
+};
+
+/*
+理想结果：串口中断（10 号）可以路由到在线的核；有第二个核时（make run SMP=2）路由到它，它停下时中断回到启动核，重新启动后又回到它，输出 Test irq route OK!
+*/
+
+const UART_IRQ: usize = 10;
+
+/// /proc/interrupts 里串口中断当前去往的核
+fn uart_hart() -> usize {
+    let mut buf = [0u8; 512];
+    let fd = open("/proc/interrupts\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    text.lines()
+        .find_map(|line| line.strip_prefix("10: uart hart "))
+        .and_then(|rest| rest.split(' ').next()?.parse().ok())
+        .unwrap()
+}
+
+/// 等待 `hart` 进入 `state`
+fn wait_for(hart: usize, state: usize) {
+    for _ in 0..100 {
+        let mut states = [0usize; 4];
+        hart_status(&mut states);
+        if states[hart] == state {
+            return;
+        }
+        sleep_blocking(10);
+    }
+    panic!("hart {} never reached state {}", hart, state);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut states = [0usize; 4];
+    hart_status(&mut states);
+    assert_eq!(irq_route(UART_IRQ, 0), 0);
+    assert_eq!(uart_hart(), 0);
+    // 没有的中断源，不在线的核
+    assert_eq!(irq_route(99, 0), -1);
+    assert_eq!(irq_route(UART_IRQ, 4), -1);
+    if states[1] != HART_ABSENT {
+        assert_eq!(irq_route(UART_IRQ, 1), 0);
+        assert_eq!(uart_hart(), 1);
+        assert_eq!(hart_ctl(1, HART_STOP), 0);
+        wait_for(1, HART_STOPPED);
+        assert_eq!(uart_hart(), 0);
+        assert_eq!(hart_ctl(1, HART_START), 0);
+        wait_for(1, HART_STARTED);
+        // 上线后才取回中断
+        for _ in 0..100 {
+            if uart_hart() == 1 {
+                break;
+            }
+            sleep_blocking(10);
+        }
+        assert_eq!(uart_hart(), 1);
+        assert_eq!(irq_route(UART_IRQ, 0), 0);
+    }
+    println!("Test irq route OK!");
+    0
+}
@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, getrusage, mmap_file, msync, munmap, open, read, write, OpenFlags, Rusage, MS_SYNC,
+    RUSAGE_SELF,
+};
+
+/*
+理想结果：映射磁盘文件后读到文件内容，缺页计为 majflt；写入映射的页在 msync 和 munmap 后写回文件，只读打开的文件不能映射为可写，输出 Test mmap file OK!
+*/
+
+const START: usize = 0x10000000;
+const LEN: usize = 3 * 4096 + 100;
+
+fn majflt() -> u64 {
+    let mut usage = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_SELF, &mut usage), 0);
+    usage.majflt
+}
+
+/// 文件第 `i` 个字节原来的值
+fn pattern(i: usize) -> u8 {
+    (i % 251) as u8
+}
+
+/// 从头读出整个文件，逐字节交给 `check`，返回文件长度
+fn read_file(check: impl Fn(usize, u8)) -> usize {
+    let fd = open("mmap_file\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 512];
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for (i, byte) in buf[..len as usize].iter().enumerate() {
+            check(total + i, *byte);
+        }
+        total += len as usize;
+    }
+    close(fd as usize);
+    total
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("mmap_file\0", OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 512];
+    for offset in (0..LEN).step_by(buf.len()) {
+        let len = buf.len().min(LEN - offset);
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = pattern(offset + i);
+        }
+        assert_eq!(write(fd as usize, &buf[..len]), len as isize);
+    }
+    close(fd as usize);
+
+    // 只读打开的文件只能映射为只读
+    let fd = open("mmap_file\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    assert_eq!(mmap_file(fd as usize, START, 0b011), -1);
+    close(fd as usize);
+
+    let fd = open("mmap_file\0", OpenFlags::RDWR);
+    assert!(fd >= 0);
+    let before = majflt();
+    assert_eq!(mmap_file(fd as usize, START, 0b011), LEN as isize);
+    close(fd as usize);
+    let data = unsafe { core::slice::from_raw_parts_mut(START as *mut u8, LEN) };
+    for (i, byte) in data.iter().enumerate() {
+        assert_eq!(*byte, pattern(i));
+    }
+    assert_eq!(majflt(), before + 4);
+
+    // msync 写回第一页的修改
+    data[0] = 0xff;
+    data[1] = 0xee;
+    assert_eq!(msync(START, 4096, MS_SYNC), 0);
+    let first = read_file(|i, byte| match i {
+        0 => assert_eq!(byte, 0xff),
+        1 => assert_eq!(byte, 0xee),
+        _ => assert_eq!(byte, pattern(i)),
+    });
+    assert_eq!(first, LEN);
+
+    // munmap 写回最后一页，文件不会变长
+    data[LEN - 1] = 0xdd;
+    assert_eq!(munmap(START, 4 * 4096), 0);
+    let last = read_file(|i, byte| match i {
+        0 => assert_eq!(byte, 0xff),
+        1 => assert_eq!(byte, 0xee),
+        _ if i == LEN - 1 => assert_eq!(byte, 0xdd),
+        _ => assert_eq!(byte, pattern(i)),
+    });
+    assert_eq!(last, LEN);
+    println!("Test mmap file OK!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{chdir, close, open, read, write, OpenFlags};
+
+/*
+理想结果：/proc 和 /dev 挂载在同一个名字空间里，/dev/null、/dev/zero 可用，挂载点可以作为工作目录，输出 Test mounts OK!
+*/
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0xffu8; 256];
+    let null = open("/dev/null\0", OpenFlags::RDWR);
+    assert!(null >= 0);
+    assert_eq!(write(null as usize, b"gone"), 4);
+    assert_eq!(read(null as usize, &mut buf), 0);
+    close(null as usize);
+
+    let zero = open("/dev/zero\0", OpenFlags::RDONLY);
+    assert!(zero >= 0);
+    assert_eq!(read(zero as usize, &mut buf[..100]), 100);
+    assert!(buf[..100].iter().all(|&b| b == 0));
+    assert_eq!(buf[100], 0xff);
+    // 只读打开的设备不能写
+    assert_eq!(write(zero as usize, b"x"), -1);
+    close(zero as usize);
+    assert_eq!(open("/dev/nothing\0", OpenFlags::RDONLY), -1);
+
+    // 挂载点是目录, 相对路径也按挂载表分派
+    assert_eq!(chdir("/proc\0"), 0);
+    let fd = open("mounts\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let mounts = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    assert!(mounts.lines().any(|line| line == "/proc procfs ro"));
+    assert!(mounts.lines().any(|line| line == "/dev devfs rw"));
+    assert_eq!(chdir("/\0"), 0);
+    println!("Test mounts OK!");
+    0
+}
@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{exit, sys_mutex_lock, sys_mutex_unlock, thread_create, waittid};
+use user_lib::{mutex_blocking_create, mutex_create, mutex_lock, mutex_unlock};
+
+/*
+理想结果：多个线程在阻塞锁和自旋锁保护下对计数器的累加都不丢失，重复加锁、非持有者解锁和不存在的锁都返回 -1，输出 Test mutex OK!
+*/
+
+static mut COUNT: usize = 0;
+const PER_THREAD: usize = 200;
+const THREAD_COUNT: usize = 4;
+
+unsafe fn add(mutex_id: usize) -> ! {
+    let mut t = 2usize;
+    for _ in 0..PER_THREAD {
+        assert_eq!(mutex_lock(mutex_id), 0);
+        let count = &mut COUNT as *mut usize;
+        let cur = count.read_volatile();
+        // 临界区足够长，持有者常在另一个 hart 上运行
+        for _ in 0..200 {
+            t = t * t % 10007;
+        }
+        count.write_volatile(cur + 1);
+        mutex_unlock(mutex_id);
+    }
+    exit(t as i32)
+}
+
+fn try_unlock(mutex_id: usize) -> ! {
+    exit((sys_mutex_unlock(mutex_id) == -1) as i32)
+}
+
+fn race(mutex_id: usize) {
+    unsafe {
+        COUNT = 0;
+    }
+    let tids: Vec<_> = (0..THREAD_COUNT)
+        .map(|_| thread_create(add as usize, mutex_id))
+        .collect();
+    for tid in tids {
+        assert!(tid > 0);
+        waittid(tid as usize);
+    }
+    assert_eq!(unsafe { COUNT }, PER_THREAD * THREAD_COUNT);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let blocking = mutex_blocking_create();
+    let spin = mutex_create();
+    assert!(blocking >= 0 && spin >= 0 && blocking != spin);
+    race(blocking as usize);
+    race(spin as usize);
+    // 已持有时再加锁、由其他线程解锁都失败
+    assert_eq!(mutex_lock(blocking as usize), 0);
+    assert_eq!(sys_mutex_lock(blocking as usize), -1);
+    let tid = thread_create(try_unlock as usize, blocking as usize);
+    assert_eq!(waittid(tid as usize), 1);
+    assert_eq!(sys_mutex_unlock(blocking as usize), 0);
+    assert_eq!(sys_mutex_unlock(blocking as usize), -1);
+    assert_eq!(sys_mutex_lock(100), -1);
+    println!("Test mutex OK!");
+    0
+}
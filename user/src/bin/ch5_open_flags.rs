@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, dup, exit, fork, open, read, waitpid, write, OpenFlags};
+
+/*
+理想结果：open 的 CREATE、TRUNC、APPEND 和读写模式生效，dup 和 fork 之后共享文件偏移，输出 Test open flags OK!
+*/
+
+const NAME: &str = "open_flags\0";
+
+fn read_back(buf: &mut [u8]) -> usize {
+    let fd = open(NAME, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 16];
+    let fd = open(NAME, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"abc"), 3);
+    // 只写打开的文件不能读
+    assert_eq!(read(fd as usize, &mut buf), -1);
+    close(fd as usize);
+
+    // APPEND 总是写到末尾
+    let fd = open(NAME, OpenFlags::WRONLY | OpenFlags::APPEND);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"de"), 2);
+    close(fd as usize);
+    let len = read_back(&mut buf);
+    assert_eq!(&buf[..len], b"abcde");
+
+    // dup 出来的描述符共享偏移
+    let fd = open(NAME, OpenFlags::RDONLY) as usize;
+    let copy = dup(fd) as usize;
+    assert_eq!(read(fd, &mut buf[..2]), 2);
+    assert_eq!(read(copy, &mut buf[..1]), 1);
+    assert_eq!(buf[0], b'c');
+    close(copy);
+    // fork 出来的子进程也一样
+    let pid = fork();
+    if pid == 0 {
+        let mut byte = [0u8; 1];
+        exit(if read(fd, &mut byte) == 1 && byte[0] == b'd' { 0 } else { 1 });
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(read(fd, &mut buf[..1]), 1);
+    assert_eq!(buf[0], b'e');
+    close(fd);
+
+    // TRUNC 清空已有的文件
+    let fd = open(NAME, OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    close(fd as usize);
+    assert_eq!(read_back(&mut buf), 0);
+
+    assert_eq!(open(NAME, OpenFlags::WRONLY | OpenFlags::RDWR), -1);
+    assert_eq!(open("open_flags_missing\0", OpenFlags::RDONLY), -1);
+    println!("Test open flags OK!");
+    0
+}
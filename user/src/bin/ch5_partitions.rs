@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, OpenFlags};
+
+/*
+理想结果：/proc/partitions 可以读出；磁盘分过区时（make PARTITIONED=1），其中有一个 Fs 分区且各分区互不重叠，输出 Test partitions OK!
+*/
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 512];
+    let fd = open("/proc/partitions\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, &mut buf);
+    assert!(len >= 0);
+    close(fd as usize);
+    let table = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    // 每行是 起始块 块数 类型
+    let mut ranges = [(0usize, 0usize); 4];
+    let mut count = 0;
+    let mut has_fs = false;
+    for line in table.lines() {
+        let mut fields = line.split(' ');
+        let start: usize = fields.next().unwrap().parse().unwrap();
+        let blocks: usize = fields.next().unwrap().parse().unwrap();
+        has_fs |= fields.next() == Some("Fs");
+        assert!(blocks > 0);
+        ranges[count] = (start, start + blocks);
+        count += 1;
+    }
+    for i in 0..count {
+        for j in i + 1..count {
+            let (a, b) = (ranges[i], ranges[j]);
+            assert!(a.1 <= b.0 || b.1 <= a.0);
+        }
+    }
+    // 没有分区表时整个磁盘就是文件系统
+    assert!(count == 0 || has_fs);
+    println!("Test partitions OK!");
+    0
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, pidfd_open, pipe, ppoll, sleep, waitpid, write, PollFd, TimeSpec, POLLIN,
+    POLLNVAL,
+};
+
+/*
+理想结果：子进程先写管道再退出，父进程用 ppoll 同时等待管道和 pidfd，先后看到两者就绪，输出 Test pidfd OK!
+*/
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[0]);
+        sleep(50);
+        assert_eq!(write(pipe_fd[1], b"x"), 1);
+        sleep(50);
+        exit(3);
+    }
+    close(pipe_fd[1]);
+    let pidfd = pidfd_open(pid as usize);
+    assert!(pidfd >= 0);
+    let mut fds = [
+        PollFd::new(pipe_fd[0], POLLIN),
+        PollFd::new(pidfd as usize, POLLIN),
+    ];
+    // 超时为 0 时立即返回
+    let now = TimeSpec::default();
+    assert_eq!(ppoll(&mut fds, Some(&now)), 0);
+    // 先是管道可读，子进程还没退出
+    assert_eq!(ppoll(&mut fds, None), 1);
+    assert_eq!(fds[0].revents, POLLIN);
+    assert_eq!(fds[1].revents, 0);
+    // 只等 pidfd
+    assert_eq!(ppoll(&mut fds[1..], None), 1);
+    assert_eq!(fds[1].revents, POLLIN);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 3);
+    // 回收之后仍然可读，关闭的描述符报告 POLLNVAL
+    assert_eq!(ppoll(&mut fds[1..], None), 1);
+    close(pidfd as usize);
+    assert_eq!(ppoll(&mut fds[1..], None), 1);
+    assert_eq!(fds[1].revents, POLLNVAL);
+    println!("Test pidfd OK!");
+    0
+}
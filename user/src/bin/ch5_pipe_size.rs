@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fcntl, ioctl, pipe, read, write, F_GETPIPE_SZ, F_SETPIPE_SZ, FIONREAD, STDOUT,
+};
+
+/*
+理想结果：管道缓冲区可以用 F_SETPIPE_SZ 调整，FIONREAD 报告其中的字节数，越界的大小被拒绝，输出 Test pipe size OK!
+*/
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(fcntl(fds[0], F_GETPIPE_SZ, 0), 512);
+    assert_eq!(fcntl(fds[1], F_SETPIPE_SZ, 4096), 4096);
+    // 放大之后，单个进程写 4000 字节不会阻塞
+    let data = [7u8; 4000];
+    assert_eq!(write(fds[1], &data), 4000);
+    let mut buffered: i32 = 0;
+    assert_eq!(ioctl(fds[0], FIONREAD, &mut buffered as *mut i32 as usize), 0);
+    assert_eq!(buffered, 4000);
+    // 放不下已有的数据，或者超过上限
+    assert_eq!(fcntl(fds[0], F_SETPIPE_SZ, 1024), -1);
+    assert_eq!(fcntl(fds[0], F_SETPIPE_SZ, 1 << 20), -1);
+    let mut buf = [0u8; 4000];
+    assert_eq!(read(fds[0], &mut buf), 4000);
+    assert!(buf.iter().all(|&b| b == 7));
+    // 太小的大小被提升到下限
+    assert_eq!(fcntl(fds[0], F_SETPIPE_SZ, 1), 512);
+    assert_eq!(fcntl(STDOUT, F_GETPIPE_SZ, 0), -1);
+    close(fds[0]);
+    close(fds[1]);
+    println!("Test pipe size OK!");
+    0
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, getpid, open, read, OpenFlags};
+
+/*
+理想结果：/proc 下的 status、maps 和 stat 可以读出，写打开和不存在的进程被拒绝，输出 Test procfs OK!
+*/
+
+/// 读出整个文件, 返回长度
+fn read_proc(path: &str, buf: &mut [u8]) -> usize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut len = 0;
+    loop {
+        let n = read(fd, &mut buf[len..]);
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        len += n as usize;
+    }
+    close(fd);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut buf = [0u8; 2048];
+    let len = read_proc("/proc/self/status\0", &mut buf);
+    let status = core::str::from_utf8(&buf[..len]).unwrap();
+    let pid = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Pid:\t"))
+        .and_then(|pid| pid.parse::<isize>().ok());
+    assert_eq!(pid, Some(getpid()));
+    assert!(status.contains("State:\tRunning"));
+
+    let len = read_proc("/proc/self/maps\0", &mut buf);
+    let maps = core::str::from_utf8(&buf[..len]).unwrap();
+    assert!(maps.lines().any(|line| line.ends_with(" Stack")));
+    assert!(maps.lines().any(|line| line.ends_with("r-x- Trampoline")));
+
+    let len = read_proc("/proc/stat\0", &mut buf);
+    let stat = core::str::from_utf8(&buf[..len]).unwrap();
+    assert!(stat.starts_with("uptime_us "));
+    assert!(stat.contains("\nframes_free "));
+
+    // 只读, 并且只有存在的进程才有目录
+    assert_eq!(open("/proc/self/status\0", OpenFlags::WRONLY), -1);
+    assert_eq!(open("/proc/stat\0", OpenFlags::RDWR), -1);
+    assert_eq!(open("/proc/99999/status\0", OpenFlags::RDONLY), -1);
+    assert_eq!(open("/proc/self/nothing\0", OpenFlags::RDONLY), -1);
+    println!("Test procfs OK!");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, read, sleep_blocking, write, OpenFlags};
+
+/*
+理想结果：顺序读文件时内核在空闲时预读后面的块，/proc/stat 的 readahead_blocks 增长，读出的数据不变，输出 Test readahead OK!
+*/
+
+/// /proc/stat 中 `name` 一行的值
+fn stat(name: &str) -> u64 {
+    let mut buf = [0u8; 1024];
+    let fd = open("/proc/stat\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, &mut buf);
+    close(fd as usize);
+    let text = core::str::from_utf8(&buf[..len as usize]).unwrap();
+    text.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .and_then(|value| value.parse().ok())
+        .unwrap()
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let fd = open("readahead\0", OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    let mut block = [0u8; 512];
+    for i in 0..16u8 {
+        block.fill(i);
+        assert_eq!(write(fd as usize, &block), 512);
+    }
+    close(fd as usize);
+
+    let before = stat("readahead_blocks");
+    let fd = open("readahead\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    for i in 0..16u8 {
+        assert_eq!(read(fd as usize, &mut block), 512);
+        assert!(block.iter().all(|&b| b == i));
+        // 睡眠时 CPU 空闲, 内核处理预读请求
+        sleep_blocking(1);
+    }
+    assert_eq!(read(fd as usize, &mut block), 0);
+    close(fd as usize);
+    assert!(stat("readahead_blocks") > before);
+    println!("Test readahead OK!");
+    0
+}
@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getrusage, pipe, read, waitpid, write, Rusage, IO_CONSOLE, IO_PIPE,
+    RUSAGE_CHILDREN, RUSAGE_SELF, STDOUT,
+};
+
+/*
+理想结果：getrusage 记下的控制台与管道字节数与实际读写的一致，子进程写时复制的缺页被计入，输出 Test rusage OK!
+*/
+
+/// 两页大小, 中间那个字节所在的页完全属于它, 父进程从未写过
+static mut PAGES: [u8; 8192] = [0; 8192];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut before = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_SELF, &mut before), 0);
+    // 两次快照之间不能有 println, 否则会多算控制台字节
+    assert_eq!(write(STDOUT, b"rusage\n"), 7);
+    assert_eq!(write(fds[1], b"hello"), 5);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fds[0], &mut buf[..3]), 3);
+    let mut after = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_SELF, &mut after), 0);
+    assert_eq!(after.written[IO_CONSOLE] - before.written[IO_CONSOLE], 7);
+    assert_eq!(after.written[IO_PIPE] - before.written[IO_PIPE], 5);
+    assert_eq!(after.read[IO_PIPE] - before.read[IO_PIPE], 3);
+    assert_eq!(after.read[IO_CONSOLE], before.read[IO_CONSOLE]);
+    // 不可写的缓冲区被拒绝, 管道中的数据也不会丢
+    let bad = unsafe { core::slice::from_raw_parts_mut(8 as *mut u8, 2) };
+    assert_eq!(read(fds[0], bad), -1);
+    assert_eq!(read(fds[0], &mut buf), 2);
+    close(fds[0]);
+    close(fds[1]);
+    // fork 之后第一次写 PAGES 是一次写时复制缺页
+    let pid = fork();
+    if pid == 0 {
+        let mut before = Rusage::default();
+        getrusage(RUSAGE_SELF, &mut before);
+        unsafe {
+            PAGES[4096] = 1;
+        }
+        let mut after = Rusage::default();
+        getrusage(RUSAGE_SELF, &mut after);
+        exit(if after.minflt > before.minflt { 0 } else { 1 });
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    let mut children = Rusage::default();
+    assert_eq!(getrusage(RUSAGE_CHILDREN, &mut children), 0);
+    assert!(children.minflt >= 1);
+    assert_eq!(children.majflt, 0);
+    println!("Test rusage OK!");
+    0
+}
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::{
+    close, exit, fork, kill, nanosleep, pipe, read, sigaction, sigreturn, sleep, waitpid, write,
+    SignalAction, TimeSpec, EINTR, SA_RESTART, SIGUSR1,
+};
+
+/*
+理想结果：阻塞在管道读上的子进程收到 SIGUSR1，处理函数设置了 SA_RESTART 时读在处理函数返回后重新进行，
+读到父进程随后写入的字节；没有设置时读以 EINTR 失败。睡眠被处理函数打断时不论是否设置 SA_RESTART 都以 EINTR 失败。
+输出 Test SA_RESTART OK!
+*/
+
+static HANDLED: AtomicBool = AtomicBool::new(false);
+
+fn on_usr1(_signum: usize) {
+    HANDLED.store(true, Ordering::SeqCst);
+    sigreturn();
+}
+
+/// 子进程以 `flags` 设置 SIGUSR1 的处理函数后运行 `child`，父进程等它阻塞后发送 SIGUSR1，
+/// 再向管道写入一个字节；返回子进程的退出码，即 `child` 的返回值
+fn interrupted(flags: u32, child: fn(usize) -> isize) -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[1]);
+        let action = SignalAction {
+            handler: on_usr1 as usize,
+            mask: 0,
+            flags,
+        };
+        assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+        let ret = child(fds[0]);
+        assert!(HANDLED.load(Ordering::SeqCst));
+        exit(ret as i32);
+    }
+    close(fds[0]);
+    // 等子进程阻塞
+    sleep(100);
+    assert_eq!(kill(pid as usize, SIGUSR1), 0);
+    sleep(100);
+    write(fds[1], b"x");
+    close(fds[1]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+fn read_byte(fd: usize) -> isize {
+    let mut buf = [0u8; 1];
+    read(fd, &mut buf)
+}
+
+fn sleep_long(_fd: usize) -> isize {
+    nanosleep(&TimeSpec { sec: 10, nsec: 0 })
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(interrupted(SA_RESTART, read_byte), 1);
+    assert_eq!(interrupted(0, read_byte), EINTR as i32);
+    assert_eq!(interrupted(SA_RESTART, sleep_long), EINTR as i32);
+    println!("Test SA_RESTART OK!");
+    0
+}
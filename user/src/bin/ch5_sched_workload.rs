@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{sched_workload, SchedWorker};
+
+/*
+理想结果：两个不睡眠的内核线程按优先级 1:2 分得 CPU，一个只用四分之一时间的线程不多占 CPU，非法参数被拒绝，输出 Test sched_workload OK!
+*/
+
+/// 允许的误差, 千分之
+const TOLERANCE: usize = 60;
+
+fn assert_share(worker: &SchedWorker, expected: usize) {
+    println!(
+        "priority {}, cpu {}us sleep {}us: ran {}us, share {}/1000",
+        worker.priority, worker.cpu_us, worker.sleep_us, worker.ran_us, worker.share
+    );
+    assert!(worker.share + TOLERANCE >= expected && worker.share <= expected + TOLERANCE);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // 优先级两倍, CPU 两倍
+    let mut workers = [SchedWorker::new(8, 100_000, 0), SchedWorker::new(16, 100_000, 0)];
+    assert_eq!(sched_workload(&mut workers, 1000), 0);
+    assert_share(&workers[0], 333);
+    assert_share(&workers[1], 667);
+    // 50ms CPU, 150ms 睡眠: 只占四分之一, 醒来晚一两个时间片会再少一点
+    let mut workers = [SchedWorker::new(16, 50_000, 150_000), SchedWorker::new(16, 100_000, 0)];
+    assert_eq!(sched_workload(&mut workers, 1000), 0);
+    assert_share(&workers[0], 240);
+    assert_share(&workers[1], 760);
+    // 非法参数
+    assert_eq!(sched_workload(&mut [], 1000), -1);
+    assert_eq!(sched_workload(&mut [SchedWorker::new(1, 1000, 0)], 1000), -1);
+    assert_eq!(sched_workload(&mut [SchedWorker::new(16, 0, 1000)], 1000), -1);
+    assert_eq!(sched_workload(&mut [SchedWorker::new(16, 1000, 0)], 0), -1);
+    println!("Test sched_workload OK!");
+    0
+}
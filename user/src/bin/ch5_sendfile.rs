@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, pipe, read, sendfile, write, STDOUT};
+
+/*
+理想结果：sendfile 在两个管道之间搬运数据，遇到短读就返回，不可用的描述符被拒绝，输出 Test sendfile OK!
+*/
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut from = [0usize; 2];
+    let mut to = [0usize; 2];
+    assert_eq!(pipe(&mut from), 0);
+    assert_eq!(pipe(&mut to), 0);
+    assert_eq!(write(from[1], b"hello, sendfile"), 15);
+    // 只有 15 字节可读, 短读之后就返回, 不会阻塞
+    assert_eq!(sendfile(to[1], from[0], 100), 15);
+    let mut buf = [0u8; 32];
+    assert_eq!(read(to[0], &mut buf), 15);
+    assert_eq!(&buf[..15], b"hello, sendfile");
+    // count 限制搬运的字节数
+    assert_eq!(write(from[1], b"abcdef"), 6);
+    assert_eq!(sendfile(to[1], from[0], 4), 4);
+    assert_eq!(read(to[0], &mut buf), 4);
+    assert_eq!(&buf[..4], b"abcd");
+    // 读端不能写, 写端不能读, 不存在的描述符
+    assert_eq!(sendfile(from[0], to[0], 1), -1);
+    assert_eq!(sendfile(to[1], STDOUT, 1), -1);
+    assert_eq!(sendfile(STDOUT, 99, 1), -1);
+    for fd in from.iter().chain(to.iter()) {
+        close(*fd);
+    }
+    println!("Test sendfile OK!");
+    0
+}
@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, open, pipe, read, splice, write, OpenFlags, STDOUT};
+
+/*
+理想结果：splice 把管道里的数据直接写进磁盘文件，再从文件直接读进另一个管道，两端都不是管道或都是管道时被拒绝，输出 Test splice OK!
+*/
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut from = [0usize; 2];
+    let mut to = [0usize; 2];
+    assert_eq!(pipe(&mut from), 0);
+    assert_eq!(pipe(&mut to), 0);
+    // 管道 -> 文件
+    let fd = open("splice_file\0", OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(write(from[1], b"hello, splice"), 13);
+    // 只有 13 字节可读, 不会阻塞
+    assert_eq!(splice(from[0], fd, 100), 13);
+    close(fd);
+    // 文件 -> 管道, len 限制搬运的字节数
+    let fd = open("splice_file\0", OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(splice(fd, to[1], 5), 5);
+    assert_eq!(splice(fd, to[1], 100), 8);
+    // 文件已读完
+    assert_eq!(splice(fd, to[1], 100), 0);
+    let mut buf = [0u8; 32];
+    assert_eq!(read(to[0], &mut buf), 13);
+    assert_eq!(&buf[..13], b"hello, splice");
+    // 两端都是管道, 都不是管道, 方向不对
+    assert_eq!(splice(from[0], to[1], 1), -1);
+    assert_eq!(splice(fd, STDOUT, 1), -1);
+    assert_eq!(splice(to[1], fd, 1), -1);
+    close(fd);
+    for fd in from.iter().chain(to.iter()) {
+        close(*fd);
+    }
+    println!("Test splice OK!");
+    0
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, sigaction, waitpid, SignalAction, SIGSEGV};
+
+/*
+理想结果：无限递归的子进程在栈增长到上限后碰到保护页，以 -2 退出；
+设置了 SIGSEGV 处理函数的子进程访问非法地址时进入处理函数，以 42 退出。
+输出 Test stack guard OK!
+*/
+
+#[allow(unconditional_recursion)]
+fn recurse(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    unsafe { core::ptr::write_volatile(&mut frame[0], depth as u8) };
+    recurse(depth + 1) + unsafe { core::ptr::read_volatile(&frame[0]) } as usize
+}
+
+fn on_segv(_signum: usize) {
+    exit(42);
+}
+
+fn exit_code_of(child: fn()) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        child();
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(
+        exit_code_of(|| {
+            recurse(0);
+        }),
+        -2
+    );
+    assert_eq!(
+        exit_code_of(|| {
+            let action = SignalAction {
+                handler: on_segv as usize,
+                mask: 0,
+                flags: 0,
+            };
+            assert_eq!(sigaction(SIGSEGV, Some(&action), None), 0);
+            unsafe { (0x8 as *mut u8).write_volatile(0) };
+        }),
+        42
+    );
+    println!("Test stack guard OK!");
+    0
+}
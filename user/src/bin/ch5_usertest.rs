@@ -3,6 +3,7 @@
 
 #[macro_use]
 extern crate user_lib;
+extern crate alloc;
 
 static TESTS: &[&str] = &[
     "ch2b_hello_world\0",
@@ -23,20 +24,43 @@ static TESTS: &[&str] = &[
     "ch5_spawn0\0",
     "ch5_spawn1\0",
     "ch5_setprio\0",
+    "ch5_waittid\0",
+    "ch5_rusage\0",
+    "ch5_stack_guard\0",
+    "ch5_pidfd\0",
+    "ch5_pipe_size\0",
+    "ch5_sendfile\0",
+    "ch5_procfs\0",
+    "ch5_open_flags\0",
+    "ch5_mounts\0",
+    "ch5_partitions\0",
+    "ch5_blk_queue\0",
+    "ch5_readahead\0",
+    "ch5_mmap_file\0",
+    "ch5_hotplug\0",
+    "ch5_irq_route\0",
+    "ch5_sa_restart\0",
+    "ch5_arg_max\0",
+    "ch5_wake_order\0",
+    "ch5_splice\0",
+    "ch5_sched_workload\0",
+    "ch5_at_random\0",
+    "ch5_mutex\0",
     // "ch5_stride\0",
 ];
 static STEST: &str = "ch5_stride\0";
 
+use alloc::vec::Vec;
 use user_lib::{spawn, waitpid};
 
 /// 辅助测例，运行所有其他测例。
 
 #[no_mangle]
 pub fn main() -> i32 {
-    let mut pid = [0; 20];
-    for (i, &test) in TESTS.iter().enumerate() {
+    let mut pid = Vec::new();
+    for &test in TESTS.iter() {
         println!("Usertests: Running {}", test);
-        pid[i] = spawn(test);
+        pid.push(spawn(test));
     }
     let mut xstate: i32 = Default::default();
     for (i, &test) in TESTS.iter().enumerate() {
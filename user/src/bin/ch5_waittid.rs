@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, gettid, thread_create, waittid};
+
+/*
+理想结果：两个线程以参数为退出码退出，主线程通过 waittid 得到它们的退出码，输出 Test waittid OK!
+*/
+
+fn worker(arg: usize) -> ! {
+    exit(arg as i32)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let a = thread_create(worker as usize, 7);
+    let b = thread_create(worker as usize, 9);
+    assert!(a > 0 && b > 0 && a != b);
+    assert_eq!(waittid(b as usize), 9);
+    assert_eq!(waittid(a as usize), 7);
+    // 已回收的线程和自己都不能等待
+    assert_eq!(waittid(a as usize), -1);
+    assert_eq!(waittid(gettid() as usize), -1);
+    println!("Test waittid OK!");
+    0
+}
@@ -0,0 +1,54 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, exit, fork, get_time, nanosleep, pipe, read, waitpid, write, TimeSpec};
+
+/*
+理想结果：两个进程阻塞在同一个管道的读上，先阻塞的那个之前占用了更多 CPU，stride 更大；
+管道里只写入一个字节时，后阻塞、stride 更小的进程先被唤醒并读到它，另一个读到文件尾。
+输出 Test wake order OK!
+*/
+
+fn nap(ms: usize) {
+    nanosleep(&TimeSpec {
+        sec: 0,
+        nsec: ms * 1_000_000,
+    });
+}
+
+/// 先忙等 `busy_ms` 毫秒，再从 `fds[0]` 读一个字节，返回读到的字节数
+fn reader(fds: [usize; 2], busy_ms: isize) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        close(fds[1]);
+        let start = get_time();
+        while get_time() < start + busy_ms {}
+        let mut buf = [0u8; 1];
+        exit(read(fds[0], &mut buf) as i32);
+    }
+    pid
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let busy = reader(fds, 100);
+    // 等它忙完并先阻塞
+    nap(200);
+    let fresh = reader(fds, 0);
+    nap(50);
+    close(fds[0]);
+    assert_eq!(write(fds[1], b"x"), 1);
+    close(fds[1]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(fresh as usize, &mut exit_code), fresh);
+    assert_eq!(exit_code, 1);
+    assert_eq!(waitpid(busy as usize, &mut exit_code), busy);
+    assert_eq!(exit_code, 0);
+    println!("Test wake order OK!");
+    0
+}
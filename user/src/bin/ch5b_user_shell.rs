@@ -12,6 +12,7 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use user_lib::console::getchar;
 use user_lib::{exec, flush, fork, waitpid};
 
@@ -26,12 +27,22 @@ pub fn main() -> i32 {
         match c {
             LF | CR => {
                 print!("\n");
-                if !line.is_empty() {
-                    line.push('\0');
+                if !line.trim().is_empty() {
+                    let args: Vec<String> = line
+                        .split_whitespace()
+                        .map(|arg| {
+                            let mut arg = String::from(arg);
+                            arg.push('\0');
+                            arg
+                        })
+                        .collect();
+                    let mut args_addr: Vec<*const u8> =
+                        args.iter().map(|arg| arg.as_ptr()).collect();
+                    args_addr.push(0 as *const u8);
                     let pid = fork();
                     if pid == 0 {
                         // child process
-                        if exec(line.as_str(), &[0 as *const u8]) == -1 {
+                        if exec(args[0].as_str(), args_addr.as_slice()) < 0 {
                             println!("Error when executing!");
                             return -4;
                         }
@@ -42,8 +53,8 @@ pub fn main() -> i32 {
                         assert_eq!(pid, exit_pid);
                         println!("Shell: Process {} exited with code {}", pid, exit_code);
                     }
-                    line.clear();
                 }
+                line.clear();
                 print!(">> ");
                 flush();
             }
@@ -1,17 +1,57 @@
-use crate::exit;
+use crate::{exit, sys_panic};
+use core::fmt::{self, Write};
+
+/// Exit code of a program that panicked, as Rust programs use
+const PANIC_EXIT_CODE: i32 = 101;
+
+/// Longest panic message handed to the kernel, the rest is cut off
+const PANIC_MSG_LEN: usize = 256;
+
+/// A panic message formatted without the heap, which may be what failed
+struct PanicMessage {
+    buf: [u8; PANIC_MSG_LEN],
+    len: usize,
+}
+
+impl PanicMessage {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+
+impl Write for PanicMessage {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = s.len().min(PANIC_MSG_LEN - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
 
 #[panic_handler]
 fn panic_handler(panic_info: &core::panic::PanicInfo) -> ! {
     let err = panic_info.message().unwrap();
+    let mut msg = PanicMessage {
+        buf: [0; PANIC_MSG_LEN],
+        len: 0,
+    };
     if let Some(location) = panic_info.location() {
-        println!(
-            "Panicked at {}:{}, {}",
+        let _ = write!(
+            msg,
+            "panicked at {}:{}, {}",
             location.file(),
             location.line(),
             err
         );
     } else {
-        println!("Panicked: {}", err);
+        let _ = write!(msg, "panicked: {}", err);
     }
-    exit(-1);
+    crate::console::flush();
+    // the kernel logs it and exits; one without `sys_panic` fails the call
+    sys_panic(msg.as_str());
+    println!("{}", msg.as_str());
+    exit(PANIC_EXIT_CODE);
 }
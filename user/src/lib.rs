@@ -44,6 +44,30 @@ fn clear_bss() {
     }
 }
 
+/// Where the kernel put the auxiliary vector, past argv and envp; 0 if it
+/// passed no argv
+static mut AUXV: usize = 0;
+
+/// Auxiliary vector entry: address of 16 random bytes
+pub const AT_RANDOM: usize = 25;
+
+/// Value of the auxiliary vector entry of type `kind`, as Linux's
+/// getauxval
+pub fn getauxval(kind: usize) -> Option<usize> {
+    let mut entry = unsafe { AUXV } as *const [usize; 2];
+    if entry.is_null() {
+        return None;
+    }
+    loop {
+        let [ty, value] = unsafe { entry.read_volatile() };
+        match ty {
+            0 => return None,
+            ty if ty == kind => return Some(value),
+            _ => entry = entry.wrapping_add(1),
+        }
+    }
+}
+
 #[no_mangle]
 #[link_section = ".text.entry"]
 pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
@@ -52,6 +76,11 @@ pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
+    if argv != 0 {
+        unsafe {
+            AUXV = argv + (argc + 2) * core::mem::size_of::<usize>();
+        }
+    }
     let mut v: Vec<&'static str> = Vec::new();
     for i in 0..argc {
         let str_start =
@@ -82,6 +111,7 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const APPEND = 1 << 11;
     }
 }
 
@@ -158,6 +188,120 @@ impl Stat {
     }
 }
 
+/// the default action of a signal
+pub const SIG_DFL: usize = 0;
+/// ignoring a signal
+pub const SIG_IGN: usize = 1;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+/// restarting a system call the signal interrupts once the handler returns
+pub const SA_RESTART: u32 = 0x1000_0000;
+/// what an interrupted system call returns when it is not restarted
+pub const EINTR: isize = -4;
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SignalAction {
+    /// handler entry point, or SIG_DFL or SIG_IGN
+    pub handler: usize,
+    /// signals blocked in addition while the handler runs
+    pub mask: u32,
+    /// SA_RESTART or 0
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
+/// One worker of `sched_workload`: its duty cycle in, what it got of the
+/// CPU out
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SchedWorker {
+    /// at least 2, as for set_priority
+    pub priority: usize,
+    /// CPU time used between two sleeps, more than 0
+    pub cpu_us: usize,
+    /// 0 for a worker that never sleeps
+    pub sleep_us: usize,
+    /// CPU time the worker got
+    pub ran_us: usize,
+    /// that in thousandths of what all the workers got
+    pub share: usize,
+}
+
+impl SchedWorker {
+    pub fn new(priority: usize, cpu_us: usize, sleep_us: usize) -> Self {
+        Self {
+            priority,
+            cpu_us,
+            sleep_us,
+            ..Self::default()
+        }
+    }
+}
+
+/// fcntl commands on a pipe end: resize its buffer, returning the new size,
+/// and get the size
+pub const F_SETPIPE_SZ: usize = 1031;
+pub const F_GETPIPE_SZ: usize = 1032;
+/// ioctl request: store the bytes a read could take right away at an i32
+pub const FIONREAD: usize = 0x541b;
+
+/// ppoll events: reading, or writing, would not block
+pub const POLLIN: i16 = 0x1;
+pub const POLLOUT: i16 = 0x4;
+/// ppoll event, always reported: the descriptor is not open
+pub const POLLNVAL: i16 = 0x20;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: i16) -> Self {
+        Self {
+            fd: fd as i32,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+/// getrusage of the caller itself
+pub const RUSAGE_SELF: isize = 0;
+/// getrusage of the reaped descendants of the caller
+pub const RUSAGE_CHILDREN: isize = -1;
+
+/// indices of the I/O counts in Rusage: console, pipe, disk file, other device
+pub const IO_CONSOLE: usize = 0;
+pub const IO_PIPE: usize = 1;
+pub const IO_FILE: usize = 2;
+pub const IO_DEVICE: usize = 3;
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Rusage {
+    pub utime: TimeVal,
+    pub stime: TimeVal,
+    /// bytes read through read, by IO_* class
+    pub read: [u64; 4],
+    /// bytes written through write, by IO_* class
+    pub written: [u64; 4],
+    /// page faults resolved without I/O: lazy pages, copy-on-write, stack growth
+    pub minflt: u64,
+    /// page faults that needed I/O: first accesses to pages of mapped files
+    pub majflt: u64,
+}
+
 impl Default for Stat {
     fn default() -> Self {
         Self::new()
@@ -180,6 +324,10 @@ pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_openat(AT_FDCWD as usize, path, flags.bits, OpenFlags::RDWR.bits)
 }
 
+pub fn chdir(path: &str) -> isize {
+    sys_chdir(path)
+}
+
 pub fn close(fd: usize) -> isize {
     if fd == STDOUT {
         console::flush();
@@ -240,6 +388,14 @@ pub fn fork() -> isize {
     sys_fork()
 }
 
+/// most arguments exec takes, and the longest one, NUL excluded
+pub const MAX_ARGS: usize = 16;
+pub const MAX_ARG_LEN: usize = 128;
+/// most bytes the arguments of exec may take, NULs and argv included
+pub const ARG_MAX: usize = 2048;
+/// what exec returns for arguments over these limits
+pub const E2BIG: isize = -7;
+
 pub fn exec(path: &str, args: &[*const u8]) -> isize {
     sys_exec(path, args)
 }
@@ -248,6 +404,12 @@ pub fn set_priority(prio: isize) -> isize {
     sys_set_priority(prio)
 }
 
+/// Run `workers` as kernel threads for `duration_ms` and fill in the CPU
+/// time each got; 0, or -1
+pub fn sched_workload(workers: &mut [SchedWorker], duration_ms: usize) -> isize {
+    sys_sched_workload(workers, duration_ms)
+}
+
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
@@ -292,6 +454,42 @@ pub fn munmap(start: usize, len: usize) -> isize {
     sys_munmap(start, len)
 }
 
+/// msync flag: write back before returning, which the kernel always does
+pub const MS_SYNC: usize = 4;
+
+pub fn msync(start: usize, len: usize, flags: usize) -> isize {
+    sys_msync(start, len, flags)
+}
+
+/// Map all of the file open as `fd` at `start`, returning its size
+pub fn mmap_file(fd: usize, start: usize, prot: usize) -> isize {
+    sys_mmap_file(fd, start, prot)
+}
+
+/// hart_ctl operations
+pub const HART_STOP: usize = 0;
+pub const HART_START: usize = 1;
+/// hart states reported by hart_status, numbered as in the SBI
+pub const HART_STARTED: usize = 0;
+pub const HART_STOPPED: usize = 1;
+/// hart_status state of a hart the machine does not have
+pub const HART_ABSENT: usize = usize::MAX;
+
+pub fn hart_ctl(hart: usize, op: usize) -> isize {
+    sys_hart_ctl(hart, op)
+}
+
+/// Fill `states` with the state of each hart, returning how many harts the
+/// kernel supports
+pub fn hart_status(states: &mut [usize]) -> isize {
+    sys_hart_status(states)
+}
+
+/// Route the interrupt source `irq` to `hart`
+pub fn irq_route(irq: usize, hart: usize) -> isize {
+    sys_irq_route(irq, hart)
+}
+
 pub fn spawn(path: &str) -> isize {
     sys_spawn(path)
 }
@@ -299,6 +497,9 @@ pub fn spawn(path: &str) -> isize {
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    sys_dup2(old_fd, new_fd)
+}
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
     sys_pipe(pipe_fd)
 }
@@ -307,6 +508,67 @@ pub fn task_info(info: &TaskInfo) -> isize {
     sys_task_info(info)
 }
 
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |action| action as *const _),
+        old_action.map_or(core::ptr::null_mut(), |old| old as *mut _),
+    )
+}
+
+/// Send `signum` to process `pid`
+pub fn kill(pid: usize, signum: usize) -> isize {
+    sys_kill(pid as isize, signum)
+}
+
+/// End a signal handler, going back to where the signal interrupted the
+/// process; every handler must end with it
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
+pub fn nanosleep(req: &TimeSpec) -> isize {
+    sys_nanosleep(req)
+}
+
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+pub fn ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_ioctl(fd, cmd, arg)
+}
+
+/// Copy up to `count` bytes from `in_fd` to `out_fd` inside the kernel
+pub fn sendfile(out_fd: usize, in_fd: usize, count: usize) -> isize {
+    sys_sendfile(out_fd, in_fd, count)
+}
+
+/// Move up to `len` bytes between a pipe and another file, at the file's
+/// own offset; exactly one of `fd_in` and `fd_out` must be a pipe
+pub fn splice(fd_in: usize, fd_out: usize, len: usize) -> isize {
+    sys_splice(fd_in, fd_out, len)
+}
+
+/// Wait for an event on one of `fds` for at most `timeout`, forever if it
+/// is None; the number of entries whose revents are set
+pub fn ppoll(fds: &mut [PollFd], timeout: Option<&TimeSpec>) -> isize {
+    sys_ppoll(fds, timeout.map_or(core::ptr::null(), |t| t as *const _))
+}
+
+/// A descriptor that ppoll reports POLLIN on once process `pid` exits
+pub fn pidfd_open(pid: usize) -> isize {
+    sys_pidfd_open(pid, 0)
+}
+
+pub fn getrusage(who: isize, usage: &mut Rusage) -> isize {
+    sys_getrusage(who, usage)
+}
+
 pub fn thread_create(entry: usize, arg: usize) -> isize {
     sys_thread_create(entry, arg)
 }
@@ -1,10 +1,14 @@
 use crate::TaskInfo;
 
-use super::{Stat, TimeVal};
+use super::{PollFd, Rusage, SchedWorker, SignalAction, Stat, TimeSpec, TimeVal};
 
+pub const SYSCALL_CHDIR: usize = 49;
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
 pub const SYSCALL_READ: usize = 63;
+pub const SYSCALL_SENDFILE: usize = 71;
+pub const SYSCALL_PPOLL: usize = 73;
+pub const SYSCALL_SPLICE: usize = 76;
 pub const SYSCALL_WRITE: usize = 64;
 pub const SYSCALL_UNLINKAT: usize = 35;
 pub const SYSCALL_LINKAT: usize = 37;
@@ -12,6 +16,11 @@ pub const SYSCALL_FSTAT: usize = 80;
 pub const SYSCALL_EXIT: usize = 93;
 pub const SYSCALL_SLEEP: usize = 101;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_KILL: usize = 129;
+pub const SYSCALL_SIGACTION: usize = 134;
+pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_PIDFD_OPEN: usize = 434;
+pub const SYSCALL_GETRUSAGE: usize = 165;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETTID: usize = 178;
@@ -21,14 +30,24 @@ pub const SYSCALL_WAITPID: usize = 260;
 pub const SYSCALL_SET_PRIORITY: usize = 140;
 pub const SYSCALL_MUNMAP: usize = 215;
 pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MSYNC: usize = 227;
+pub const SYSCALL_MMAP_FILE: usize = 477;
+pub const SYSCALL_HART_CTL: usize = 475;
+pub const SYSCALL_IRQ_ROUTE: usize = 474;
+pub const SYSCALL_HART_STATUS: usize = 476;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
 pub const SYSCALL_DUP: usize = 24;
+pub const SYSCALL_FCNTL: usize = 25;
+pub const SYSCALL_IOCTL: usize = 29;
 pub const SYSCALL_PIPE: usize = 59;
+pub const SYSCALL_DUP2: usize = 495;
+pub const SYSCALL_PANIC: usize = 496;
 pub const SYSCALL_TASK_INFO: usize = 410;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
-pub const SYSCALL_WAITTID: usize = 462;
+pub const SYSCALL_WAITTID: usize = 494;
+pub const SYSCALL_SCHED_WORKLOAD: usize = 500;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
 pub const SYSCALL_MUTEX_LOCK: usize = 464;
 pub const SYSCALL_MUTEX_UNLOCK: usize = 466;
@@ -70,6 +89,10 @@ pub fn syscall6(id: usize, args: [usize; 6]) -> isize {
     ret
 }
 
+pub fn sys_chdir(path: &str) -> isize {
+    syscall(SYSCALL_CHDIR, [path.as_ptr() as usize, 0, 0])
+}
+
 pub fn sys_openat(dirfd: usize, path: &str, flags: u32, mode: u32) -> isize {
     syscall6(
         SYSCALL_OPENAT,
@@ -146,6 +169,12 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("sys_exit never returns!");
 }
 
+/// Hand the panic message to the kernel, which logs it and exits the
+/// program; returns only if the kernel has no `sys_panic`
+pub fn sys_panic(msg: &str) -> isize {
+    syscall(SYSCALL_PANIC, [msg.as_ptr() as usize, msg.len(), 0])
+}
+
 pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
@@ -177,6 +206,29 @@ pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
     syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
 }
 
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_kill(pid: isize, signum: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid as usize, signum, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_nanosleep(req: &TimeSpec) -> isize {
+    syscall(SYSCALL_SLEEP, [req as *const _ as usize, 0, 0])
+}
+
 pub fn sys_set_priority(prio: isize) -> isize {
     syscall(SYSCALL_SET_PRIORITY, [prio as usize, 0, 0])
 }
@@ -189,6 +241,26 @@ pub fn sys_munmap(start: usize, len: usize) -> isize {
     syscall(SYSCALL_MUNMAP, [start, len, 0])
 }
 
+pub fn sys_msync(start: usize, len: usize, flags: usize) -> isize {
+    syscall(SYSCALL_MSYNC, [start, len, flags])
+}
+
+pub fn sys_mmap_file(fd: usize, start: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MMAP_FILE, [fd, start, prot])
+}
+
+pub fn sys_hart_ctl(hart: usize, op: usize) -> isize {
+    syscall(SYSCALL_HART_CTL, [hart, op, 0])
+}
+
+pub fn sys_hart_status(states: &mut [usize]) -> isize {
+    syscall(SYSCALL_HART_STATUS, [states.as_mut_ptr() as usize, states.len(), 0])
+}
+
+pub fn sys_irq_route(irq: usize, hart: usize) -> isize {
+    syscall(SYSCALL_IRQ_ROUTE, [irq, hart, 0])
+}
+
 pub fn sys_spawn(path: &str) -> isize {
     syscall(SYSCALL_SPAWN, [path.as_ptr() as usize, 0, 0])
 }
@@ -197,6 +269,10 @@ pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    syscall(SYSCALL_DUP2, [old_fd, new_fd, 0])
+}
+
 pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
 }
@@ -205,6 +281,44 @@ pub fn sys_task_info(info: &TaskInfo) -> isize {
     syscall(SYSCALL_TASK_INFO, [info as *const _ as usize, 0, 0])
 }
 
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+pub fn sys_ioctl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, cmd, arg])
+}
+
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, count: usize) -> isize {
+    syscall(SYSCALL_SENDFILE, [out_fd, in_fd, count])
+}
+
+pub fn sys_splice(fd_in: usize, fd_out: usize, len: usize) -> isize {
+    syscall(SYSCALL_SPLICE, [fd_in, fd_out, len])
+}
+
+pub fn sys_sched_workload(workers: &mut [SchedWorker], duration_ms: usize) -> isize {
+    syscall(
+        SYSCALL_SCHED_WORKLOAD,
+        [workers.as_mut_ptr() as usize, workers.len(), duration_ms],
+    )
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd], timeout: *const TimeSpec) -> isize {
+    syscall(
+        SYSCALL_PPOLL,
+        [fds.as_mut_ptr() as usize, fds.len(), timeout as usize],
+    )
+}
+
+pub fn sys_pidfd_open(pid: usize, flags: usize) -> isize {
+    syscall(SYSCALL_PIDFD_OPEN, [pid, flags, 0])
+}
+
+pub fn sys_getrusage(who: isize, usage: &mut Rusage) -> isize {
+    syscall(SYSCALL_GETRUSAGE, [who as usize, usage as *mut _ as usize, 0])
+}
+
 pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
     syscall(SYSCALL_THREAD_CREATE, [entry, arg, 0])
 }